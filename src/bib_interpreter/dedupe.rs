@@ -0,0 +1,270 @@
+//! Find the duplicate entries of bib files, and merge them
+//!
+//! Two entries are duplicates if they have the same doi (ignoring case and a `https://doi.org/`
+//! prefix), or the same title, year and last name of the first author (or editor). Titles and
+//! names are compared in lower case, with only their letters and digits, so that `{DNA} Repair`
+//! and `DNA repair` match. The `date` field stands for a missing `year`.
+//!
+//! The first entry of a group of duplicates is kept. The fields it lacks are taken from the
+//! others, and the fields they disagree on are reported.
+
+use super::formatter::{format_value, BibStyle};
+use super::parser::{BibFile, Entry, Field};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DuplicateReason {
+    Doi(String),
+    TitleYearAuthor,
+}
+
+/// Entries found to be duplicates, in file order, the first of which is kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup<'a> {
+    /// The index of the file of the entry, and the entry
+    pub entries: Vec<(usize, &'a Entry)>,
+    /// Why the second entry is a duplicate of the first one
+    pub reason: DuplicateReason,
+}
+
+/// The groups of duplicate entries of the bibs, in the order of their first entry
+pub fn find_duplicates<'a>(bibs: &[&'a BibFile]) -> Vec<DuplicateGroup<'a>> {
+    // the groups, with the doi and title keys of their entries
+    let mut groups: Vec<(DuplicateGroup, Vec<String>, Vec<String>)> = vec![];
+    for (file, bib) in bibs.iter().enumerate() {
+        for entry in bib.entries() {
+            let doi = doi_key(entry);
+            let title = title_key(entry);
+            let found = groups.iter_mut().find(|(_, dois, titles)| {
+                doi.as_ref().is_some_and(|d| dois.contains(d))
+                    || title.as_ref().is_some_and(|t| titles.contains(t))
+            });
+            match found {
+                Some((group, dois, titles)) => {
+                    if group.entries.len() == 1 {
+                        group.reason = match &doi {
+                            Some(d) if dois.contains(d) => {
+                                DuplicateReason::Doi(d.clone())
+                            }
+                            _ => DuplicateReason::TitleYearAuthor,
+                        };
+                    }
+                    group.entries.push((file, entry));
+                    dois.extend(doi);
+                    titles.extend(title);
+                }
+                None => groups.push((
+                    DuplicateGroup {
+                        entries: vec![(file, entry)],
+                        reason: DuplicateReason::TitleYearAuthor,
+                    },
+                    doi.into_iter().collect(),
+                    title.into_iter().collect(),
+                )),
+            }
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(group, _, _)| group)
+        .filter(|group| group.entries.len() > 1)
+        .collect()
+}
+
+/// The lower case letters and digits of text
+fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect()
+}
+
+fn doi_key(entry: &Entry) -> Option<String> {
+    let doi = entry.get_field("doi")?.value.to_plain().to_lowercase();
+    let doi = doi.trim();
+    let doi = ["https://", "http://"]
+        .iter()
+        .find_map(|p| doi.strip_prefix(p))
+        .unwrap_or(doi);
+    let doi = ["doi.org/", "dx.doi.org/", "doi:"]
+        .iter()
+        .find_map(|p| doi.strip_prefix(p))
+        .unwrap_or(doi);
+    (!doi.is_empty()).then(|| doi.to_string())
+}
+
+fn title_key(entry: &Entry) -> Option<String> {
+    let title = normalize(&entry.get_field("title")?.value.to_plain());
+    let year = match entry.get_field("year") {
+        Some(year) => year.value.to_plain(),
+        None => entry
+            .get_field("date")?
+            .value
+            .to_plain()
+            .chars()
+            .take(4)
+            .collect(),
+    };
+    let names = entry
+        .get_field("author")
+        .or_else(|| entry.get_field("editor"))?
+        .value
+        .to_plain();
+    let first = names.split(" and ").next().unwrap_or_default();
+    let last = match first.split_once(',') {
+        Some((last, _)) => last,
+        None => first.split_whitespace().last().unwrap_or_default(),
+    };
+    if title.is_empty() || last.is_empty() {
+        return None;
+    }
+    Some(format!("{}|{}|{}", title, year.trim(), normalize(last)))
+}
+
+/// Merge the entries into the first one: the fields it lacks are taken from the others, in order
+/// Returns the merged entry, and the names of the fields whose values differ
+pub fn merge(entries: &[&Entry]) -> (Entry, Vec<String>) {
+    let mut merged = entries[0].clone();
+    let mut conflicts: Vec<String> = vec![];
+    for entry in &entries[1..] {
+        for field in &entry.fields {
+            let name = field.get_name();
+            match merged.get_field(&name) {
+                None => merged.fields.push(field.clone()),
+                Some(kept)
+                    if normalize(&kept.value.to_plain())
+                        != normalize(&field.value.to_plain())
+                        && !conflicts.contains(&name) =>
+                {
+                    conflicts.push(name)
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    (merged, conflicts)
+}
+
+const DIFF_WIDTH: usize = 40;
+
+/// The fields of a and b side by side, each column headed by its title
+/// The first column marks the fields that differ by `!`, and those only on the left or the right
+/// by `<` or `>`
+pub fn side_by_side(a: (&str, &Entry), b: (&str, &Entry)) -> String {
+    let cell = |field: Option<&Field>| match field {
+        Some(f) => {
+            let text = format!(
+                "{} = {}",
+                f.get_name(),
+                format_value(&f.value, BibStyle::Keep)
+            );
+            match text.char_indices().nth(DIFF_WIDTH - 1) {
+                Some((i, _)) => format!("{}…", &text[..i]),
+                None => text,
+            }
+        }
+        None => String::new(),
+    };
+
+    let mut names: Vec<String> =
+        a.1.fields.iter().map(|f| f.get_name()).collect();
+    for field in &b.1.fields {
+        if !names.contains(&field.get_name()) {
+            names.push(field.get_name());
+        }
+    }
+    let mut rows = vec![(' ', a.0.to_string(), b.0.to_string())];
+    for name in names {
+        let (left, right) = (a.1.get_field(&name), b.1.get_field(&name));
+        let mark = match (left, right) {
+            (Some(l), Some(r))
+                if normalize(&l.value.to_plain())
+                    == normalize(&r.value.to_plain()) =>
+            {
+                ' '
+            }
+            (Some(_), Some(_)) => '!',
+            (Some(_), None) => '<',
+            _ => '>',
+        };
+        rows.push((mark, cell(left), cell(right)));
+    }
+
+    let width = rows
+        .iter()
+        .map(|(_, left, _)| left.chars().count())
+        .max()
+        .unwrap_or(0);
+    rows.iter()
+        .map(|(mark, left, right)| {
+            format!("{} {:width$} | {}", mark, left, right)
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bib_interpreter::parser::parse;
+    use crate::utils::FileInput;
+
+    fn parse_str(source: &str) -> BibFile {
+        parse(&FileInput::from_str("a.bib", source)).unwrap()
+    }
+
+    #[test]
+    fn test_dedupe() {
+        let ours = parse_str(
+            r#"@article{knuth84, author = {Donald E. Knuth}, title = {Literate {P}rogramming}, year = 1984}
+@book{ovid, author = {Ovid}, title = {Metamorphoses}, doi = {10.1000/met}}
+@misc{other, title = {Literate Programming}, year = 1984}"#,
+        );
+        let theirs = parse_str(
+            r#"@article{Knuth:1984, author = {Knuth, D.}, title = "Literate programming", date = {1984-05}, pages = {97--111}}
+@book{met, title = {Metamorphoses}, doi = {https://doi.org/10.1000/MET}, year = 8}"#,
+        );
+        let groups = find_duplicates(&[&ours, &theirs]);
+        let summary: Vec<Vec<(usize, &str)>> = groups
+            .iter()
+            .map(|g| {
+                g.entries
+                    .iter()
+                    .map(|(f, e)| (*f, e.key.as_str()))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                vec![(0, "knuth84"), (1, "Knuth:1984")],
+                vec![(0, "ovid"), (1, "met")]
+            ]
+        );
+        assert_eq!(groups[0].reason, DuplicateReason::TitleYearAuthor);
+        assert_eq!(
+            groups[1].reason,
+            DuplicateReason::Doi("10.1000/met".into())
+        );
+
+        let entries: Vec<&Entry> =
+            groups[0].entries.iter().map(|(_, e)| *e).collect();
+        let (merged, conflicts) = merge(&entries);
+        assert_eq!(merged.key, "knuth84");
+        let names: Vec<String> =
+            merged.fields.iter().map(|f| f.get_name()).collect();
+        assert_eq!(names, vec!["author", "title", "year", "date", "pages"]);
+        assert_eq!(conflicts, vec!["author"]);
+
+        assert_eq!(
+            side_by_side(("knuth84", entries[0]), ("Knuth:1984", entries[1])),
+            r#"  knuth84                          | Knuth:1984
+! author = {Donald E. Knuth}       | author = {Knuth, D.}
+  title = {Literate {P}rogramming} | title = "Literate programming"
+< year = 1984                      |
+>                                  | date = {1984-05}
+>                                  | pages = {97--111}"#
+        );
+    }
+}
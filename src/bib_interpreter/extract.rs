@@ -0,0 +1,158 @@
+//! Extract the entries cited by a document from bib files
+//!
+//! Besides the cited entries, the result has the entries they `crossref` (and the entries those
+//! cross-reference, and so on), the `@string` macros used by all of them (and the macros those
+//! use), and the `@preamble`s. The items are copied from the source text as they are, in the order
+//! of the files, with a blank line between them.
+//! `@string` names are compared ignoring case, as bibtex does.
+
+use std::collections::HashSet;
+
+use super::parser::{BibFile, Entry, Item, Value, ValuePart};
+use crate::utils::FileInput;
+
+/// The bib text of the items of bibs needed by keys, see the module doc
+/// Returns the text, and the keys with no entry
+pub fn extract(
+    bibs: &[(FileInput, BibFile)],
+    keys: &[String],
+) -> (String, Vec<String>) {
+    let entries: Vec<&Entry> =
+        bibs.iter().flat_map(|(_, bib)| bib.entries()).collect();
+
+    // the keys needed, with the crossref parents
+    let mut needed: HashSet<&str> = HashSet::new();
+    let mut todo: Vec<&str> = keys.iter().map(|k| k.as_str()).collect();
+    let mut missing: Vec<String> = vec![];
+    while let Some(key) = todo.pop() {
+        if !needed.insert(key) {
+            continue;
+        }
+        match entries.iter().find(|e| e.key == key) {
+            Some(entry) => {
+                if let Some(parent) = entry.get_field("crossref") {
+                    if let Some(
+                        ValuePart::Braced(parent) | ValuePart::Quoted(parent),
+                    ) = parent.value.parts.first()
+                    {
+                        todo.push(parent);
+                    }
+                }
+            }
+            None => missing.push(key.to_string()),
+        }
+    }
+    missing.sort_by_key(|k| keys.iter().position(|c| c == k));
+
+    // the macros used by the needed entries, with the macros used by those
+    let mut macros: HashSet<String> = HashSet::new();
+    let mut todo: Vec<&Value> = entries
+        .iter()
+        .filter(|e| needed.contains(e.key.as_str()))
+        .flat_map(|e| e.fields.iter().map(|f| &f.value))
+        .collect();
+    while let Some(value) = todo.pop() {
+        for part in &value.parts {
+            let ValuePart::Macro(name) = part else {
+                continue;
+            };
+            if !macros.insert(name.to_lowercase()) {
+                continue;
+            }
+            for (_, bib) in bibs {
+                for item in &bib.items {
+                    match item {
+                        Item::String(s)
+                            if s.name.eq_ignore_ascii_case(name) =>
+                        {
+                            todo.push(&s.value)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    let mut ret: Vec<&str> = vec![];
+    // an entry defined twice is only extracted once
+    let mut extracted: HashSet<&str> = HashSet::new();
+    for (file_input, bib) in bibs {
+        for item in &bib.items {
+            let span = match item {
+                Item::Entry(e)
+                    if needed.contains(e.key.as_str())
+                        && extracted.insert(&e.key) =>
+                {
+                    e.span
+                }
+                Item::String(s) if macros.contains(&s.name.to_lowercase()) => {
+                    s.span
+                }
+                Item::Preamble(_, span) => *span,
+                _ => continue,
+            };
+            ret.push(file_input.get_span_str(&span));
+        }
+    }
+    let mut ret = ret.join("\n\n");
+    ret.push('\n');
+    (ret, missing)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bib_interpreter::parser::parse;
+
+    #[test]
+    fn test_extract() {
+        let group = r#"% the group bibliography
+@string{cj = "The Computer " # jour}
+@String{JOUR = {Journal}}
+@string{unused = {Unused}}
+@preamble{"\newcommand{\noop}[1]{}"}
+@article{knuth, author = {Knuth}, journal = cj, month = jan}
+@misc{unused, note = unused}
+@inbook{part, crossref = {whole}, pages = {1--2}}
+@book{whole, crossref = "series"}
+@book{series, title = {Series}}
+"#;
+        let mine = "@misc{mine}\n@article{knuth}\n";
+        let bibs = vec![
+            (
+                FileInput::from_str("group.bib", group),
+                parse(&FileInput::from_str("group.bib", group)).unwrap(),
+            ),
+            (
+                FileInput::from_str("mine.bib", mine),
+                parse(&FileInput::from_str("mine.bib", mine)).unwrap(),
+            ),
+        ];
+        let keys: Vec<String> = ["mine", "part", "ghost", "knuth"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        let (text, missing) = extract(&bibs, &keys);
+        assert_eq!(
+            text,
+            r#"@string{cj = "The Computer " # jour}
+
+@String{JOUR = {Journal}}
+
+@preamble{"\newcommand{\noop}[1]{}"}
+
+@article{knuth, author = {Knuth}, journal = cj, month = jan}
+
+@inbook{part, crossref = {whole}, pages = {1--2}}
+
+@book{whole, crossref = "series"}
+
+@book{series, title = {Series}}
+
+@misc{mine}
+"#
+        );
+        assert_eq!(missing, vec!["ghost"]);
+    }
+}
@@ -0,0 +1,301 @@
+//! Format a parsed bib file
+//!
+//! 1. Entry types and field names are written in lower case, eg `@Article{k, Title = ...}` gives
+//!    `@article{k, title = ...}`
+//! 1. Each field is on its own line, indented, with the `=` signs of an entry aligned
+//! 1. Braced and quoted values are written in the style of the options. A braced value containing
+//!    a `"` outside inner braces can not be quoted, and is left braced
+//! 1. `@string`, `@preamble` and `@comment` are kept, as are the comments between entries. Blank
+//!    text between entries is dropped, and items are separated by a blank line
+//! 1. The entries may be sorted by key, or by the order they are cited in. When sorting,
+//!    `@string` and `@preamble` stay at the top (a `@string` must be defined before it is used),
+//!    and a comment moves with the entry after it
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::parser::{BibFile, Entry, Item, Value, ValuePart};
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum BibSort {
+    /// Keep the order of the file
+    #[default]
+    None,
+    /// Sort by key, ignoring case
+    Key,
+    /// Sort by the first citation in the document, uncited entries last
+    Citation,
+}
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum BibStyle {
+    /// `title = {...}`
+    #[default]
+    Braces,
+    /// `title = "..."`
+    Quotes,
+    /// Keep the delimiters of the file
+    Keep,
+}
+
+#[derive(Debug, Default)]
+pub struct BibFormatOptions {
+    pub sort: BibSort,
+    pub style: BibStyle,
+    /// The cited keys in document order, needed by BibSort::Citation
+    pub citations: Vec<String>,
+}
+
+const INDENT: &str = "  ";
+
+/// Format bib, see the module doc
+pub fn format(bib: &BibFile, options: &BibFormatOptions) -> String {
+    let items: Vec<&Item> = bib
+        .items
+        .iter()
+        .filter(|i| !matches!(i, Item::Comment { text, is_junk: true, .. } if text.trim().is_empty()))
+        .collect();
+
+    let items: Vec<&Item> = match options.sort {
+        BibSort::None => items,
+        _ => sort(items, options),
+    };
+
+    let mut ret: Vec<String> = vec![];
+    for item in items {
+        ret.push(match item {
+            Item::Entry(entry) => format_entry(entry, options.style),
+            Item::String(s) => format!(
+                "@string{{{} = {}}}",
+                s.name,
+                format_value(&s.value, options.style)
+            ),
+            Item::Preamble(value, _) => {
+                format!("@preamble{{{}}}", format_value(value, options.style))
+            }
+            Item::Comment {
+                text,
+                is_junk: true,
+                ..
+            } => text.trim().to_string(),
+            Item::Comment { text, .. } => format!("@comment{{{}}}", text),
+        });
+    }
+    let mut ret = ret.join("\n\n");
+    ret.push('\n');
+    ret
+}
+
+/// Sort the entries, each with the comments before it, see the module doc
+fn sort<'a>(items: Vec<&'a Item>, options: &BibFormatOptions) -> Vec<&'a Item> {
+    let mut top: Vec<&Item> = vec![];
+    // the entries with the comments before them
+    let mut groups: Vec<(&Entry, Vec<&Item>)> = vec![];
+    let mut comments: Vec<&Item> = vec![];
+    // the comments at the top of the file are about the file
+    let mut at_top = true;
+    for item in items {
+        if !matches!(item, Item::Comment { .. }) {
+            at_top = false;
+        }
+        match item {
+            Item::Comment { .. } if at_top => top.push(item),
+            Item::Entry(entry) => {
+                comments.push(item);
+                groups.push((entry, std::mem::take(&mut comments)));
+            }
+            Item::Comment { .. } => comments.push(item),
+            _ => top.push(item),
+        }
+    }
+
+    match options.sort {
+        BibSort::Key => groups.sort_by_key(|(e, _)| e.key.to_lowercase()),
+        _ => groups.sort_by_key(|(e, _)| {
+            options
+                .citations
+                .iter()
+                .position(|c| *c == e.key)
+                .unwrap_or(usize::MAX)
+        }),
+    }
+
+    top.into_iter()
+        .chain(groups.into_iter().flat_map(|(_, items)| items))
+        .chain(comments)
+        .collect()
+}
+
+pub(crate) fn format_entry(entry: &Entry, style: BibStyle) -> String {
+    let mut ret = format!("@{}{{{}", entry.kind(), entry.key);
+    let width = entry
+        .fields
+        .iter()
+        .map(|f| f.name.chars().count())
+        .max()
+        .unwrap_or(0);
+    for field in &entry.fields {
+        ret.push_str(&format!(
+            ",\n{}{:width$} = {}",
+            INDENT,
+            field.get_name(),
+            format_value(&field.value, style),
+        ));
+    }
+    ret.push_str("\n}");
+    ret
+}
+
+pub(crate) fn format_value(value: &Value, style: BibStyle) -> String {
+    let parts: Vec<String> = value
+        .parts
+        .iter()
+        .map(|part| match (part, style) {
+            (ValuePart::Braced(s), BibStyle::Quotes) if can_quote(s) => {
+                format!("\"{}\"", s)
+            }
+            (ValuePart::Quoted(s), BibStyle::Braces) => format!("{{{}}}", s),
+            (ValuePart::Braced(s), _) => format!("{{{}}}", s),
+            (ValuePart::Quoted(s), _) => format!("\"{}\"", s),
+            (ValuePart::Number(s), _) | (ValuePart::Macro(s), _) => s.clone(),
+        })
+        .collect();
+    parts.join(" # ")
+}
+
+/// Whether s has no `"` outside braces, which would end a quoted value
+fn can_quote(s: &str) -> bool {
+    let mut depth = 0;
+    for c in s.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '"' if depth == 0 => return false,
+            _ => {}
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bib_interpreter::parser::parse;
+    use crate::utils::FileInput;
+
+    const SOURCE: &str = r##"% My references
+@comment{generated}
+
+@String{ov = "Ovid"}
+@COMMENT{checked}
+% the old one
+@Book{virgil,
+  Title="Aeneid", YEAR = 19,
+  Author = {Virgil}}
+
+@misc (Ovid,
+  author = ov # { and others},
+  note = {a "quoted" word},
+)
+"##;
+
+    fn format_str(options: &BibFormatOptions) -> String {
+        let bib = parse(&FileInput::from_str("a.bib", SOURCE)).unwrap();
+        format(&bib, options)
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(
+            format_str(&BibFormatOptions::default()),
+            r##"% My references
+
+@comment{generated}
+
+@string{ov = {Ovid}}
+
+@comment{checked}
+
+% the old one
+
+@book{virgil,
+  title  = {Aeneid},
+  year   = 19,
+  author = {Virgil}
+}
+
+@misc{Ovid,
+  author = ov # { and others},
+  note   = {a "quoted" word}
+}
+"##
+        );
+    }
+
+    #[test]
+    fn test_quotes_and_sort() {
+        let options = BibFormatOptions {
+            sort: BibSort::Key,
+            style: BibStyle::Quotes,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_str(&options),
+            r##"% My references
+
+@comment{generated}
+
+@string{ov = "Ovid"}
+
+@misc{Ovid,
+  author = ov # " and others",
+  note   = {a "quoted" word}
+}
+
+@comment{checked}
+
+% the old one
+
+@book{virgil,
+  title  = "Aeneid",
+  year   = 19,
+  author = "Virgil"
+}
+"##
+        );
+
+        let options = BibFormatOptions {
+            sort: BibSort::Citation,
+            style: BibStyle::Keep,
+            citations: vec!["Ovid".into(), "virgil".into()],
+        };
+        let formatted = format_str(&options);
+        assert!(
+            formatted.find("@misc{Ovid").unwrap()
+                < formatted.find("@book").unwrap()
+        );
+        assert!(formatted.contains("title  = \"Aeneid\""));
+    }
+}
@@ -0,0 +1,446 @@
+//! Hygiene checks of bib files
+//!
+//! 1. MissingField: an entry lacks a field required by its type, eg `@article` without `journal`.
+//!    The biblatex alternatives count (`date` for `year`, `journaltitle` for `journal`, ...).
+//!    Entries with a `crossref` inherit the fields of their parent, and are not checked.
+//! 1. UnprotectedCapitals: a word of the title with capitals after its first letter, like `DNA`
+//!    or `LaTeX`, which most styles lower case unless it is protected by braces: `{DNA}`
+//! 1. AuthorFormat: the names of an author (or editor) list mix `Last, First` and `First Last`, a
+//!    name has more than two commas (the names are probably separated by commas instead of
+//!    `and`), or a list uses the form less common in the file
+//! 1. Doi: the doi is not of the form `10.NNNN/suffix`, or carries a `https://doi.org/` prefix
+//! 1. PageRange: the pages are a range with `-` or `–` instead of `--`
+
+use std::fmt;
+use std::path::PathBuf;
+
+use colored::*;
+
+use super::parser::{BibFile, Entry, Field};
+use crate::latex_interpreter::token::Span;
+use crate::utils::FileInput;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BibLintKind {
+    MissingField,
+    UnprotectedCapitals,
+    AuthorFormat,
+    Doi,
+    PageRange,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BibWarning {
+    pub kind: BibLintKind,
+    /// The key of the entry
+    pub key: String,
+    pub file: PathBuf,
+    /// The span of the field, or of the key for missing fields
+    pub span: Span,
+    /// Starting from 0
+    pub row: usize,
+    /// Starting from 0, in characters
+    pub col: usize,
+    pub message: String,
+}
+
+impl fmt::Display for BibWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{} {}: `{}`: {}",
+            self.file.display(),
+            self.row + 1,
+            self.col + 1,
+            "WARNING".yellow().bold(),
+            self.key,
+            self.message
+        )
+    }
+}
+
+/// The required fields of the standard entry types, each with its alternatives
+const REQUIRED_FIELDS: [(&str, &[&[&str]]); 14] = [
+    (
+        "article",
+        &[AUTHOR, TITLE, &["journal", "journaltitle"], YEAR],
+    ),
+    (
+        "book",
+        &[&["author", "editor"], TITLE, &["publisher"], YEAR],
+    ),
+    ("booklet", &[TITLE]),
+    (
+        "inbook",
+        &[
+            &["author", "editor"],
+            TITLE,
+            &["chapter", "pages"],
+            &["publisher"],
+            YEAR,
+        ],
+    ),
+    (
+        "incollection",
+        &[AUTHOR, TITLE, &["booktitle"], &["publisher"], YEAR],
+    ),
+    ("inproceedings", &[AUTHOR, TITLE, &["booktitle"], YEAR]),
+    ("conference", &[AUTHOR, TITLE, &["booktitle"], YEAR]),
+    ("manual", &[TITLE]),
+    (
+        "mastersthesis",
+        &[AUTHOR, TITLE, &["school", "institution"], YEAR],
+    ),
+    (
+        "phdthesis",
+        &[AUTHOR, TITLE, &["school", "institution"], YEAR],
+    ),
+    (
+        "thesis",
+        &[AUTHOR, TITLE, &["type"], &["school", "institution"], YEAR],
+    ),
+    ("proceedings", &[TITLE, YEAR]),
+    ("techreport", &[AUTHOR, TITLE, &["institution"], YEAR]),
+    ("unpublished", &[AUTHOR, TITLE, &["note"]]),
+];
+const AUTHOR: &[&str] = &["author"];
+const TITLE: &[&str] = &["title"];
+const YEAR: &[&str] = &["year", "date"];
+
+/// The ways an author list is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NameFormat {
+    /// `Knuth, Donald E.`
+    Comma,
+    /// `Donald E. Knuth`
+    Space,
+}
+
+/// Check bib, see the module doc
+pub fn lint(file_input: &FileInput, bib: &BibFile) -> Vec<BibWarning> {
+    let mut ret: Vec<BibWarning> = vec![];
+    let mut warn =
+        |kind: BibLintKind, entry: &Entry, span: Span, message: String| {
+            let (row, col) = file_input.get_row_col(span.start);
+            ret.push(BibWarning {
+                kind,
+                key: entry.key.clone(),
+                file: file_input.get_file_path().clone(),
+                span,
+                row,
+                col,
+                message,
+            });
+        };
+
+    // the format of each author list, to find the less common one
+    let mut name_lists: Vec<(&Entry, &Field, NameFormat)> = vec![];
+    for entry in bib.entries() {
+        for missing in missing_fields(entry) {
+            warn(
+                BibLintKind::MissingField,
+                entry,
+                entry.key_span,
+                format!("@{} requires the field `{}`", entry.kind(), missing),
+            );
+        }
+
+        for field in &entry.fields {
+            let value = field.value.to_plain();
+            match field.get_name().as_str() {
+                "title" => {
+                    for word in unprotected_words(&value) {
+                        warn(
+                            BibLintKind::UnprotectedCapitals,
+                            entry,
+                            field.span,
+                            format!(
+                                "protect `{}` in the title as `{{{}}}`, or it may be lower cased",
+                                word, word
+                            ),
+                        );
+                    }
+                }
+                "author" | "editor" => match name_format(&value) {
+                    Ok(Some(format)) => name_lists.push((entry, field, format)),
+                    Ok(None) => {}
+                    Err(message) => warn(
+                        BibLintKind::AuthorFormat,
+                        entry,
+                        field.span,
+                        message,
+                    ),
+                },
+                "doi" => {
+                    if let Err(message) = check_doi(&value) {
+                        warn(BibLintKind::Doi, entry, field.span, message);
+                    }
+                }
+                "pages" => {
+                    if let Some(fixed) = fix_page_range(&value) {
+                        warn(
+                            BibLintKind::PageRange,
+                            entry,
+                            field.span,
+                            format!("write the page range as `{}`", fixed),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let commas = name_lists
+        .iter()
+        .filter(|(_, _, f)| *f == NameFormat::Comma)
+        .count();
+    let spaces = name_lists.len() - commas;
+    // ties go to the form used first
+    let common = match name_lists.first() {
+        _ if commas > spaces => NameFormat::Comma,
+        _ if spaces > commas => NameFormat::Space,
+        Some((_, _, format)) => *format,
+        None => NameFormat::Comma,
+    };
+    for (entry, field, format) in name_lists {
+        if format != common {
+            let message = match common {
+                NameFormat::Comma => {
+                    "most names of the file are written as `Last, First`"
+                }
+                NameFormat::Space => {
+                    "most names of the file are written as `First Last`"
+                }
+            };
+            warn(
+                BibLintKind::AuthorFormat,
+                entry,
+                field.span,
+                message.to_string(),
+            );
+        }
+    }
+
+    ret.sort_by_key(|w| w.span.start);
+    ret
+}
+
+/// The required fields entry lacks, each given with its alternatives as `year` or `date`
+fn missing_fields(entry: &Entry) -> Vec<String> {
+    if entry.get_field("crossref").is_some() {
+        return vec![];
+    }
+    let kind = entry.kind();
+    let Some((_, required)) = REQUIRED_FIELDS.iter().find(|(t, _)| *t == kind)
+    else {
+        return vec![];
+    };
+    required
+        .iter()
+        .filter(|alternatives| {
+            alternatives
+                .iter()
+                .all(|name| entry.get_field(name).is_none())
+        })
+        .map(|alternatives| alternatives.join("` or `"))
+        .collect()
+}
+
+/// Split text on sep outside braces
+fn split_top_level<'a>(text: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut ret: Vec<&str> = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    let mut i = 0;
+    while i < text.len() {
+        match text.as_bytes()[i] {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            _ if depth == 0 && text[i..].starts_with(sep) => {
+                ret.push(&text[start..i]);
+                i += sep.len();
+                start = i;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    ret.push(&text[start..]);
+    ret
+}
+
+/// The words of a title, outside braces, with capitals after their first letter
+fn unprotected_words(title: &str) -> Vec<String> {
+    let mut ret: Vec<String> = vec![];
+    let mut depth = 0;
+    let mut word = String::new();
+    let mut check = |word: &mut String| {
+        // a command like \LaTeX is not a word
+        if !word.starts_with('\\')
+            && word.chars().skip(1).any(|c| c.is_uppercase())
+            && !ret.contains(word)
+        {
+            ret.push(word.clone());
+        }
+        word.clear();
+    };
+    for c in title.chars() {
+        match c {
+            '{' => {
+                check(&mut word);
+                depth += 1;
+            }
+            '}' => depth -= 1,
+            _ if depth > 0 => {}
+            _ if c.is_alphanumeric() || c == '\\' => word.push(c),
+            _ => check(&mut word),
+        }
+    }
+    check(&mut word);
+    ret
+}
+
+/// The format of a name list, None if no name tells, an error message if it is malformed
+fn name_format(names: &str) -> Result<Option<NameFormat>, String> {
+    let mut format: Option<NameFormat> = None;
+    for name in split_top_level(names, " and ") {
+        let commas = split_top_level(name, ",").len() - 1;
+        let words = split_top_level(name.trim(), " ").len();
+        let name_format = match commas {
+            0 if words > 1 => NameFormat::Space,
+            0 => continue,
+            1 | 2 => NameFormat::Comma,
+            _ => {
+                return Err(format!(
+                "`{}` has more than two commas, separate the names with `and`",
+                name.trim()
+            ))
+            }
+        };
+        match format {
+            Some(f) if f != name_format => {
+                return Err(
+                    "the names mix `Last, First` and `First Last`".to_string()
+                )
+            }
+            _ => format = Some(name_format),
+        }
+    }
+    Ok(format)
+}
+
+/// Check a doi, see the module doc
+fn check_doi(doi: &str) -> Result<(), String> {
+    let doi = doi.trim();
+    for prefix in [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "http://dx.doi.org/",
+        "doi:",
+    ] {
+        if let Some(stripped) = doi.strip_prefix(prefix) {
+            return Err(format!(
+                "write the doi without `{}`: `{}`",
+                prefix, stripped
+            ));
+        }
+    }
+    let malformed = || {
+        Err(format!(
+            "malformed doi `{}`, expected `10.NNNN/suffix`",
+            doi
+        ))
+    };
+    let Some((prefix, suffix)) = doi.split_once('/') else {
+        return malformed();
+    };
+    let Some(registrant) = prefix.strip_prefix("10.") else {
+        return malformed();
+    };
+    let digits = registrant.split('.').next().unwrap_or_default();
+    if digits.len() < 4
+        || !registrant.chars().all(|c| c.is_ascii_digit() || c == '.')
+        || suffix.is_empty()
+        || suffix.contains(char::is_whitespace)
+    {
+        return malformed();
+    }
+    Ok(())
+}
+
+/// The page range written with `--`, None if pages is not a range with `-` or `–`
+fn fix_page_range(pages: &str) -> Option<String> {
+    let mut fixed: Vec<String> = vec![];
+    let mut changed = false;
+    for range in pages.split(',') {
+        let bounds: Vec<&str> = range.split(['-', '–']).collect();
+        match bounds.as_slice() {
+            [first, last]
+                if !first.trim().is_empty() && !last.trim().is_empty() =>
+            {
+                changed = true;
+                fixed.push(format!("{}--{}", first.trim(), last.trim()));
+            }
+            _ => fixed.push(range.trim().to_string()),
+        }
+    }
+    changed.then(|| fixed.join(", "))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bib_interpreter::parser::parse;
+
+    fn lint_str(source: &str) -> Vec<(BibLintKind, String, String)> {
+        let file_input = FileInput::from_str("a.bib", source);
+        let bib = parse(&file_input).unwrap();
+        lint(&file_input, &bib)
+            .into_iter()
+            .map(|w| (w.kind, w.key, w.message))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        use BibLintKind::*;
+        let source = r##"
+@article{a,
+  author = {Knuth, Donald E. and Lesk, Michael},
+  title = {The {DNA} of LaTeX and {\TeX} in iOS},
+  journaltitle = {TUGboat},
+  pages = {12-34},
+  doi = {https://doi.org/10.1093/comjnl/27.2.97},
+}
+@book{b, author = {Donald Knuth}, title = {Literate}, year = 1992, doi = {10.12/x}}
+@inbook{c, crossref = {b}, pages = {3–5, 7, 9--10}}
+@misc{d, author = {Ovid and Virgil, P. and A, B, C, D}}
+"##;
+        assert_eq!(
+            lint_str(source),
+            vec![
+                (MissingField, "a".into(), "@article requires the field `year` or `date`".into()),
+                (UnprotectedCapitals, "a".into(), "protect `LaTeX` in the title as `{LaTeX}`, or it may be lower cased".into()),
+                (UnprotectedCapitals, "a".into(), "protect `iOS` in the title as `{iOS}`, or it may be lower cased".into()),
+                (PageRange, "a".into(), "write the page range as `12--34`".into()),
+                (Doi, "a".into(), "write the doi without `https://doi.org/`: `10.1093/comjnl/27.2.97`".into()),
+                (MissingField, "b".into(), "@book requires the field `publisher`".into()),
+                (AuthorFormat, "b".into(), "most names of the file are written as `Last, First`".into()),
+                (Doi, "b".into(), "malformed doi `10.12/x`, expected `10.NNNN/suffix`".into()),
+                (PageRange, "c".into(), "write the page range as `3--5, 7, 9--10`".into()),
+                (AuthorFormat, "d".into(), "`A, B, C, D` has more than two commas, separate the names with `and`".into()),
+            ]
+        );
+        assert_eq!(
+            name_format("Ovid and Knuth, D. and Michael Lesk"),
+            Err("the names mix `Last, First` and `First Last`".to_string())
+        );
+        assert_eq!(
+            name_format("{World Health Organization} and Ovid"),
+            Ok(None)
+        );
+    }
+}
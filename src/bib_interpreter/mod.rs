@@ -0,0 +1,6 @@
+pub mod dedupe;
+pub mod extract;
+pub mod formatter;
+pub mod lint;
+pub mod parser;
+pub mod scanner;
@@ -0,0 +1,355 @@
+//! A parser for bibtex (`.bib`) files
+//!
+//! The file is parsed into a list of items in source order: entries, `@string` and `@preamble`
+//! definitions, and comments (`@comment` and the text between entries), so that a bib file can
+//! be written back without losing anything.
+//!
+//! A field value is a list of parts concatenated with `#`: braced and quoted text, numbers and
+//! the names of `@string` macros (like the predefined month names `jan`, `feb`, ...).
+//! A trailing comma after the last field is allowed, as bibtex does.
+
+use std::error::Error;
+
+use super::scanner::{scan, Token, TokenType};
+use crate::latex_interpreter::token::Span;
+use crate::utils::FileInput;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BibFile {
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Entry(Entry),
+    /// `@string{name = value}`
+    String(StringDef),
+    /// `@preamble{value}`
+    Preamble(Value, Span),
+    /// `@comment{text}` (the text without the braces), or the text between entries (is_junk)
+    Comment {
+        text: String,
+        is_junk: bool,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// As written, see kind() for the normalised type
+    pub entry_type: String,
+    pub key: String,
+    pub key_span: Span,
+    pub fields: Vec<Field>,
+    /// From `@` to the closing delimiter
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StringDef {
+    pub name: String,
+    pub value: Value,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// As written, see get_name() for the normalised name
+    pub name: String,
+    pub value: Value,
+    /// From the name to the end of the value
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Value {
+    pub parts: Vec<ValuePart>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValuePart {
+    Braced(String),
+    Quoted(String),
+    Number(String),
+    /// The name of a `@string`
+    Macro(String),
+}
+
+impl Entry {
+    /// The entry type in lower case, eg `article`
+    pub fn kind(&self) -> String {
+        self.entry_type.to_lowercase()
+    }
+
+    /// The field named name (in lower case), ignoring the case of the field names
+    pub fn get_field(&self, name: &str) -> Option<&Field> {
+        self.fields.iter().find(|f| f.get_name() == name)
+    }
+}
+
+impl Field {
+    /// The name in lower case, eg `title`
+    pub fn get_name(&self) -> String {
+        self.name.to_lowercase()
+    }
+}
+
+impl Value {
+    /// The text of the value, with the delimiters removed and the parts joined
+    /// Macros are kept as their names
+    pub fn to_plain(&self) -> String {
+        self.parts
+            .iter()
+            .map(|p| match p {
+                ValuePart::Braced(s)
+                | ValuePart::Quoted(s)
+                | ValuePart::Number(s)
+                | ValuePart::Macro(s) => s.as_str(),
+            })
+            .collect()
+    }
+}
+
+impl BibFile {
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.items.iter().filter_map(|i| match i {
+            Item::Entry(e) => Some(e),
+            _ => None,
+        })
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    file_input: &'a FileInput,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn error(&self, msg: &str) -> Box<dyn Error> {
+        let offset = match self.peek() {
+            Some(t) => t.span.start,
+            None => self.file_input.get_str_content().len(),
+        };
+        let (row, col) = self.file_input.get_row_col(offset);
+        format!(
+            "{}:{}:{} {}",
+            self.file_input.get_file_path().display(),
+            row + 1,
+            col + 1,
+            msg
+        )
+        .into()
+    }
+
+    /// Consume the next token if it is of token_type, an error with msg otherwise
+    fn expect(
+        &mut self,
+        token_type: TokenType,
+        msg: &str,
+    ) -> Result<Token, Box<dyn Error>> {
+        match self.peek() {
+            Some(t) if t.token_type == token_type => {
+                self.pos += 1;
+                Ok(self.tokens[self.pos - 1].clone())
+            }
+            _ => Err(self.error(msg)),
+        }
+    }
+
+    fn matches(&mut self, token_type: TokenType) -> bool {
+        if self.peek().is_some_and(|t| t.token_type == token_type) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn item(&mut self) -> Result<Item, Box<dyn Error>> {
+        let token = self.tokens[self.pos].clone();
+        self.pos += 1;
+        if token.token_type == TokenType::Junk {
+            return Ok(Item::Comment {
+                text: token.lexeme,
+                is_junk: true,
+                span: token.span,
+            });
+        }
+
+        let start = token.span.start;
+        let entry_type =
+            self.expect(TokenType::Ident, "Expected an entry type")?;
+        match entry_type.lexeme.to_lowercase().as_str() {
+            "comment" => {
+                let text = self.expect(TokenType::Braced, "Expected `{`")?;
+                Ok(Item::Comment {
+                    text: text.lexeme,
+                    is_junk: false,
+                    span: Span::new(start, text.span.end),
+                })
+            }
+            "string" => {
+                self.expect(TokenType::Open, "Expected `{`")?;
+                let name = self.expect(TokenType::Ident, "Expected a name")?;
+                self.expect(TokenType::Equals, "Expected `=`")?;
+                let value = self.value()?;
+                let close = self.expect(TokenType::Close, "Expected `}`")?;
+                Ok(Item::String(StringDef {
+                    name: name.lexeme,
+                    value,
+                    span: Span::new(start, close.span.end),
+                }))
+            }
+            "preamble" => {
+                self.expect(TokenType::Open, "Expected `{`")?;
+                let value = self.value()?;
+                let close = self.expect(TokenType::Close, "Expected `}`")?;
+                Ok(Item::Preamble(value, Span::new(start, close.span.end)))
+            }
+            _ => {
+                self.expect(TokenType::Open, "Expected `{`")?;
+                let key = self.expect(TokenType::Ident, "Expected a key")?;
+                let mut fields: Vec<Field> = vec![];
+                while self.matches(TokenType::Comma) {
+                    if self
+                        .peek()
+                        .is_some_and(|t| t.token_type == TokenType::Close)
+                    {
+                        break;
+                    }
+                    let name =
+                        self.expect(TokenType::Ident, "Expected a field name")?;
+                    self.expect(TokenType::Equals, "Expected `=`")?;
+                    let value = self.value()?;
+                    fields.push(Field {
+                        name: name.lexeme,
+                        span: Span::new(name.span.start, value.span.end),
+                        value,
+                    });
+                }
+                let close =
+                    self.expect(TokenType::Close, "Expected `,` or `}`")?;
+                Ok(Item::Entry(Entry {
+                    entry_type: entry_type.lexeme,
+                    key: key.lexeme,
+                    key_span: key.span,
+                    fields,
+                    span: Span::new(start, close.span.end),
+                }))
+            }
+        }
+    }
+
+    /// Parts concatenated with `#`
+    fn value(&mut self) -> Result<Value, Box<dyn Error>> {
+        let mut parts: Vec<ValuePart> = vec![];
+        let mut span: Option<Span> = None;
+        loop {
+            let Some(token) = self.peek().cloned() else {
+                return Err(self.error("Expected a value"));
+            };
+            let part = match token.token_type {
+                TokenType::Braced => ValuePart::Braced(token.lexeme),
+                TokenType::Quoted => ValuePart::Quoted(token.lexeme),
+                TokenType::Ident
+                    if token.lexeme.chars().all(|c| c.is_ascii_digit()) =>
+                {
+                    ValuePart::Number(token.lexeme)
+                }
+                TokenType::Ident => ValuePart::Macro(token.lexeme),
+                _ => return Err(self.error("Expected a value")),
+            };
+            self.pos += 1;
+            parts.push(part);
+            span = Some(match span {
+                Some(s) => s.merge(&token.span),
+                None => token.span,
+            });
+            if !self.matches(TokenType::Hash) {
+                break;
+            }
+        }
+        Ok(Value {
+            parts,
+            span: span.unwrap(),
+        })
+    }
+}
+
+/// Scan and parse a bib file
+pub fn parse(file_input: &FileInput) -> Result<BibFile, Box<dyn Error>> {
+    let tokens = scan(file_input)?;
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        file_input,
+    };
+    let mut items: Vec<Item> = vec![];
+    while parser.peek().is_some() {
+        items.push(parser.item()?);
+    }
+    Ok(BibFile { items })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let source = "% header\n@String{ov = \"Ovid\"}\n@preamble{\"\\newcommand{\\x}{x}\"}\n@comment{a {b}}\n@MISC (Ovid:1,\n  Author = ov # { and } # \"Virgil\",\n  year = 8,\n)\n";
+        let bib = parse(&FileInput::from_str("a.bib", source)).unwrap();
+        assert_eq!(bib.items.len(), 9);
+        assert!(matches!(&bib.items[0], Item::Comment { is_junk: true, .. }));
+        assert!(matches!(
+            &bib.items[1],
+            Item::String(s) if s.value.to_plain() == "Ovid"
+        ));
+        assert!(matches!(
+            &bib.items[5],
+            Item::Comment { text, is_junk: false, .. } if text == "a {b}"
+        ));
+
+        let entry = bib.entries().next().unwrap();
+        assert_eq!(entry.entry_type, "MISC");
+        assert_eq!(entry.key, "Ovid:1");
+        assert_eq!(&source[entry.key_span.start..entry.key_span.end], "Ovid:1");
+        let author = entry.get_field("author").unwrap();
+        assert_eq!(
+            author.value.parts,
+            vec![
+                ValuePart::Macro("ov".into()),
+                ValuePart::Braced(" and ".into()),
+                ValuePart::Quoted("Virgil".into()),
+            ]
+        );
+        assert_eq!(
+            &source[author.span.start..author.span.end],
+            "Author = ov # { and } # \"Virgil\""
+        );
+        assert_eq!(
+            entry.get_field("year").unwrap().value.parts,
+            vec![ValuePart::Number("8".into())]
+        );
+        assert_eq!(
+            &source[entry.span.start..entry.span.end],
+            &source[source.find("@MISC").unwrap()..source.len() - 1]
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = parse(&FileInput::from_str("a.bib", "@misc{k,\n  title x}"))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "a.bib:2:9 Expected `=`");
+        let err =
+            parse(&FileInput::from_str("a.bib", "@misc{k, t = }")).unwrap_err();
+        assert_eq!(err.to_string(), "a.bib:1:14 Expected a value");
+    }
+}
@@ -0,0 +1,297 @@
+//! A scanner for bibtex (`.bib`) files
+//!
+//! A bib file is a sequence of entries `@type{key, field = value, ...}` (parentheses may replace
+//! the outer braces), with any text between them being ignored by bibtex. So the scanner has two
+//! modes:
+//! 1. Outside entries, everything up to the next `@` is a single Junk token. A `%` comments out
+//!    the rest of the line, `@` included, as biber does.
+//! 1. Inside entries, names and keys are Idents, and the punctuation has its own token types.
+//!    A value in braces or quotes is scanned as a whole, since it may contain anything as long as
+//!    the braces are balanced. Its lexeme is the text without the delimiters, and its span covers
+//!    the delimiters.
+//!
+//! The content of `@comment{...}` is scanned as a braced value.
+
+use std::error::Error;
+
+use crate::latex_interpreter::token::Span;
+use crate::utils::FileInput;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Token {
+    pub token_type: TokenType,
+    pub lexeme: String,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TokenType {
+    Junk,   // text outside entries
+    At,     // @
+    Ident,  // entry types, keys, field names, numbers and @string names
+    Open,   // { or ( opening an entry
+    Close,  // } or ) closing an entry
+    Comma,  // ,
+    Equals, // =
+    Hash,   // # concatenating values
+    Braced, // {value}
+    Quoted, // "value"
+}
+
+enum Mode {
+    Outside,
+    /// After `@`, before the opening delimiter, with the entry type if scanned
+    EntryHead(Option<String>),
+    /// In the entry, with its closing delimiter, and whether a value is expected
+    Entry(char, bool),
+}
+
+/// Whether c may be part of an Ident
+fn is_ident_char(c: char) -> bool {
+    !c.is_whitespace() && !"{}(),=#\"%@".contains(c)
+}
+
+pub fn scan(file_input: &FileInput) -> Result<Vec<Token>, Box<dyn Error>> {
+    let source = file_input.get_str_content();
+    let error = |offset: usize, msg: &str| -> Box<dyn Error> {
+        let (row, col) = file_input.get_row_col(offset);
+        format!(
+            "{}:{}:{} {}",
+            file_input.get_file_path().display(),
+            row + 1,
+            col + 1,
+            msg
+        )
+        .into()
+    };
+
+    let mut ret: Vec<Token> = vec![];
+    let mut push = |token_type: TokenType, lexeme: &str, start: usize, end| {
+        ret.push(Token {
+            token_type,
+            lexeme: lexeme.to_string(),
+            span: Span::new(start, end),
+        })
+    };
+    let mut mode = Mode::Outside;
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match mode {
+            Mode::Outside => {
+                let mut end = start;
+                let mut in_comment = false;
+                while let Some(&(i, c)) = chars.peek() {
+                    if c == '@' && !in_comment {
+                        break;
+                    }
+                    match c {
+                        '%' => in_comment = true,
+                        '\n' => in_comment = false,
+                        _ => {}
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                if end > start {
+                    push(TokenType::Junk, &source[start..end], start, end);
+                }
+                if chars.next().is_some() {
+                    push(TokenType::At, "@", end, end + 1);
+                    mode = Mode::EntryHead(None);
+                }
+            }
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            Mode::EntryHead(None) if is_ident_char(c) => {
+                let end = scan_ident(source, &mut chars);
+                push(TokenType::Ident, &source[start..end], start, end);
+                mode = Mode::EntryHead(Some(source[start..end].to_lowercase()));
+            }
+            Mode::EntryHead(Some(ref entry_type)) if c == '{' || c == '(' => {
+                let close = if c == '{' { '}' } else { ')' };
+                if entry_type == "comment" {
+                    let end = scan_braced(&mut chars, c, close)
+                        .ok_or_else(|| error(start, "Unterminated @comment"))?;
+                    push(
+                        TokenType::Braced,
+                        &source[start + 1..end - 1],
+                        start,
+                        end,
+                    );
+                    mode = Mode::Outside;
+                } else {
+                    chars.next();
+                    push(
+                        TokenType::Open,
+                        &source[start..start + 1],
+                        start,
+                        start + 1,
+                    );
+                    mode = Mode::Entry(close, entry_type == "preamble");
+                }
+            }
+            Mode::EntryHead(_) => {
+                return Err(error(
+                    start,
+                    "Expected an entry type and `{` after `@`",
+                ));
+            }
+            Mode::Entry(close, _) if c == close => {
+                chars.next();
+                push(
+                    TokenType::Close,
+                    &source[start..start + 1],
+                    start,
+                    start + 1,
+                );
+                mode = Mode::Outside;
+            }
+            Mode::Entry(close, expect_value) => {
+                let (token_type, end) = match c {
+                    ',' | '=' | '#' => {
+                        chars.next();
+                        let token_type = match c {
+                            ',' => TokenType::Comma,
+                            '=' => TokenType::Equals,
+                            _ => TokenType::Hash,
+                        };
+                        (token_type, start + 1)
+                    }
+                    '{' if expect_value => {
+                        let end = scan_braced(&mut chars, '{', '}')
+                            .ok_or_else(|| error(start, "Unbalanced braces"))?;
+                        (TokenType::Braced, end)
+                    }
+                    '"' => {
+                        let end = scan_quoted(&mut chars).ok_or_else(|| {
+                            error(start, "Unterminated quote")
+                        })?;
+                        (TokenType::Quoted, end)
+                    }
+                    _ if is_ident_char(c) => {
+                        (TokenType::Ident, scan_ident(source, &mut chars))
+                    }
+                    _ => {
+                        return Err(error(
+                            start,
+                            &format!("Unexpected `{}` in entry", c),
+                        ))
+                    }
+                };
+                let lexeme = match token_type {
+                    TokenType::Braced | TokenType::Quoted => {
+                        &source[start + 1..end - 1]
+                    }
+                    _ => &source[start..end],
+                };
+                push(token_type, lexeme, start, end);
+                let expect_value =
+                    matches!(token_type, TokenType::Equals | TokenType::Hash);
+                mode = Mode::Entry(close, expect_value);
+            }
+        }
+    }
+    if !matches!(mode, Mode::Outside) {
+        return Err(error(source.len(), "Unterminated entry"));
+    }
+    Ok(ret)
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+/// Scan an Ident, return its end
+fn scan_ident(source: &str, chars: &mut Chars) -> usize {
+    while let Some(&(i, c)) = chars.peek() {
+        if !is_ident_char(c) {
+            return i;
+        }
+        chars.next();
+    }
+    source.len()
+}
+
+/// Scan from open to the matching close, return the end after close, None if unbalanced
+fn scan_braced(chars: &mut Chars, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in chars.by_ref() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i + 1);
+            }
+        }
+    }
+    None
+}
+
+/// Scan from `"` to the closing `"` outside braces, return the end after it, None if unterminated
+fn scan_quoted(chars: &mut Chars) -> Option<usize> {
+    chars.next();
+    let mut depth = 0;
+    for (i, c) in chars.by_ref() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '"' if depth == 0 => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn types(source: &str) -> Vec<TokenType> {
+        scan(&FileInput::from_str("a.bib", source))
+            .unwrap()
+            .iter()
+            .map(|t| t.token_type)
+            .collect()
+    }
+
+    #[test]
+    fn test_scan() {
+        use TokenType::*;
+        let source = "% @misc{no,}\n@Article {k:1,\n  title = {On {B}races, \"and\"},\n  year=1999 # \"a{\"}\"\n}";
+        let tokens = scan(&FileInput::from_str("a.bib", source)).unwrap();
+        assert_eq!(
+            tokens.iter().map(|t| t.token_type).collect::<Vec<_>>(),
+            vec![
+                Junk, At, Ident, Open, Ident, Comma, Ident, Equals, Braced,
+                Comma, Ident, Equals, Ident, Hash, Quoted, Close
+            ]
+        );
+        assert_eq!(tokens[0].lexeme, "% @misc{no,}\n");
+        assert_eq!(tokens[4].lexeme, "k:1");
+        assert_eq!(tokens[8].lexeme, "On {B}races, \"and\"");
+        assert_eq!(
+            &source[tokens[8].span.start..tokens[8].span.end],
+            "{On {B}races, \"and\"}"
+        );
+        assert_eq!(tokens[14].lexeme, "a{\"}");
+
+        assert_eq!(
+            types("@comment{x {y} @z} @preamble({\\foo})"),
+            vec![At, Ident, Braced, Junk, At, Ident, Open, Braced, Close]
+        );
+    }
+
+    #[test]
+    fn test_scan_error() {
+        let err = scan(&FileInput::from_str("a.bib", "@misc{k,\n title = {x}"))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "a.bib:2:13 Unterminated entry");
+        assert!(
+            scan(&FileInput::from_str("a.bib", "@misc{k, t = {x}}}")).is_ok()
+        );
+        assert!(scan(&FileInput::from_str("a.bib", "@misc{k, t = x}}")).is_ok());
+        assert!(
+            scan(&FileInput::from_str("a.bib", "@misc{k, t = {x}")).is_err()
+        );
+    }
+}
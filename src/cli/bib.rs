@@ -0,0 +1,343 @@
+//! This file contains the logic of the `bib` commands, which work on the `.bib` files given, or
+//! on those of the document (see latex_interpreter::cites::read_bibliography) if none is given.
+//! `bib lint` prints the problems found by bib_interpreter::lint, one per line, as
+//! `file:row:col WARNING: message`
+//! `bib dedupe` prints the duplicate entries found by bib_interpreter::dedupe side by side. With
+//! `--in-place`, each group is merged into its first entry, the others are removed, and the
+//! citations of the removed keys in the document are changed to the kept key. The changed files
+//! are backed up as `.<file>.backup`, just as `format --in-place` does. Without it, the citations
+//! are printed as `file:row:col: would change `old` -> `new``, and nothing is written.
+//! `bib extract` writes the entries cited by the document, see bib_interpreter::extract
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::PathBuf;
+
+use super::project::{build_graph, main_file_path};
+use crate::bib_interpreter::dedupe::{
+    find_duplicates, merge, side_by_side, DuplicateReason,
+};
+use crate::bib_interpreter::extract::extract as extract_bib;
+use crate::bib_interpreter::formatter::{format_entry, BibStyle};
+use crate::bib_interpreter::lint::lint as lint_bib;
+use crate::bib_interpreter::parser::{
+    parse as parse_bib, BibFile, Entry, ValuePart,
+};
+use crate::config::Config;
+use crate::latex_interpreter::cites::{collect_citations, read_bibliography};
+use crate::latex_interpreter::edit::{apply_edits, Edit};
+use crate::latex_interpreter::include_graph::IncludeGraph;
+use crate::latex_interpreter::token::Span;
+use crate::utils::{
+    overwrite_to_file_path_buf, overwrite_with_backup, FileInput,
+};
+
+/// Parse files, or the bib files of the document of graph if there is no file
+fn read_files(
+    files: &[PathBuf],
+    graph: Option<&IncludeGraph>,
+) -> Result<Vec<(FileInput, BibFile)>, Box<dyn Error>> {
+    let Some(graph) = graph.filter(|_| files.is_empty()) else {
+        let mut ret = vec![];
+        for file in files {
+            let file_input = FileInput::from_file_path(file)?;
+            let bib = parse_bib(&file_input)?;
+            ret.push((file_input, bib));
+        }
+        return Ok(ret);
+    };
+    let ret = read_bibliography(graph)?;
+    if ret.is_empty() {
+        warn!("The document has no bib file");
+    }
+    Ok(ret)
+}
+
+/// Lint files, or the bib files of the document
+pub(super) fn lint(
+    files: &[PathBuf],
+    main_file: &Option<String>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let graph = match files.is_empty() {
+        true => Some(build_graph(main_file, config)?),
+        false => None,
+    };
+    let mut warnings = 0;
+    for (file_input, bib) in read_files(files, graph.as_ref())? {
+        for warning in lint_bib(&file_input, &bib) {
+            warnings += 1;
+            println!("{}", warning);
+        }
+    }
+    println!("{} warning(s) found", warnings);
+    Ok(())
+}
+
+/// The span of entry, with the line break after it
+fn entry_line_span(file_input: &FileInput, entry: &Entry) -> Span {
+    let rest = &file_input.get_str_content()[entry.span.end..];
+    match rest.strip_prefix('\n') {
+        Some(_) => Span::new(entry.span.start, entry.span.end + 1),
+        None => entry.span,
+    }
+}
+
+/// Find the duplicate entries of files, or of the bib files of the document, and merge them if
+/// in_place. The citations are only changed if the document exists
+pub(super) fn dedupe(
+    files: &[PathBuf],
+    main_file: &Option<String>,
+    in_place: bool,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let main_path = main_file_path(main_file, config);
+    let graph = match files.is_empty() || main_path.is_file() {
+        true => Some(build_graph(main_file, config)?),
+        false => {
+            warn!(
+                "Main file `{}` does not exist, the citations are not changed",
+                main_path.display()
+            );
+            None
+        }
+    };
+    dedupe_bibs(files, graph.as_ref(), in_place)
+}
+
+/// dedupe files, or the bib files of graph, changing the citations of graph
+fn dedupe_bibs(
+    files: &[PathBuf],
+    graph: Option<&IncludeGraph>,
+    in_place: bool,
+) -> Result<(), Box<dyn Error>> {
+    let bibs = read_files(files, graph)?;
+    let bib_refs: Vec<&BibFile> = bibs.iter().map(|(_, bib)| bib).collect();
+    let groups = find_duplicates(&bib_refs);
+    if groups.is_empty() {
+        println!("No duplicate entries found");
+        return Ok(());
+    }
+
+    // the removed keys, with the keys they are merged into
+    let mut renames: HashMap<&str, &str> = HashMap::new();
+    for group in &groups {
+        let kept = group.entries[0].1;
+        for (_, entry) in &group.entries[1..] {
+            if entry.key != kept.key {
+                renames.insert(&entry.key, &kept.key);
+            }
+        }
+    }
+
+    let title = |(file, entry): (usize, &Entry)| {
+        let file_input = &bibs[file].0;
+        let (row, _) = file_input.get_row_col(entry.key_span.start);
+        format!(
+            "{} ({}:{})",
+            entry.key,
+            file_input.get_file_path().display(),
+            row + 1
+        )
+    };
+    let mut bib_edits: Vec<Vec<Edit>> = vec![vec![]; bibs.len()];
+    for group in &groups {
+        let (kept_file, kept) = group.entries[0];
+        match &group.reason {
+            DuplicateReason::Doi(doi) => {
+                println!("Duplicate entries with the doi `{}`:", doi)
+            }
+            DuplicateReason::TitleYearAuthor => println!(
+                "Duplicate entries with the same title, year and first author:"
+            ),
+        }
+        for &(file, entry) in &group.entries[1..] {
+            println!(
+                "{}\n",
+                side_by_side(
+                    (&title((kept_file, kept)), kept),
+                    (&title((file, entry)), entry)
+                )
+            );
+            bib_edits[file]
+                .push(Edit::delete(entry_line_span(&bibs[file].0, entry)));
+        }
+
+        let entries: Vec<&Entry> =
+            group.entries.iter().map(|(_, e)| *e).collect();
+        let (mut merged, conflicts) = merge(&entries);
+        if !conflicts.is_empty() {
+            warn!(
+                "`{}`: the {} of the entries differ, the first one is kept",
+                kept.key,
+                conflicts.join(", ")
+            );
+        }
+        for field in merged.fields.iter_mut() {
+            let parent = field.value.to_plain();
+            match renames.get(parent.as_str()) {
+                Some(key) if field.get_name() == "crossref" => {
+                    field.value.parts = vec![ValuePart::Braced(key.to_string())]
+                }
+                _ => {}
+            }
+        }
+        bib_edits[kept_file]
+            .push(Edit::new(kept.span, &format_entry(&merged, BibStyle::Keep)));
+    }
+    // the crossrefs to removed entries, edits inside removed entries are dropped
+    for (file, (_, bib)) in bibs.iter().enumerate() {
+        for entry in bib.entries() {
+            let Some(field) = entry.get_field("crossref") else {
+                continue;
+            };
+            if let Some(key) = renames.get(field.value.to_plain().as_str()) {
+                bib_edits[file]
+                    .push(Edit::new(field.value.span, &format!("{{{}}}", key)));
+            }
+        }
+    }
+
+    let mut tex_edits: Vec<(PathBuf, Vec<Edit>)> = vec![];
+    for citation in graph.map(collect_citations).unwrap_or_default() {
+        let Some(key) = renames.get(citation.key.as_str()) else {
+            continue;
+        };
+        let change = if in_place { "" } else { "would change " };
+        println!(
+            "{}: {}`{}` -> `{}`",
+            citation.location(),
+            change,
+            citation.key,
+            key
+        );
+        match tex_edits.iter_mut().find(|(f, _)| *f == citation.file) {
+            Some((_, edits)) => edits.push(Edit::new(citation.span, key)),
+            None => tex_edits.push((
+                citation.file.clone(),
+                vec![Edit::new(citation.span, key)],
+            )),
+        }
+    }
+    let citations: usize = tex_edits.iter().map(|(_, e)| e.len()).sum();
+
+    if !in_place {
+        println!(
+            "{} duplicate group(s) and {} citation(s) to change, run with `--in-place` to merge them",
+            groups.len(),
+            citations
+        );
+        return Ok(());
+    }
+    for ((file_input, _), edits) in bibs.iter().zip(bib_edits) {
+        if !edits.is_empty() {
+            let content = apply_edits(file_input.get_str_content(), &edits);
+            overwrite_with_backup(file_input.get_file_path(), &content)?;
+        }
+    }
+    for (path, edits) in tex_edits {
+        let file_input = FileInput::from_file_path(&path)?;
+        let content = apply_edits(file_input.get_str_content(), &edits);
+        overwrite_with_backup(&path, &content)?;
+    }
+    println!(
+        "Merged {} duplicate group(s) and changed {} citation(s)",
+        groups.len(),
+        citations
+    );
+    Ok(())
+}
+
+/// Write the entries of files (or of the bib files of the document) cited by the document of
+/// main_file to outfile, or print them if None
+pub(super) fn extract(
+    files: &[PathBuf],
+    main_file: &Option<String>,
+    outfile: &Option<String>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let graph = build_graph(main_file, config)?;
+    let bibs = read_files(files, Some(&graph))?;
+    let citations = collect_citations(&graph);
+    // \nocite{*} cites every entry
+    let keys: Vec<String> = match citations.iter().any(|c| c.key == "*") {
+        true => bibs
+            .iter()
+            .flat_map(|(_, bib)| bib.entries().map(|e| e.key.clone()))
+            .collect(),
+        false => citations.into_iter().map(|c| c.key).collect(),
+    };
+
+    let (res, missing) = extract_bib(&bibs, &keys);
+    for key in missing {
+        warn!("Citation `{}` not found in the bibliography", key);
+    }
+    match outfile {
+        Some(out) => {
+            overwrite_to_file_path_buf(&PathBuf::from(out), &res)?;
+            info!("Extracted the cited entries to `{}`", out);
+        }
+        None => print!("{}", res),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cli::init::default_assets::reference_bib;
+    use crate::utils::create_test_project;
+    use std::fs;
+
+    #[test]
+    fn test_lint_reference_bib() {
+        // the generated bib, and its commented out examples, follow the advice of the lints
+        let source = reference_bib();
+        let uncommented: String = source
+            .lines()
+            .map(|l| l.strip_prefix("% ").unwrap_or(l))
+            .collect::<Vec<_>>()
+            .join("\n");
+        for source in [source.clone(), uncommented] {
+            let file_input = FileInput::from_str("references.bib", &source);
+            let bib = parse_bib(&file_input).unwrap();
+            assert!(lint_bib(&file_input, &bib).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_dedupe_in_place() {
+        let dir = create_test_project(
+            "bib-dedupe",
+            &[
+                (
+                    "main.tex",
+                    "\\addbibresource{ours.bib}\\addbibresource{theirs.bib}\n\\begin{document}\n\\cite{met, ovid} met \\input{part}\n\\end{document}",
+                ),
+                ("part.tex", "\\textcite{met}"),
+                (
+                    "ours.bib",
+                    "@book{ovid, title = {Metamorphoses}, doi = {10.1000/met}}\n@inbook{ch, crossref = {met}}\n",
+                ),
+                (
+                    "theirs.bib",
+                    "@misc{a}\n@book{met, title = {Metamorphoses}, doi = {10.1000/MET}, year = 8}\n@misc{b}\n",
+                ),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        dedupe_bibs(&[], Some(&graph), true).unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(
+            read("ours.bib"),
+            "@book{ovid,\n  title = {Metamorphoses},\n  doi   = {10.1000/met},\n  year  = 8\n}\n@inbook{ch, crossref = {ovid}}\n"
+        );
+        assert_eq!(read("theirs.bib"), "@misc{a}\n@misc{b}\n");
+        assert_eq!(
+            read("main.tex"),
+            "\\addbibresource{ours.bib}\\addbibresource{theirs.bib}\n\\begin{document}\n\\cite{ovid, ovid} met \\input{part}\n\\end{document}"
+        );
+        assert_eq!(read("part.tex"), "\\textcite{ovid}");
+        assert!(dir.join(".part.tex.backup").is_file());
+    }
+}
@@ -0,0 +1,287 @@
+//! This file contains the `bundle` command logic
+//! It packs the files the document actually uses into a gzipped tarball, keeping the directory
+//! layout relative to the main file:
+//! 1. the source files of the include graph
+//! 1. the figures of `\includegraphics`
+//! 1. local classes, packages and bibliography styles (`\documentclass`, `\usepackage`,
+//!    `\bibliographystyle`) found next to the main file
+//! 1. the bibliography databases, or with `--arxiv` the generated `.bbl` instead, as arXiv does
+//!    not run bibtex or biber
+//!
+//! With `--arxiv`, comments are also stripped from the source files.
+//! Anything else in the project directory, like unused figures and the `.backup` files created by
+//! `format --in-place`, is left out.
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+use super::compile::BUILD_DIR;
+use super::project::build_graph;
+use crate::config::Config;
+use crate::latex_interpreter::comment::{comment_edits, StripOptions};
+use crate::latex_interpreter::edit::apply_edits;
+use crate::latex_interpreter::flatten::find_bbl;
+use crate::latex_interpreter::graphics::{find_graphics, graphics_path};
+use crate::latex_interpreter::include_graph::{IncludeGraph, SourceFile};
+use crate::utils::normalize_path;
+
+/// A file in the bundle
+#[derive(Debug)]
+struct BundleFile {
+    /// The path in the tarball, relative to the directory of the main file
+    path: PathBuf,
+    content: Vec<u8>,
+}
+
+/// Pack the files used by main_file (the main file of the config if None) into a tarball
+/// The tarball is written to outfile, default to `<main file stem>.tar.gz` in the current
+/// directory
+pub(super) fn bundle(
+    main_file: &Option<String>,
+    outfile: &Option<String>,
+    arxiv: bool,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let graph = build_graph(main_file, config)?;
+    let files = collect_bundle_files(&graph, arxiv)?;
+
+    let outfile = match outfile {
+        Some(f) => PathBuf::from(f),
+        None => {
+            let stem = graph.get_main_file().get_path().file_stem();
+            let stem = stem.unwrap_or_default().to_string_lossy();
+            PathBuf::from(format!("{}.tar.gz", stem))
+        }
+    };
+    write_tarball(&outfile, &files)?;
+
+    let total: usize = files.iter().map(|f| f.content.len()).sum();
+    println!(
+        "Bundled {} files ({}) into `{}` ({})",
+        files.len(),
+        human_size(total as u64),
+        outfile.display(),
+        human_size(fs::metadata(&outfile)?.len())
+    );
+
+    Ok(())
+}
+
+/// Collect the files used by the document, see the module doc
+fn collect_bundle_files(
+    graph: &IncludeGraph,
+    arxiv: bool,
+) -> Result<Vec<BundleFile>, Box<dyn Error>> {
+    let main = graph.get_main_file();
+    let main_dir = main.input_dir.clone();
+    let mut ret: Vec<BundleFile> = vec![];
+    let mut seen: HashSet<PathBuf> = HashSet::new();
+
+    let mut push_file =
+        |path: &Path, content: Vec<u8>| -> Result<(), Box<dyn Error>> {
+            let rel = relative_to(path, &main_dir)?;
+            if is_backup(&rel) {
+                debug!("Skipped backup file {}", path.display());
+            } else if seen.insert(rel.clone()) {
+                ret.push(BundleFile { path: rel, content });
+            }
+            Ok(())
+        };
+
+    let graphics_path = graphics_path(graph);
+    for file in graph.get_files() {
+        let source = file.file_input.get_str_content();
+        let content = if arxiv {
+            let options = StripOptions::default();
+            apply_edits(
+                source,
+                &comment_edits(file.ast.clone(), source, &options),
+            )
+        } else {
+            source.to_string()
+        };
+        push_file(file.get_path(), content.into_bytes())?;
+
+        for graphic in find_graphics(file, &graphics_path) {
+            match graphic.resolved {
+                Some(p) => push_file(&p, fs::read(&p)?)?,
+                None => warn!(
+                    "{}: figure `{}` not found",
+                    file.get_path().display(),
+                    graphic.target
+                ),
+            }
+        }
+    }
+
+    for file in graph.get_files() {
+        for path in local_dependencies(file, &main_dir) {
+            push_file(&path, fs::read(&path)?)?;
+        }
+    }
+
+    if arxiv {
+        match find_bbl(main.get_path(), BUILD_DIR) {
+            Some(bbl) => {
+                // the .bbl shall lie next to the main file, even if generated in .build
+                let dest = main.get_path().with_extension("bbl");
+                push_file(&dest, fs::read(&bbl)?)?;
+            }
+            None => warn!(
+                "No .bbl found for `{}`, compile the document first if it has a bibliography",
+                main.get_path().display()
+            ),
+        }
+    } else {
+        for file in graph.get_files() {
+            for path in file.bibliography_files(&main_dir) {
+                push_file(&path, fs::read(&path)?)?;
+            }
+        }
+    }
+
+    Ok(ret)
+}
+
+/// Local `.cls`, `.sty` and `.bst` files used by file, which lie in main_dir
+fn local_dependencies(file: &SourceFile, main_dir: &Path) -> Vec<PathBuf> {
+    let mut ret: Vec<PathBuf> = vec![];
+    for (command, ext) in [
+        ("documentclass", "cls"),
+        ("usepackage", "sty"),
+        ("RequirePackage", "sty"),
+        ("bibliographystyle", "bst"),
+    ] {
+        for name in file.command_arg_list(command) {
+            let path = main_dir.join(format!("{}.{}", name, ext));
+            if path.is_file() {
+                ret.push(normalize_path(&path));
+            }
+        }
+    }
+    ret
+}
+
+/// path relative to main_dir, which is an error if path lies outside of main_dir
+fn relative_to(
+    path: &Path,
+    main_dir: &Path,
+) -> Result<PathBuf, Box<dyn Error>> {
+    match path.strip_prefix(main_dir) {
+        Ok(rel) if !rel.starts_with("..") => Ok(rel.to_path_buf()),
+        _ => Err(format!(
+            "`{}` lies outside of `{}`, move it into the project to bundle it",
+            path.display(),
+            main_dir.display()
+        )
+        .into()),
+    }
+}
+
+/// The backups created by `format --in-place` are named `.<file>.backup`
+fn is_backup(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "backup")
+}
+
+fn write_tarball(
+    outfile: &Path,
+    files: &[BundleFile],
+) -> Result<(), Box<dyn Error>> {
+    let encoder =
+        GzEncoder::new(fs::File::create(outfile)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    let mtime = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    for file in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(file.content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+        builder.append_data(
+            &mut header,
+            &file.path,
+            file.content.as_slice(),
+        )?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// EG: 1536 -> "1.5 KiB"
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_test_project;
+
+    #[test]
+    fn test_collect_bundle_files() {
+        let dir = create_test_project(
+            "bundle",
+            &[
+                (
+                    "main.tex",
+                    "\\documentclass{mine}\n\\usepackage{amsmath,notes}\n% secret\n\\input{sec/a}\n\\bibliography{refs}",
+                ),
+                ("mine.cls", ""),
+                ("notes.sty", ""),
+                ("sec/a.tex", "\\includegraphics{figs/used} % todo"),
+                ("figs/used.png", ""),
+                ("figs/unused.png", ""),
+                (".main.tex.backup", ""),
+                ("refs.bib", ""),
+                ("main.bbl", "\\begin{thebibliography}{}\\end{thebibliography}"),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+
+        let files = collect_bundle_files(&graph, true).unwrap();
+        let paths: Vec<&Path> =
+            files.iter().map(|f| f.path.as_path()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                Path::new("main.tex"),
+                Path::new("sec/a.tex"),
+                Path::new("figs/used.png"),
+                Path::new("mine.cls"),
+                Path::new("notes.sty"),
+                Path::new("main.bbl"),
+            ]
+        );
+        assert_eq!(
+            files[0].content,
+            b"\\documentclass{mine}\n\\usepackage{amsmath,notes}\n\\input{sec/a}\n\\bibliography{refs}"
+        );
+
+        let files = collect_bundle_files(&graph, false).unwrap();
+        assert!(files.iter().any(|f| f.path == Path::new("refs.bib")));
+        assert!(!files.iter().any(|f| f.path == Path::new("main.bbl")));
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(512), "512 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
@@ -0,0 +1,25 @@
+//! This file contains the `check` command logic
+//! It checks the document for the problems latex would only report after a full run, by running
+//! the rules of linter::document on labels, references and citations, see `tex-helper lint`.
+//! The problems are printed one per line, as `file:row:col LEVEL: message [rule]`. If any of them
+//! is an error, the command fails.
+use std::error::Error;
+
+use super::lint::print_diagnostics;
+use super::project::build_graph;
+use crate::config::Config;
+use crate::linter::document::CHECK_RULES;
+use crate::linter::{configure, lint};
+
+/// Check main_file (the main file of the config if None)
+pub(super) fn check(
+    main_file: &Option<String>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let graph = build_graph(main_file, config)?;
+    let mut rules: Vec<_> = configure(config.get_lint_config())
+        .into_iter()
+        .filter(|(rule, _)| CHECK_RULES.contains(&rule.name()))
+        .collect();
+    print_diagnostics(&lint(&graph, &mut rules)?)
+}
@@ -0,0 +1,147 @@
+//! This file contains the `clean` command logic
+//! It removes the auxiliary files produced by compiling the latex project.
+//! What counts as an auxiliary file is decided by default_assets::BUILD_ARTEFACTS, which is also
+//! used to generate the .gitignore of a new project.
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::compile::BUILD_DIR;
+use super::init::default_assets::BUILD_ARTEFACTS;
+use crate::config::Config;
+
+/// The stem of the file name if it ends with one of the BUILD_ARTEFACTS suffixes
+/// EG: main.aux -> main, chapter1.synctex.gz -> chapter1
+fn artefact_stem(file_name: &str) -> Option<&str> {
+    BUILD_ARTEFACTS
+        .iter()
+        .find_map(|ext| file_name.strip_suffix(&format!(".{}", ext)))
+        .filter(|stem| !stem.is_empty())
+}
+
+/// Recursively collect the build artefacts in dir
+/// An artefact is only removed next to the `.tex` file it is produced from, so that a
+/// `data/run.log` is left alone.
+/// `.git` and the build directory are skipped, the latter is removed as a whole
+fn collect_artefacts(
+    dir: &Path,
+    ret: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_dir() {
+            if file_name != ".git" && file_name != BUILD_DIR {
+                collect_artefacts(&path, ret)?;
+            }
+        } else if let Some(stem) = artefact_stem(&file_name) {
+            if dir.join(format!("{}.tex", stem)).is_file() {
+                ret.push(path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Remove the build artefacts and the build directory in project_dir
+/// If all is true, the pdf compiled from the main file is removed as well
+/// If dry_run is true, only print what would be removed
+pub(super) fn clean(
+    project_dir: &Path,
+    dry_run: bool,
+    all: bool,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    if !project_dir.is_dir() {
+        return Err(format!(
+            "Project directory `{}` does not exist",
+            project_dir.display()
+        )
+        .into());
+    }
+
+    let mut files: Vec<PathBuf> = vec![];
+    collect_artefacts(project_dir, &mut files)?;
+
+    if all {
+        let pdf = project_dir
+            .join(config.get_main_file_name())
+            .with_extension("pdf");
+        if pdf.is_file() {
+            files.push(pdf);
+        }
+    }
+
+    let build_dir = project_dir.join(BUILD_DIR);
+
+    if dry_run {
+        for file in &files {
+            println!("Would remove {}", file.display());
+        }
+        if build_dir.is_dir() {
+            println!("Would remove {}/", build_dir.display());
+        }
+        return Ok(());
+    }
+
+    for file in &files {
+        fs::remove_file(file)?;
+        debug!("Removed {}", file.display());
+    }
+    if build_dir.is_dir() {
+        fs::remove_dir_all(&build_dir)?;
+        debug!("Removed {}/", build_dir.display());
+    }
+    info!("Removed {} build artefacts", files.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::utils::create_test_project;
+
+    #[test]
+    fn test_artefact_stem() {
+        assert_eq!(artefact_stem("main.aux"), Some("main"));
+        assert_eq!(artefact_stem("main.synctex.gz"), Some("main"));
+        assert_eq!(artefact_stem("chapter1.fdb_latexmk"), Some("chapter1"));
+        assert_eq!(artefact_stem("main.run.xml"), Some("main"));
+
+        assert_eq!(artefact_stem("main.tex"), None);
+        assert_eq!(artefact_stem("main.pdf"), None);
+        assert_eq!(artefact_stem("references.bib"), None);
+        assert_eq!(artefact_stem("aux"), None);
+    }
+
+    #[test]
+    fn test_collect_artefacts() {
+        let dir = create_test_project(
+            "clean",
+            &[
+                ("main.tex", ""),
+                ("main.aux", ""),
+                ("main.synctex.gz", ""),
+                ("notes.log", ""),
+                ("chapters/one.tex", ""),
+                ("chapters/one.aux", ""),
+                ("data/run.log", ""),
+                (".build/main.log", ""),
+            ],
+        );
+        let mut files = vec![];
+        collect_artefacts(&dir, &mut files).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                dir.join("chapters/one.aux"),
+                dir.join("main.aux"),
+                dir.join("main.synctex.gz"),
+            ]
+        );
+    }
+}
@@ -4,6 +4,8 @@ use std::error::Error;
 use std::fs;
 use std::process::Command;
 
+/// The directory in which latex is run, so the auxiliary files do not clutter the project
+pub(super) const BUILD_DIR: &str = ".build";
 
 pub fn compile(main_file_path: &str) -> Result<(), Box<dyn Error>> {
 
@@ -20,10 +22,10 @@ pub fn compile(main_file_path: &str) -> Result<(), Box<dyn Error>> {
         }
     };
 
-    fs::create_dir_all(".build/")?;
-    fs::copy(main_file_path, ".build/main.tex")?;
+    fs::create_dir_all(BUILD_DIR)?;
+    fs::copy(main_file_path, format!("{}/main.tex", BUILD_DIR))?;
 
-    env::set_current_dir(".build/")?;
+    env::set_current_dir(BUILD_DIR)?;
 
     let output = Command::new(&binary)
         .arg(main_file_path)
@@ -0,0 +1,94 @@
+//! This file contains the `detex` command logic
+//! It extracts the prose of a latex file, see latex_interpreter::detex.
+//!
+//! The source map is written as JSON:
+//! ```json
+//! {
+//!   "source": "main.tex",
+//!   "segments": [
+//!     { "offset": 0, "length": 4, "row": 3, "col": 1, "source_length": 4, "verbatim": true }
+//!   ]
+//! }
+//! ```
+//! offset and length are the byte offset and length of a segment in the text, row and col (both
+//! starting from 1, col counting characters) the location of its source.
+//! If verbatim, the segment is the source copied, and offsets inside it map one to one. Otherwise
+//! (math placeholders, spaces, escaped characters, macro expansions) the whole segment maps to row
+//! and col.
+use std::error::Error;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::latex_interpreter::detex::{detex as detex_file, DetexOptions};
+use crate::utils::{overwrite_to_file_path_buf, FileInput};
+
+#[derive(Debug, Serialize)]
+struct SourceMap {
+    source: String,
+    segments: Vec<MapSegment>,
+}
+
+#[derive(Debug, Serialize)]
+struct MapSegment {
+    offset: usize,
+    length: usize,
+    row: usize,
+    col: usize,
+    source_length: usize,
+    verbatim: bool,
+}
+
+/// Extract the prose of input, written to outfile if given, and printed otherwise
+/// The source map is written to map if given
+pub(super) fn detex(
+    input: &str,
+    outfile: &Option<String>,
+    map: &Option<String>,
+    options: &DetexOptions,
+) -> Result<(), Box<dyn Error>> {
+    let path = PathBuf::from(input);
+    if !path.is_file() {
+        return Err(
+            format!("Input file `{}` does not exist", path.display()).into()
+        );
+    }
+    let file_input = FileInput::from_file_path(&path)?;
+    let detexed = detex_file(&file_input, options)?;
+
+    match outfile {
+        Some(out) => {
+            overwrite_to_file_path_buf(&PathBuf::from(out), &detexed.text)?;
+            info!("Extracted the text of `{}` to `{}`", input, out);
+        }
+        None => print!("{}", detexed.text),
+    }
+
+    if let Some(map) = map {
+        let segments = detexed
+            .segments
+            .iter()
+            .map(|s| {
+                let (row, col) = file_input.get_row_col(s.src.start);
+                MapSegment {
+                    offset: s.out.start,
+                    length: s.out.end - s.out.start,
+                    row: row + 1,
+                    col: col + 1,
+                    source_length: s.src.end - s.src.start,
+                    verbatim: s.verbatim,
+                }
+            })
+            .collect();
+        let source_map = SourceMap {
+            source: input.to_string(),
+            segments,
+        };
+        overwrite_to_file_path_buf(
+            &PathBuf::from(map),
+            &serde_json::to_string_pretty(&source_map)?,
+        )?;
+        info!("Wrote the source map to `{}`", map);
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+//! This file contains the `flatten` command logic
+//! It writes the document as a single file into the output directory, along with the figures it
+//! uses. See latex_interpreter::flatten for how the files are inlined.
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use super::compile::BUILD_DIR;
+use super::project::build_graph;
+use crate::config::Config;
+use crate::latex_interpreter::flatten::{
+    find_bbl, flatten as flatten_graph, FlattenOptions,
+};
+use crate::utils::normalize_path;
+
+/// Flatten main_file (the main file of the config if None) into out_dir
+/// If keep_comments is false, comments are stripped
+/// If bbl is true, the generated .bbl is inlined in place of `\bibliography`
+pub(super) fn flatten(
+    main_file: &Option<String>,
+    out_dir: &Path,
+    keep_comments: bool,
+    bbl: bool,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    // absolute paths, so that the output file can be compared to the main file
+    let graph = build_graph(main_file, config)?;
+    let main_path = graph.get_main_file().get_path().to_path_buf();
+
+    let bbl_path = if bbl {
+        match find_bbl(&main_path, BUILD_DIR) {
+            Some(p) => Some(p),
+            None => {
+                return Err(format!(
+                    "No .bbl found for `{}`, compile the document first",
+                    main_path.display()
+                )
+                .into())
+            }
+        }
+    } else {
+        None
+    };
+    let options = FlattenOptions {
+        strip_comments: !keep_comments,
+        bbl: match &bbl_path {
+            Some(p) => Some(fs::read_to_string(p)?),
+            None => None,
+        },
+    };
+    let res = flatten_graph(&graph, &options)?;
+
+    let cwd = std::env::current_dir()?;
+    let out_dir = normalize_path(&cwd.join(out_dir));
+    let file_name = main_path.file_name().unwrap_or_default();
+    let out_file = out_dir.join(file_name);
+    if out_file == main_path {
+        return Err(format!(
+            "Flattening `{}` would overwrite itself, choose another output directory",
+            main_path.display()
+        )
+        .into());
+    }
+
+    fs::create_dir_all(&out_dir)?;
+    fs::write(&out_file, &res.text)?;
+    info!(
+        "Flattened {} files into `{}`",
+        graph.get_files().len(),
+        out_file.display()
+    );
+
+    for figure in &res.figures {
+        let dest = out_dir.join(&figure.dest);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&figure.source, &dest)?;
+        debug!("Copied {} to {}", figure.source.display(), dest.display());
+    }
+    if !res.figures.is_empty() {
+        info!("Copied {} figures", res.figures.len());
+    }
+
+    // biblatex has no \bibliography to replace, so the .bbl is shipped next to the file
+    if let Some(p) = bbl_path {
+        if !res.bbl_inlined {
+            let dest = out_file.with_extension("bbl");
+            fs::copy(&p, &dest)?;
+            info!("Copied {} to {}", p.display(), dest.display());
+        }
+    }
+
+    Ok(())
+}
@@ -1,10 +1,17 @@
 //! Format function definedi nthis module is called by the cli module to perform formatting
 //! This module essentially calls the latex_interpreter::formatter module  to format the AST
+//! `.bib` files are formatted by the bib_interpreter::formatter module instead
 use std::error::Error;
 use std::path::PathBuf;
 
 use crate::config::Config;
 
+use crate::bib_interpreter::formatter::{
+    format as format_bib_private, BibFormatOptions, BibSort, BibStyle,
+};
+use crate::bib_interpreter::parser::parse as parse_bib;
+use crate::latex_interpreter::cites::collect_citations;
+use crate::latex_interpreter::include_graph::IncludeGraph;
 use crate::latex_interpreter::{
     formatter::format as format_private, parser::parse, scanner::scan,
 };
@@ -12,10 +19,17 @@ use crate::utils::*;
 
 /// May PANIC!
 /// This function is called by the cli module to format the files
+/// sort and style only apply to `.bib` files, overriding the `[bib]` section of the config
 pub fn format(
     file_path: &PathBuf,
+    sort: Option<BibSort>,
+    style: Option<BibStyle>,
     config: &Config,
 ) -> Result<String, Box<dyn Error>> {
+    if file_path.extension().is_some_and(|ext| ext == "bib") {
+        return format_bib(file_path, sort, style, config);
+    }
+
     let file_input = FileInput::from_file_path(file_path)?;
     let tokens = scan(file_input.clone())?;
     let ast = match parse(&tokens, file_input) {
@@ -29,3 +43,37 @@ pub fn format(
 
     Ok(res)
 }
+
+fn format_bib(
+    file_path: &PathBuf,
+    sort: Option<BibSort>,
+    style: Option<BibStyle>,
+    config: &Config,
+) -> Result<String, Box<dyn Error>> {
+    let bib = parse_bib(&FileInput::from_file_path(file_path)?)?;
+    let mut options = BibFormatOptions {
+        sort: sort.unwrap_or(config.get_bib_config().sort),
+        style: style.unwrap_or(config.get_bib_config().style),
+        citations: vec![],
+    };
+
+    // the citation order is that of the document of the main file
+    if options.sort == BibSort::Citation {
+        let main_file =
+            std::env::current_dir()?.join(config.get_main_file_name());
+        if !main_file.is_file() {
+            return Err(format!(
+                "Main file `{}` does not exist, which is needed to sort by citation",
+                main_file.display()
+            )
+            .into());
+        }
+        let graph = IncludeGraph::build(&normalize_path(&main_file))?;
+        options.citations = collect_citations(&graph)
+            .into_iter()
+            .map(|c| c.key)
+            .collect();
+    }
+
+    Ok(format_bib_private(&bib, &options))
+}
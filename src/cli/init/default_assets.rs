@@ -128,7 +128,7 @@ pub(super) fn default_preable(doc_mode: &str) -> String {
     }
 }
 
-pub(super) fn reference_bib() -> String {
+pub(crate) fn reference_bib() -> String {
     String::from(
         r##"% @inproceedings{lesk:1977,
 %   title={Computer Typesetting of Technical Journals on {UNIX}},
@@ -141,7 +141,7 @@ pub(super) fn reference_bib() -> String {
 %   address={Dallas, Texas}
 % }
 % @article{knuth:1984,
-%   title={Literate Programming}.
+%   title={Literate Programming},
 %   author={Donald E. Knuth},
 %   journal={The Computer Journal},
 %   volume={27},
@@ -166,28 +166,34 @@ pub(super) fn reference_bib() -> String {
     ) // End of String::from
 }
 
+/// Suffixes of the auxiliary files produced when compiling a latex document.
+/// The generated .gitignore lists them, and `tex-helper clean` removes them, so
+/// the two always agree on what counts as a build artefact.
+pub(crate) const BUILD_ARTEFACTS: [&str; 16] = [
+    "aux",
+    "latexmk",
+    "fls",
+    "fdb_latexmk",
+    "synctex.gz",
+    "blg",
+    "log",
+    "out",
+    "toc",
+    "nav",
+    "snm",
+    "vrb",
+    "bcf",
+    "run.xml",
+    "dvi",
+    "bbl",
+];
+
+/// The .gitignore of a new project: the outputs, the BUILD_ARTEFACTS, and the broader `*.gz` and
+/// `*.xml` patterns, which `tex-helper clean` does not remove
 pub(super) fn gitignore() -> String {
-    String::from(
-        r##"*.pdf
-*.txt
-*.aux
-*.gz
-*.latexmk
-*.fls
-*.fdb_latexmk
-*.synctex.gz
-*.blg
-*.log
-*.out
-*.toc
-*.nav
-*.snm
-*.vrb
-*.synctex.gz
-*.bcf
-*.xml
-*.dvi
-*.bbl
-"##,
-    ) // End of String::from
+    let mut ret = String::from("*.pdf\n*.txt\n*.gz\n*.xml\n");
+    for ext in BUILD_ARTEFACTS {
+        ret.push_str(&format!("*.{}\n", ext));
+    }
+    ret
 }
@@ -1,7 +1,7 @@
 //! This file contains the `init` command logic
 //! This file is not the initialisation of the crate
 
-mod default_assets;
+pub(super) mod default_assets;
 
 use crate::config::Config;
 use crate::utils;
@@ -141,3 +141,36 @@ fn custom_template_exists(
     }
     Ok(String::new())
 }
+
+/// The content of the main file of the template for doc_mode, for documents generated by other
+/// commands, see create_preamble_contents for the lookup of custom templates
+/// A custom directory template contributes its main file.
+/// Without a template for doc_mode, the default article template is used with doc_mode as the
+/// document class.
+pub(super) fn template_main_file(
+    doc_mode: &str,
+    config: &Config,
+) -> Result<String, Box<dyn Error>> {
+    let custom_file_path = custom_template_exists(doc_mode)?;
+    if custom_file_path.is_empty() {
+        let ret = default_assets::default_preable(doc_mode);
+        if !ret.is_empty() {
+            return Ok(ret);
+        }
+        return Ok(default_assets::default_preable("article").replacen(
+            "\\documentclass{article}",
+            &format!("\\documentclass{{{}}}", doc_mode),
+            1,
+        ));
+    }
+
+    let path = if Path::new(&custom_file_path).is_dir() {
+        Path::new(&custom_file_path).join(config.get_main_file_name())
+    } else {
+        PathBuf::from(&custom_file_path)
+    };
+    info!("Using custom template at {}", path.display());
+    fs::read_to_string(&path).map_err(|e| {
+        format!("Cannot read template `{}`: {}", path.display(), e).into()
+    })
+}
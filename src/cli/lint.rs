@@ -0,0 +1,93 @@
+//! This file contains the `lint` command logic
+//! It runs the rules of the linter enabled by the `[lint]` section of the config on the document,
+//! and prints the diagnostics one per line, as `file:row:col LEVEL: message [rule]`. If any of
+//! them is an error, the command fails.
+//! With `--fix`, the fixes of the diagnostics are applied (the changed files are backed up as
+//! `.<file>.backup`), and the problems left are printed.
+use std::error::Error;
+
+use super::project::build_graph;
+use crate::config::Config;
+use crate::linter::{
+    all_rules, configure, fix as fix_graph, lint as lint_graph, Diagnostic,
+    LintLevel, Severity,
+};
+use crate::utils::overwrite_with_backup;
+
+/// Print the diagnostics, and fail if any of them is an error
+pub(super) fn print_diagnostics(
+    diagnostics: &[Diagnostic],
+) -> Result<(), Box<dyn Error>> {
+    let mut errors = 0;
+    let mut warnings = 0;
+    for diagnostic in diagnostics {
+        match diagnostic.severity {
+            Severity::Error => errors += 1,
+            Severity::Warning => warnings += 1,
+        }
+        println!("{}", diagnostic);
+    }
+    if errors > 0 {
+        return Err(format!(
+            "{} error(s) and {} warning(s) found",
+            errors, warnings
+        )
+        .into());
+    }
+    println!("{} warning(s) found", warnings);
+    Ok(())
+}
+
+/// Print the rules with their levels and descriptions
+fn list_rules(config: &Config) {
+    let rules = all_rules();
+    let width = rules.iter().map(|r| r.name().len()).max().unwrap_or(0);
+    for rule in rules {
+        let level = config
+            .get_lint_config()
+            .get(rule.name())
+            .copied()
+            .unwrap_or(rule.default_level());
+        let level = match level {
+            LintLevel::Off => "off",
+            LintLevel::Warn => "warn",
+            LintLevel::Error => "error",
+        };
+        println!(
+            "{:width$}  {:5}  {}",
+            rule.name(),
+            level,
+            rule.description()
+        );
+    }
+}
+
+/// Lint main_file (the main file of the config if None)
+pub(super) fn lint(
+    main_file: &Option<String>,
+    fix: bool,
+    list: bool,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    if list {
+        list_rules(config);
+        return Ok(());
+    }
+
+    let mut rules = configure(config.get_lint_config());
+    let graph = build_graph(main_file, config)?;
+    let mut diagnostics = lint_graph(&graph, &mut rules)?;
+    if fix {
+        let (files, fixed) = fix_graph(&graph, &diagnostics);
+        for (path, content) in &files {
+            overwrite_with_backup(path, content)?;
+        }
+        println!("Fixed {} problem(s) in {} file(s)", fixed, files.len());
+        if !files.is_empty() {
+            let graph = build_graph(main_file, config)?;
+            diagnostics =
+                lint_graph(&graph, &mut configure(config.get_lint_config()))?;
+        }
+    }
+    print_diagnostics(&diagnostics)
+}
@@ -0,0 +1,275 @@
+//! This file contains the `md2tex` command logic
+//! It converts a markdown file to a latex fragment, see markdown_interpreter::emitter.
+//!
+//! If the markdown has front matter (see markdown_interpreter::front_matter), or `--standalone`
+//! is given, the fragment is wrapped into a complete document. The preamble is taken from the
+//! template `init` would use for the documentclass of the front matter (article by default),
+//! custom templates in `~/.config/tex-helper` included:
+//! 1. `\title`, `\author` and `\date` of the template are replaced by those of the front matter
+//! 1. the packages of the front matter and those needed by the fragment are added, unless the
+//!    template already loads them. The options of a package of the front matter replace those
+//!    of the template
+//! 1. the bibliography of the front matter is added with biblatex, in place of the
+//!    `\addbibresource` of the template, and printed at the end
+//! 1. the content of the document environment of the template is replaced by the fragment
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use super::init::template_main_file;
+use crate::config::Config;
+use crate::markdown_interpreter::emitter::{emit, EmitOptions, Latex};
+use crate::markdown_interpreter::front_matter::{
+    split_front_matter, FrontMatter,
+};
+use crate::markdown_interpreter::parser::parse;
+use crate::utils::overwrite_to_file_path_buf;
+
+/// Convert input to latex, written to outfile if given, and printed otherwise
+pub(super) fn md2tex(
+    input: &str,
+    outfile: &Option<String>,
+    standalone: bool,
+    options: &EmitOptions,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let path = PathBuf::from(input);
+    if !path.is_file() {
+        return Err(
+            format!("Input file `{}` does not exist", path.display()).into()
+        );
+    }
+    let source = fs::read_to_string(&path)?;
+    let (front_matter, markdown) = split_front_matter(&source)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let res = emit(&parse(markdown), options);
+
+    let output = match front_matter {
+        Some(front_matter) => {
+            wrap_document(&front_matter, &res, options, config)?
+        }
+        None if standalone => {
+            wrap_document(&FrontMatter::default(), &res, options, config)?
+        }
+        None => {
+            if !res.packages.is_empty() {
+                let packages: Vec<String> =
+                    res.packages.iter().map(|p| p.to_string()).collect();
+                info!(
+                    "The output needs the packages:\n{}",
+                    packages.join("\n")
+                );
+            }
+            res.body
+        }
+    };
+
+    match outfile {
+        Some(out) => {
+            overwrite_to_file_path_buf(&PathBuf::from(out), &output)?;
+            info!("Converted `{}` to `{}`", input, out);
+        }
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+fn wrap_document(
+    front_matter: &FrontMatter,
+    fragment: &Latex,
+    options: &EmitOptions,
+    config: &Config,
+) -> Result<String, Box<dyn Error>> {
+    let doc_mode = front_matter.documentclass.as_deref().unwrap_or("article");
+    let template = template_main_file(doc_mode, config)?;
+    standalone(front_matter, fragment, &template, options)
+}
+
+/// Wrap fragment into a document with the preamble of template, see the module doc
+fn standalone(
+    front_matter: &FrontMatter,
+    fragment: &Latex,
+    template: &str,
+    options: &EmitOptions,
+) -> Result<String, Box<dyn Error>> {
+    let Some(begin) = template.find("\\begin{document}") else {
+        return Err("The template has no `\\begin{document}`".into());
+    };
+
+    // (name, options) of the packages of the front matter
+    let front_packages: Vec<(&str, Option<&str>)> = front_matter
+        .packages
+        .iter()
+        .map(|package| match package.split_once('[') {
+            Some((name, opts)) => {
+                (name.trim(), Some(opts.trim_end_matches(']')))
+            }
+            None => (package.trim(), None),
+        })
+        .collect();
+
+    let mut preamble = String::new();
+    let mut loaded: Vec<String> = vec![];
+    for line in template[..begin].split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        if ["\\title", "\\author", "\\date"]
+            .iter()
+            .any(|c| trimmed.starts_with(c))
+        {
+            continue;
+        }
+        if trimmed.starts_with("\\addbibresource")
+            && !front_matter.bibliography.is_empty()
+        {
+            continue;
+        }
+        if trimmed.starts_with("\\usepackage") {
+            let names = usepackage_names(trimmed);
+            let options =
+                front_packages.iter().find_map(|(name, opts)| match opts {
+                    Some(opts) if names.iter().any(|n| n == name) => {
+                        Some((*name, *opts))
+                    }
+                    _ => None,
+                });
+            let alone = names.len() == 1;
+            loaded.extend(names);
+            match options {
+                Some((name, opts)) if alone => {
+                    let line = format!("\\usepackage[{}]{{{}}}\n", opts, name);
+                    preamble.push_str(&line);
+                    continue;
+                }
+                Some((name, opts)) => warn!(
+                    "The template loads `{}` together with other packages, the options `{}` of the front matter are ignored",
+                    name, opts
+                ),
+                None => {}
+            }
+        }
+        preamble.push_str(line);
+    }
+    // the blank lines around the removed title
+    preamble.truncate(preamble.trim_end().len());
+    preamble.push('\n');
+
+    // The title, author and date are markdown too, so that math and `\today` work
+    let mut packages = fragment.packages.clone();
+    let mut inline = |text: &str| -> String {
+        let res = emit(&parse(text), options);
+        packages.extend(res.packages);
+        res.body.trim().to_string()
+    };
+    let mut titles = String::new();
+    if let Some(title) = &front_matter.title {
+        titles.push_str(&format!("\\title{{{}}}\n", inline(title)));
+    }
+    if !front_matter.author.is_empty() {
+        let authors: Vec<String> =
+            front_matter.author.iter().map(|a| inline(a)).collect();
+        titles.push_str(&format!("\\author{{{}}}\n", authors.join(" \\and ")));
+    }
+    if let Some(date) = &front_matter.date {
+        titles.push_str(&format!("\\date{{{}}}\n", inline(date)));
+    }
+
+    let mut added: Vec<String> = vec![];
+    let mut add_package = |name: &str, package: String| {
+        if !loaded.iter().any(|l| l == name) {
+            loaded.push(name.to_string());
+            added.push(package);
+        }
+    };
+    for (name, opts) in &front_packages {
+        let package = match opts {
+            Some(opts) => format!("\\usepackage[{}]{{{}}}", opts, name),
+            None => format!("\\usepackage{{{}}}", name),
+        };
+        add_package(name, package);
+    }
+    packages.sort();
+    packages.dedup();
+    for package in &packages {
+        add_package(package.name, package.to_string());
+    }
+    if !front_matter.bibliography.is_empty() {
+        add_package("biblatex", "\\usepackage{biblatex}".to_string());
+    }
+    for bib in &front_matter.bibliography {
+        added.push(format!("\\addbibresource{{{}}}", bib));
+    }
+
+    let mut ret = preamble;
+    if !added.is_empty() {
+        ret.push('\n');
+        ret.push_str(&added.join("\n"));
+        ret.push('\n');
+    }
+    if !titles.is_empty() {
+        ret.push('\n');
+        ret.push_str(&titles);
+    }
+    ret.push_str("\n\\begin{document}\n");
+    if front_matter.title.is_some() {
+        ret.push_str("\\maketitle\n\n");
+    }
+    ret.push_str(&fragment.body);
+    if !front_matter.bibliography.is_empty() {
+        ret.push_str("\n\\printbibliography\n");
+    }
+    ret.push_str("\\end{document}\n");
+    Ok(ret)
+}
+
+/// The package names of a `\usepackage` line
+/// EG: `\usepackage[utf8]{inputenc, fontenc} % comment` gives ["inputenc", "fontenc"]
+fn usepackage_names(line: &str) -> Vec<String> {
+    let line = line.split('%').next().unwrap_or_default();
+    let Some(start) = line.find('{') else {
+        return vec![];
+    };
+    let end = line[start..].find('}').map_or(line.len(), |e| start + e);
+    line[start + 1..end]
+        .split(',')
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_standalone() {
+        let template = "\\documentclass{article}\n\\usepackage{hyperref,amsmath}\n\\usepackage[tmargin=3cm]{geometry} % margins\n\\usepackage{biblatex}\n\\addbibresource{./references.bib}\n\\title{Old}\n\\author{Someone}\n\\begin{document}\n\\maketitle\nOld text\n\\end{document}";
+        let front_matter = FrontMatter {
+            title: Some("On $x$ and *y*".into()),
+            author: vec!["A".into(), "B".into()],
+            packages: vec![
+                "amsmath".into(),
+                "geometry[margin=2cm]".into(),
+                "xcolor[dvipsnames]".into(),
+            ],
+            bibliography: vec!["refs.bib".into()],
+            ..Default::default()
+        };
+        let options = EmitOptions::default();
+        let fragment = emit(&parse("[a](http://a.org)\n"), &options);
+        assert_eq!(
+            standalone(&front_matter, &fragment, template, &options).unwrap(),
+            "\\documentclass{article}\n\\usepackage{hyperref,amsmath}\n\\usepackage[margin=2cm]{geometry}\n\\usepackage{biblatex}\n\n\\usepackage[dvipsnames]{xcolor}\n\\addbibresource{refs.bib}\n\n\\title{On $x$ and \\emph{y}}\n\\author{A \\and B}\n\n\\begin{document}\n\\maketitle\n\n\\href{http://a.org}{a}\n\n\\printbibliography\n\\end{document}\n"
+        );
+
+        assert!(standalone(&front_matter, &fragment, "", &options).is_err());
+    }
+
+    #[test]
+    fn test_usepackage_names() {
+        assert_eq!(
+            usepackage_names("\\usepackage[utf8]{inputenc, fontenc} % {x}"),
+            vec!["inputenc", "fontenc"]
+        );
+        assert!(usepackage_names("\\usepackage % nothing").is_empty());
+    }
+}
@@ -3,11 +3,30 @@
 use std::fs;
 use std::path::PathBuf;
 
+mod bib;
+mod bundle;
+mod check;
+mod clean;
 mod compile;
+mod detex;
+mod flatten;
 mod format;
 mod init;
+mod lint;
+mod md2tex;
+mod outline;
+mod project;
+mod rename;
+mod strip_comments;
+mod tex2md;
+mod wordcount;
 
+use crate::bib_interpreter::formatter::{BibSort, BibStyle};
 use crate::config;
+use crate::latex_interpreter::comment::StripOptions;
+use crate::latex_interpreter::detex::DetexOptions;
+use crate::latex_interpreter::to_markdown::{MarkdownOptions, UnknownCommands};
+use crate::markdown_interpreter::emitter::EmitOptions;
 use crate::utils;
 
 use clap::{Parser, Subcommand};
@@ -44,7 +63,7 @@ pub enum Commands {
         ]
         doc_mode: String,
     },
-    /// Format Latex
+    /// Format Latex, or a `.bib` file
     Format {
         target: String,
 
@@ -53,10 +72,220 @@ pub enum Commands {
 
         #[arg(short, long, value_name = "outfile")]
         outfile: Option<String>,
+
+        /// The order of the entries of a `.bib` file, overriding `sort` in the `[bib]` section of
+        /// the config
+        #[arg(long, value_enum)]
+        sort: Option<BibSort>,
+
+        /// The delimiters of the values of a `.bib` file, overriding `style` in the `[bib]`
+        /// section of the config
+        #[arg(long, value_enum)]
+        style: Option<BibStyle>,
+    },
+    /// Remove the auxiliary files produced by compiling latex
+    Clean {
+        #[arg(default_value_t = String::from("."))]
+        project_dir: String,
+
+        /// Only print the files that would be removed
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+
+        /// Also remove the pdf compiled from the main file
+        #[arg(long, default_value_t = false)]
+        all: bool,
+    },
+    /// Inline all included files of the document into a single file
+    Flatten {
+        /// The main file, default to the main file in the config
+        main_file: Option<String>,
+
+        /// The directory to write the flattened file and its figures to
+        #[arg(short, long, default_value_t = String::from("flattened"))]
+        outdir: String,
+
+        /// Keep the comments instead of stripping them
+        #[arg(long, default_value_t = false)]
+        keep_comments: bool,
+
+        /// Inline the generated .bbl in place of \bibliography
+        #[arg(long, default_value_t = false)]
+        bbl: bool,
+    },
+    /// Pack the files used by the document into a tarball
+    Bundle {
+        /// The main file, default to the main file in the config
+        main_file: Option<String>,
+
+        /// The tarball to write, default to <main file stem>.tar.gz
+        #[arg(short, long, value_name = "outfile")]
+        outfile: Option<String>,
+
+        /// Prepare for arXiv: strip comments and ship the generated .bbl
+        #[arg(long, default_value_t = false)]
+        arxiv: bool,
+    },
+    /// Remove the comments of a latex file
+    StripComments {
+        target: String,
+
+        #[arg(short, long, default_value_t = false)]
+        in_place: bool,
+
+        #[arg(short, long, value_name = "outfile")]
+        outfile: Option<String>,
+
+        /// Also remove \begin{comment} ... \end{comment} blocks
+        #[arg(long, default_value_t = false)]
+        comment_envr: bool,
+
+        /// Also remove \iffalse ... \fi regions
+        #[arg(long, default_value_t = false)]
+        iffalse: bool,
+    },
+    /// Convert markdown to latex
+    Md2tex {
+        input: String,
+
+        #[arg(short, long, value_name = "outfile")]
+        outfile: Option<String>,
+
+        /// Typeset tables with the rules of booktabs
+        #[arg(long, default_value_t = false)]
+        booktabs: bool,
+
+        /// Output a complete document even without front matter
+        #[arg(long, default_value_t = false)]
+        standalone: bool,
+    },
+    /// Convert latex to markdown
+    Tex2md {
+        input: String,
+
+        #[arg(short, long, value_name = "outfile")]
+        outfile: Option<String>,
+
+        /// Keep or drop the commands without a markdown counterpart, overriding
+        /// `unknown_commands` in the `[tex2md]` section of the config
+        #[arg(long, value_enum)]
+        unknown: Option<UnknownCommands>,
+    },
+    /// Extract the prose of a latex file for spell and grammar checkers
+    Detex {
+        input: String,
+
+        #[arg(short, long, value_name = "outfile")]
+        outfile: Option<String>,
+
+        /// Write the map from the offsets of the text to the rows and cols of the source, as
+        /// JSON
+        #[arg(short, long, value_name = "map")]
+        map: Option<String>,
+
+        /// The text replacing math
+        #[arg(long, default_value = "X")]
+        math: String,
+    },
+    /// Count the words of the document by category and section
+    Wordcount {
+        /// The main file, default to the main file in the config
+        main_file: Option<String>,
+    },
+    /// Show the sections of the document with their labels and locations
+    Outline {
+        /// The main file, default to the main file in the config
+        main_file: Option<String>,
+
+        /// Print as JSON instead of a tree
+        #[arg(long, default_value_t = false)]
+        json: bool,
+
+        /// Include the theorem environments defined by the document, as `\begin{lemma}`
+        #[arg(long, default_value_t = false)]
+        theorems: bool,
+    },
+    /// Check the labels, references and citations of the document without running latex
+    Check {
+        /// The main file, default to the main file in the config
+        main_file: Option<String>,
+    },
+    /// Run the lint rules on the document, see `--list` for the rules
+    Lint {
+        /// The main file, default to the main file in the config
+        main_file: Option<String>,
+
+        /// Apply the fixes of the problems which have one, backing up the changed files
+        #[arg(long, default_value_t = false)]
+        fix: bool,
+
+        /// List the rules with their levels instead
+        #[arg(long, default_value_t = false)]
+        list: bool,
+    },
+    /// Rename labels and macros in their definitions and every usage across the document
+    Rename {
+        /// The main file, default to the main file in the config
+        main_file: Option<String>,
+
+        /// A label to rename, as `old:new`, can be repeated
+        #[arg(long, value_name = "OLD:NEW")]
+        label: Vec<String>,
+
+        /// A macro to rename, as `\old:\new`, can be repeated
+        #[arg(long = "macro", value_name = "OLD:NEW")]
+        macros: Vec<String>,
+    },
+    /// Work on the bib files given, or on those of the document
+    Bib {
+        #[command(subcommand)]
+        command: BibCommands,
     }, // Compile the latex files
        // Compile { targets: Vec<String> },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum BibCommands {
+    /// Report missing fields, unprotected capitals, inconsistent names, malformed dois and page
+    /// ranges
+    Lint {
+        /// The bib files, default to the bib files of the document
+        files: Vec<PathBuf>,
+
+        /// The main file of the document, default to the main file in the config
+        #[arg(long)]
+        main_file: Option<String>,
+    },
+    /// Find the entries with the same doi, or the same title, year and first author, and show
+    /// them side by side
+    Dedupe {
+        /// The bib files, default to the bib files of the document
+        files: Vec<PathBuf>,
+
+        /// The main file of the document whose citations are changed, default to the main file
+        /// in the config
+        #[arg(long)]
+        main_file: Option<String>,
+
+        /// Merge the duplicates into the first entry, and cite it instead of the others
+        #[arg(short, long, default_value_t = false)]
+        in_place: bool,
+    },
+    /// Write the entries cited by the document, with their crossref parents and the @string
+    /// macros they use
+    Extract {
+        /// The bib files to extract from, default to the bib files of the document
+        files: Vec<PathBuf>,
+
+        /// The main file of the document, default to the main file in the config
+        #[arg(long)]
+        main_file: Option<String>,
+
+        #[arg(short, long, value_name = "outfile")]
+        outfile: Option<String>,
+    },
+}
+
 /// Init logger according to debug flag
 // recall the hierarchy of debug level Trace < Debug < Warn <Error
 // When set to debug level A, only message of those higher than A are logged
@@ -105,6 +334,8 @@ pub fn cli() -> Result<(), Box<dyn std::error::Error>> {
             target,
             in_place,
             outfile,
+            sort,
+            style,
         } => {
             let mut path = PathBuf::from(".");
             path.push(target);
@@ -115,7 +346,7 @@ pub fn cli() -> Result<(), Box<dyn std::error::Error>> {
                 )
                 .into());
             }
-            let res = format::format(&path, &config)?;
+            let res = format::format(&path, *sort, *style, &config)?;
 
             // TODO: debug level output
             if *in_place {
@@ -133,6 +364,128 @@ pub fn cli() -> Result<(), Box<dyn std::error::Error>> {
                 utils::overwrite_to_file_path_buf(&PathBuf::from(out), &res)?;
             }
         }
+        Commands::Clean {
+            project_dir,
+            dry_run,
+            all,
+        } => {
+            clean::clean(&PathBuf::from(project_dir), *dry_run, *all, &config)?;
+        }
+        Commands::Flatten {
+            main_file,
+            outdir,
+            keep_comments,
+            bbl,
+        } => {
+            flatten::flatten(
+                main_file,
+                &PathBuf::from(outdir),
+                *keep_comments,
+                *bbl,
+                &config,
+            )?;
+        }
+        Commands::Bundle {
+            main_file,
+            outfile,
+            arxiv,
+        } => {
+            bundle::bundle(main_file, outfile, *arxiv, &config)?;
+        }
+        Commands::StripComments {
+            target,
+            in_place,
+            outfile,
+            comment_envr,
+            iffalse,
+        } => {
+            let options = StripOptions {
+                comment_envr: *comment_envr,
+                iffalse: *iffalse,
+            };
+            strip_comments::strip_comments(
+                target, *in_place, outfile, &options,
+            )?;
+        }
+        Commands::Md2tex {
+            input,
+            outfile,
+            booktabs,
+            standalone,
+        } => {
+            let options = EmitOptions {
+                booktabs: *booktabs,
+            };
+            md2tex::md2tex(input, outfile, *standalone, &options, &config)?;
+        }
+        Commands::Tex2md {
+            input,
+            outfile,
+            unknown,
+        } => {
+            let options = MarkdownOptions {
+                unknown_commands: unknown
+                    .unwrap_or(config.get_tex2md_config().unknown_commands),
+            };
+            tex2md::tex2md(input, outfile, &options)?;
+        }
+        Commands::Detex {
+            input,
+            outfile,
+            map,
+            math,
+        } => {
+            let options = DetexOptions {
+                math_placeholder: math.clone(),
+            };
+            detex::detex(input, outfile, map, &options)?;
+        }
+        Commands::Wordcount { main_file } => {
+            wordcount::wordcount(main_file, &config)?;
+        }
+        Commands::Outline {
+            main_file,
+            json,
+            theorems,
+        } => {
+            outline::outline(main_file, *json, *theorems, &config)?;
+        }
+        Commands::Check { main_file } => {
+            check::check(main_file, &config)?;
+        }
+        Commands::Lint {
+            main_file,
+            fix,
+            list,
+        } => {
+            lint::lint(main_file, *fix, *list, &config)?;
+        }
+        Commands::Rename {
+            main_file,
+            label,
+            macros,
+        } => {
+            rename::rename(main_file, label, macros, &config)?;
+        }
+        Commands::Bib { command } => match command {
+            BibCommands::Lint { files, main_file } => {
+                bib::lint(files, main_file, &config)?;
+            }
+            BibCommands::Dedupe {
+                files,
+                main_file,
+                in_place,
+            } => {
+                bib::dedupe(files, main_file, *in_place, &config)?;
+            }
+            BibCommands::Extract {
+                files,
+                main_file,
+                outfile,
+            } => {
+                bib::extract(files, main_file, outfile, &config)?;
+            }
+        },
     }
     Ok(())
 }
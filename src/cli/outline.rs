@@ -0,0 +1,47 @@
+//! This file contains the `outline` command logic
+//! It prints the sectioning commands of the document as a tree, or as JSON, see
+//! latex_interpreter::outline. The files are relative to the directory of the main file.
+use std::error::Error;
+use std::path::Path;
+
+use super::project::build_graph;
+use crate::config::Config;
+use crate::latex_interpreter::outline::{outline as outline_graph, Heading};
+
+/// Print the outline of main_file (the main file of the config if None), with the theorems if
+/// theorems
+pub(super) fn outline(
+    main_file: &Option<String>,
+    json: bool,
+    theorems: bool,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let graph = build_graph(main_file, config)?;
+    let main_path = graph.get_main_file().get_path();
+    let main_dir = main_path.parent().unwrap_or(Path::new(""));
+
+    let mut headings = outline_graph(&graph, theorems);
+    for heading in headings.iter_mut() {
+        relative_to(heading, main_dir);
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&headings)?);
+    } else if headings.is_empty() {
+        info!("No sectioning command found in `{}`", main_path.display());
+    } else {
+        for heading in &headings {
+            println!("{}", heading);
+        }
+    }
+    Ok(())
+}
+
+fn relative_to(heading: &mut Heading, dir: &Path) {
+    if let Ok(file) = heading.file.strip_prefix(dir) {
+        heading.file = file.to_path_buf();
+    }
+    for child in heading.children.iter_mut() {
+        relative_to(child, dir);
+    }
+}
@@ -0,0 +1,27 @@
+//! This file contains the helpers shared by the commands working on the document of the project
+use std::error::Error;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::latex_interpreter::include_graph::IncludeGraph;
+
+/// main_file, or the main file of the config if None
+pub(super) fn main_file_path(
+    main_file: &Option<String>,
+    config: &Config,
+) -> PathBuf {
+    match main_file {
+        Some(f) => PathBuf::from(f),
+        None => PathBuf::from(config.get_main_file_name()),
+    }
+}
+
+/// Build the include graph of main_file (the main file of the config if None), with absolute
+/// paths
+pub(super) fn build_graph(
+    main_file: &Option<String>,
+    config: &Config,
+) -> Result<IncludeGraph, Box<dyn Error>> {
+    let cwd = std::env::current_dir()?;
+    IncludeGraph::build(&cwd.join(main_file_path(main_file, config)))
+}
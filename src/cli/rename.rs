@@ -0,0 +1,120 @@
+//! This file contains the `rename` command logic
+//! It renames labels (`--label old:new`) and macros (`--macro \old:\new`) across the document, in
+//! the definitions and in every usage, see latex_interpreter::rename. Each change is printed as
+//! `file:row:col: `old` -> `new``, and the changed files are backed up as `.<file>.backup`.
+//! All of the renames are checked before any file is written, so that a failing one changes
+//! nothing.
+use std::error::Error;
+use std::path::PathBuf;
+
+use super::project::build_graph;
+use crate::config::Config;
+use crate::latex_interpreter::edit::{apply_edits, Edit};
+use crate::latex_interpreter::include_graph::IncludeGraph;
+use crate::latex_interpreter::refs::Occurrence;
+use crate::latex_interpreter::rename::{
+    label_occurrences, rename_edits, rename_label, rename_macro,
+};
+use crate::utils::overwrite_with_backup;
+
+/// Rename the labels and the macros, given as `old:new`, in the document of main_file (the main
+/// file of the config if None)
+pub(super) fn rename(
+    main_file: &Option<String>,
+    labels: &[String],
+    macros: &[String],
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    if labels.is_empty() && macros.is_empty() {
+        return Err(
+            "Nothing to rename, give `--label old:new` or `--macro \\old:\\new`"
+                .into(),
+        );
+    }
+    let graph = build_graph(main_file, config)?;
+
+    // (old, new, occurrences), the old and new names being as printed
+    let mut renames: Vec<(String, String, Vec<Occurrence>)> = vec![];
+    for arg in labels {
+        let (old, new) = split_label(&graph, arg)?;
+        let occurrences = rename_label(&graph, old, new)?;
+        renames.push((old.to_string(), new.to_string(), occurrences));
+    }
+    for arg in macros {
+        let Some((old, new)) = arg.split_once(':') else {
+            return Err(
+                format!("`{}` is not of the form `\\old:\\new`", arg).into()
+            );
+        };
+        let (old, new) =
+            (old.trim_start_matches('\\'), new.trim_start_matches('\\'));
+        let occurrences = rename_macro(&graph, old, new)?;
+        renames.push((format!("\\{}", old), format!("\\{}", new), occurrences));
+    }
+    for (i, (old, new, _)) in renames.iter().enumerate() {
+        for (other_old, other_new, _) in &renames[..i] {
+            if old == other_old || new == other_new {
+                return Err(format!(
+                    "`{}:{}` conflicts with `{}:{}`",
+                    old, new, other_old, other_new
+                )
+                .into());
+            }
+        }
+    }
+
+    let mut file_edits: Vec<(PathBuf, Vec<Edit>)> = vec![];
+    let mut count = 0;
+    for (old, new, occurrences) in &renames {
+        for occurrence in occurrences {
+            println!("{}: `{}` -> `{}`", occurrence.location(), old, new);
+        }
+        count += occurrences.len();
+        for (path, edits) in rename_edits(occurrences, new) {
+            match file_edits.iter_mut().find(|(p, _)| *p == path) {
+                Some((_, all)) => all.extend(edits),
+                None => file_edits.push((path, edits)),
+            }
+        }
+    }
+    for (path, edits) in &mut file_edits {
+        edits.sort_by_key(|e| e.span.start);
+        let file = graph
+            .get_file(path)
+            .ok_or(format!("`{}` is not in the document", path.display()))?;
+        let content = apply_edits(file.file_input.get_str_content(), edits);
+        overwrite_with_backup(path, &content)?;
+    }
+    info!(
+        "Renamed {} occurrence(s) in {} file(s)",
+        count,
+        file_edits.len()
+    );
+    Ok(())
+}
+
+/// Split `old:new` at the colon whose left part is a label of the document, as labels often
+/// contain colons themselves (`sec:intro:sec:overview`)
+fn split_label<'a>(
+    graph: &IncludeGraph,
+    arg: &'a str,
+) -> Result<(&'a str, &'a str), Box<dyn Error>> {
+    let candidates: Vec<(&str, &str)> = arg
+        .match_indices(':')
+        .map(|(i, _)| (&arg[..i], &arg[i + 1..]))
+        .filter(|(old, _)| !label_occurrences(graph, old).is_empty())
+        .collect();
+    match candidates[..] {
+        [split] => Ok(split),
+        [] if arg.contains(':') => {
+            Err(format!("No label of `{}` is found in the document", arg)
+                .into())
+        }
+        [] => Err(format!("`{}` is not of the form `old:new`", arg).into()),
+        _ => Err(format!(
+            "`{}` is ambiguous, as `{}` and `{}` are both labels",
+            arg, candidates[0].0, candidates[1].0
+        )
+        .into()),
+    }
+}
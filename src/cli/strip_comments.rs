@@ -0,0 +1,40 @@
+//! This file contains the `strip-comments` command logic
+//! See latex_interpreter::comment for what is removed.
+use std::error::Error;
+use std::path::PathBuf;
+
+use crate::latex_interpreter::comment::{
+    strip_comments as strip, StripOptions,
+};
+use crate::utils::{
+    overwrite_to_file_path_buf, overwrite_with_backup, FileInput,
+};
+
+/// Strip the comments of target
+/// The result overwrites target if in_place (the original is backed up as `.<target>.backup`,
+/// just as `format --in-place` does), is written to outfile if given, and is printed otherwise
+pub(super) fn strip_comments(
+    target: &str,
+    in_place: bool,
+    outfile: &Option<String>,
+    options: &StripOptions,
+) -> Result<(), Box<dyn Error>> {
+    let path = PathBuf::from(target);
+    if !path.is_file() {
+        return Err(
+            format!("Target path `{}` does not exist", path.display()).into()
+        );
+    }
+    let res = strip(&FileInput::from_file_path(&path)?, options)?;
+
+    if in_place {
+        overwrite_with_backup(&path, &res)?;
+    }
+    if let Some(out) = outfile {
+        overwrite_to_file_path_buf(&PathBuf::from(out), &res)?;
+    }
+    if !in_place && outfile.is_none() {
+        print!("{}", res);
+    }
+    Ok(())
+}
@@ -0,0 +1,31 @@
+//! This file contains the `tex2md` command logic
+//! It converts a latex file to markdown, see latex_interpreter::to_markdown.
+use std::error::Error;
+use std::path::PathBuf;
+
+use crate::latex_interpreter::to_markdown::{to_markdown, MarkdownOptions};
+use crate::utils::{overwrite_to_file_path_buf, FileInput};
+
+/// Convert input to markdown, written to outfile if given, and printed otherwise
+pub(super) fn tex2md(
+    input: &str,
+    outfile: &Option<String>,
+    options: &MarkdownOptions,
+) -> Result<(), Box<dyn Error>> {
+    let path = PathBuf::from(input);
+    if !path.is_file() {
+        return Err(
+            format!("Input file `{}` does not exist", path.display()).into()
+        );
+    }
+    let markdown = to_markdown(&FileInput::from_file_path(&path)?, options)?;
+
+    match outfile {
+        Some(out) => {
+            overwrite_to_file_path_buf(&PathBuf::from(out), &markdown)?;
+            info!("Converted `{}` to `{}`", input, out);
+        }
+        None => print!("{}", markdown),
+    }
+    Ok(())
+}
@@ -0,0 +1,92 @@
+//! This file contains the `wordcount` command logic
+//! It counts the words of the document by category and section, see
+//! latex_interpreter::wordcount.
+//!
+//! The counts are printed as a table, one row per section, the titles indented by their level:
+//! ```text
+//!  Body Headers Captions Footnotes Inline Display Floats  Section
+//!    12       0        0         0      0       0      0  (main.tex:4)
+//!   130       2        4         3      5       1      1  Introduction (main.tex:6)
+//!    85       1        0         0      2       0      0    Results (chapters/a.tex:1)
+//!   227       3        4         3      7       1      1  Total
+//! ```
+use std::error::Error;
+use std::path::Path;
+
+use super::project::build_graph;
+use crate::config::Config;
+use crate::latex_interpreter::wordcount::{count_words, Counts, SECTIONS};
+
+/// Count the words of main_file (the main file of the config if None)
+pub(super) fn wordcount(
+    main_file: &Option<String>,
+    config: &Config,
+) -> Result<(), Box<dyn Error>> {
+    let graph = build_graph(main_file, config)?;
+    let count = count_words(&graph);
+
+    // levels relative to the highest sectioning command used
+    let top = count
+        .sections
+        .iter()
+        .filter_map(|s| s.command.as_deref())
+        .filter_map(level)
+        .min()
+        .unwrap_or(0);
+    let main_dir = graph
+        .get_main_file()
+        .get_path()
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    println!(
+        "{:>5} {:>7} {:>8} {:>9} {:>6} {:>7} {:>6}  Section",
+        "Body",
+        "Headers",
+        "Captions",
+        "Footnotes",
+        "Inline",
+        "Display",
+        "Floats"
+    );
+    for section in &count.sections {
+        let indent = section
+            .command
+            .as_deref()
+            .and_then(level)
+            .map_or(0, |l| l.saturating_sub(top));
+        let file = section
+            .file
+            .strip_prefix(&main_dir)
+            .unwrap_or(&section.file);
+        let location = format!("({}:{})", file.display(), section.row + 1);
+        let title = if section.title.is_empty() {
+            location
+        } else {
+            format!("{} {}", section.title, location)
+        };
+        println!("{}  {}{}", row(&section.counts), "  ".repeat(indent), title);
+    }
+    let total = count.total();
+    println!("{}  Total", row(&total));
+    info!("{} words in total", total.words());
+    Ok(())
+}
+
+fn level(command: &str) -> Option<usize> {
+    SECTIONS.iter().position(|s| *s == command)
+}
+
+fn row(counts: &Counts) -> String {
+    format!(
+        "{:>5} {:>7} {:>8} {:>9} {:>6} {:>7} {:>6}",
+        counts.body,
+        counts.headers,
+        counts.captions,
+        counts.footnotes,
+        counts.inline_math,
+        counts.display_math,
+        counts.floats
+    )
+}
@@ -1,10 +1,14 @@
 //! Config struct for configuration
 //! The default config file is $HOME/.config/tex-helper/config.toml
 
+use std::collections::HashMap;
 use std::error::Error;
 use toml;
 
+use crate::bib_interpreter::formatter::{BibSort, BibStyle};
 use crate::cli::Cli;
+use crate::latex_interpreter::to_markdown::UnknownCommands;
+use crate::linter::LintLevel;
 use crate::utils;
 use serde::{Deserialize, Serialize};
 
@@ -18,14 +22,57 @@ use serde::{Deserialize, Serialize};
 pub struct Config {
     main_file_name: Option<String>,
     latex_binary: Option<String>,
+    #[serde(default)]
     debug: DebugLevel,
 
+    /// The `[tex2md]` section
+    #[serde(default)]
+    tex2md: Tex2mdConfig,
+
+    /// The `[bib]` section
+    #[serde(default)]
+    bib: BibConfig,
+
+    /// The `[lint]` section, the level of each rule whose default is changed
+    /// ```toml
+    /// [lint]
+    /// unused-label = "off" # or "warn", "error"
+    /// ```
+    #[serde(default)]
+    lint: HashMap<String, LintLevel>,
+
     // This field stores the log info. As config is initialised before logger, those info can only
     // be logged after the logged is initialised
+    #[serde(default)]
     log_warn_message: Vec<String>,
+    #[serde(default)]
     log_debug_message: Vec<String>,
 }
 
+/// Config of `tex-helper tex2md`
+/// ```toml
+/// [tex2md]
+/// unknown_commands = "drop" # or "keep"
+/// ```
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Tex2mdConfig {
+    pub unknown_commands: UnknownCommands,
+}
+
+/// Config of formatting `.bib` files
+/// ```toml
+/// [bib]
+/// sort = "key" # or "none", "citation"
+/// style = "braces" # or "quotes", "keep"
+/// ```
+#[derive(Debug, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct BibConfig {
+    pub sort: BibSort,
+    pub style: BibStyle,
+}
+
 /// The debug level, which is the same as simplelog::LevelFilter
 /// The default level is Warn
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
@@ -44,6 +91,9 @@ impl Config {
             main_file_name: Some("main.tex".into()),
             latex_binary: None,
             debug: DebugLevel::default(),
+            tex2md: Tex2mdConfig::default(),
+            bib: BibConfig::default(),
+            lint: HashMap::new(),
             log_warn_message: vec![],
             log_debug_message: vec![],
         }
@@ -91,6 +141,18 @@ impl Config {
         self.debug.clone()
     }
 
+    pub fn get_tex2md_config(&self) -> &Tex2mdConfig {
+        &self.tex2md
+    }
+
+    pub fn get_bib_config(&self) -> &BibConfig {
+        &self.bib
+    }
+
+    pub fn get_lint_config(&self) -> &HashMap<String, LintLevel> {
+        &self.lint
+    }
+
     fn read_config_file() -> Result<Self, Box<dyn Error>> {
         use std::env;
         use std::fs;
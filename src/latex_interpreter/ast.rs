@@ -9,8 +9,14 @@ use std::convert;
 use std::fmt;
 use std::sync::{Arc, Mutex};
 
+pub use super::token::Span;
+
 pub type NodePtr = Arc<Mutex<Node>>;
 
+/// The environments whose content is not parsed by LaTeX, though it is parsed into the AST
+pub(crate) const VERBATIM_ENVRS: [&str; 5] =
+    ["verbatim", "Verbatim", "lstlisting", "minted", "comment"];
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum NodeType {
     Passage,   // A passage consisists of many paragraphs
@@ -30,17 +36,23 @@ pub enum NodeType {
     Envr, // environment
 
     Comment,
+
+    Parameter, // #1, in the body of a macro definition
 }
 
 /// A Node in AST
 /// Node itself does not keep track of its parent, so by itself it is not enougth to traverse the
 /// tree with backtracking.
 /// For traversing the tree, use the WALKER struct
+///
+/// span is the location in the source file the node is parsed from. For bracket args and
+/// environments it includes the brackets and the `\begin` and `\end` commands.
 #[derive(Debug)]
 pub struct Node {
     pub lexeme: String,
     pub node_type: NodeType,
     pub children: Vec<NodePtr>,
+    pub span: Span,
 }
 
 impl Node {
@@ -50,9 +62,15 @@ impl Node {
             lexeme,
             node_type,
             children: vec![],
+            span: Span::default(),
         }
     }
 
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
     pub fn attach(&mut self, ptr: NodePtr) {
         self.children.push(ptr);
     }
@@ -62,6 +80,7 @@ impl Node {
             lexeme: String::new(),
             node_type: NodeType::Passage,
             children: vec![],
+            span: Span::default(),
         }))
     }
     pub fn empty_paragraph_ptr() -> NodePtr {
@@ -69,6 +88,7 @@ impl Node {
             lexeme: String::new(),
             node_type: NodeType::Paragraph,
             children: vec![],
+            span: Span::default(),
         }))
     }
 
@@ -77,6 +97,7 @@ impl Node {
             lexeme: String::new(),
             node_type: NodeType::Paragraph,
             children: vec![],
+            span: Span::default(),
         }))
     }
 
@@ -134,9 +155,47 @@ impl Node {
             lexeme: String::new(),
             node_type: NodeType::Paragraph,
             children: vec![],
+            span: Span::default(),
+        }
+    }
+
+    pub fn get_span(&self) -> Span {
+        self.span
+    }
+
+    pub fn get_span_nodeptr(node: NodePtr) -> Span {
+        let node = node.lock().unwrap();
+        node.get_span()
+    }
+
+    /// The span of the content of a bracket arg, that is, without the brackets
+    /// For other nodes, it is the same as the span
+    ///
+    /// EG: for `{chapters/intro}`, the content span points to `chapters/intro`
+    pub fn get_content_span(&self) -> Span {
+        match self.node_type {
+            NodeType::CurlyBracketArg | NodeType::SquareBracketArg => {
+                match self.children.first() {
+                    Some(c) => c.lock().unwrap().span,
+                    None => self.span,
+                }
+            }
+            _ => self.span,
         }
     }
 
+    /// Return the curly bracket args of self, in order
+    /// EG: for `\import[opt]{dir/}{file}`, return the args `{dir/}` and `{file}`
+    pub fn get_curly_bracket_args(&self) -> Vec<NodePtr> {
+        self.children
+            .iter()
+            .filter(|c| {
+                c.lock().unwrap().node_type == NodeType::CurlyBracketArg
+            })
+            .cloned()
+            .collect()
+    }
+
     pub fn get_node_type(&self) -> &NodeType {
         &self.node_type
     }
@@ -167,6 +226,42 @@ impl Node {
         node.get_nth_child(id)
     }
 
+    /// Return all nodes in the tree rooted at node (node included) satisfying pred, in depth
+    /// first order
+    pub fn find_all_nodeptr(
+        node: NodePtr,
+        pred: &dyn Fn(&Node) -> bool,
+    ) -> Vec<NodePtr> {
+        let mut ret: Vec<NodePtr> = vec![];
+        let children = {
+            let n = node.lock().unwrap();
+            if pred(&n) {
+                ret.push(node.clone());
+            }
+            n.get_children().to_vec()
+        };
+        for c in children {
+            ret.extend(Node::find_all_nodeptr(c, pred));
+        }
+        ret
+    }
+
+    /// Return all Command nodes named name in the tree rooted at node
+    /// EG: find_commands_nodeptr(ast, "bibliography")
+    pub fn find_commands_nodeptr(node: NodePtr, name: &str) -> Vec<NodePtr> {
+        Node::find_all_nodeptr(node, &|n: &Node| {
+            n.node_type == NodeType::Command && n.lexeme == name
+        })
+    }
+
+    /// The body Passage of the document environment in the tree rooted at node, if there is one
+    pub fn find_document_body_nodeptr(node: NodePtr) -> Option<NodePtr> {
+        let documents = Node::find_all_nodeptr(node, &|n: &Node| {
+            n.node_type == NodeType::Envr && n.lexeme == "document"
+        });
+        Node::get_nth_child_nodeptr(documents.first()?.clone(), 0)
+    }
+
     pub fn is_container(&self) -> bool {
         match self.node_type {
             NodeType::Passage | NodeType::Paragraph => true,
@@ -246,33 +341,15 @@ mod test_node {
 impl fmt::Display for Node {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fn aux(node: &Node) -> Vec<String> {
-            let mut ret: Vec<String> = vec![];
-            ret.push(format!("{:?}({})", node.node_type, node.lexeme));
-
-            let children = &node.children;
-            for i in 0..children.len() {
-                let child = children[i].lock().unwrap();
-                let child_display = aux(&*child);
-                if i != children.len() - 1 {
-                    for j in 0..child_display.len() {
-                        if j == 0 {
-                            ret.push(format!("├── {}", child_display[j]));
-                        } else {
-                            ret.push(format!("│   {}", child_display[j]));
-                        }
-                    }
-                } else {
-                    for j in 0..child_display.len() {
-                        if j == 0 {
-                            ret.push(format!("└── {}", child_display[j]));
-                        } else {
-                            ret.push(format!("    {}", child_display[j]));
-                        }
-                    }
-                }
-            }
-
-            ret
+            let children: Vec<Vec<String>> = node
+                .children
+                .iter()
+                .map(|c| aux(&c.lock().unwrap()))
+                .collect();
+            draw_tree(
+                format!("{:?}({})", node.node_type, node.lexeme),
+                &children,
+            )
         }
 
         let mut dis = String::new();
@@ -287,6 +364,33 @@ impl fmt::Display for Node {
     }
 }
 
+/// Draw a tree with box-drawing characters, see the Display of Node
+/// Each child is given as the lines of its own drawing
+pub fn draw_tree(root: String, children: &[Vec<String>]) -> Vec<String> {
+    let mut ret: Vec<String> = vec![root];
+    for i in 0..children.len() {
+        let child_display = &children[i];
+        if i != children.len() - 1 {
+            for j in 0..child_display.len() {
+                if j == 0 {
+                    ret.push(format!("├── {}", child_display[j]));
+                } else {
+                    ret.push(format!("│   {}", child_display[j]));
+                }
+            }
+        } else {
+            for j in 0..child_display.len() {
+                if j == 0 {
+                    ret.push(format!("└── {}", child_display[j]));
+                } else {
+                    ret.push(format!("    {}", child_display[j]));
+                }
+            }
+        }
+    }
+    ret
+}
+
 impl convert::Into<NodePtr> for Node {
     fn into(self) -> NodePtr {
         Arc::new(Mutex::new(self))
@@ -316,6 +420,9 @@ impl convert::Into<NodePtr> for Node {
 pub struct Walker {
     root: NodePtr,
     stack: Vec<(NodePtr, usize)>,
+    // whether next_node has returned the root, and whether it has returned every node
+    started: bool,
+    done: bool,
 }
 
 impl Walker {
@@ -323,6 +430,8 @@ impl Walker {
         Walker {
             root,
             stack: vec![],
+            started: false,
+            done: false,
         }
     }
 
@@ -377,15 +486,85 @@ impl Walker {
         Node::get_nth_child_nodeptr(cur, 0)
     }
 
-    pub fn next_content_node(&mut self) -> Option<NodePtr> {
-        let mut is_root: bool = false;
-        let node = if self.stack.is_empty() {
-            is_root = true;
-            self.root.clone()
-        } else {
-            let (parent, index) = self.stack[self.stack.len() - 1].clone();
-            Node::get_nth_child_nodeptr(parent, index).unwrap()
-        };
+    /// Return the node before the current one at the same level, see next_sibling
+    pub fn prev_sibling(&self) -> Option<NodePtr> {
+        let (parent, index) = self.stack.last()?.clone();
+        Node::get_nth_child_nodeptr(parent, index.checked_sub(1)?)
+    }
+
+    /// Return the nodes from the root down to the parent of the current node
+    pub fn ancestors(&self) -> Vec<NodePtr> {
+        self.stack
+            .iter()
+            .map(|(parent, _)| parent.clone())
+            .collect()
+    }
+
+    /// Move to the next node in pre-order (a node before its children, the children in order),
+    /// and return it. The first call returns the root, and None is returned once every node is
+    /// visited.
+    ///
+    /// eg, on the tree above, the nodes are Root, A, A1, A2, A3, B, B1, B2, B3
+    pub fn next_node(&mut self) -> Option<NodePtr> {
+        if !self.started {
+            self.started = true;
+            return Some(self.root.clone());
+        }
+        if self.done {
+            return None;
+        }
+        if self.first_child().is_some() {
+            self.stack.push((self.cur_loc(), 0));
+            return Some(self.cur_loc());
+        }
+        while let Some((parent, index)) = self.stack.pop() {
+            if Node::get_nth_child_nodeptr(parent.clone(), index + 1).is_some()
+            {
+                self.stack.push((parent, index + 1));
+                return Some(self.cur_loc());
+            }
+        }
+        self.done = true;
         None
     }
 }
+
+#[cfg(test)]
+mod test_walker {
+    use super::*;
+
+    #[test]
+    fn test_next_node() {
+        let mut root = Node::new("Root", NodeType::Passage);
+        for name in ["A", "B"] {
+            let mut node = Node::new(name, NodeType::Paragraph);
+            for i in 1..=3 {
+                node.attach(
+                    Node::new(&format!("{}{}", name, i), NodeType::Word).into(),
+                );
+            }
+            root.attach(node.into());
+        }
+
+        let mut walker = Walker::from_root(root.into());
+        let mut visited: Vec<String> = vec![];
+        while let Some(node) = walker.next_node() {
+            let lexeme = node.lock().unwrap().lexeme.clone();
+            if lexeme == "B2" {
+                let lexeme_of = |n: Option<NodePtr>| {
+                    n.unwrap().lock().unwrap().lexeme.clone()
+                };
+                assert_eq!(lexeme_of(walker.prev_sibling()), "B1");
+                assert_eq!(lexeme_of(walker.next_sibling()), "B3");
+                assert_eq!(lexeme_of(walker.ancestors().pop()), "B");
+                assert_eq!(walker.ancestors().len(), 2);
+            }
+            visited.push(lexeme);
+        }
+        assert_eq!(
+            visited,
+            vec!["Root", "A", "A1", "A2", "A3", "B", "B1", "B2", "B3"]
+        );
+        assert!(walker.next_node().is_none());
+    }
+}
@@ -0,0 +1,291 @@
+//! Check the citations of the document against its bibliography, without running bibtex or biber
+//!
+//! The keys of `\cite`, `\parencite`, `\textcite` and the other citation commands of natbib and
+//! biblatex are collected across the include graph (`\cites{a}{b}` cites a and b), and compared
+//! with the entries of the `.bib` files of `\bibliography` and `\addbibresource`, and with the
+//! `\bibitem`s of a thebibliography environment. The following are reported:
+//! 1. cited keys with no entry
+//! 1. entries defined more than once
+//! 1. entries never cited. An entry is cited if it is the `crossref` of a cited entry, and all
+//!    entries are cited by `\nocite{*}`.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use colored::*;
+
+use super::ast::{NodePtr, NodeType};
+use super::include_graph::{IncludeGraph, SourceFile};
+use super::refs::{collect_keys, keys_of, Occurrence};
+use crate::bib_interpreter::parser::{parse as parse_bib, BibFile};
+use crate::utils::FileInput;
+
+pub const CITE_COMMANDS: [&str; 26] = [
+    "cite",
+    "Cite",
+    "nocite",
+    "parencite",
+    "Parencite",
+    "textcite",
+    "Textcite",
+    "autocite",
+    "Autocite",
+    "footcite",
+    "smartcite",
+    "supercite",
+    "fullcite",
+    "citeauthor",
+    "Citeauthor",
+    "citeyear",
+    "citetitle",
+    "citep",
+    "citet",
+    "citealp",
+    "citealt",
+    "cites",
+    "parencites",
+    "textcites",
+    "autocites",
+    "footcites",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CiteProblem {
+    Missing(Occurrence),
+    /// The entry defined again, and its first definition
+    Duplicate(Occurrence, Occurrence),
+    Uncited(Occurrence),
+}
+
+impl CiteProblem {
+    /// Missing and duplicate entries break the bibliography, uncited ones do not
+    pub fn is_error(&self) -> bool {
+        !matches!(self, CiteProblem::Uncited(_))
+    }
+
+    /// The citation or entry the problem is located at
+    pub fn occurrence(&self) -> &Occurrence {
+        match self {
+            CiteProblem::Missing(o)
+            | CiteProblem::Duplicate(o, _)
+            | CiteProblem::Uncited(o) => o,
+        }
+    }
+
+    /// The problem, without its location
+    pub fn message(&self) -> String {
+        match self {
+            CiteProblem::Missing(c) => {
+                format!("citation `{}` not found in the bibliography", c.key)
+            }
+            CiteProblem::Duplicate(entry, first) => format!(
+                "entry `{}` already defined at {}",
+                entry.key,
+                first.location()
+            ),
+            CiteProblem::Uncited(entry) => {
+                format!("entry `{}` is never cited", entry.key)
+            }
+        }
+    }
+}
+
+impl fmt::Display for CiteProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.is_error() {
+            true => "ERROR".red().bold(),
+            false => "WARNING".yellow().bold(),
+        };
+        write!(
+            f,
+            "{} {}: {}",
+            self.occurrence().location(),
+            level,
+            self.message()
+        )
+    }
+}
+
+/// The keys cited in the document, in document order
+pub fn collect_citations(graph: &IncludeGraph) -> Vec<Occurrence> {
+    let mut ret: Vec<Occurrence> = vec![];
+    graph.walk(&mut |file: &SourceFile, node: &NodePtr| {
+        let node = node.lock().unwrap();
+        if node.node_type != NodeType::Command
+            || !CITE_COMMANDS.contains(&node.lexeme.as_str())
+        {
+            return;
+        }
+        let args = node.get_curly_bracket_args();
+        // the multicite commands take a bracket arg per citation
+        let args = if node.lexeme.ends_with("cites") {
+            &args[..]
+        } else {
+            &args[..args.len().min(1)]
+        };
+        for arg in args {
+            ret.extend(keys_of(arg, file));
+        }
+    });
+    ret
+}
+
+/// The bib files of the document, parsed, in the order they are given
+pub fn read_bibliography(
+    graph: &IncludeGraph,
+) -> Result<Vec<(FileInput, BibFile)>, Box<dyn Error>> {
+    let main_dir = graph
+        .get_main_file()
+        .get_path()
+        .parent()
+        .unwrap_or(Path::new(""))
+        .to_path_buf();
+    let mut paths: Vec<PathBuf> = vec![];
+    for file in graph.get_files() {
+        for path in file.bibliography_files(&main_dir) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    let mut ret = vec![];
+    for path in paths {
+        let file_input = FileInput::from_file_path(&path)?;
+        let bib = parse_bib(&file_input)?;
+        ret.push((file_input, bib));
+    }
+    Ok(ret)
+}
+
+/// The problems of the citations of the document, see the module doc
+/// The missing citations come first, in document order, then the duplicate and uncited entries
+/// in the order of the bib files
+pub fn check_citations(
+    graph: &IncludeGraph,
+) -> Result<Vec<CiteProblem>, Box<dyn Error>> {
+    let citations = collect_citations(graph);
+    let bibliography = read_bibliography(graph)?;
+
+    // the entries and bibitems, with the crossref of each entry
+    let mut entries: Vec<(Occurrence, Option<String>)> = vec![];
+    for (file_input, bib) in &bibliography {
+        for entry in bib.entries() {
+            let (row, col) = file_input.get_row_col(entry.key_span.start);
+            let occurrence = Occurrence {
+                key: entry.key.clone(),
+                file: file_input.get_file_path().clone(),
+                span: entry.key_span,
+                row,
+                col,
+            };
+            let crossref =
+                entry.get_field("crossref").map(|f| f.value.to_plain());
+            entries.push((occurrence, crossref));
+        }
+    }
+    for item in collect_keys(graph, &["bibitem"]) {
+        entries.push((item, None));
+    }
+
+    let mut ret: Vec<CiteProblem> = vec![];
+    let mut defined: HashMap<&str, &Occurrence> = HashMap::new();
+    let mut duplicates: Vec<CiteProblem> = vec![];
+    for (entry, _) in &entries {
+        match defined.get(entry.key.as_str()) {
+            Some(first) => duplicates
+                .push(CiteProblem::Duplicate(entry.clone(), (*first).clone())),
+            None => {
+                defined.insert(&entry.key, entry);
+            }
+        }
+    }
+    for citation in &citations {
+        if citation.key != "*" && !defined.contains_key(citation.key.as_str()) {
+            ret.push(CiteProblem::Missing(citation.clone()));
+        }
+    }
+    ret.extend(duplicates);
+
+    if citations.iter().any(|c| c.key == "*") {
+        return Ok(ret);
+    }
+    let mut cited: HashSet<&str> =
+        citations.iter().map(|c| c.key.as_str()).collect();
+    for (entry, crossref) in &entries {
+        if let Some(crossref) = crossref {
+            if cited.contains(entry.key.as_str()) {
+                cited.insert(crossref);
+            }
+        }
+    }
+    let mut reported: HashSet<&str> = HashSet::new();
+    for (entry, _) in &entries {
+        if !cited.contains(entry.key.as_str()) && reported.insert(&entry.key) {
+            ret.push(CiteProblem::Uncited(entry.clone()));
+        }
+    }
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_test_project;
+
+    fn summary(problems: &[CiteProblem]) -> Vec<(&str, &str, usize)> {
+        problems
+            .iter()
+            .map(|p| match p {
+                CiteProblem::Missing(o) => ("missing", o),
+                CiteProblem::Duplicate(o, _) => ("duplicate", o),
+                CiteProblem::Uncited(o) => ("uncited", o),
+            })
+            .map(|(kind, o)| (kind, o.key.as_str(), o.row))
+            .collect()
+    }
+
+    #[test]
+    fn test_check_citations() {
+        let dir = create_test_project(
+            "cites",
+            &[
+                (
+                    "main.tex",
+                    "\\documentclass{article}\n\\usepackage{biblatex}\n\\addbibresource{refs.bib}\n\\begin{document}\n\\parencite[p.~5]{ovid, virgil} \\textcite{homer}\n\\cites{part}{ovid}\n\\begin{verbatim}\n\\cite{lit}\n\\end{verbatim}\n\\end{document}",
+                ),
+                (
+                    "refs.bib",
+                    "@book{ovid, title = {Metamorphoses}}\n@inbook{part, crossref = {whole}}\n@book{whole, title = {Whole}}\n@misc{unused}\n@misc{ovid}\n",
+                ),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let problems = check_citations(&graph).unwrap();
+        assert_eq!(
+            summary(&problems),
+            vec![
+                ("missing", "virgil", 4),
+                ("missing", "homer", 4),
+                ("duplicate", "ovid", 4),
+                ("uncited", "unused", 3),
+            ]
+        );
+        assert!(problems[0].is_error());
+        assert!(!problems[3].is_error());
+    }
+
+    #[test]
+    fn test_nocite_and_bibitem() {
+        let dir = create_test_project(
+            "cites-bibitem",
+            &[(
+                "main.tex",
+                "\\begin{document}\n\\cite{a}\\nocite{*}\n\\begin{thebibliography}{9}\n\\bibitem{a} A.\n\\bibitem{b} B.\n\\end{thebibliography}\n\\end{document}",
+            )],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        assert!(check_citations(&graph).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,463 @@
+//! Removing comments from the source text.
+//!
+//! Check doc/latex_grammar/1_overview.md#Comments for the behaviour of comments in latex:
+//! 1. If the line consists only of spaces and the comment, the entire line is ignored,
+//!    including the new line. So the whole line is removed.
+//! 1. If there are non-space characters before `%`, the new line is kept. The comment and the
+//!    spaces before it are removed, the new line is kept.
+//!
+//! A comment directly following a non-space character, like `\foo{a}%`, is commonly used to
+//! avoid the space the new line would otherwise create. In this case the `%` is kept, and only
+//! the comment text is removed.
+//!
+//! Optionally, text commented out by other means is removed as well:
+//! 1. `\begin{comment} ... \end{comment}` of the comment package
+//! 1. `\iffalse ... \fi`, nested conditionals included
+//!
+//! Such a block occupying whole lines removes the lines; otherwise only the block is removed.
+//!
+//! A `%` is a character, not a comment, in `\verb|...|`, in the url of `\url{...}` and
+//! `\href{...}{text}`, and in the verbatim environments. The scanner does not know them, so the
+//! comments are found in the source instead, see comment_spans.
+
+use super::ast::{Node, NodePtr, NodeType, VERBATIM_ENVRS};
+use super::edit::{apply_edits, Edit};
+use super::parser::parse_with_errors;
+use super::scanner::scan;
+use super::token::{Span, TokenType};
+use crate::utils::FileInput;
+use std::error::Error;
+
+#[derive(Debug, Default, Clone)]
+pub struct StripOptions {
+    /// Remove `\begin{comment} ... \end{comment}` blocks
+    pub comment_envr: bool,
+    /// Remove `\iffalse ... \fi` regions
+    pub iffalse: bool,
+}
+
+/// Return the edit removing the comment at span from source
+/// span shall be the span of a Comment token (or node), starting at `%`
+pub fn comment_edit(source: &str, span: Span) -> Edit {
+    let line_start = match source[..span.start].rfind('\n') {
+        Some(i) => i + 1,
+        None => 0,
+    };
+    let before = &source[line_start..span.start];
+
+    if before.trim().is_empty() {
+        // whole line comment: also remove the new line, if any
+        let end = if source[span.end..].starts_with('\n') {
+            span.end + 1
+        } else {
+            span.end
+        };
+        return Edit::delete(Span::new(line_start, end));
+    }
+
+    let trimmed = before.trim_end_matches([' ', '\t']);
+    if trimmed.len() == before.len() {
+        // `text%comment`: keep the `%`
+        Edit::new(span, "%")
+    } else {
+        Edit::delete(Span::new(line_start + trimmed.len(), span.end))
+    }
+}
+
+/// Return the edit removing the block at span from source
+/// If the block occupies whole lines, the lines are removed, including the new line
+fn block_edit(source: &str, span: Span) -> Edit {
+    let line_start = match source[..span.start].rfind('\n') {
+        Some(i) => i + 1,
+        None => 0,
+    };
+    let line_end = match source[span.end..].find('\n') {
+        Some(i) => span.end + i,
+        None => source.len(),
+    };
+    if source[line_start..span.start].trim().is_empty()
+        && source[span.end..line_end].trim().is_empty()
+    {
+        let end = if line_end < source.len() {
+            line_end + 1
+        } else {
+            line_end
+        };
+        Edit::delete(Span::new(line_start, end))
+    } else {
+        Edit::delete(span)
+    }
+}
+
+/// The commands whose first arg is a url, in which `%` is a character
+const URL_COMMANDS: [&str; 2] = ["url", "href"];
+
+/// Return the spans of the comments of source, from `%` to the end of the line
+/// The `%` of `\%`, of `\verb|...|`, of the first arg of the URL_COMMANDS and of the
+/// VERBATIM_ENVRS are characters, see literal_end
+pub fn comment_spans(source: &str) -> Vec<Span> {
+    let mut ret: Vec<Span> = vec![];
+    let mut i = 0;
+    while let Some(c) = source[i..].chars().next() {
+        match c {
+            '%' => {
+                let end = match source[i..].find('\n') {
+                    Some(n) => i + n,
+                    None => source.len(),
+                };
+                ret.push(Span::new(i, end));
+                i = end;
+            }
+            '\\' => {
+                let rest = &source[i + 1..];
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphabetic())
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    // an escaped character, as `\%` or `\\`
+                    i += 1 + rest.chars().next().map_or(0, |c| c.len_utf8());
+                } else {
+                    i += 1 + len;
+                    i = literal_end(source, &rest[..len], i).unwrap_or(i);
+                }
+            }
+            _ => i += c.len_utf8(),
+        }
+    }
+    ret
+}
+
+/// Return the end of the text following the command name, ending at end, in which `%` is a
+/// character: the content of `\verb|...|`, the first arg of the URL_COMMANDS, and the content of
+/// the VERBATIM_ENVRS. None if there is no such text
+pub(crate) fn literal_end(
+    source: &str,
+    name: &str,
+    end: usize,
+) -> Option<usize> {
+    let rest = &source[end..];
+    match name {
+        // \verb|...| and \verb*|...|, which does not span lines
+        "verb" => {
+            let arg = rest.strip_prefix('*').unwrap_or(rest);
+            let delim = arg.chars().next().filter(|c| *c != '\n')?;
+            let start = source.len() - arg.len() + delim.len_utf8();
+            let line = source[start..].split('\n').next().unwrap_or_default();
+            Some(match line.find(delim) {
+                Some(i) => start + i + delim.len_utf8(),
+                None => start + line.len(),
+            })
+        }
+        c if URL_COMMANDS.contains(&c) => {
+            let arg = rest.trim_start_matches([' ', '\t']);
+            if !arg.starts_with('{') {
+                return None;
+            }
+            // up to the matching brace, or to the end of the line
+            let start = source.len() - arg.len();
+            let line = arg.split('\n').next().unwrap_or_default();
+            let mut depth = 0;
+            for (i, c) in line.char_indices() {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                if depth == 0 {
+                    return Some(start + i + 1);
+                }
+            }
+            Some(start + line.len())
+        }
+        "begin" => {
+            let (envr, _) =
+                rest.trim_start().strip_prefix('{')?.split_once('}')?;
+            let envr = envr.trim();
+            if !VERBATIM_ENVRS.contains(&envr) {
+                return None;
+            }
+            let close = format!("\\end{{{}}}", envr);
+            Some(match rest.find(&close) {
+                Some(i) => end + i,
+                None => source.len(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Return the edits removing the comments in the AST of source
+pub fn comment_edits(
+    ast: NodePtr,
+    source: &str,
+    options: &StripOptions,
+) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = vec![];
+
+    // blocks go first, so that the comments inside them are dropped as overlapping edits
+    if options.comment_envr {
+        let envrs = Node::find_all_nodeptr(ast.clone(), &|n: &Node| {
+            n.node_type == NodeType::Envr && n.lexeme == "comment"
+        });
+        for envr in envrs {
+            edits.push(block_edit(source, Node::get_span_nodeptr(envr)));
+        }
+    }
+    if options.iffalse {
+        for span in iffalse_spans(ast.clone()) {
+            edits.push(block_edit(source, span));
+        }
+    }
+
+    // the scanner makes a comment of the `%` of urls and verbatim text, up to the end of the line:
+    // only the comments of comment_spans in the comment nodes are removed
+    let spans = comment_spans(source);
+    let comments = Node::find_all_nodeptr(ast, &|n: &Node| {
+        n.node_type == NodeType::Comment
+    });
+    for comment in comments {
+        let node = Node::get_span_nodeptr(comment);
+        for span in &spans {
+            if node.start <= span.start && span.start < node.end {
+                edits.push(comment_edit(source, *span));
+            }
+        }
+    }
+    edits
+}
+
+/// The conditionals of tex, e-tex and pdftex
+const IF_PRIMITIVES: [&str; 24] = [
+    "if",
+    "ifcase",
+    "ifcat",
+    "ifcsname",
+    "ifdefined",
+    "ifdim",
+    "ifeof",
+    "iffalse",
+    "iffontchar",
+    "ifhbox",
+    "ifhmode",
+    "ifincsname",
+    "ifinner",
+    "ifmmode",
+    "ifnum",
+    "ifodd",
+    "ifpdfabsdim",
+    "ifpdfabsnum",
+    "ifpdfprimitive",
+    "iftrue",
+    "ifvbox",
+    "ifvmode",
+    "ifvoid",
+    "ifx",
+];
+
+/// Return the spans from `\iffalse` to its matching `\fi`
+/// The conditionals are matched in source order, a conditional being one of IF_PRIMITIVES or one
+/// declared by `\newif`
+fn iffalse_spans(ast: NodePtr) -> Vec<Span> {
+    let mut commands: Vec<(String, Span)> =
+        Node::find_all_nodeptr(ast, &|n: &Node| {
+            n.node_type == NodeType::Command
+        })
+        .into_iter()
+        .map(|c| {
+            let c = c.lock().unwrap();
+            (c.lexeme.clone(), c.get_span())
+        })
+        .collect();
+    commands.sort_by_key(|(_, span)| span.start);
+
+    let declared: Vec<&str> = commands
+        .windows(2)
+        .filter(|w| w[0].0 == "newif")
+        .map(|w| w[1].0.as_str())
+        .collect();
+    let is_conditional =
+        |name: &str| IF_PRIMITIVES.contains(&name) || declared.contains(&name);
+
+    let mut ret: Vec<Span> = vec![];
+    // the start of each open conditional, Some if it is an \iffalse
+    let mut stack: Vec<Option<usize>> = vec![];
+    let mut after_newif = false;
+    for (name, span) in &commands {
+        if after_newif {
+            after_newif = false;
+            continue;
+        }
+        if name == "newif" {
+            after_newif = true;
+        } else if name == "fi" {
+            if let Some(Some(start)) = stack.pop() {
+                ret.push(Span::new(start, span.end));
+            }
+        } else if is_conditional(name) {
+            stack.push((name == "iffalse").then_some(span.start));
+        }
+    }
+
+    // nested \iffalse lie in the outer ones
+    ret.sort_by_key(|s| s.start);
+    let mut outer: Vec<Span> = vec![];
+    for span in ret {
+        match outer.last() {
+            Some(last) if span.start < last.end => {}
+            _ => outer.push(span),
+        }
+    }
+    outer
+}
+
+/// Remove the comments of file_input, see the module doc
+/// The file is parsed tolerantly, parse errors are only logged
+pub fn strip_comments(
+    file_input: &FileInput,
+    options: &StripOptions,
+) -> Result<String, Box<dyn Error>> {
+    let tokens = scan(file_input.clone())?;
+    let (ast, errs) = parse_with_errors(&tokens, file_input.clone())?;
+    if !errs.is_empty() {
+        debug!("Parse errors ignored:\n{}", errs);
+    }
+    let source = file_input.get_str_content();
+    let mut edits = comment_edits(ast.clone(), source, options);
+
+    // the parser stops at a stray `}`, the comments after it are found in the source instead
+    let node_count = Node::find_all_nodeptr(ast, &|n: &Node| {
+        n.node_type == NodeType::Comment
+    })
+    .len();
+    let token_count = tokens
+        .iter()
+        .filter(|t| t.token_type == TokenType::Comment)
+        .count();
+    if node_count < token_count {
+        warn!(
+            "{}: could not parse the whole file, falling back to the source for comments",
+            file_input.get_file_path().display()
+        );
+        edits.extend(comment_edits_from_source(source));
+    }
+    Ok(apply_edits(source, &edits))
+}
+
+/// Return the edits removing all comments of source, found by comment_spans, so escaped `\%`
+/// and the `%` of urls and verbatim text are not mistaken as comments
+pub fn comment_edits_from_source(source: &str) -> Vec<Edit> {
+    comment_spans(source)
+        .into_iter()
+        .map(|span| comment_edit(source, span))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn strip(source: &str) -> String {
+        let file_input = FileInput::from_str("dummy/path", source);
+        let res = apply_edits(source, &comment_edits_from_source(source));
+        // the AST agrees with the source
+        assert_eq!(
+            strip_comments(&file_input, &StripOptions::default()).unwrap(),
+            res
+        );
+        res
+    }
+
+    fn strip_all(source: &str) -> String {
+        let options = StripOptions {
+            comment_envr: true,
+            iffalse: true,
+        };
+        let file_input = FileInput::from_str("dummy/path", source);
+        strip_comments(&file_input, &options).unwrap()
+    }
+
+    #[test]
+    fn test_whole_line_comment() {
+        assert_eq!(strip("a\n    % comment\n% comment\nb"), "a\nb");
+        assert_eq!(strip("% comment"), "");
+    }
+
+    #[test]
+    fn test_trailing_comment() {
+        assert_eq!(strip("a % comment\nb"), "a\nb");
+        assert_eq!(strip("\\foo{a}% comment\nb"), "\\foo{a}%\nb");
+    }
+
+    #[test]
+    fn test_escaped_percent() {
+        assert_eq!(strip("50\\% of them % comment"), "50\\% of them");
+    }
+
+    #[test]
+    fn test_verb() {
+        assert_eq!(
+            strip("See \\verb|50%| and \\verb*+%x+ % c\nb"),
+            "See \\verb|50%| and \\verb*+%x+\nb"
+        );
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(
+            strip("See \\url{http://x.org/a%20b} % c\nb"),
+            "See \\url{http://x.org/a%20b}\nb"
+        );
+        assert_eq!(
+            strip("\\href{x.org/a%20b}{text} % c"),
+            "\\href{x.org/a%20b}{text}"
+        );
+    }
+
+    #[test]
+    fn test_verbatim_envr() {
+        assert_eq!(
+            strip(
+                "a % c\n\\begin{verbatim}\n% kept\nx % kept\n\\end{verbatim}\nb % c"
+            ),
+            "a\n\\begin{verbatim}\n% kept\nx % kept\n\\end{verbatim}\nb"
+        );
+    }
+
+    #[test]
+    fn test_unparsed_rest() {
+        assert_eq!(strip_all("a } % c\nb % d"), "a }\nb");
+    }
+
+    #[test]
+    fn test_comment_envr() {
+        assert_eq!(
+            strip_all("a\n\\begin{comment}\nhidden % x\n\\end{comment}\nb"),
+            "a\nb"
+        );
+        assert_eq!(
+            strip_all("a \\begin{comment} hidden \\end{comment} b"),
+            "a  b"
+        );
+    }
+
+    #[test]
+    fn test_iffalse() {
+        assert_eq!(
+            strip_all("a\n\\iffalse\n\\ifx a b c \\fi\n\\fi\nb \\iftrue c\\fi"),
+            "a\nb \\iftrue c\\fi"
+        );
+        assert_eq!(
+            strip_all("\\newif\\ifdraft\n\\iffalse x \\fi y"),
+            "\\newif\\ifdraft\n y"
+        );
+        assert_eq!(
+            strip_all("\\newif\\ifdraft\n\\iffalse \\ifdraft x\\fi \\fi y"),
+            "\\newif\\ifdraft\n y"
+        );
+        // \iff is not a conditional
+        assert_eq!(
+            strip_all("$a \\iff b$\n\\iffalse x \\fi\nc"),
+            "$a \\iff b$\nc"
+        );
+    }
+}
@@ -0,0 +1,555 @@
+//! Extract the prose of a latex file, for spell and grammar checkers
+//!
+//! 1. Commands whose args are not prose, like `\label`, `\ref`, `\cite`, `\includegraphics` and
+//!    `\vspace`, are dropped with their args
+//! 1. Other commands, like `\emph` and `\section`, keep the text of their curly bracket args.
+//!    Square bracket args (options) are dropped
+//! 1. Math, inline or display, is replaced by a placeholder, so that the sentence around it stays
+//!    a sentence
+//! 1. Headings, items, environments and paragraphs are separated by empty lines, footnotes are
+//!    moved to the end
+//! 1. Verbatim, tabular, tikzpicture and comment environments are dropped
+//!
+//! If there is a document environment, only its content is extracted. The macros defined in the
+//! file are expanded first, see expand.
+//!
+//! Along with the text, a source map is produced: the text is made of segments, each of which
+//! comes from a span of the source. A verbatim segment (a word) is its source copied and maps
+//! offset by offset, others (placeholders, spaces, escaped characters) map to the start of their
+//! source. The text of a macro expansion comes from the whole call.
+
+use std::error::Error;
+
+use super::ast::{Node, NodePtr, NodeType, Span};
+use super::expand::Expander;
+use super::include_graph::parse_file;
+use super::symbols::SymbolTable;
+use super::wordcount::section_title;
+use crate::utils::FileInput;
+
+#[derive(Debug)]
+pub struct DetexOptions {
+    /// The text replacing math
+    pub math_placeholder: String,
+}
+
+impl Default for DetexOptions {
+    fn default() -> Self {
+        DetexOptions {
+            math_placeholder: "X".to_string(),
+        }
+    }
+}
+
+/// A piece of the output text, `out`, produced from the source text `src`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub out: Span,
+    pub src: Span,
+    /// Whether the output is the source copied, so that the offsets map one to one
+    pub verbatim: bool,
+}
+
+#[derive(Debug)]
+pub struct Detexed {
+    pub text: String,
+    /// In the order of the output
+    pub segments: Vec<Segment>,
+}
+
+/// Commands dropped with their args
+pub(crate) const NON_PROSE: [&str; 47] = [
+    "label",
+    "ref",
+    "eqref",
+    "autoref",
+    "cref",
+    "Cref",
+    "pageref",
+    "nameref",
+    "cite",
+    "citep",
+    "citet",
+    "parencite",
+    "textcite",
+    "autocite",
+    "footcite",
+    "nocite",
+    "url",
+    "input",
+    "include",
+    "subfile",
+    "import",
+    "subimport",
+    "includegraphics",
+    "bibliography",
+    "bibliographystyle",
+    "addbibresource",
+    "printbibliography",
+    "documentclass",
+    "usepackage",
+    "newcommand",
+    "renewcommand",
+    "providecommand",
+    "newenvironment",
+    "newtheorem",
+    "theoremstyle",
+    "DeclareMathOperator",
+    "setlength",
+    "setcounter",
+    "addtocounter",
+    "vspace",
+    "hspace",
+    "hypersetup",
+    "graphicspath",
+    "pagestyle",
+    "thispagestyle",
+    "index",
+    "color",
+];
+
+const SECTIONS: [&str; 8] = [
+    "part",
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+    "caption",
+];
+
+pub(crate) const MATH_ENVRS: [&str; 15] = [
+    "equation",
+    "equation*",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "flalign",
+    "flalign*",
+    "eqnarray",
+    "eqnarray*",
+    "displaymath",
+    "math",
+    "split",
+];
+
+pub(crate) const DROPPED_ENVRS: [&str; 11] = [
+    "verbatim",
+    "Verbatim",
+    "lstlisting",
+    "minted",
+    "comment",
+    "tabular",
+    "tabular*",
+    "tabularx",
+    "array",
+    "tikzpicture",
+    "thebibliography",
+];
+
+/// The output with its segments
+#[derive(Debug, Default)]
+struct Writer {
+    text: String,
+    segments: Vec<Segment>,
+    /// The source offset of a space to be written before the next text
+    space: Option<usize>,
+}
+
+impl Writer {
+    fn raw(&mut self, s: &str, src: Span, verbatim: bool) {
+        let start = self.text.len();
+        self.text.push_str(s);
+        self.segments.push(Segment {
+            out: Span::new(start, self.text.len()),
+            src,
+            verbatim,
+        });
+    }
+
+    fn write(&mut self, s: &str, src: Span, verbatim: bool) {
+        if let Some(at) = self.space.take() {
+            if !self.text.is_empty() && !self.text.ends_with('\n') {
+                self.raw(" ", Span::new(at, at), false);
+            }
+        }
+        self.raw(s, src, verbatim);
+    }
+
+    fn push(&mut self, s: &str, src: Span) {
+        self.write(s, src, false);
+    }
+
+    /// Write s, the source at src copied
+    fn copy(&mut self, s: &str, src: Span) {
+        self.write(s, src, true);
+    }
+
+    /// Consecutive spaces are written once
+    fn space(&mut self, at: usize) {
+        self.space.get_or_insert(at);
+    }
+
+    fn paragraph_break(&mut self, at: usize) {
+        self.space = None;
+        if self.text.is_empty() || self.text.ends_with("\n\n") {
+            return;
+        }
+        let newlines = if self.text.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        };
+        self.raw(newlines, Span::new(at, at), false);
+    }
+
+    fn append(&mut self, other: Writer) {
+        let shift = self.text.len();
+        self.text.push_str(&other.text);
+        self.segments
+            .extend(other.segments.into_iter().map(|s| Segment {
+                out: Span::new(s.out.start + shift, s.out.end + shift),
+                src: s.src,
+                verbatim: s.verbatim,
+            }));
+    }
+}
+
+struct DetexState<'a> {
+    source: &'a str,
+    options: &'a DetexOptions,
+    writer: Writer,
+    footnotes: Vec<Writer>,
+}
+
+/// Extract the prose of file_input, see the module doc
+pub fn detex(
+    file_input: &FileInput,
+    options: &DetexOptions,
+) -> Result<Detexed, Box<dyn Error>> {
+    let ast = parse_file(file_input)?;
+    let symbols = SymbolTable::from_file(file_input, &ast);
+    let expanded = Expander::new(&symbols).expand(file_input, &ast);
+    let ast = if expanded.edits.is_empty() {
+        ast
+    } else {
+        parse_file(&expanded.file_input)?
+    };
+    let root = Node::find_document_body_nodeptr(ast.clone()).unwrap_or(ast);
+    let mut state = DetexState {
+        source: expanded.file_input.get_str_content(),
+        options,
+        writer: Writer::default(),
+        footnotes: vec![],
+    };
+    passage(&root, &mut state);
+
+    let mut writer = state.writer;
+    for footnote in state.footnotes {
+        let at = footnote.segments.first().map_or(0, |s| s.src.start);
+        writer.paragraph_break(at);
+        writer.append(footnote);
+    }
+    // end with a single newline
+    while writer.text.ends_with("\n\n") {
+        writer.text.pop();
+        let len = writer.text.len();
+        if let Some(last) = writer.segments.last_mut() {
+            last.out.end = last.out.end.min(len);
+            if last.out.start == last.out.end {
+                writer.segments.pop();
+            }
+        }
+    }
+    if !writer.text.is_empty() && !writer.text.ends_with('\n') {
+        let at = writer.segments.last().map_or(0, |s| s.src.end);
+        writer.raw("\n", Span::new(at, at), false);
+    }
+
+    let source = file_input.get_str_content();
+    for segment in writer.segments.iter_mut() {
+        segment.src = expanded.source_span(segment.src);
+        // the text of an expansion is not in the source
+        segment.verbatim &= source.get(segment.src.start..segment.src.end)
+            == writer.text.get(segment.out.start..segment.out.end);
+    }
+    Ok(Detexed {
+        text: writer.text,
+        segments: writer.segments,
+    })
+}
+
+fn children_of(node: &NodePtr) -> Vec<NodePtr> {
+    node.lock().unwrap().children.clone()
+}
+
+fn passage(node: &NodePtr, state: &mut DetexState) {
+    for paragraph in children_of(node) {
+        nodes(&children_of(&paragraph), state);
+        let end = paragraph.lock().unwrap().span.end;
+        state.writer.paragraph_break(end);
+    }
+}
+
+/// The nodes of a paragraph, with the spaces between them
+fn nodes(nodes: &[NodePtr], state: &mut DetexState) {
+    let mut prev_end: Option<usize> = None;
+    let mut i = 0;
+    while i < nodes.len() {
+        let n = &nodes[i];
+        let (node_type, lexeme, mut span) = {
+            let n = n.lock().unwrap();
+            (n.node_type.clone(), n.lexeme.clone(), n.span)
+        };
+        i += 1;
+        if node_type == NodeType::Comment {
+            continue;
+        }
+        if let Some(prev_end) = prev_end {
+            // `word%comment` joins the words, as latex does
+            if state.source[prev_end..span.start]
+                .starts_with(char::is_whitespace)
+            {
+                state.writer.space(prev_end);
+            }
+        }
+        if node_type == NodeType::Command && SECTIONS.contains(&lexeme.as_str())
+        {
+            let (title, skipped) = section_title(nodes, i - 1);
+            i += skipped;
+            if let Some(title) = &title {
+                span.end = span.end.max(title.lock().unwrap().span.end);
+            }
+            state.writer.paragraph_break(span.start);
+            if let Some(title) = &title {
+                arg(title, state);
+            }
+            state.writer.paragraph_break(span.end);
+        } else {
+            node(n, state);
+        }
+        prev_end = Some(span.end);
+    }
+}
+
+/// The nodes of the paragraph in a bracket arg
+fn arg(arg: &NodePtr, state: &mut DetexState) {
+    for paragraph in children_of(arg) {
+        nodes(&children_of(&paragraph), state);
+    }
+}
+
+fn node(n: &NodePtr, state: &mut DetexState) {
+    let (node_type, lexeme, span) = {
+        let n = n.lock().unwrap();
+        (n.node_type.clone(), n.lexeme.clone(), n.span)
+    };
+    match node_type {
+        NodeType::Word => match lexeme.as_str() {
+            // the line break `\\` and the forced space `\ `
+            "\n" | " " | "\\ " => state.writer.space(span.start),
+            _ => {
+                let text = match lexeme.strip_prefix('\\') {
+                    Some(c) => c.to_string(),
+                    None => lexeme.replace("``", "\"").replace("''", "\""),
+                };
+                if text == state.source[span.start..span.end] {
+                    state.writer.copy(&text, span);
+                } else {
+                    state.writer.push(&text, span);
+                }
+            }
+        },
+        NodeType::Operation if lexeme == "~" => state.writer.space(span.start),
+        NodeType::Operation | NodeType::Parameter => {
+            state.writer.copy(&state.source[span.start..span.end], span)
+        }
+        NodeType::InlineMath | NodeType::DisplayMath => {
+            let placeholder = state.options.math_placeholder.clone();
+            state.writer.push(&placeholder, span);
+        }
+        NodeType::Ampersand | NodeType::DoubleBackSlash => {
+            state.writer.space(span.start)
+        }
+        NodeType::Comment => {}
+        NodeType::CurlyBracketArg => arg(n, state),
+        NodeType::SquareBracketArg => {
+            state
+                .writer
+                .push("[", Span::new(span.start, span.start + 1));
+            arg(n, state);
+            state.writer.push("]", Span::new(span.end - 1, span.end));
+        }
+        NodeType::Passage => passage(n, state),
+        NodeType::Paragraph => nodes(&children_of(n), state),
+        NodeType::Command => command(n, &lexeme, span, state),
+        NodeType::Envr => envr(n, &lexeme, span, state),
+    }
+}
+
+fn command(n: &NodePtr, name: &str, span: Span, state: &mut DetexState) {
+    let args = n.lock().unwrap().get_curly_bracket_args();
+    match name {
+        // `cano~\cite{key},` gives `cano,`
+        _ if NON_PROSE.contains(&name) => state.writer.space = None,
+        "item" => {
+            state.writer.paragraph_break(span.start);
+            for a in &args {
+                arg(a, state);
+            }
+        }
+        "footnote" => {
+            let writer = std::mem::take(&mut state.writer);
+            for a in &args {
+                arg(a, state);
+            }
+            let footnote = std::mem::replace(&mut state.writer, writer);
+            state.footnotes.push(footnote);
+        }
+        // the link text, without the url
+        "href" => {
+            if let Some(text) = args.get(1) {
+                arg(text, state);
+            }
+        }
+        "LaTeX" | "TeX" => state.writer.push(name, span),
+        "ldots" | "dots" | "textellipsis" => state.writer.push("...", span),
+        _ => {
+            for a in &args {
+                arg(a, state);
+            }
+        }
+    }
+}
+
+fn envr(n: &NodePtr, name: &str, span: Span, state: &mut DetexState) {
+    if MATH_ENVRS.contains(&name) {
+        let placeholder = state.options.math_placeholder.clone();
+        state.writer.push(&placeholder, span);
+        return;
+    }
+    if DROPPED_ENVRS.contains(&name) {
+        state.writer.space(span.start);
+        return;
+    }
+    state.writer.paragraph_break(span.start);
+    if let Some(body) = Node::get_nth_child_nodeptr(n.clone(), 0) {
+        passage(&body, state);
+    }
+    state.writer.paragraph_break(span.end);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The source offset an output offset is produced from
+    fn source_offset(detexed: &Detexed, offset: usize) -> usize {
+        let i = detexed.segments.partition_point(|s| s.out.end <= offset);
+        let segment = detexed.segments[i];
+        if segment.verbatim {
+            segment.src.start + offset - segment.out.start
+        } else {
+            segment.src.start
+        }
+    }
+
+    #[test]
+    fn test_detex() {
+        let source = r##"\documentclass{article}
+\begin{document}
+\section{Intro}\label{sec:intro}
+Arma \emph{virumque} cano~\cite{vergil}, see
+Section~\ref{sec:intro} and $x^2$.\footnote{A \textbf{note}.}
+50\% of it% comment
+.
+
+\begin{itemize}
+  \item[a] one
+  \item two
+\end{itemize}
+\begin{equation}
+  e = mc^2
+\end{equation}
+\end{document}
+"##;
+        let detexed = detex(
+            &FileInput::from_str("test.tex", source),
+            &DetexOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            detexed.text,
+            "Intro\n\nArma virumque cano, see Section and X. 50% of it.\n\none\n\ntwo\n\nX\n\nA note.\n"
+        );
+    }
+
+    #[test]
+    fn test_source_map() {
+        let source = "\\begin{document}\nArma \\emph{virumque}\n$x$ cano\\footnote{Troiae}\n\\end{document}";
+        let file_input = FileInput::from_str("test.tex", source);
+        let detexed = detex(&file_input, &DetexOptions::default()).unwrap();
+        assert_eq!(detexed.text, "Arma virumque X cano\n\nTroiae\n");
+
+        let locate = |word: &str| {
+            let offset = detexed.text.find(word).unwrap();
+            file_input.get_row_col(source_offset(&detexed, offset))
+        };
+        assert_eq!(locate("virumque"), (1, 11));
+        assert_eq!(locate("X"), (2, 0));
+        assert_eq!(locate("cano"), (2, 4));
+        assert_eq!(locate("Troiae"), (2, 18));
+        // inside a word
+        let offset = detexed.text.find("umque").unwrap();
+        let at = source_offset(&detexed, offset);
+        assert_eq!(&source[at..at + 5], "umque");
+
+        // a placeholder of the length of its math is not the source
+        let options = DetexOptions {
+            math_placeholder: "MATH".to_string(),
+        };
+        let file_input = FileInput::from_str("test.tex", "Let $ab$ be");
+        let detexed = detex(&file_input, &options).unwrap();
+        assert_eq!(detexed.text, "Let MATH be\n");
+        let offset = detexed.text.find("ATH").unwrap();
+        assert_eq!(source_offset(&detexed, offset), 4);
+
+        // the title of a starred section
+        let source = "\\section*{Starred one}\nSix of them.";
+        let file_input = FileInput::from_str("test.tex", source);
+        let detexed = detex(&file_input, &DetexOptions::default()).unwrap();
+        assert_eq!(detexed.text, "Starred one\n\nSix of them.\n");
+        let offset = detexed.text.find("one").unwrap();
+        assert_eq!(&source[source_offset(&detexed, offset)..][..3], "one");
+        let offset = detexed.text.find("Six").unwrap();
+        assert_eq!(
+            file_input.get_row_col(source_offset(&detexed, offset)),
+            (1, 0)
+        );
+    }
+    #[test]
+    fn test_macros() {
+        let source = "\\newcommand\\poet[1]{Publius #1 Maro}\n\\begin{document}\nArma \\poet{Vergilius} cano\n\\end{document}";
+        let file_input = FileInput::from_str("test.tex", source);
+        let detexed = detex(&file_input, &DetexOptions::default()).unwrap();
+        assert_eq!(detexed.text, "Arma Publius Vergilius Maro cano\n");
+
+        // the expansion comes from the call
+        let locate = |word: &str| {
+            let offset = detexed.text.find(word).unwrap();
+            file_input.get_row_col(source_offset(&detexed, offset))
+        };
+        assert_eq!(locate("Vergilius"), (2, 5));
+        assert_eq!(locate("cano"), (2, 22));
+
+        // the spaces of a body, and `##` giving `#`
+        let source = "\\newcommand{\\foo}{Hello }\n\\newcommand{\\bar}[1]{#1 }\n\\newcommand{\\hash}{a##b}\n\\begin{document}\n\\foo world and \\bar{x}y. \\hash\n\\end{document}";
+        let file_input = FileInput::from_str("test.tex", source);
+        let detexed = detex(&file_input, &DetexOptions::default()).unwrap();
+        assert_eq!(detexed.text, "Hello world and x y. a#b\n");
+    }
+}
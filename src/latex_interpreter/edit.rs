@@ -0,0 +1,81 @@
+//! Edits on the source text.
+//!
+//! The AST does not keep the spacing and line breaks of the source, so regenerating the text from
+//! the AST changes far more than intended. Transformations which shall leave everything else
+//! untouched (flattening, stripping comments, etc) instead describe their changes as Edits on
+//! the spans of the nodes, which are then applied to the original source text.
+
+use super::token::Span;
+
+/// Replace the text in span by replacement
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Edit {
+    pub fn new(span: Span, replacement: &str) -> Self {
+        Edit {
+            span,
+            replacement: replacement.to_string(),
+        }
+    }
+
+    /// An edit deleting the text in span
+    pub fn delete(span: Span) -> Self {
+        Edit::new(span, "")
+    }
+}
+
+/// Apply the edits to source, and return the edited text
+/// The order of edits does not matter. If two edits overlap, the one starting later is dropped.
+pub fn apply_edits(source: &str, edits: &[Edit]) -> String {
+    apply_edits_in(source, Span::new(0, source.len()), edits)
+}
+
+/// Same as apply_edits, but only the text in range is returned, and edits not lying in range are
+/// ignored
+pub fn apply_edits_in(source: &str, range: Span, edits: &[Edit]) -> String {
+    let mut edits: Vec<&Edit> = edits
+        .iter()
+        .filter(|e| e.span.start >= range.start && e.span.end <= range.end)
+        .collect();
+    edits.sort_by_key(|e| (e.span.start, e.span.end));
+
+    let mut ret = String::new();
+    let mut cur = range.start;
+    for edit in edits {
+        if edit.span.start < cur {
+            debug!("Overlapping edit dropped: {:?}", edit);
+            continue;
+        }
+        ret.push_str(&source[cur..edit.span.start]);
+        ret.push_str(&edit.replacement);
+        cur = edit.span.end;
+    }
+    ret.push_str(&source[cur..range.end]);
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_apply_edits() {
+        let source = "arma virumque cano";
+        let edits = vec![
+            Edit::new(Span::new(14, 18), "canto"),
+            Edit::delete(Span::new(4, 13)),
+            // overlaps with the deletion
+            Edit::new(Span::new(5, 8), "xxx"),
+        ];
+        assert_eq!(apply_edits(source, &edits), "arma canto");
+        // the deletion does not lie in the range, so it is ignored
+        assert_eq!(
+            apply_edits_in(source, Span::new(5, 18), &edits),
+            "xxxumque canto"
+        );
+    }
+}
@@ -0,0 +1,369 @@
+//! A limited expansion of the macros defined by the document
+//!
+//! The calls of the commands of the symbol table (`\newcommand`, `\def`, `\NewDocumentCommand`,
+//! ...) are replaced by their replacement text with `#1`, `#2`, ... substituted by the args, so
+//! that the tools reading the prose (detex, wordcount, tex2md) see the text the macros stand for.
+//! 1. The args are the bracket args after the command, as in `\pair[a]{b}`. An optional arg not
+//!    given gets its default. A call missing a required arg is left as it is
+//! 1. The args are expanded first, then the substituted text is expanded again, so that macros
+//!    using other macros are expanded in full
+//! 1. A macro met again in its own expansion is recursive and is left as it is, as is a call
+//!    nested more than MAX_DEPTH levels deep
+//! 1. The commands the document does not define, primitives and those of packages, are left
+//!    untouched, as are the definitions themselves
+//!
+//! Like the other transformations of the source, the expansion of a file is a list of Edits,
+//! each replacing a call with its args, see edit.
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use super::ast::{NodePtr, NodeType, Span};
+use super::edit::{apply_edits, apply_edits_in, Edit};
+use super::include_graph::parse_file;
+use super::symbols::{Args, Param, SymbolTable};
+use crate::utils::FileInput;
+
+/// How deep the expansions of macros in the expansions of macros can go
+pub const MAX_DEPTH: usize = 32;
+
+/// A file with its macro calls expanded
+#[derive(Debug)]
+pub struct Expanded {
+    /// The expanded text, with the path of the source
+    pub file_input: FileInput,
+    /// The edits on the source giving the text, in order
+    pub edits: Vec<Edit>,
+}
+
+impl Expanded {
+    /// The span of the source a span of the expanded text is produced from
+    /// The text of an expansion is produced from the whole call
+    pub fn source_span(&self, span: Span) -> Span {
+        let start = self.source_offset(span.start, false);
+        let end = self.source_offset(span.end, true);
+        Span::new(start, end.max(start))
+    }
+
+    /// The source offset of an offset of the expanded text, which is the start of a span if
+    /// !end, and its end if end
+    fn source_offset(&self, offset: usize, end: bool) -> usize {
+        // the offsets of the source and the text after the last edit
+        let (mut src, mut out) = (0, 0);
+        for edit in &self.edits {
+            let out_start = out + edit.span.start - src;
+            let out_end = out_start + edit.replacement.len();
+            if offset < out_start || (end && offset == out_start) {
+                break;
+            }
+            if offset < out_end || (end && offset == out_end) {
+                return if end { edit.span.end } else { edit.span.start };
+            }
+            (src, out) = (edit.span.end, out_end);
+        }
+        src + offset - out
+    }
+}
+
+pub struct Expander<'a> {
+    symbols: &'a SymbolTable,
+    /// Where the names and bodies of the definitions start, which are not expanded
+    definitions: HashSet<(PathBuf, usize)>,
+}
+
+impl<'a> Expander<'a> {
+    pub fn new(symbols: &'a SymbolTable) -> Self {
+        let mut definitions = HashSet::new();
+        for d in symbols.commands.iter().chain(&symbols.environments) {
+            let file = d.occurrence.file.clone();
+            definitions.insert((file.clone(), d.occurrence.span.start));
+            for body in d.end.iter().chain([&d.body]) {
+                let start = body.lock().unwrap().span.start;
+                definitions.insert((file.clone(), start));
+            }
+        }
+        Expander {
+            symbols,
+            definitions,
+        }
+    }
+
+    /// Expand the macro calls of file_input, whose AST is ast
+    pub fn expand(&self, file_input: &FileInput, ast: &NodePtr) -> Expanded {
+        let mut edits = vec![];
+        self.calls(file_input, ast, &mut vec![], &mut edits);
+        let text = apply_edits(file_input.get_str_content(), &edits);
+        Expanded {
+            file_input: FileInput::from_str(
+                &file_input.get_file_path().to_string_lossy(),
+                &text,
+            ),
+            edits,
+        }
+    }
+
+    /// The edit replacing the command siblings[i] of file_input and its args by its expansion,
+    /// None if it is not a call of a macro of the table, or if it can not be expanded
+    pub fn expand_call(
+        &self,
+        file_input: &FileInput,
+        siblings: &[NodePtr],
+        i: usize,
+    ) -> Option<Edit> {
+        self.call(file_input, siblings, i, &mut vec![])
+    }
+
+    /// Push the edits expanding the calls in node, stack being the macros being expanded
+    fn calls(
+        &self,
+        file_input: &FileInput,
+        node: &NodePtr,
+        stack: &mut Vec<String>,
+        edits: &mut Vec<Edit>,
+    ) {
+        let children = node.lock().unwrap().children.clone();
+        // the end of the last call, whose args are not visited again
+        let mut call_end = 0;
+        for (i, child) in children.iter().enumerate() {
+            let (node_type, span) = {
+                let child = child.lock().unwrap();
+                (child.node_type.clone(), child.span)
+            };
+            if span.start < call_end {
+                continue;
+            }
+            if node_type == NodeType::Command {
+                if let Some(edit) = self.call(file_input, &children, i, stack) {
+                    call_end = edit.span.end;
+                    edits.push(edit);
+                    continue;
+                }
+            }
+            let in_definition = matches!(
+                node_type,
+                NodeType::Command | NodeType::CurlyBracketArg
+            ) && self
+                .definitions
+                .contains(&(file_input.get_file_path().clone(), span.start));
+            if !in_definition {
+                self.calls(file_input, child, stack, edits);
+            }
+        }
+    }
+
+    fn call(
+        &self,
+        file_input: &FileInput,
+        siblings: &[NodePtr],
+        i: usize,
+        stack: &mut Vec<String>,
+    ) -> Option<Edit> {
+        let (name, start) = {
+            let command = siblings[i].lock().unwrap();
+            if command.node_type != NodeType::Command {
+                return None;
+            }
+            (command.lexeme.clone(), command.span.start)
+        };
+        let path = file_input.get_file_path().clone();
+        if self.definitions.contains(&(path, start)) {
+            return None;
+        }
+        let definition = self.symbols.command(&name)?;
+        if stack.contains(&name) {
+            warn!("Recursive macro `\\{}` is not expanded", name);
+            return None;
+        }
+        if stack.len() >= MAX_DEPTH {
+            warn!(
+                "Macro `\\{}` is nested more than {} levels deep, and is not expanded",
+                name, MAX_DEPTH
+            );
+            return None;
+        }
+
+        let mut args = Args::new(siblings, i);
+        let mut values: Vec<String> = vec![];
+        for param in &definition.params {
+            let arg = match param {
+                Param::Required => Some(args.curly()?),
+                Param::Optional(_) => args.square(),
+                Param::Star => None,
+            };
+            let value = match (param, arg) {
+                (_, Some(arg)) => {
+                    let span = arg.lock().unwrap().get_content_span();
+                    let mut edits = vec![];
+                    self.calls(file_input, &arg, stack, &mut edits);
+                    apply_edits_in(file_input.get_str_content(), span, &edits)
+                }
+                // the value of an optional arg of xparse without default
+                (Param::Optional(default), None) => {
+                    default.clone().unwrap_or("-NoValue-".to_string())
+                }
+                (_, None) if args.star() => "\\BooleanTrue".to_string(),
+                (_, None) => "\\BooleanFalse".to_string(),
+            };
+            values.push(value);
+        }
+        let end = args.end();
+
+        stack.push(name.clone());
+        let text = self
+            .expand_text(&substitute(&definition.replacement, &values), stack);
+        stack.pop();
+
+        let end = match end {
+            Some(end) => end,
+            // the spaces after a control word are skipped, as in tex, unless the text ends with
+            // a control word, which the next letter would join
+            None if name.chars().all(|c| c.is_ascii_alphabetic())
+                && !ends_with_control_word(&text) =>
+            {
+                space_end(file_input.get_str_content(), start + name.len() + 1)
+            }
+            None => start + name.len() + 1,
+        };
+        Some(Edit::new(Span::new(start, end), &text))
+    }
+
+    /// Expand the macro calls in text, stack being the macros being expanded
+    fn expand_text(&self, text: &str, stack: &mut Vec<String>) -> String {
+        let file_input = FileInput::from_str("expansion", text);
+        let Ok(ast) = parse_file(&file_input) else {
+            return text.to_string();
+        };
+        let mut edits = vec![];
+        self.calls(&file_input, &ast, stack, &mut edits);
+        apply_edits(text, &edits)
+    }
+}
+
+/// The end of the spaces of source from end, with a single new line, but not an empty line
+fn space_end(source: &str, end: usize) -> usize {
+    let rest = &source[end..];
+    let after = rest.trim_start_matches([' ', '\t']);
+    if let Some(next) = after.strip_prefix('\n') {
+        let next = next.trim_start_matches([' ', '\t']);
+        if !next.starts_with('\n') {
+            return source.len() - next.len();
+        }
+    }
+    source.len() - after.len()
+}
+
+/// Whether text ends with a control word, as `\foo`
+fn ends_with_control_word(text: &str) -> bool {
+    let rest = text.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    rest.len() < text.len() && rest.ends_with('\\')
+}
+
+/// The replacement text with `#1`, `#2`, ... replaced by the args, and `##` by `#`
+fn substitute(replacement: &str, args: &[String]) -> String {
+    let mut ret = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '#' {
+            ret.push(c);
+            continue;
+        }
+        if chars.next_if_eq(&'#').is_some() {
+            ret.push('#');
+        } else if let Some(n) = chars.peek().and_then(|d| d.to_digit(10)) {
+            chars.next();
+            let arg = (n as usize).checked_sub(1).and_then(|i| args.get(i));
+            ret.push_str(arg.map_or("", |a| a.as_str()));
+        } else {
+            ret.push('#');
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn expand_str(source: &str) -> Expanded {
+        let file_input = FileInput::from_str("test.tex", source);
+        let ast = parse_file(&file_input).unwrap();
+        let symbols = SymbolTable::from_file(&file_input, &ast);
+        Expander::new(&symbols).expand(&file_input, &ast)
+    }
+
+    #[test]
+    fn test_expand() {
+        let expanded = expand_str(
+            r"\newcommand{\R}{\mathbb{R}}
+\newcommand\pair[2][x]{(#1, #2)}
+\def\abs#1{|#1|}
+\NewDocumentCommand{\norm}{s O{2} m}{\IfBooleanTF{#1}{}{\|#3\|_{#2}}}
+\newcommand\loop{a \loop}
+\begin{document}
+$\R$, $\pair{\abs{y}}$ and $\pair[z] {w}$, $\norm*[p]{v}$ \norm{u}
+\pair \loop \emph{\R}
+\end{document}",
+        );
+        let text = expanded.file_input.get_str_content();
+        let body = &text[text.find("\\begin{document}").unwrap()..];
+        assert_eq!(
+            body,
+            r"\begin{document}
+$\mathbb{R}$, $(x, |y|)$ and $(z, w)$, $\IfBooleanTF{\BooleanTrue}{}{\|v\|_{p}}$ \IfBooleanTF{\BooleanFalse}{}{\|u\|_{2}}
+\pair a \loop \emph{\mathbb{R}}
+\end{document}"
+        );
+        // the definitions are left untouched
+        assert!(text.starts_with(r"\newcommand{\R}{\mathbb{R}}"));
+    }
+
+    #[test]
+    fn test_body_text() {
+        let expanded = expand_str(
+            r"\newcommand{\foo}{Hello }
+\newcommand{\bar}[1]{#1 }
+\newcommand{\hash}{a##b}
+\newcommand{\none}{}
+\foo world and \bar{x}y, \hash, [\none]",
+        );
+        let text = expanded.file_input.get_str_content();
+        assert_eq!(
+            text.lines().last().unwrap(),
+            "Hello world and x y, a#b, []"
+        );
+
+        // a control word at the end of the text keeps the space, an empty line is kept
+        let expanded = expand_str(
+            "\\newcommand{\\foo}{\\relax}\n\\newcommand{\\bar}{a}\n\\foo y \\bar\n\nz",
+        );
+        let text = expanded.file_input.get_str_content();
+        assert!(text.ends_with("\\relax y a\n\nz"), "{}", text);
+    }
+
+    #[test]
+    fn test_source_span() {
+        let source = "\\newcommand\\ab{a b c}\nx \\ab{} y";
+        let expanded = expand_str(source);
+        assert_eq!(
+            expanded.file_input.get_str_content(),
+            "\\newcommand\\ab{a b c}\nx a b c{} y"
+        );
+        let call = source.find("\\ab{}").unwrap();
+        // before the call
+        assert_eq!(
+            expanded.source_span(Span::new(call - 2, call - 1)),
+            Span::new(call - 2, call - 1)
+        );
+        // in the expansion
+        assert_eq!(
+            expanded.source_span(Span::new(call + 2, call + 3)),
+            Span::new(call, call + 3)
+        );
+        // after the call, 2 bytes further
+        let y = source.len() - 1;
+        assert_eq!(
+            expanded.source_span(Span::new(y + 2, y + 3)),
+            Span::new(y, y + 1)
+        );
+    }
+}
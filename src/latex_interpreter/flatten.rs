@@ -0,0 +1,296 @@
+//! Flatten a multi-file document into a single self-contained source, as required by many
+//! journals and arXiv.
+//!
+//! Starting from the main file, the include commands found by the include graph are replaced by
+//! the (flattened) content of the included files:
+//! 1. `\input{file}` and `\import{dir}{file}` are replaced by the content of the file
+//! 1. `\include{file}` is replaced by the content of the file between two `\clearpage`, and
+//!    nothing if the file is excluded by `\includeonly`
+//! 1. `\subfile{file}` is replaced by the body of the document environment of the file
+//! 1. Commands including missing files are kept as they are
+//!
+//! Optionally, comments are stripped (see comment.rs) and the `.bbl` replaces `\bibliography`.
+//!
+//! Paths of `\includegraphics` are rewritten if they no longer resolve from the flattened file,
+//! which happens for files brought in by `\import`. The figures used are returned so that they can
+//! be copied along with the flattened file.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use super::ast::{Node, NodeType, Span};
+use super::comment::{comment_edits, StripOptions};
+use super::edit::{apply_edits_in, Edit};
+use super::graphics::{find_graphics, graphics_path, resolve_graphic};
+use super::include_graph::{IncludeGraph, IncludeKind, SourceFile};
+
+#[derive(Debug, Default)]
+pub struct FlattenOptions {
+    pub strip_comments: bool,
+    /// The content of the .bbl file, which replaces `\bibliography{...}` if given
+    pub bbl: Option<String>,
+}
+
+/// A figure used by the document
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Figure {
+    /// The figure file
+    pub source: PathBuf,
+    /// The path to copy the figure to, relative to the directory of the flattened file
+    pub dest: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct Flattened {
+    pub text: String,
+    /// Figures used by the document, each appears once
+    pub figures: Vec<Figure>,
+    /// True if the .bbl is inlined in place of `\bibliography`
+    pub bbl_inlined: bool,
+}
+
+/// Context passed along the recursion
+struct FlattenState<'a> {
+    graph: &'a IncludeGraph,
+    options: &'a FlattenOptions,
+    /// The directory of the main file, where the flattened file is compiled
+    main_dir: PathBuf,
+    graphics_path: Vec<PathBuf>,
+    figures: Vec<Figure>,
+    bbl_inlined: bool,
+}
+
+pub fn flatten(
+    graph: &IncludeGraph,
+    options: &FlattenOptions,
+) -> Result<Flattened, Box<dyn Error>> {
+    let main = graph.get_main_file();
+    let mut state = FlattenState {
+        graph,
+        options,
+        main_dir: main.input_dir.clone(),
+        graphics_path: graphics_path(graph),
+        figures: vec![],
+        bbl_inlined: false,
+    };
+
+    let text = flatten_file(main, false, &mut state)?;
+
+    let mut seen: HashSet<Figure> = HashSet::new();
+    let figures = state
+        .figures
+        .into_iter()
+        .filter(|f| seen.insert(f.clone()))
+        .collect();
+
+    Ok(Flattened {
+        text,
+        figures,
+        bbl_inlined: state.bbl_inlined,
+    })
+}
+
+/// Return the flattened text of file
+/// If body_only, only the body of the document environment is returned (used for subfiles)
+fn flatten_file(
+    file: &SourceFile,
+    body_only: bool,
+    state: &mut FlattenState,
+) -> Result<String, Box<dyn Error>> {
+    let source = file.file_input.get_str_content();
+    // figures of the file come before those of its includes
+    let mut edits: Vec<Edit> = graphics_edits(file, state);
+
+    for inc in &file.includes {
+        if inc.excluded {
+            edits.push(Edit::delete(inc.span));
+            continue;
+        }
+        let child = match &inc.resolved {
+            Some(path) => state.graph.get_file(path),
+            None => None,
+        };
+        let child = match child {
+            Some(c) => c,
+            None => continue,
+        };
+        let text = match inc.kind {
+            IncludeKind::Subfile => flatten_file(child, true, state)?,
+            IncludeKind::Include => {
+                format!(
+                    "\\clearpage\n{}\n\\clearpage",
+                    flatten_file(child, false, state)?
+                )
+            }
+            _ => flatten_file(child, false, state)?,
+        };
+        edits.push(Edit::new(inc.span, &text));
+    }
+
+    if let Some(bbl) = &state.options.bbl {
+        for node in
+            Node::find_commands_nodeptr(file.ast.clone(), "bibliography")
+        {
+            edits.push(Edit::new(Node::get_span_nodeptr(node), bbl.trim_end()));
+            state.bbl_inlined = true;
+        }
+    }
+
+    if state.options.strip_comments {
+        let options = StripOptions::default();
+        edits.extend(comment_edits(file.ast.clone(), source, &options));
+    }
+
+    let range = if body_only {
+        document_body_span(file).unwrap_or(Span::new(0, source.len()))
+    } else {
+        Span::new(0, source.len())
+    };
+
+    Ok(apply_edits_in(source, range, &edits))
+}
+
+/// Record the figures used in file, and return the edits rewriting the paths which do not
+/// resolve to the same figure from the main directory
+fn graphics_edits(file: &SourceFile, state: &mut FlattenState) -> Vec<Edit> {
+    let mut edits: Vec<Edit> = vec![];
+    let mut main_dirs = vec![state.main_dir.clone()];
+    main_dirs.extend(state.graphics_path.iter().cloned());
+
+    for graphic in find_graphics(file, &state.graphics_path) {
+        let source = match graphic.resolved {
+            Some(s) => s,
+            None => {
+                warn!(
+                    "{}: figure `{}` not found",
+                    file.get_path().display(),
+                    graphic.target
+                );
+                continue;
+            }
+        };
+
+        // figures outside the main directory are copied next to the flattened file
+        let dest = match source.strip_prefix(&state.main_dir) {
+            Ok(rel) if !rel.starts_with("..") => rel.to_path_buf(),
+            _ => PathBuf::from(source.file_name().unwrap_or_default()),
+        };
+
+        let resolved_from_main = resolve_graphic(&graphic.target, &main_dirs);
+        let dest_dir =
+            state.main_dir.join(dest.parent().unwrap_or(Path::new("")));
+        let unchanged = resolved_from_main.as_ref() == Some(&source)
+            && dest_dir == source.parent().unwrap_or(Path::new(""));
+        if !unchanged {
+            let mut new_target = dest.clone();
+            if Path::new(&graphic.target).extension().is_none() {
+                new_target.set_extension("");
+            }
+            edits.push(Edit::new(
+                graphic.span,
+                &new_target.display().to_string(),
+            ));
+        }
+
+        state.figures.push(Figure { source, dest });
+    }
+    edits
+}
+
+/// The span of the body of the document environment, excluding `\begin{document}` and
+/// `\end{document}`
+fn document_body_span(file: &SourceFile) -> Option<Span> {
+    let envrs = Node::find_all_nodeptr(file.ast.clone(), &|n: &Node| {
+        n.node_type == NodeType::Envr && n.lexeme == "document"
+    });
+    let envr = envrs.first()?;
+    let body = Node::get_nth_child_nodeptr(envr.clone(), 0)?;
+    Some(Node::get_span_nodeptr(body))
+}
+
+/// The .bbl generated for main_file, searched in the directory of main_file and in build_dir,
+/// the directory of main_file in which `tex-helper compile` runs latex
+pub fn find_bbl(main_file: &Path, build_dir: &str) -> Option<PathBuf> {
+    let bbl = main_file.with_extension("bbl");
+    let file_name = bbl.file_name()?;
+    let in_build_dir = main_file
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(build_dir)
+        .join(file_name);
+    [bbl, in_build_dir].into_iter().find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_test_project as create_project;
+
+    #[test]
+    fn test_flatten() {
+        let dir = create_project(
+            "flatten",
+            &[
+                (
+                    "main.tex",
+                    "\\begin{document}\n\\input{intro} % intro\n\\include{one}\n\\subfile{sub}\n\\bibliography{refs}\n\\end{document}",
+                ),
+                (
+                    "intro.tex",
+                    "% private note\nIntro, 50\\% done.\n\\begin{verbatim}\n100% % x\n\\end{verbatim}",
+                ),
+                ("one.tex", "One"),
+                (
+                    "sub.tex",
+                    "\\documentclass[main]{subfiles}\n\\begin{document}\nSub\n\\end{document}",
+                ),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let options = FlattenOptions {
+            strip_comments: true,
+            bbl: Some(
+                "\\begin{thebibliography}{}\n\\end{thebibliography}\n".into(),
+            ),
+        };
+        let res = flatten(&graph, &options).unwrap();
+        assert_eq!(
+            res.text,
+            "\\begin{document}\nIntro, 50\\% done.\n\\begin{verbatim}\n100% % x\n\\end{verbatim}\n\\clearpage\nOne\n\\clearpage\nSub\n\\begin{thebibliography}{}\n\\end{thebibliography}\n\\end{document}"
+        );
+        assert!(res.bbl_inlined);
+    }
+
+    #[test]
+    fn test_flatten_figures() {
+        let dir = create_project(
+            "flatten-figures",
+            &[
+                ("main.tex", "\\includegraphics{figs/a}\n\\import{part/}{p}"),
+                ("figs/a.pdf", ""),
+                ("part/p.tex", "\\includegraphics{b.png}"),
+                ("part/b.png", ""),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let res = flatten(&graph, &FlattenOptions::default()).unwrap();
+        assert_eq!(
+            res.text,
+            "\\includegraphics{figs/a}\n\\includegraphics{part/b.png}"
+        );
+        assert_eq!(
+            res.figures,
+            vec![
+                Figure {
+                    source: dir.join("figs/a.pdf"),
+                    dest: PathBuf::from("figs/a.pdf")
+                },
+                Figure {
+                    source: dir.join("part/b.png"),
+                    dest: PathBuf::from("part/b.png")
+                },
+            ]
+        );
+    }
+}
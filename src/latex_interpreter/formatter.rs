@@ -70,8 +70,9 @@ impl FormatState {
     }
 }
 
+/// A package loaded by `\usepackage`
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct Package {
+pub(crate) struct Package {
     name: String,
     // Although the options are usually in square brackets, there may be several square brackets or curly brackets
     // so the options are simple saved as a string containing all the options and brackets.
@@ -87,13 +88,38 @@ impl Package {
         !self.options.is_empty()
     }
 
-    fn get_name(&self) -> String {
+    pub(crate) fn get_name(&self) -> String {
         self.name.to_string()
     }
 
-    fn get_options(&self) -> String {
+    pub(crate) fn get_options(&self) -> String {
         self.options.to_string()
     }
+
+    /// The packages loaded by a usepackage command node, with the options in square brackets
+    /// EG: `\usepackage[utf8]{inputenc, fontenc}` gives inputenc and fontenc with options utf8
+    pub(crate) fn from_usepackage(node: &Node) -> Vec<Package> {
+        let mut options = String::new();
+        let mut names = String::new();
+        for arg in node.get_children() {
+            let arg = arg.lock().unwrap();
+            match arg.get_node_type() {
+                NodeType::SquareBracketArg => {
+                    options = arg.get_children_lexeme_recur()
+                }
+                NodeType::CurlyBracketArg => {
+                    names = arg.get_children_lexeme_recur()
+                }
+                _ => {}
+            }
+        }
+        names
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| Package::new(s.to_string(), options.clone()))
+            .collect()
+    }
 }
 
 /// This struct is necessary, as formattor will
@@ -292,57 +318,11 @@ fn process_usepackage(
     res: &mut FormatRes,
 ) -> Result<(), Box<dyn Error>> {
     let node = node.lock().unwrap();
-    let children = node.get_children();
-    match children.len() {
-        0 => {
-            panic!("Internal Error! process_usepackege: usepackage command has no arguments");
-        }
-        1 => {
-            // Only one argument, must be curly bracket arg
-            let arg = children[0].clone();
-            if Node::get_node_type_nodeptr(arg.clone())
-                != NodeType::CurlyBracketArg
-            {
-                panic!("Internal Error! process_usepackege: usepackage command has invalid argument");
-            }
-
-            // arg is a curly bracket arg
-            let arg_content = Node::get_children_string_lexeme_ptr(arg.clone());
-            let pkgs: Vec<Package> = arg_content
-                .split(',')
-                .map(|s| Package::new(s.trim().to_string(), String::new()))
-                .collect();
-            for pkg in &pkgs {
-                res.add_package(pkg.clone());
-            }
-        }
-        2 => {
-            // Two arguments, first must be square bracket arg, second must be curly bracket arg
-            let options = children[0].clone();
-            let packages = children[1].clone();
-            if Node::get_node_type_nodeptr(options.clone())
-                != NodeType::SquareBracketArg
-            {
-                panic!("Internal Error! process_usepackege: usepackage command has invalid first argument");
-            }
-            if Node::get_node_type_nodeptr(packages.clone())
-                != NodeType::CurlyBracketArg
-            {
-                panic!("Internal Error! process_usepackege: usepackage command has invalid second argument");
-            }
-
-            let options = Node::get_children_string_lexeme_ptr(options.clone());
-            let arg_content =
-                Node::get_children_string_lexeme_ptr(packages.clone());
-            let pkgs: Vec<Package> = arg_content
-                .split(',')
-                .map(|s| Package::new(s.trim().to_string(), options.clone()))
-                .collect();
-            for pkg in &pkgs {
-                res.add_package(pkg.clone());
-            }
-        }
-        _ => {}
+    if node.get_children().is_empty() {
+        panic!("Internal Error! process_usepackege: usepackage command has no arguments");
+    }
+    for pkg in Package::from_usepackage(&node) {
+        res.add_package(pkg);
     }
     Ok(())
 }
@@ -0,0 +1,153 @@
+//! Resolve the figures used by `\includegraphics`
+//!
+//! Just as the graphicx package does, a figure is searched for in the directory its file is
+//! relative to (see include_graph::SourceFile::input_dir), then in the directories listed in
+//! `\graphicspath{{dir1/}{dir2/}}`. If the path has no extension, the extensions in
+//! GRAPHICS_EXTENSIONS are tried in order.
+
+use std::path::{Path, PathBuf};
+
+use super::ast::{NodePtr, NodeType, Span};
+use super::include_graph::{IncludeGraph, SourceFile};
+use crate::utils::{normalize_path, FileInput};
+
+pub const GRAPHICS_EXTENSIONS: [&str; 5] = ["pdf", "png", "jpg", "jpeg", "eps"];
+
+/// One `\includegraphics` found in a source file
+#[derive(Debug, Clone)]
+pub struct Graphic {
+    /// The path as written in the source
+    pub target: String,
+    /// The span of the path in the source, excluding the brackets
+    pub span: Span,
+    /// The figure file, None if it can not be found
+    pub resolved: Option<PathBuf>,
+}
+
+/// The directories listed in `\graphicspath` of the main file, relative to the main directory
+pub fn graphics_path(graph: &IncludeGraph) -> Vec<PathBuf> {
+    let main = graph.get_main_file();
+    let mut ret: Vec<PathBuf> = vec![];
+    collect_graphics_path(main.ast.clone(), &main.file_input, &mut ret);
+    ret.iter()
+        .map(|p| normalize_path(&main.input_dir.join(p)))
+        .collect()
+}
+
+fn collect_graphics_path(
+    node: NodePtr,
+    file_input: &FileInput,
+    ret: &mut Vec<PathBuf>,
+) {
+    let node = node.lock().unwrap();
+    if node.node_type == NodeType::Command && node.lexeme == "graphicspath" {
+        if let Some(arg) = node.get_curly_bracket_args().first() {
+            let span = arg.lock().unwrap().get_content_span();
+            // the arg is like {figs/}{img/}
+            for dir in file_input.get_span_str(&span).split(['{', '}']) {
+                if !dir.trim().is_empty() {
+                    ret.push(PathBuf::from(dir.trim()));
+                }
+            }
+        }
+        return;
+    }
+    for c in node.get_children() {
+        collect_graphics_path(c.clone(), file_input, ret);
+    }
+}
+
+/// Find all `\includegraphics` in file
+/// graphics_path is the return value of graphics_path()
+pub fn find_graphics(
+    file: &SourceFile,
+    graphics_path: &[PathBuf],
+) -> Vec<Graphic> {
+    let mut dirs = vec![file.input_dir.clone()];
+    dirs.extend(graphics_path.iter().cloned());
+
+    let mut ret: Vec<Graphic> = vec![];
+    collect_graphics(file.ast.clone(), &file.file_input, &dirs, &mut ret);
+    ret
+}
+
+fn collect_graphics(
+    node: NodePtr,
+    file_input: &FileInput,
+    dirs: &[PathBuf],
+    ret: &mut Vec<Graphic>,
+) {
+    let node = node.lock().unwrap();
+    if node.node_type == NodeType::Command && node.lexeme == "includegraphics" {
+        if let Some(arg) = node.get_curly_bracket_args().first() {
+            let span = arg.lock().unwrap().get_content_span();
+            let target = file_input.get_span_str(&span).trim().to_string();
+            if !target.is_empty() && !target.contains('#') {
+                ret.push(Graphic {
+                    resolved: resolve_graphic(&target, dirs),
+                    target,
+                    span,
+                });
+            }
+        }
+        return;
+    }
+    for c in node.get_children() {
+        collect_graphics(c.clone(), file_input, dirs, ret);
+    }
+}
+
+/// Find the figure target in dirs, trying GRAPHICS_EXTENSIONS if target has no extension
+pub fn resolve_graphic(target: &str, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let has_extension = Path::new(target).extension().is_some();
+    for dir in dirs {
+        let path = dir.join(target);
+        if has_extension && path.is_file() {
+            return Some(normalize_path(&path));
+        }
+        for ext in GRAPHICS_EXTENSIONS {
+            let candidate =
+                PathBuf::from(format!("{}.{}", path.display(), ext));
+            if candidate.is_file() {
+                return Some(normalize_path(&candidate));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_test_project;
+
+    #[test]
+    fn test_find_graphics() {
+        let dir = create_test_project(
+            "graphics",
+            &[
+                (
+                    "main.tex",
+                    "\\graphicspath{{figs/}}\n\\includegraphics[width=3cm]{plot}\n\\includegraphics{logo.png}\n\\includegraphics{missing}",
+                ),
+                ("figs/plot.pdf", ""),
+                ("logo.png", ""),
+            ],
+        );
+
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let graphics =
+            find_graphics(graph.get_main_file(), &graphics_path(&graph));
+        let resolved: Vec<Option<PathBuf>> =
+            graphics.iter().map(|g| g.resolved.clone()).collect();
+        assert_eq!(
+            resolved,
+            vec![
+                Some(dir.join("figs/plot.pdf")),
+                Some(dir.join("logo.png")),
+                None
+            ]
+        );
+        assert_eq!(graphics[0].target, "plot");
+    }
+}
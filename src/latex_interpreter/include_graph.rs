@@ -0,0 +1,629 @@
+//! The include graph resolves the files making up a (multi-file) latex document.
+//!
+//! Starting from the main file, the following commands are followed recursively:
+//! `\input{file}`, `\include{file}`, `\subfile{file}`, `\import{dir}{file}` and
+//! `\subimport{dir}{file}`. `\includeonly{file1,file2}` in the main file restricts which
+//! `\include`s are followed, just as latex does.
+//!
+//! The paths are resolved in the way latex (and the import package) does:
+//! 1. `\input` and `\include` are relative to the directory latex runs in, which is the directory
+//!    of the main file, unless the current file is brought in by `\import` or `\subimport`, in
+//!    which case they are relative to the imported directory.
+//! 1. `\import{dir}{file}`: dir is relative to the directory of the main file (or absolute).
+//! 1. `\subimport{dir}{file}`: dir is relative to the current imported directory.
+//! 1. `\subfile{file}` is relative to the directory of the current file.
+//! 1. If the file has no `.tex` extension, `file.tex` is tried before `file`.
+//!
+//! Each file is read, scanned and parsed exactly once. The files are kept in document order (the
+//! order in which latex reads them), with the main file being the first. A file included more
+//! than once is only kept at its first appearance. Include cycles are reported as errors.
+//!
+//! Tools working on "the whole document" (formatting, checking, counting, etc) shall use the
+//! include graph instead of a single FileInput.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use super::ast::{Node, NodePtr, NodeType, Span, VERBATIM_ENVRS};
+use super::parser::parse_with_errors;
+use super::scanner::scan;
+use crate::utils::{normalize_path, FileInput};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncludeKind {
+    Input,     // \input{file}
+    Include,   // \include{file}
+    Subfile,   // \subfile{file}
+    Import,    // \import{dir}{file}
+    Subimport, // \subimport{dir}{file}
+}
+
+impl IncludeKind {
+    fn from_command(command: &str) -> Option<Self> {
+        match command {
+            "input" => Some(IncludeKind::Input),
+            "include" => Some(IncludeKind::Include),
+            "subfile" => Some(IncludeKind::Subfile),
+            "import" => Some(IncludeKind::Import),
+            "subimport" => Some(IncludeKind::Subimport),
+            _ => None,
+        }
+    }
+
+    /// Number of curly bracket args the command takes
+    fn arg_count(&self) -> usize {
+        match self {
+            IncludeKind::Import | IncludeKind::Subimport => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// One include command found in a source file
+#[derive(Debug, Clone)]
+pub struct Include {
+    pub kind: IncludeKind,
+    /// The directory arg of `\import` and `\subimport`, empty for the other commands
+    pub dir: String,
+    /// The file as written in the source, eg `chapters/intro`
+    pub target: String,
+    /// The path of the included file, None if it can not be found
+    pub resolved: Option<PathBuf>,
+    /// True if the file is an `\include` not listed in `\includeonly`
+    pub excluded: bool,
+    /// The span of the whole command in the including file
+    pub span: Span,
+}
+
+/// A file of the document, with its AST and the includes found in it
+#[derive(Debug)]
+pub struct SourceFile {
+    pub file_input: FileInput,
+    pub ast: NodePtr,
+    pub includes: Vec<Include>,
+    /// The directory `\input` and `\includegraphics` are relative to in this file
+    pub input_dir: PathBuf,
+}
+
+impl SourceFile {
+    pub fn get_path(&self) -> &PathBuf {
+        self.file_input.get_file_path()
+    }
+
+    /// The comma separated names in the first curly bracket argument of all commands named command
+    /// EG: `\usepackage{amsmath, amssymb}` gives ["amsmath", "amssymb"]
+    pub fn command_arg_list(&self, command: &str) -> Vec<String> {
+        let mut ret: Vec<String> = vec![];
+        for node in Node::find_commands_nodeptr(self.ast.clone(), command) {
+            let node = node.lock().unwrap();
+            if let Some(arg) = node.get_curly_bracket_args().first() {
+                let span = arg.lock().unwrap().get_content_span();
+                for name in self.file_input.get_span_str(&span).split(',') {
+                    if !name.trim().is_empty() {
+                        ret.push(name.trim().to_string());
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    /// The `.bib` files of `\bibliography` and `\addbibresource` in the file
+    /// Both are relative to the directory of the main file, where bibtex and biber are run
+    pub fn bibliography_files(&self, main_dir: &Path) -> Vec<PathBuf> {
+        let mut ret: Vec<PathBuf> = vec![];
+        for name in self
+            .command_arg_list("bibliography")
+            .into_iter()
+            .chain(self.command_arg_list("addbibresource"))
+        {
+            let mut path = main_dir.join(&name);
+            if path.extension().is_none() {
+                path.set_extension("bib");
+            }
+            if path.is_file() {
+                ret.push(normalize_path(&path));
+            } else {
+                warn!(
+                    "{}: bibliography `{}` not found",
+                    self.get_path().display(),
+                    name
+                );
+            }
+        }
+        ret
+    }
+}
+
+#[derive(Debug)]
+pub struct IncludeGraph {
+    /// In document order, files[0] is the main file
+    files: Vec<SourceFile>,
+    /// Normalised path to index in files
+    index: HashMap<PathBuf, usize>,
+}
+
+impl IncludeGraph {
+    /// Read the main file and all files it includes, recursively
+    pub fn build(main_file: &Path) -> Result<Self, Box<dyn Error>> {
+        let main_file = normalize_path(main_file);
+        if !main_file.is_file() {
+            return Err(format!(
+                "Main file `{}` does not exist",
+                main_file.display()
+            )
+            .into());
+        }
+        let main_dir =
+            main_file.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        let mut graph = IncludeGraph {
+            files: vec![],
+            index: HashMap::new(),
+        };
+        let mut stack: Vec<PathBuf> = vec![];
+        let mut include_only: Option<Vec<String>> = None;
+        graph.visit(
+            &main_file,
+            &main_dir,
+            &main_dir,
+            &mut include_only,
+            &mut stack,
+        )?;
+
+        Ok(graph)
+    }
+
+    /// Depth first traversal of the includes
+    ///
+    /// input_dir is the directory `\input` is relative to in this file, see the module doc.
+    /// stack holds the files being visited, which is used for cycle detection.
+    fn visit(
+        &mut self,
+        path: &Path,
+        input_dir: &Path,
+        main_dir: &Path,
+        include_only: &mut Option<Vec<String>>,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), Box<dyn Error>> {
+        let file_input = FileInput::from_file_path(&path.to_path_buf())?;
+        let ast = parse_file(&file_input)?;
+
+        // \includeonly is only allowed in the preamble of the main file
+        if self.files.is_empty() {
+            *include_only = find_include_only(ast.clone(), &file_input);
+        }
+
+        let mut includes: Vec<Include> = vec![];
+        collect_includes(ast.clone(), &file_input, &mut includes);
+
+        // the directory `\input` is relative to in each included file
+        let mut child_dirs: Vec<PathBuf> = vec![];
+        let cur_dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        for inc in includes.iter_mut() {
+            let (base, child_input_dir) = match inc.kind {
+                IncludeKind::Input | IncludeKind::Include => {
+                    (input_dir.to_path_buf(), input_dir.to_path_buf())
+                }
+                IncludeKind::Import => {
+                    let dir = normalize_path(&main_dir.join(&inc.dir));
+                    (dir.clone(), dir)
+                }
+                IncludeKind::Subimport => {
+                    let dir = normalize_path(&input_dir.join(&inc.dir));
+                    (dir.clone(), dir)
+                }
+                IncludeKind::Subfile => (cur_dir.clone(), PathBuf::new()),
+            };
+            inc.resolved = resolve(&base, &inc.target, inc.kind);
+            if inc.kind == IncludeKind::Include {
+                if let Some(only) = include_only {
+                    let name = inc.target.trim_end_matches(".tex");
+                    inc.excluded = !only.iter().any(|o| o == name);
+                }
+            }
+            if inc.resolved.is_none() && !inc.excluded {
+                warn!(
+                    "{}: included file `{}` not found",
+                    path.display(),
+                    inc.target
+                );
+            }
+
+            let child_input_dir = match (&inc.kind, &inc.resolved) {
+                // a subfile is compiled on its own, so it is relative to itself
+                (IncludeKind::Subfile, Some(p)) => {
+                    p.parent().unwrap_or(Path::new("")).to_path_buf()
+                }
+                _ => child_input_dir,
+            };
+            child_dirs.push(child_input_dir);
+        }
+
+        let to_visit: Vec<(PathBuf, PathBuf)> = includes
+            .iter()
+            .zip(child_dirs)
+            .filter(|(inc, _)| !inc.excluded)
+            .filter_map(|(inc, dir)| inc.resolved.clone().map(|p| (p, dir)))
+            .collect();
+
+        self.index.insert(path.to_path_buf(), self.files.len());
+        self.files.push(SourceFile {
+            file_input,
+            ast,
+            includes,
+            input_dir: input_dir.to_path_buf(),
+        });
+
+        stack.push(path.to_path_buf());
+        for (child, child_input_dir) in to_visit {
+            if let Some(i) = stack.iter().position(|p| *p == child) {
+                let mut cycle: Vec<String> = stack[i..]
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect();
+                cycle.push(child.display().to_string());
+                return Err(format!(
+                    "Include cycle detected: {}",
+                    cycle.join(" -> ")
+                )
+                .into());
+            }
+            if self.index.contains_key(&child) {
+                continue;
+            }
+            self.visit(
+                &child,
+                &child_input_dir,
+                main_dir,
+                include_only,
+                stack,
+            )?;
+        }
+        stack.pop();
+
+        Ok(())
+    }
+
+    pub fn get_main_file(&self) -> &SourceFile {
+        &self.files[0]
+    }
+
+    /// All files of the document, in document order
+    pub fn get_files(&self) -> &[SourceFile] {
+        &self.files
+    }
+
+    pub fn get_file(&self, path: &Path) -> Option<&SourceFile> {
+        self.index
+            .get(&normalize_path(path))
+            .map(|i| &self.files[*i])
+    }
+
+    /// The file included by the command at span in file, None if the command is not an include,
+    /// or the file is excluded or can not be found
+    pub fn get_included<'a>(
+        &'a self,
+        file: &'a SourceFile,
+        span: Span,
+    ) -> Option<(&'a Include, &'a SourceFile)> {
+        let include = file
+            .includes
+            .iter()
+            .find(|i| i.span == span && !i.excluded)?;
+        let included = self.get_file(include.resolved.as_ref()?)?;
+        Some((include, included))
+    }
+
+    /// Visit the nodes of the document in the order latex reads them, with the file each node is
+    /// in. The nodes of an included file are visited right after its include command, each file
+    /// once. Only the content of the document environment of the main file and of subfiles is
+    /// visited, if they have one. The content of verbatim environments is not visited, as it is not
+    /// latex.
+    pub fn walk(&self, visit: &mut dyn FnMut(&SourceFile, &NodePtr)) {
+        let main = self.get_main_file();
+        let root = Node::find_document_body_nodeptr(main.ast.clone())
+            .unwrap_or(main.ast.clone());
+        let mut visited = vec![main.get_path().clone()];
+        self.walk_node(main, &root, &mut visited, visit);
+    }
+
+    fn walk_node(
+        &self,
+        file: &SourceFile,
+        node: &NodePtr,
+        visited: &mut Vec<PathBuf>,
+        visit: &mut dyn FnMut(&SourceFile, &NodePtr),
+    ) {
+        let (node_type, span, children) = {
+            let n = node.lock().unwrap();
+            if n.node_type == NodeType::Envr
+                && VERBATIM_ENVRS.contains(&n.lexeme.as_str())
+            {
+                return;
+            }
+            (n.node_type.clone(), n.span, n.children.clone())
+        };
+        visit(file, node);
+        for child in &children {
+            self.walk_node(file, child, visited, visit);
+        }
+        if node_type != NodeType::Command {
+            return;
+        }
+        let Some((include, included)) = self.get_included(file, span) else {
+            return;
+        };
+        if visited.contains(included.get_path()) {
+            return;
+        }
+        visited.push(included.get_path().clone());
+        let root = match include.kind {
+            IncludeKind::Subfile => {
+                Node::find_document_body_nodeptr(included.ast.clone())
+                    .unwrap_or(included.ast.clone())
+            }
+            _ => included.ast.clone(),
+        };
+        self.walk_node(included, &root, visited, visit);
+    }
+}
+
+/// Scan and parse a file. The parse errors are logged instead of returned, see
+/// parser::parse_with_errors
+pub(crate) fn parse_file(
+    file_input: &FileInput,
+) -> Result<NodePtr, Box<dyn Error>> {
+    let tokens = scan(file_input.clone())?;
+    let (ast, errs) = parse_with_errors(&tokens, file_input.clone())?;
+    if !errs.is_empty() {
+        debug!("Parse errors ignored:\n{}", errs);
+    }
+    Ok(ast)
+}
+
+/// The text in a bracket arg, as written in the source
+fn arg_text(arg: NodePtr, file_input: &FileInput) -> String {
+    let span = arg.lock().unwrap().get_content_span();
+    file_input.get_span_str(&span).trim().to_string()
+}
+
+/// Recursively find the include commands in node
+fn collect_includes(
+    node: NodePtr,
+    file_input: &FileInput,
+    ret: &mut Vec<Include>,
+) {
+    let node = node.lock().unwrap();
+    if node.node_type == NodeType::Command {
+        if let Some(kind) = IncludeKind::from_command(&node.lexeme) {
+            let args = node.get_curly_bracket_args();
+            if args.len() >= kind.arg_count() {
+                let (dir, target) = if kind.arg_count() == 2 {
+                    (
+                        arg_text(args[0].clone(), file_input),
+                        arg_text(args[1].clone(), file_input),
+                    )
+                } else {
+                    (String::new(), arg_text(args[0].clone(), file_input))
+                };
+                // arguments of macros, like \newcommand{\chap}[1]{\input{#1}}, are not files
+                if !target.is_empty()
+                    && !target.contains('#')
+                    && !dir.contains('#')
+                {
+                    ret.push(Include {
+                        kind,
+                        dir,
+                        target,
+                        resolved: None,
+                        excluded: false,
+                        span: node.span,
+                    });
+                }
+            }
+        }
+    }
+    for c in node.get_children() {
+        collect_includes(c.clone(), file_input, ret);
+    }
+}
+
+/// Return the file names listed in `\includeonly`, or None if there is no `\includeonly`
+fn find_include_only(
+    node: NodePtr,
+    file_input: &FileInput,
+) -> Option<Vec<String>> {
+    let node = node.lock().unwrap();
+    if node.node_type == NodeType::Command && node.lexeme == "includeonly" {
+        if let Some(arg) = node.get_curly_bracket_args().first() {
+            let files = arg_text(arg.clone(), file_input)
+                .split(',')
+                .map(|s| s.trim().trim_end_matches(".tex").to_string())
+                .filter(|s| !s.is_empty())
+                .collect();
+            return Some(files);
+        }
+    }
+    for c in node.get_children() {
+        if let Some(res) = find_include_only(c.clone(), file_input) {
+            return Some(res);
+        }
+    }
+    None
+}
+
+/// Find the included file: `base/target.tex` is tried before `base/target`
+/// `\include` only accepts .tex files
+fn resolve(base: &Path, target: &str, kind: IncludeKind) -> Option<PathBuf> {
+    let path = base.join(target);
+    let mut candidates: Vec<PathBuf> = vec![];
+    if target.ends_with(".tex") {
+        candidates.push(path);
+    } else {
+        candidates.push(PathBuf::from(format!("{}.tex", path.display())));
+        if kind != IncludeKind::Include {
+            candidates.push(path);
+        }
+    }
+    candidates
+        .into_iter()
+        .map(|p| normalize_path(&p))
+        .find(|p| p.is_file())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_test_project as create_project;
+
+    fn file_names(graph: &IncludeGraph, dir: &Path) -> Vec<String> {
+        graph
+            .get_files()
+            .iter()
+            .map(|f| {
+                f.get_path()
+                    .strip_prefix(dir)
+                    .unwrap()
+                    .display()
+                    .to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_document_order() {
+        let dir = create_project(
+            "include-graph-order",
+            &[
+                (
+                    "main.tex",
+                    "\\begin{document}\n\\input{intro}\n\\include{chapters/one}\n% \\input{commented}\n\\input{end.tex}\n\\end{document}",
+                ),
+                ("intro.tex", "Intro \\input{chapters/defs}"),
+                ("chapters/one.tex", "One"),
+                ("chapters/defs.tex", "Defs"),
+                ("end.tex", "\\input{intro}"),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        assert_eq!(
+            file_names(&graph, &dir),
+            vec![
+                "main.tex",
+                "intro.tex",
+                "chapters/defs.tex",
+                "chapters/one.tex",
+                "end.tex"
+            ]
+        );
+
+        let main = graph.get_main_file();
+        assert_eq!(main.includes.len(), 3);
+        assert_eq!(
+            main.file_input.get_span_str(&main.includes[1].span),
+            "\\include{chapters/one}"
+        );
+    }
+
+    #[test]
+    fn test_import() {
+        let dir = create_project(
+            "include-graph-import",
+            &[
+                ("main.tex", "\\import{parts/}{a}"),
+                ("parts/a.tex", "\\input{b} \\subimport{sub/}{c}"),
+                ("parts/b.tex", "B"),
+                ("parts/sub/c.tex", "\\input{d}"),
+                ("parts/sub/d.tex", "D"),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        assert_eq!(
+            file_names(&graph, &dir),
+            vec![
+                "main.tex",
+                "parts/a.tex",
+                "parts/b.tex",
+                "parts/sub/c.tex",
+                "parts/sub/d.tex"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_include_only() {
+        let dir = create_project(
+            "include-graph-include-only",
+            &[
+                (
+                    "main.tex",
+                    "\\includeonly{one}\n\\include{one}\n\\include{two}",
+                ),
+                ("one.tex", "One"),
+                ("two.tex", "Two"),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        assert_eq!(file_names(&graph, &dir), vec!["main.tex", "one.tex"]);
+        assert!(graph.get_main_file().includes[1].excluded);
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let dir = create_project(
+            "include-graph-missing",
+            &[(
+                "main.tex",
+                "\\input{missing}\\newcommand{\\chap}[1]{\\input{#1}}",
+            )],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let main = graph.get_main_file();
+        assert_eq!(main.includes.len(), 1);
+        assert!(main.includes[0].resolved.is_none());
+    }
+
+    #[test]
+    fn test_cycle() {
+        let dir = create_project(
+            "include-graph-cycle",
+            &[
+                ("main.tex", "\\input{a}"),
+                ("a.tex", "\\input{b}"),
+                ("b.tex", "\\input{a}"),
+            ],
+        );
+        let err = IncludeGraph::build(&dir.join("main.tex")).unwrap_err();
+        assert!(err.to_string().contains("Include cycle detected"));
+        assert!(err.to_string().contains("a.tex -> "));
+    }
+
+    #[test]
+    fn test_walk() {
+        let dir = create_project(
+            "include-graph-walk",
+            &[
+                (
+                    "main.tex",
+                    "\\title{T}\n\\begin{document}\nA \\input{a} B \\subfile{s} \\input{a}\n\\begin{verbatim}\nE\n\\end{verbatim}\n\\end{document}",
+                ),
+                ("a.tex", "C"),
+                (
+                    "s.tex",
+                    "\\documentclass[main]{subfiles}\n\\begin{document}\nD\n\\end{document}",
+                ),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let mut words: Vec<String> = vec![];
+        graph.walk(&mut |_, node| {
+            let node = node.lock().unwrap();
+            if node.node_type == NodeType::Word {
+                words.push(node.lexeme.clone());
+            }
+        });
+        assert_eq!(words, vec!["A", "a", "C", "B", "s", "D", "a"]);
+    }
+}
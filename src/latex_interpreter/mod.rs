@@ -1,7 +1,21 @@
-mod ast;
+pub mod ast;
+pub mod cites;
+pub mod comment;
+pub mod detex;
+pub mod edit;
 pub mod error;
+pub mod expand;
+pub mod flatten;
 pub mod formatter;
+pub mod graphics;
+pub mod include_graph;
+pub mod outline;
 pub mod parser;
+pub mod refs;
+pub mod rename;
 /// For more on the implementation of the scanner, see documents in doc/latexg_grammar/
 pub mod scanner;
+pub mod symbols;
+pub mod to_markdown;
 pub mod token;
+pub mod wordcount;
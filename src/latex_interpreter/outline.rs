@@ -0,0 +1,284 @@
+//! The outline of a document: its sectioning commands (`\part`, `\chapter`, `\section`, ...,
+//! `\subparagraph`) across the include graph, nested by level
+//!
+//! A heading gets the labels in its title, and those right after it (only separated by
+//! whitespace), as in `\section{Introduction}\label{sec:intro}`.
+//! A heading is nested in the closest heading before it of a higher level, so that a document
+//! without chapters has sections at the top.
+//!
+//! The environments of the theorems defined by the document (`\newtheorem`, see symbols) can be
+//! included as headings below all the sectioning commands, titled by their names and notes, as
+//! `Lemma (Zorn)` for `\begin{lemma}[Zorn]`, and starred if unnumbered.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use super::ast::{draw_tree, NodePtr, NodeType, Span};
+use super::include_graph::{IncludeGraph, SourceFile};
+use super::symbols::SymbolTable;
+use super::wordcount::SECTIONS;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Heading {
+    /// The sectioning command, like `section`, or the theorem environment
+    pub command: String,
+    /// Whether the command is starred, as in `\section*{...}`
+    pub starred: bool,
+    pub title: String,
+    pub labels: Vec<String>,
+    pub file: PathBuf,
+    /// The line of the command, starting from 1
+    pub line: usize,
+    pub children: Vec<Heading>,
+}
+
+impl Heading {
+    /// 0 for `\part`, 1 for `\chapter`, ..., the lowest for the theorems
+    pub fn level(&self) -> usize {
+        SECTIONS
+            .iter()
+            .position(|s| *s == self.command)
+            .unwrap_or(SECTIONS.len())
+    }
+
+    fn draw(&self) -> Vec<String> {
+        let mut root = format!(
+            "{}{}: {}",
+            self.command,
+            if self.starred { "*" } else { "" },
+            self.title
+        );
+        if !self.labels.is_empty() {
+            root.push_str(&format!(" [{}]", self.labels.join(", ")));
+        }
+        root.push_str(&format!(" ({}:{})", self.file.display(), self.line));
+        let children: Vec<Vec<String>> =
+            self.children.iter().map(|c| c.draw()).collect();
+        draw_tree(root, &children)
+    }
+}
+
+/// Display the heading and its children as a tree
+/// EG:
+/// ```text
+/// chapter: Introduction [chap:intro] (main.tex:5)
+/// ├── section: Motivation (chapters/intro.tex:1)
+/// └── section*: Outline (chapters/intro.tex:9)
+/// ```
+impl fmt::Display for Heading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.draw().join("\n"))
+    }
+}
+
+/// The top level headings of the document, with the theorems if theorems, see the module doc
+pub fn outline(graph: &IncludeGraph, theorems: bool) -> Vec<Heading> {
+    let symbols = if theorems {
+        SymbolTable::build(graph)
+    } else {
+        SymbolTable::default()
+    };
+    let mut headings: Vec<Heading> = vec![];
+    // where the last heading (with its title and labels) ends, in its file
+    let mut heading_end: Option<(PathBuf, usize)> = None;
+    // `\section*{title}` is parsed as the command, a word `*` and a bracket arg
+    let mut star_pending = false;
+    // and the note of `\begin{lemma}[note]` as the first node of its body
+    let mut note_pending = false;
+
+    graph.walk(&mut |file: &SourceFile, node: &NodePtr| {
+        let (node_type, lexeme, span) = {
+            let n = node.lock().unwrap();
+            (n.node_type.clone(), n.lexeme.clone(), n.span)
+        };
+        let path = file.get_path();
+        let follows = |end: &Option<(PathBuf, usize)>| match end {
+            Some((p, end)) if p == path && *end <= span.start => file
+                .file_input
+                .get_span_str(&Span::new(*end, span.start))
+                .trim()
+                .is_empty(),
+            _ => false,
+        };
+
+        match node_type {
+            NodeType::Command if SECTIONS.contains(&lexeme.as_str()) => {
+                let title = node.lock().unwrap().get_curly_bracket_args().pop();
+                star_pending = title.is_none();
+                let (row, _) = file.file_input.get_row_col(span.start);
+                headings.push(Heading {
+                    command: lexeme,
+                    starred: false,
+                    title: title
+                        .map(|t| arg_text(&t, file))
+                        .unwrap_or_default(),
+                    labels: vec![],
+                    file: path.clone(),
+                    line: row + 1,
+                    children: vec![],
+                });
+                heading_end = Some((path.clone(), span.end));
+            }
+            NodeType::Envr if symbols.theorem(&lexeme).is_some() => {
+                let theorem = symbols.theorem(&lexeme).unwrap();
+                let (row, _) = file.file_input.get_row_col(span.start);
+                headings.push(Heading {
+                    command: lexeme.clone(),
+                    starred: !theorem.numbered,
+                    title: theorem.title.clone(),
+                    labels: vec![],
+                    file: path.clone(),
+                    line: row + 1,
+                    children: vec![],
+                });
+                note_pending = true;
+                // `\begin{lemma}`
+                let end = span.start + lexeme.len() + 8;
+                heading_end = Some((path.clone(), end));
+            }
+            NodeType::SquareBracketArg
+                if note_pending && follows(&heading_end) =>
+            {
+                note_pending = false;
+                let note = arg_text(node, file);
+                let heading = headings.last_mut().unwrap();
+                heading.title = format!("{} ({})", heading.title, note);
+                heading_end = Some((path.clone(), span.end));
+            }
+            NodeType::Word
+                if star_pending && lexeme == "*" && follows(&heading_end) =>
+            {
+                headings.last_mut().unwrap().starred = true;
+                heading_end = Some((path.clone(), span.end));
+            }
+            NodeType::CurlyBracketArg
+                if star_pending && follows(&heading_end) =>
+            {
+                star_pending = false;
+                headings.last_mut().unwrap().title = arg_text(node, file);
+                heading_end = Some((path.clone(), span.end));
+            }
+            NodeType::Command if lexeme == "label" => {
+                star_pending = false;
+                note_pending = false;
+                let in_title = match &heading_end {
+                    Some((p, end)) => p == path && span.end <= *end,
+                    None => false,
+                };
+                if in_title || follows(&heading_end) {
+                    let label = node
+                        .lock()
+                        .unwrap()
+                        .get_curly_bracket_args()
+                        .first()
+                        .map(|l| arg_text(l, file));
+                    if let Some(label) = label {
+                        headings.last_mut().unwrap().labels.push(label);
+                    }
+                    if !in_title {
+                        heading_end = Some((path.clone(), span.end));
+                    }
+                }
+            }
+            NodeType::Comment if follows(&heading_end) => {
+                heading_end = Some((path.clone(), span.end));
+            }
+            // the content of the title and the label are visited after them
+            NodeType::Paragraph
+            | NodeType::Word
+            | NodeType::CurlyBracketArg
+            | NodeType::Comment => {}
+            NodeType::Passage if note_pending => {}
+            _ => {
+                star_pending = false;
+                note_pending = false;
+            }
+        }
+    });
+
+    nest(headings)
+}
+
+/// The text in a bracket arg, whitespace collapsed
+fn arg_text(arg: &NodePtr, file: &SourceFile) -> String {
+    let span = arg.lock().unwrap().get_content_span();
+    file.file_input
+        .get_span_str(&span)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Nest each heading in the closest heading before it of a higher level
+fn nest(headings: Vec<Heading>) -> Vec<Heading> {
+    let mut ret: Vec<Heading> = vec![];
+    // the chain of the last heading and its ancestors
+    let mut stack: Vec<Heading> = vec![];
+    for heading in headings {
+        while stack.last().is_some_and(|h| h.level() >= heading.level()) {
+            pop_into(&mut stack, &mut ret);
+        }
+        stack.push(heading);
+    }
+    while !stack.is_empty() {
+        pop_into(&mut stack, &mut ret);
+    }
+    ret
+}
+
+fn pop_into(stack: &mut Vec<Heading>, ret: &mut Vec<Heading>) {
+    let heading = stack.pop().unwrap();
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(heading),
+        None => ret.push(heading),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_test_project;
+
+    #[test]
+    fn test_outline() {
+        let dir = create_test_project(
+            "outline",
+            &[
+                (
+                    "main.tex",
+                    "\\documentclass{article}\n\\newtheorem{lemma}{Lemma}\n\\begin{document}\n\\section{Arma\n  virumque}\\label{sec:arma} % comment\n\\label{sec:cano}\nText \\label{eq:not}\n\\input{a}\n\\section*{Troiae}\n\\newtheorem*{remark}{Remark}\n\\begin{remark}Sunt lacrimae\\end{remark}\n\\begin{verbatim}\n\\section{Fake}\n\\end{verbatim}\n\\end{document}\n",
+                ),
+                (
+                    "a.tex",
+                    "\\subsection{Qui \\label{sec:qui}primus}\n\\paragraph{Ab oris}\n\\subsection{Italiam}\n\\begin{lemma}[Fato\n  profugus] \\label{lem:fato}\\end{lemma}",
+                ),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let display: Vec<String> = outline(&graph, false)
+            .iter()
+            .map(|h| h.to_string().replace(&format!("{}/", dir.display()), ""))
+            .collect();
+        assert_eq!(
+            display,
+            vec![
+                "section: Arma virumque [sec:arma, sec:cano] (main.tex:4)\n├── subsection: Qui \\label{sec:qui}primus [sec:qui] (a.tex:1)\n│   └── paragraph: Ab oris (a.tex:2)\n└── subsection: Italiam (a.tex:3)",
+                "section*: Troiae (main.tex:9)",
+            ]
+        );
+
+        let display: Vec<String> = outline(&graph, true)
+            .iter()
+            .map(|h| h.to_string().replace(&format!("{}/", dir.display()), ""))
+            .collect();
+        assert_eq!(
+            display,
+            vec![
+                "section: Arma virumque [sec:arma, sec:cano] (main.tex:4)\n├── subsection: Qui \\label{sec:qui}primus [sec:qui] (a.tex:1)\n│   └── paragraph: Ab oris (a.tex:2)\n└── subsection: Italiam (a.tex:3)\n    └── lemma: Lemma (Fato profugus) [lem:fato] (a.tex:4)",
+                "section*: Troiae (main.tex:9)\n└── remark*: Remark (main.tex:11)",
+            ]
+        );
+    }
+}
@@ -38,7 +38,7 @@ use std::sync::{Arc, Mutex};
 
 use super::ast::{Node, NodePtr, NodeType};
 use super::error::TokenErrList;
-use super::token::{Token, TokenType};
+use super::token::{Span, Token, TokenType};
 use crate::utils::FileInput;
 
 /// This is the main function of this file
@@ -47,10 +47,7 @@ pub fn parse(
     input: &[Token],
     file_source: FileInput,
 ) -> Result<NodePtr, Box<dyn Error>> {
-    let mut pos: usize = 0;
-    let mut errs = TokenErrList::empty(file_source);
-
-    let ret = parse_passage(input, &mut pos, &mut errs)?;
+    let (ret, errs) = parse_with_errors(input, file_source)?;
 
     if errs.is_empty() {
         Ok(ret)
@@ -60,6 +57,64 @@ pub fn parse(
     }
 }
 
+/// Same as parse, but the recoverable errors are returned together with the AST instead of
+/// replacing it.
+///
+/// Tools which only inspect the document (include graph, word count, etc) shall not refuse to
+/// work because of a construct the parser does not understand yet. The tokens causing the errors
+/// are skipped in the AST, and an environment or a math group never closed runs to where its
+/// enclosing group ends.
+pub fn parse_with_errors(
+    input: &[Token],
+    file_source: FileInput,
+) -> Result<(NodePtr, TokenErrList), Box<dyn Error>> {
+    let mut pos: usize = 0;
+    let mut errs = TokenErrList::empty(file_source);
+
+    let ret = parse_passage(input, &mut pos, &mut errs)?;
+
+    // The passage stops at a closing token without its opening one, as a stray `}` or `\end`:
+    // skip it, and parse the rest
+    while pos < input.len() {
+        errs.push(
+            input[pos].clone(),
+            &format!("Unexpected token: {:?}", input[pos]),
+        );
+        pos += 1;
+        if input[pos - 1].is_end_envr()
+            && poke(input, pos, TokenType::LeftCurlyBracket)
+        {
+            parse_curly_bracket_arg(input, &mut pos, &mut errs)?;
+        }
+        let rest = parse_passage(input, &mut pos, &mut errs)?;
+        let rest = rest.lock().unwrap();
+        let mut root = ret.lock().unwrap();
+        for paragraph in &rest.children {
+            root.attach(paragraph.clone());
+        }
+        root.span = span_of(input, 0, pos);
+    }
+
+    Ok((ret, errs))
+}
+
+/// Return the span covering input[start..end]
+/// If the range is empty, return an empty span located at input[start], or at the end of the last
+/// token if start is out of bound
+fn span_of(input: &[Token], start: usize, end: usize) -> Span {
+    if end > start && end <= input.len() {
+        return input[start].span.merge(&input[end - 1].span);
+    }
+    if start < input.len() {
+        let loc = input[start].span.start;
+        return Span::new(loc, loc);
+    }
+    match input.last() {
+        Some(t) => Span::new(t.span.end, t.span.end),
+        None => Span::default(),
+    }
+}
+
 // Only for testing
 fn parse_testing(input: &[Token]) -> Result<NodePtr, Box<dyn Error>> {
     parse(input, FileInput::dummy())
@@ -75,6 +130,7 @@ fn parse_passage(
 
     let mut root = root_ptr.lock().unwrap();
 
+    let start_pos = *pos;
     let mut prev_pos = *pos; // For debug purpose
 
     while *pos < input.len() {
@@ -96,6 +152,7 @@ fn parse_passage(
         }
         prev_pos = *pos;
     }
+    root.span = span_of(input, start_pos, *pos);
 
     Ok(root_ptr.clone())
 }
@@ -158,6 +215,7 @@ fn parse_square_bracket_arg(
     errs: &mut TokenErrList,
 ) -> Result<NodePtr, Box<dyn Error>> {
     let mut ret = Node::new("".into(), NodeType::SquareBracketArg);
+    let start_pos = *pos;
 
     if !poke(input, *pos, TokenType::LeftSquareBracket) {
         panic!(
@@ -170,12 +228,14 @@ fn parse_square_bracket_arg(
     let tmp = parse_paragraph(input, pos, errs)?;
 
     if !poke(input, *pos, TokenType::RightSquareBracket) {
-        errs.push(input[*pos].clone(), "Expected Right Square Bracket!");
+        let at = (*pos).min(input.len() - 1);
+        errs.push(input[at].clone(), "Expected Right Square Bracket!");
     } else {
         *pos += 1;
     }
 
     ret.children.push(tmp);
+    ret.span = span_of(input, start_pos, *pos);
 
     Ok(ret.into())
 }
@@ -186,6 +246,7 @@ fn parse_curly_bracket_arg(
     errs: &mut TokenErrList,
 ) -> Result<NodePtr, Box<dyn Error>> {
     let mut ret = Node::new("".into(), NodeType::CurlyBracketArg);
+    let start_pos = *pos;
 
     if !poke(input, *pos, TokenType::LeftCurlyBracket) {
         panic!("Internal Error! Expected Left Curly Bracket!")
@@ -196,12 +257,14 @@ fn parse_curly_bracket_arg(
 
     // TODO ERROR HANDLING
     if !poke(input, *pos, TokenType::RightCurlyBracket) {
-        errs.push(input[*pos].clone(), "Expected Right Curly Bracket!");
+        let at = (*pos).min(input.len() - 1);
+        errs.push(input[at].clone(), "Expected Right Curly Bracket!");
     } else {
         *pos += 1;
     }
 
     ret.children.push(tmp);
+    ret.span = span_of(input, start_pos, *pos);
 
     Ok(ret.into())
 }
@@ -242,33 +305,38 @@ fn parse_operator(
     // input = WORD   OP          ...
     //         *pos   *pos + 1
     op_root.lexeme = (&input[*pos + 1].lexeme).into();
+    let word_span = input[*pos].span;
+    // the span of the operation starts at the first operand
+    let mut op_start = word_span.start;
 
     // Check the lexeme of Word. as ab^2 shall be considered as a b^2
     // In latex, a lone ^2 is valid
-    if input[*pos].lexeme.len() <= 1 {
-        op_root
-            .children
-            .push(Node::new(&input[*pos].lexeme, NodeType::Word).into());
+    let lexeme = &input[*pos].lexeme;
+    if lexeme.chars().nth(1).is_none() {
+        op_root.children.push(
+            Node::new(&input[*pos].lexeme, NodeType::Word)
+                .with_span(word_span)
+                .into(),
+        );
     } else {
         // we are at the case of ab^2. Create new word a, append to ret. Create a new
         // word with lexeme b and append to the child of op_root, as the first
         // arg of operation
-        let pre_word_len = input[*pos].lexeme.len();
-        let pre_word = Node::new(
-            &input[*pos].lexeme[0..(pre_word_len - 1)],
-            NodeType::Word,
-        );
+        // the last char, which may be more than a byte
+        let pre_word_len = lexeme.char_indices().last().map_or(0, |(i, _)| i);
+        op_start = word_span.start + pre_word_len;
+        let pre_word = Node::new(&lexeme[..pre_word_len], NodeType::Word)
+            .with_span(Span::new(word_span.start, op_start));
 
         ret.push(pre_word.into());
 
         op_root.children.push(
-            Node::new(
-                &input[*pos].lexeme[(pre_word_len - 1)..],
-                NodeType::Word,
-            )
-            .into(),
+            Node::new(&lexeme[pre_word_len..], NodeType::Word)
+                .with_span(Span::new(op_start, word_span.end))
+                .into(),
         );
     }
+    op_root.span = Span::new(op_start, input[*pos + 1].span.end);
 
     *pos += 2;
 
@@ -289,10 +357,13 @@ fn parse_operator(
             op_root
                 .children
                 .push(parse_curly_bracket_arg(input, pos, errs)?);
+            op_root.span.end = input[*pos - 1].span.end;
             ret.push(op_root.into());
         }
         TokenType::Word => {
-            let wordlen = input[*pos].lexeme.len();
+            let lexeme = &input[*pos].lexeme;
+            let wordlen = lexeme.chars().count();
+            let word_span = input[*pos].span;
 
             match wordlen {
                 0 => {
@@ -301,19 +372,28 @@ fn parse_operator(
                 }
                 1 => {
                     op_root.children.push(
-                        Node::new(&input[*pos].lexeme, NodeType::Word).into(),
+                        Node::new(&input[*pos].lexeme, NodeType::Word)
+                            .with_span(word_span)
+                            .into(),
                     );
+                    op_root.span.end = word_span.end;
                     ret.push(op_root.into());
                 }
                 _ => {
                     // we are in the case a^23, which shall be parsed as a^2 3
+                    let first_len =
+                        lexeme.chars().next().map_or(0, char::len_utf8);
+                    let split = word_span.start + first_len;
                     op_root.children.push(
-                        Node::new(&input[*pos].lexeme[0..1], NodeType::Word)
+                        Node::new(&lexeme[..first_len], NodeType::Word)
+                            .with_span(Span::new(word_span.start, split))
                             .into(),
                     );
+                    op_root.span.end = split;
                     ret.push(op_root.into());
                     let post_word =
-                        Node::new(&input[*pos].lexeme[1..], NodeType::Word);
+                        Node::new(&lexeme[first_len..], NodeType::Word)
+                            .with_span(Span::new(split, word_span.end));
                     ret.push(post_word.into());
                 }
             }
@@ -331,6 +411,62 @@ fn parse_operator(
     Ok(ret)
 }
 
+/// Parse a macro parameter, as `#1` in the body of `\newcommand`, or `##1` in a definition
+/// nested in another
+/// The scanner gives the digit with the word after it, as in `#1st`, so the rest of the word is
+/// returned as a Word after the parameter, like in parse_operator
+fn parse_parameter(
+    input: &[Token],
+    pos: &mut usize,
+    errs: &mut TokenErrList,
+) -> Vec<NodePtr> {
+    if !poke(input, *pos, TokenType::Hash) {
+        panic!("Internal Error! Expected Hash!");
+    }
+    let start = input[*pos].span.start;
+    let mut hashes = String::new();
+    while poke(input, *pos, TokenType::Hash)
+        && input[*pos].span.start == start + hashes.len()
+    {
+        hashes.push('#');
+        *pos += 1;
+    }
+
+    let digit = match input.get(*pos) {
+        Some(t)
+            if t.token_type == TokenType::Word
+                && t.span.start == start + hashes.len()
+                && t.lexeme.starts_with(|c: char| c.is_ascii_digit()) =>
+        {
+            t
+        }
+        // kept as it is, as `a#b` given by `a##b` in the body of a macro
+        _ => {
+            errs.push(input[*pos - 1].clone(), "Expected a digit after #!");
+            return vec![Node::new(&hashes, NodeType::Parameter)
+                .with_span(Span::new(start, start + hashes.len()))
+                .into()];
+        }
+    };
+    *pos += 1;
+
+    let split = digit.span.start + 1;
+    let mut ret: Vec<NodePtr> = vec![Node::new(
+        &format!("{}{}", hashes, &digit.lexeme[..1]),
+        NodeType::Parameter,
+    )
+    .with_span(Span::new(start, split))
+    .into()];
+    if digit.lexeme.len() > 1 {
+        ret.push(
+            Node::new(&digit.lexeme[1..], NodeType::Word)
+                .with_span(Span::new(split, digit.span.end))
+                .into(),
+        );
+    }
+    ret
+}
+
 fn parse_command(
     input: &[Token],
     pos: &mut usize,
@@ -340,6 +476,7 @@ fn parse_command(
         panic!("Internal error! Expected Command! Internal Bug!");
     }
     let mut ret = Node::new(&input[*pos].lexeme, NodeType::Command);
+    let start_pos = *pos;
 
     *pos += 1;
 
@@ -353,6 +490,7 @@ fn parse_command(
             ret.attach(parse_curly_bracket_arg(input, pos, errs)?);
         }
     }
+    ret.span = span_of(input, start_pos, *pos);
 
     Ok(ret.into())
 }
@@ -389,6 +527,7 @@ fn parse_math(
         }
     }
     let mut ret = Node::new("", node_t);
+    let start_pos = *pos;
 
     *pos += 1; // we have parsed Dollar or Double Dollar
     let initial_pos = *pos;
@@ -415,13 +554,15 @@ fn parse_math(
             input[*pos - 1].clone(),
             "Expected end marker for math mode!",
         );
-        return Err(errs.clone().into());
+        ret.span = span_of(input, start_pos, *pos);
+        return Ok(ret.into());
     }
     if poke(input, *pos, TokenType::NewParagraph) {
         errs.push(
             input[*pos].clone(),
             "Expected end marker for math mode, found new paragraph!",
         );
+        ret.span = span_of(input, start_pos, *pos);
         return Ok(ret.into());
     }
 
@@ -432,6 +573,7 @@ fn parse_math(
     ret.attach(paragraph);
 
     *pos += 1;
+    ret.span = span_of(input, start_pos, *pos);
 
     Ok(ret.into())
 }
@@ -442,6 +584,7 @@ fn parse_slash_open_bracket(
     errs: &mut TokenErrList,
 ) -> Result<NodePtr, Box<dyn Error>> {
     let mut ret = Node::new("", NodeType::DisplayMath);
+    let start_pos = *pos;
 
     if !poke(input, *pos, TokenType::SlashOpenBracket) {
         panic!("Internal Error! Expected SlashOpenBracket!")
@@ -450,11 +593,13 @@ fn parse_slash_open_bracket(
     *pos += 1;
     ret.children.push(parse_paragraph(input, pos, errs)?);
 
-    // TODO: ERROR HANDLING
     if !poke(input, *pos, TokenType::SlashCloseBracket) {
-        panic!("Internal Error! Expected SlashCloseBracket!")
+        let at = (*pos).min(input.len() - 1);
+        errs.push(input[at].clone(), "Expected \\]!");
+    } else {
+        *pos += 1;
     }
-    *pos += 1;
+    ret.span = span_of(input, start_pos, *pos);
 
     Ok(ret.into())
 }
@@ -470,9 +615,17 @@ fn parse_envr(
     // The environments are like
     // \begin{envr_name}
     // \end{envr_name}
+    let start_pos = *pos;
 
     *pos += 1;
 
+    // A \begin without its name is kept as a plain command
+    if !poke(input, *pos, TokenType::LeftCurlyBracket) {
+        errs.push(input[start_pos].clone(), "Expected environment name!");
+        return Ok(Node::new(&input[start_pos].lexeme, NodeType::Command)
+            .with_span(input[start_pos].span)
+            .into());
+    }
     let envr_arg = parse_curly_bracket_arg(input, pos, errs)?;
     let envr_name: String = Node::get_lexeme_recur_ptr(envr_arg.clone());
 
@@ -480,9 +633,16 @@ fn parse_envr(
 
     ret.children.push(parse_passage(input, pos, errs)?);
 
-    // TODO: ERROR HANDLING
+    // The environment is not closed: it runs to where the passage stopped, as the end of the
+    // file or of an enclosing group
     if !poke(input, *pos, TokenType::Command) || !input[*pos].is_end_envr() {
-        panic!("Internal Error! Expected End environment!")
+        let at = (*pos).min(input.len() - 1);
+        errs.push(
+            input[at].clone(),
+            &format!("Expected \\end{{{}}}!", envr_name),
+        );
+        ret.span = span_of(input, start_pos, *pos);
+        return Ok(ret.into());
     }
 
     *pos += 1;
@@ -491,6 +651,11 @@ fn parse_envr(
     //      ^
     // still need to parse the end brace arg
 
+    if !poke(input, *pos, TokenType::LeftCurlyBracket) {
+        errs.push(input[*pos - 1].clone(), "Expected environment name!");
+        ret.span = span_of(input, start_pos, *pos);
+        return Ok(ret.into());
+    }
     let envr_end_arg = parse_curly_bracket_arg(input, pos, errs)?;
     let envr_end_name: String =
         Node::get_lexeme_recur_ptr(envr_end_arg.clone());
@@ -503,8 +668,8 @@ fn parse_envr(
                 envr_name, envr_end_name
             ),
         );
-        return Err(errs.clone().into());
     }
+    ret.span = span_of(input, start_pos, *pos);
 
     Ok(ret.into())
 }
@@ -517,6 +682,7 @@ fn parse_paragraph(
 ) -> Result<NodePtr, Box<dyn Error>> {
     let ret: Arc<Mutex<Node>> = Node::empty_paragraph_ptr();
     let mut paragraph = ret.lock().unwrap();
+    let start_pos = *pos;
 
     while *pos < input.len() {
         let cur_token = &input[*pos];
@@ -531,31 +697,36 @@ fn parse_paragraph(
                     }
                 } else {
                     if cur_token.lexeme.len() > 0 {
-                        paragraph.attach(Node::new(&cur_token.lexeme, NodeType::Word).into());
+                        paragraph.attach(Node::new(&cur_token.lexeme, NodeType::Word).with_span(cur_token.span).into());
                     }
                     *pos += 1;
                 }
             }
             TokenType::Comment => {
-                paragraph.attach(Node::new(&cur_token.lexeme, NodeType::Comment).into());
+                paragraph.attach(Node::new(&cur_token.lexeme, NodeType::Comment).with_span(cur_token.span).into());
                 *pos += 1;
             }
             TokenType::Backslash => {
                 // This is forced, deliberate, space
                 *pos += 1;
-                paragraph.attach(Node::new(" ", NodeType::Word).into());
+                paragraph.attach(Node::new(" ", NodeType::Word).with_span(cur_token.span).into());
             }
             TokenType::DoubleBackslash => {
                 // Line break but not paragraph break
                 *pos += 1;
-                paragraph.attach(Node::new("\n", NodeType::Word).into());
+                paragraph.attach(Node::new("\n", NodeType::Word).with_span(cur_token.span).into());
+            }
+            TokenType::EscapedChar => {
+                // An escaped character is text. The lexeme keeps the backslash, as in `\%`
+                paragraph.attach(Node::new(&format!("\\{}", cur_token.lexeme), NodeType::Word).with_span(cur_token.span).into());
+                *pos += 1;
             }
             TokenType::Ampersand => {
-                paragraph.attach(Node::new(&cur_token.lexeme, NodeType::Ampersand).into());
+                paragraph.attach(Node::new(&cur_token.lexeme, NodeType::Ampersand).with_span(cur_token.span).into());
                 *pos += 1;
             }
             TokenType::Tilde => {
-                paragraph.attach(Node::new(&cur_token.lexeme, NodeType::Operation).into());
+                paragraph.attach(Node::new(&cur_token.lexeme, NodeType::Operation).with_span(cur_token.span).into());
                 *pos += 1;
             }
             TokenType::LeftCurlyBracket => {
@@ -580,15 +751,20 @@ fn parse_paragraph(
                 if input[*pos].is_begin_envr() {
                     paragraph.attach(parse_envr(input, pos, errs)?);
                 } else if input[*pos].is_end_envr() {
-                    return Ok(ret.clone());
+                    break;
                 } else {
                     paragraph.attach(parse_command(input, pos, errs)?);
                 }
             }
+            TokenType::Hash => {
+                for i in parse_parameter(input, pos, errs) {
+                    paragraph.attach(i);
+                }
+            }
             TokenType::RightCurlyBracket  // end of brace args 
             | TokenType::RightSquareBracket  // end of bracket args 
             | TokenType::SlashCloseBracket  // end of display math
-            | TokenType::NewParagraph => return Ok(ret.clone()),
+            | TokenType::NewParagraph => break,
             _ => {
                 errs.push(
                     input[*pos].clone(),
@@ -598,6 +774,7 @@ fn parse_paragraph(
             }
         }
     }
+    paragraph.span = span_of(input, start_pos, *pos);
 
     Ok(ret.clone())
 }
@@ -677,6 +854,68 @@ Hope there is success!
         println!("{}", ast.lock().unwrap());
     }
 
+    #[test]
+    fn parser_span() {
+        let input = "\\input{chapters/intro} ab^2 \\begin{eq}x\\end{eq}";
+        let tokens = scanner::scan_str(input);
+        let ast = parser::parse_testing(&tokens).unwrap();
+        let paragraph = ast.lock().unwrap().get_nth_child(0).unwrap();
+        let texts: Vec<String> = paragraph
+            .lock()
+            .unwrap()
+            .get_children()
+            .iter()
+            .map(|c| {
+                let span = c.lock().unwrap().get_span();
+                input[span.start..span.end].to_string()
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "\\input{chapters/intro}",
+                "a",
+                "b^2",
+                "\\begin{eq}x\\end{eq}"
+            ]
+        );
+
+        let command = paragraph.lock().unwrap().get_nth_child(0).unwrap();
+        let arg = command.lock().unwrap().get_nth_child(0).unwrap();
+        let span = arg.lock().unwrap().get_content_span();
+        assert_eq!(&input[span.start..span.end], "chapters/intro");
+    }
+
+    #[test]
+    fn parser_operator_non_ascii() {
+        let input = "aé^2 x_éb é^";
+        let tokens = scanner::scan_str(input);
+        let file_input = FileInput::from_str("test.tex", input);
+        let (ast, errs) =
+            parser::parse_with_errors(&tokens, file_input).unwrap();
+        assert!(!errs.is_empty());
+        let paragraph = ast.lock().unwrap().get_nth_child(0).unwrap();
+        let texts: Vec<String> = paragraph
+            .lock()
+            .unwrap()
+            .get_children()
+            .iter()
+            .map(|c| {
+                let span = c.lock().unwrap().get_span();
+                input[span.start..span.end].to_string()
+            })
+            .collect();
+        assert_eq!(texts, vec!["a", "é^2", "x_é", "b"]);
+    }
+
+    #[test]
+    fn parser_escaped_char() {
+        let input = r##"50\% \& more"##;
+        let tokens = scanner::scan_str(input);
+        let ast = parser::parse_testing(&tokens).unwrap();
+        assert_eq!(ast.lock().unwrap().get_lexeme_recur(), "50\\%\\&more");
+    }
+
     #[test]
     fn parser_command() {
         let input = r##"\a{aaa}[abb]{asb}"##;
@@ -739,6 +978,69 @@ Another paragraph!
         println!("{}", ast.lock().unwrap());
     }
 
+    #[test]
+    fn parser_parameter() {
+        let input = "\\newcommand{\\a}[1]{#1st} \\def\\b#1#2{##1}";
+        let tokens = scanner::scan_str(input);
+        let ast = parser::parse_testing(&tokens).unwrap();
+        let texts: Vec<(String, String)> =
+            ast::Node::find_all_nodeptr(ast, &|n: &ast::Node| {
+                n.node_type == ast::NodeType::Parameter || n.lexeme == "st"
+            })
+            .iter()
+            .map(|n| {
+                let n = n.lock().unwrap();
+                (
+                    n.lexeme.clone(),
+                    input[n.span.start..n.span.end].to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                ("#1".to_string(), "#1".to_string()),
+                ("st".to_string(), "st".to_string()),
+                ("#1".to_string(), "#1".to_string()),
+                ("#2".to_string(), "#2".to_string()),
+                ("##1".to_string(), "##1".to_string()),
+            ]
+        );
+
+        let tokens = scanner::scan_str("a # b");
+        let (_, errs) =
+            parser::parse_with_errors(&tokens, FileInput::dummy()).unwrap();
+        assert!(!errs.is_empty());
+    }
+
+    #[test]
+    fn parser_recover() {
+        // unclosed environment, stray \end, unclosed display math and inline math
+        let input = r##"\begin{document}
+\begin{itemize}
+\item a
+\end{document}
+\end{x} b
+\[ c
+$d"##;
+        let tokens = scanner::scan_str(input);
+        let (ast, errs) =
+            parser::parse_with_errors(&tokens, FileInput::dummy()).unwrap();
+        assert!(!errs.is_empty());
+        assert_eq!(ast.lock().unwrap().span.end, input.len());
+        let lexemes = |node_type: ast::NodeType| -> Vec<String> {
+            ast::Node::find_all_nodeptr(ast.clone(), &|n| {
+                n.node_type == node_type
+            })
+            .iter()
+            .map(|n| n.lock().unwrap().lexeme.clone())
+            .collect()
+        };
+        assert_eq!(lexemes(ast::NodeType::Envr), vec!["document", "itemize"]);
+        assert!(lexemes(ast::NodeType::Word).contains(&"b".to_string()));
+        assert!(parser::parse_testing(&tokens).is_err());
+    }
+
     #[test]
     fn empty_doc() {
         let input = r##"\begin{document}
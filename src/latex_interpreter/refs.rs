@@ -0,0 +1,248 @@
+//! Check the labels and references across the include graph, without running latex
+//!
+//! `\label{key}` defines a label, `\ref`, `\eqref`, `\autoref`, `\cref`, `\Cref`, `\pageref`,
+//! `\nameref` and `\vref` refer to it (`\cref{a,b}` refers to both a and b).
+//! The following are reported:
+//! 1. references to labels defined nowhere
+//! 1. labels defined more than once
+//! 1. labels never referred to
+//!
+//! Keys containing `#` are the arguments of macro definitions, and are ignored.
+//! The content of verbatim environments is ignored too, as it is not latex.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+
+use colored::*;
+
+use super::ast::{NodePtr, NodeType, Span};
+use super::include_graph::{IncludeGraph, SourceFile};
+
+pub const REF_COMMANDS: [&str; 8] = [
+    "ref", "eqref", "autoref", "cref", "Cref", "pageref", "nameref", "vref",
+];
+
+/// A key of a label, reference, citation, etc, where it is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Occurrence {
+    pub key: String,
+    pub file: PathBuf,
+    /// The span of the key alone, without the brackets
+    pub span: Span,
+    /// Starting from 0
+    pub row: usize,
+    /// Starting from 0, in characters
+    pub col: usize,
+}
+
+impl Occurrence {
+    /// The `file:row:col` of the key, row and col starting from 1
+    pub fn location(&self) -> String {
+        format!("{}:{}:{}", self.file.display(), self.row + 1, self.col + 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefProblem {
+    Undefined(Occurrence),
+    /// The label defined again, and its first definition
+    Duplicate(Occurrence, Occurrence),
+    Unused(Occurrence),
+}
+
+impl RefProblem {
+    /// Undefined references and duplicate labels break the document, unused labels do not
+    pub fn is_error(&self) -> bool {
+        !matches!(self, RefProblem::Unused(_))
+    }
+
+    /// The reference or label the problem is located at
+    pub fn occurrence(&self) -> &Occurrence {
+        match self {
+            RefProblem::Undefined(o)
+            | RefProblem::Duplicate(o, _)
+            | RefProblem::Unused(o) => o,
+        }
+    }
+
+    /// The problem, without its location
+    pub fn message(&self) -> String {
+        match self {
+            RefProblem::Undefined(r) => {
+                format!("reference to undefined label `{}`", r.key)
+            }
+            RefProblem::Duplicate(label, first) => format!(
+                "label `{}` already defined at {}",
+                label.key,
+                first.location()
+            ),
+            RefProblem::Unused(label) => {
+                format!("label `{}` is never referred to", label.key)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RefProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.is_error() {
+            true => "ERROR".red().bold(),
+            false => "WARNING".yellow().bold(),
+        };
+        write!(
+            f,
+            "{} {}: {}",
+            self.occurrence().location(),
+            level,
+            self.message()
+        )
+    }
+}
+
+/// The keys in the curly bracket args of the commands in names, in document order
+pub(crate) fn collect_keys(
+    graph: &IncludeGraph,
+    names: &[&str],
+) -> Vec<Occurrence> {
+    let mut ret: Vec<Occurrence> = vec![];
+    graph.walk(&mut |file: &SourceFile, node: &NodePtr| {
+        let node = node.lock().unwrap();
+        if node.node_type != NodeType::Command
+            || !names.contains(&node.lexeme.as_str())
+        {
+            return;
+        }
+        if let Some(arg) = node.get_curly_bracket_args().first() {
+            ret.extend(keys_of(arg, file));
+        }
+    });
+    ret
+}
+
+/// The comma separated keys in a bracket arg, with their own spans
+pub(crate) fn keys_of(arg: &NodePtr, file: &SourceFile) -> Vec<Occurrence> {
+    let span = arg.lock().unwrap().get_content_span();
+    let text = file.file_input.get_span_str(&span);
+    let mut ret: Vec<Occurrence> = vec![];
+    let mut offset = span.start;
+    for part in text.split(',') {
+        let key = part.trim();
+        if !key.is_empty() && !key.contains('#') {
+            let start = offset + part.find(key).unwrap();
+            let (row, col) = file.file_input.get_row_col(start);
+            ret.push(Occurrence {
+                key: key.to_string(),
+                file: file.get_path().clone(),
+                span: Span::new(start, start + key.len()),
+                row,
+                col,
+            });
+        }
+        offset += part.len() + 1;
+    }
+    ret
+}
+
+/// The problems of the labels and references of the document, see the module doc
+/// The undefined references and duplicate labels come first, in document order, then the
+/// unused labels
+pub fn check_refs(graph: &IncludeGraph) -> Vec<RefProblem> {
+    let labels = collect_keys(graph, &["label"]);
+    let refs = collect_keys(graph, &REF_COMMANDS);
+
+    // the problems with their positions in the document
+    let mut errors: Vec<((usize, usize), RefProblem)> = vec![];
+    let mut defined: HashMap<&str, &Occurrence> = HashMap::new();
+    for label in &labels {
+        match defined.get(label.key.as_str()) {
+            Some(first) => errors.push((
+                order(graph, label),
+                RefProblem::Duplicate(label.clone(), (*first).clone()),
+            )),
+            None => {
+                defined.insert(&label.key, label);
+            }
+        }
+    }
+    for r in &refs {
+        if !defined.contains_key(r.key.as_str()) {
+            errors.push((order(graph, r), RefProblem::Undefined(r.clone())));
+        }
+    }
+    errors.sort_by_key(|(order, _)| *order);
+    let mut ret: Vec<RefProblem> = errors.into_iter().map(|(_, p)| p).collect();
+
+    let used: HashSet<&str> = refs.iter().map(|r| r.key.as_str()).collect();
+    let mut reported: HashSet<&str> = HashSet::new();
+    for label in &labels {
+        if !used.contains(label.key.as_str()) && reported.insert(&label.key) {
+            ret.push(RefProblem::Unused(label.clone()));
+        }
+    }
+    ret
+}
+
+/// Sort key of an occurrence: the files in document order, then the offset
+fn order(graph: &IncludeGraph, o: &Occurrence) -> (usize, usize) {
+    let file = graph
+        .get_files()
+        .iter()
+        .position(|f| *f.get_path() == o.file)
+        .unwrap_or(usize::MAX);
+    (file, o.span.start)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_test_project;
+
+    #[test]
+    fn test_check_refs() {
+        let dir = create_test_project(
+            "refs",
+            &[
+                (
+                    "main.tex",
+                    "\\begin{document}\n\\section{A}\\label{sec:a}\nSee \\cref{sec:a, sec:b} and \\eqref{eq:x}.\n\\input{b}\n\\newcommand{\\r}[1]{\\ref{#1}}\n\\end{document}",
+                ),
+                (
+                    "b.tex",
+                    "\\label{eq:x} \\label{sec:a}\n\\label{fig:unused} \\pageref{sec:c}\n\\begin{verbatim}\n\\label{sec:a} \\ref{sec:none}\n\\end{verbatim}",
+                ),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let problems = check_refs(&graph);
+        let summary: Vec<(&str, String, usize, usize)> = problems
+            .iter()
+            .map(|p| match p {
+                RefProblem::Undefined(o) => ("undefined", o, o.row, o.col),
+                RefProblem::Duplicate(o, _) => ("duplicate", o, o.row, o.col),
+                RefProblem::Unused(o) => ("unused", o, o.row, o.col),
+            })
+            .map(|(kind, o, row, col)| (kind, o.key.clone(), row, col))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("undefined", "sec:b".to_string(), 2, 17),
+                ("duplicate", "sec:a".to_string(), 0, 20),
+                ("undefined", "sec:c".to_string(), 1, 28),
+                ("unused", "fig:unused".to_string(), 1, 7),
+            ]
+        );
+        match &problems[1] {
+            RefProblem::Duplicate(_, first) => {
+                assert_eq!(
+                    first.location(),
+                    format!("{}:2:19", dir.join("main.tex").display())
+                )
+            }
+            _ => panic!(),
+        }
+        assert!(problems[0].is_error());
+        assert!(!problems[3].is_error());
+    }
+}
@@ -0,0 +1,306 @@
+//! Rename a label or a macro across the include graph
+//!
+//! The occurrences are found in the AST rather than in the text, so that only the keys of
+//! `\label`, `\ref` and the like, and only the commands of the name, are renamed: never `\oldx`,
+//! nor the word old in the text.
+//! 1. A label is renamed in its `\label`, and in the references to it (`\ref`, `\cref{a,b}`, ...,
+//!    see refs, and `\hyperref[key]{text}`)
+//! 1. A macro is renamed in its definitions (`\newcommand{\old}`, `\def\old`, ...) and in all of
+//!    its calls, those in the definitions of other macros included
+//!
+//! All of the files are searched, preamble included, except the content of verbatim environments
+//! and the text of `\verb|...|`, `\lstinline` and the like.
+//! Renaming to a name already used is an error, as it would merge two labels or macros.
+
+use std::error::Error;
+use std::path::PathBuf;
+
+use super::ast::{NodePtr, NodeType, Span, VERBATIM_ENVRS};
+use super::comment::literal_end;
+use super::edit::Edit;
+use super::include_graph::IncludeGraph;
+use super::refs::{keys_of, Occurrence, REF_COMMANDS};
+use super::symbols::SymbolTable;
+
+/// The commands of inline verbatim text, with the number of curly bracket args before the text.
+/// The text is either delimited by any character, as `\verb|...|`, or is one more curly bracket
+/// arg, as `\lstinline{...}`
+const VERB_COMMANDS: [(&str, usize); 4] = [
+    ("verb", 0),
+    ("Verb", 0),
+    ("lstinline", 0),
+    ("mintinline", 1),
+];
+
+/// Visit node and its descendants in depth first order, except the content of verbatim
+/// environments and the text of the VERB_COMMANDS, which are not latex
+fn visit(node: &NodePtr, source: &str, f: &mut dyn FnMut(&NodePtr)) {
+    let children = {
+        let n = node.lock().unwrap();
+        if n.node_type == NodeType::Envr
+            && VERBATIM_ENVRS.contains(&n.lexeme.as_str())
+        {
+            return;
+        }
+        n.children.clone()
+    };
+    f(node);
+    // the delimited text of `\verb|...|` is parsed as the nodes after the command
+    let mut text_end = 0;
+    for child in &children {
+        let (start, end, verb_args, args) = {
+            let c = child.lock().unwrap();
+            let verb_args = VERB_COMMANDS
+                .iter()
+                .find(|(name, _)| {
+                    c.node_type == NodeType::Command && c.lexeme == *name
+                })
+                .map(|(_, args)| *args);
+            (
+                c.span.start,
+                c.span.end,
+                verb_args,
+                c.get_curly_bracket_args().len(),
+            )
+        };
+        if start < text_end {
+            continue;
+        }
+        match verb_args {
+            Some(verb_args) if args <= verb_args => {
+                text_end = literal_end(source, "verb", end).unwrap_or(end);
+            }
+            // the text in a curly bracket arg
+            Some(_) => {}
+            None => visit(child, source, f),
+        }
+    }
+}
+
+/// The occurrences of the label key, in `\label` and in the references, in document order
+pub fn label_occurrences(graph: &IncludeGraph, key: &str) -> Vec<Occurrence> {
+    let mut ret: Vec<Occurrence> = vec![];
+    for file in graph.get_files() {
+        let source = file.file_input.get_str_content();
+        visit(&file.ast, source, &mut |node: &NodePtr| {
+            let node = node.lock().unwrap();
+            if node.node_type != NodeType::Command {
+                return;
+            }
+            let arg = match node.lexeme.as_str() {
+                "hyperref" => node.children.first().cloned().filter(|a| {
+                    a.lock().unwrap().node_type == NodeType::SquareBracketArg
+                }),
+                c if c == "label" || REF_COMMANDS.contains(&c) => {
+                    node.get_curly_bracket_args().first().cloned()
+                }
+                _ => None,
+            };
+            if let Some(arg) = arg {
+                ret.extend(
+                    keys_of(&arg, file).into_iter().filter(|o| o.key == key),
+                );
+            }
+        });
+    }
+    ret
+}
+
+/// The occurrences of the command name (without the backslash), in document order. The key of
+/// an occurrence is `\name`, and its span that of `\name` alone, without the args
+pub fn macro_occurrences(graph: &IncludeGraph, name: &str) -> Vec<Occurrence> {
+    let mut ret: Vec<Occurrence> = vec![];
+    for file in graph.get_files() {
+        let source = file.file_input.get_str_content();
+        visit(&file.ast, source, &mut |node: &NodePtr| {
+            let node = node.lock().unwrap();
+            if node.node_type != NodeType::Command || node.lexeme != name {
+                return;
+            }
+            let start = node.span.start;
+            let (row, col) = file.file_input.get_row_col(start);
+            ret.push(Occurrence {
+                key: format!("\\{}", name),
+                file: file.get_path().clone(),
+                span: Span::new(start, start + name.len() + 1),
+                row,
+                col,
+            });
+        });
+    }
+    ret
+}
+
+/// The occurrences of the label old to be renamed to new
+pub fn rename_label(
+    graph: &IncludeGraph,
+    old: &str,
+    new: &str,
+) -> Result<Vec<Occurrence>, Box<dyn Error>> {
+    if new.is_empty() || new.contains([',', '{', '}', '[', ']', '#', '\\']) {
+        return Err(format!("`{}` is not a valid label", new).into());
+    }
+    if let Some(used) = label_occurrences(graph, new).first() {
+        return Err(format!(
+            "label `{}` is already used at {}",
+            new,
+            used.location()
+        )
+        .into());
+    }
+    let ret = label_occurrences(graph, old);
+    if ret.is_empty() {
+        return Err(format!("label `{}` is found nowhere", old).into());
+    }
+    Ok(ret)
+}
+
+/// The occurrences of the command old to be renamed to new, both without the backslash
+pub fn rename_macro(
+    graph: &IncludeGraph,
+    old: &str,
+    new: &str,
+) -> Result<Vec<Occurrence>, Box<dyn Error>> {
+    // a command name is made of letters, or is a single other character
+    let valid = new.chars().all(|c| c.is_ascii_alphabetic())
+        || new.chars().count() == 1;
+    if new.is_empty() || !valid {
+        return Err(format!("`\\{}` is not a valid command name", new).into());
+    }
+    if let Some(used) = macro_occurrences(graph, new).first() {
+        return Err(format!(
+            "`\\{}` is already used at {}",
+            new,
+            used.location()
+        )
+        .into());
+    }
+    let ret = macro_occurrences(graph, old);
+    if ret.is_empty() {
+        return Err(format!("`\\{}` is found nowhere", old).into());
+    }
+    if SymbolTable::build(graph).command(old).is_none() {
+        warn!(
+            "`\\{}` is not defined by the document, only its uses are renamed",
+            old
+        );
+    }
+    Ok(ret)
+}
+
+/// The edits replacing the occurrences by replacement, grouped by file
+pub fn rename_edits(
+    occurrences: &[Occurrence],
+    replacement: &str,
+) -> Vec<(PathBuf, Vec<Edit>)> {
+    let mut ret: Vec<(PathBuf, Vec<Edit>)> = vec![];
+    for o in occurrences {
+        let edit = Edit::new(o.span, replacement);
+        match ret.iter_mut().find(|(f, _)| *f == o.file) {
+            Some((_, edits)) => edits.push(edit),
+            None => ret.push((o.file.clone(), vec![edit])),
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::latex_interpreter::edit::apply_edits;
+    use crate::utils::create_test_project;
+
+    #[test]
+    fn test_rename() {
+        let dir = create_test_project(
+            "rename",
+            &[
+                (
+                    "main.tex",
+                    r"\documentclass{article}
+\newcommand{\old}[1]{\emph{#1}}
+\newcommand\oldx{\old{x}}
+\begin{document}
+\section{Old}\label{sec:old}
+The old \old{text} and \oldx, see \cref{sec:a,sec:old}.
+\begin{verbatim}
+\old{code} \ref{sec:old}
+\end{verbatim}
+\input{a}
+\end{document}",
+                ),
+                (
+                    "a.tex",
+                    r"\hyperref[sec:old]{there} \ref{ sec:old }
+\old{a} \verb|\old{v}| \verb*+\old+ \lstinline{\old} \lstinline[style=x]!\old! \old{b}",
+                ),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let rename = |occurrences: &[Occurrence], replacement: &str| {
+            rename_edits(occurrences, replacement)
+                .iter()
+                .map(|(path, edits)| {
+                    let file = graph.get_file(path).unwrap();
+                    apply_edits(file.file_input.get_str_content(), edits)
+                })
+                .collect::<Vec<String>>()
+        };
+
+        let labels = rename_label(&graph, "sec:old", "sec:new").unwrap();
+        let locations: Vec<(usize, usize)> =
+            labels.iter().map(|o| (o.row, o.col)).collect();
+        assert_eq!(locations, vec![(4, 20), (5, 46), (0, 10), (0, 32)]);
+        assert_eq!(
+            rename(&labels, "sec:new"),
+            vec![
+                r"\documentclass{article}
+\newcommand{\old}[1]{\emph{#1}}
+\newcommand\oldx{\old{x}}
+\begin{document}
+\section{Old}\label{sec:new}
+The old \old{text} and \oldx, see \cref{sec:a,sec:new}.
+\begin{verbatim}
+\old{code} \ref{sec:old}
+\end{verbatim}
+\input{a}
+\end{document}",
+                r"\hyperref[sec:new]{there} \ref{ sec:new }
+\old{a} \verb|\old{v}| \verb*+\old+ \lstinline{\old} \lstinline[style=x]!\old! \old{b}",
+            ]
+        );
+
+        let macros = rename_macro(&graph, "old", "new").unwrap();
+        assert_eq!(
+            rename(&macros, "\\new")[0],
+            r"\documentclass{article}
+\newcommand{\new}[1]{\emph{#1}}
+\newcommand\oldx{\new{x}}
+\begin{document}
+\section{Old}\label{sec:old}
+The old \new{text} and \oldx, see \cref{sec:a,sec:old}.
+\begin{verbatim}
+\old{code} \ref{sec:old}
+\end{verbatim}
+\input{a}
+\end{document}"
+        );
+
+        // the text of \verb and \lstinline is not latex
+        assert_eq!(
+            rename(&macros, "\\new")[1],
+            r"\hyperref[sec:old]{there} \ref{ sec:old }
+\new{a} \verb|\old{v}| \verb*+\old+ \lstinline{\old} \lstinline[style=x]!\old! \new{b}"
+        );
+
+        assert_eq!(
+            rename_macro(&graph, "old", "oldx").unwrap_err().to_string(),
+            format!(
+                "`\\oldx` is already used at {}:3:12",
+                dir.join("main.tex").display()
+            )
+        );
+        assert!(rename_macro(&graph, "old", "new1").is_err());
+        assert!(rename_label(&graph, "sec:none", "sec:b").is_err());
+    }
+}
@@ -21,8 +21,9 @@
 //! So `apple banana orange` will be scanned into 5 tokens: Word(apple), Space, Word(b), Space, Word(orange)
 //! 1. Commands are scanned into command tokens, the beginning backslash is not in the lexeme.
 //! 1. Escaped characters are into EscapedChar, the backslash is not in the lexeme.
+//! 1. Each token records its Span, the byte offsets of the text it is scanned from.
 
-use super::token::{Token, TokenType};
+use super::token::{Span, Token, TokenType};
 use crate::utils::FileInput;
 use std::error::Error;
 
@@ -40,6 +41,13 @@ pub fn scan(file_input: FileInput) -> Result<Vec<Token>, Box<dyn Error>> {
 
     let chars: Vec<char> = source.chars().collect();
     let length = chars.len();
+    // byte_offsets[i] is the byte offset of chars[i] in source, used for the span of tokens
+    // The extra last entry is the length of source
+    let byte_offsets: Vec<usize> = source
+        .char_indices()
+        .map(|(b, _)| b)
+        .chain(std::iter::once(source.len()))
+        .collect();
 
     let mut ret: Vec<Token> = Vec::new();
     let mut i = 0;
@@ -51,6 +59,8 @@ pub fn scan(file_input: FileInput) -> Result<Vec<Token>, Box<dyn Error>> {
                         // Note we have an i+=1 at the end of the loop
                         // so in match, i shall only be incremented with the extra space
     while i < length {
+        let token_start = i;
+        let token_count = ret.len();
         match chars[i] {
             '#' => {
                 ret.push(Token::new(TokenType::Hash, "#".into(), row, col));
@@ -296,6 +306,13 @@ pub fn scan(file_input: FileInput) -> Result<Vec<Token>, Box<dyn Error>> {
             }
         } // end of match
 
+        // i is now at the last character of the token just scanned (if any)
+        if ret.len() > token_count {
+            let last = ret.len() - 1;
+            ret[last].span =
+                Span::new(byte_offsets[token_start], byte_offsets[i + 1]);
+        }
+
         i += 1;
         if !encountered_newline {
             col += i - prev_i;
@@ -639,6 +656,34 @@ Triae qui"##,
         compare_expected_and_tokens(expected, tokens);
     }
 
+    #[test]
+    fn test_span() {
+        let input = "\\section{Ab} % ça\n\n$x^2$ \\% \\\\";
+        let tokens = scan_str(input);
+        let texts: Vec<&str> = tokens
+            .iter()
+            .map(|t| &input[t.span.start..t.span.end])
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "\\section",
+                "{",
+                "Ab",
+                "}",
+                "% ça",
+                "\n\n",
+                "$",
+                "x",
+                "^",
+                "2",
+                "$",
+                "\\%",
+                "\\\\"
+            ]
+        );
+    }
+
     #[test]
     fn comprehensive_test_1() {
         let tokens = scan_str(
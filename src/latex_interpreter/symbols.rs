@@ -0,0 +1,628 @@
+//! The symbol table of a document: the commands and environments it defines
+//!
+//! The definitions recorded are
+//! 1. commands: `\newcommand`, `\renewcommand`, `\providecommand`, `\DeclareRobustCommand`,
+//!    `\DeclareMathOperator`, `\def` (and `\gdef`, `\edef`, `\xdef`), and `\NewDocumentCommand`
+//!    and the like of xparse
+//! 1. environments: `\newenvironment` and `\renewenvironment`
+//! 1. theorem environments: `\newtheorem` of amsthm and `\declaretheorem` of thmtools
+//!
+//! The parser sees a definition as any other command, and the args of the definition are not
+//! always its args: `\newcommand\foo[1]{#1}` is parsed as `\newcommand` without args followed by
+//! `\foo` with two, and `\newtheorem*{a}{A}` as `\newtheorem`, a word `*` and two bracket args.
+//! So the args of a definition are read as latex does, from the args of the defining command
+//! and the nodes after it.
+//! The definitions in verbatim environments are not recorded.
+
+use super::ast::{NodePtr, NodeType, Span, VERBATIM_ENVRS};
+use super::include_graph::IncludeGraph;
+use super::refs::Occurrence;
+use crate::utils::FileInput;
+
+const NEWCOMMAND: [&str; 4] = [
+    "newcommand",
+    "renewcommand",
+    "providecommand",
+    "DeclareRobustCommand",
+];
+const DEF: [&str; 4] = ["def", "gdef", "edef", "xdef"];
+const XPARSE: [&str; 4] = [
+    "NewDocumentCommand",
+    "RenewDocumentCommand",
+    "ProvideDocumentCommand",
+    "DeclareDocumentCommand",
+];
+const NEWENVIRONMENT: [&str; 2] = ["newenvironment", "renewenvironment"];
+
+/// A parameter of a macro
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Param {
+    /// `{...}`
+    Required,
+    /// `[...]`, with its default value
+    Optional(Option<String>),
+    /// `*`, the `s` of xparse
+    Star,
+}
+
+/// A command or environment defined by the document
+#[derive(Debug, Clone)]
+pub struct Definition {
+    /// The name, without the backslash of a command
+    pub name: String,
+    /// The defining command, as `newcommand`
+    pub command: String,
+    pub params: Vec<Param>,
+    /// The curly bracket arg of the replacement text, the begin code of an environment
+    pub body: NodePtr,
+    /// The text of body as written, without the brackets
+    pub replacement: String,
+    /// The end code of an environment
+    pub end: Option<NodePtr>,
+    /// Where the name is written in the definition
+    pub occurrence: Occurrence,
+}
+
+/// A theorem environment
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theorem {
+    /// The name of the environment, as `thm`
+    pub name: String,
+    /// The printed name, as `Theorem`
+    pub title: String,
+    /// Whether it is numbered, `\newtheorem*` is not
+    pub numbered: bool,
+    /// The theorem whose counter it shares, as in `\newtheorem{lemma}[theorem]{Lemma}`
+    pub shared: Option<String>,
+    /// The counter its number is within, as in `\newtheorem{theorem}{Theorem}[section]`
+    pub within: Option<String>,
+    pub occurrence: Occurrence,
+}
+
+/// The definitions of a document, in the order latex reads them (the files in the order of the
+/// include graph), redefinitions included
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    pub commands: Vec<Definition>,
+    pub environments: Vec<Definition>,
+    pub theorems: Vec<Theorem>,
+}
+
+impl SymbolTable {
+    pub fn build(graph: &IncludeGraph) -> Self {
+        let mut ret = SymbolTable::default();
+        for file in graph.get_files() {
+            ret.add_node(&file.file_input, &file.ast);
+        }
+        ret
+    }
+
+    /// The definitions of a single file, with its AST
+    pub fn from_file(file_input: &FileInput, ast: &NodePtr) -> Self {
+        let mut ret = SymbolTable::default();
+        ret.add_node(file_input, ast);
+        ret
+    }
+
+    /// The definition of the command named name (without the backslash) in effect at the end of
+    /// the document: the last one, except that `\providecommand` does not override
+    pub fn command(&self, name: &str) -> Option<&Definition> {
+        let mut ret: Option<&Definition> = None;
+        for d in self.commands.iter().filter(|d| d.name == name) {
+            let provide = d.command.starts_with("Provide")
+                || d.command == "providecommand";
+            if ret.is_none() || !provide {
+                ret = Some(d);
+            }
+        }
+        ret
+    }
+
+    /// The last definition of the environment named name
+    pub fn environment(&self, name: &str) -> Option<&Definition> {
+        self.environments.iter().rev().find(|d| d.name == name)
+    }
+
+    /// The theorem environment named name
+    pub fn theorem(&self, name: &str) -> Option<&Theorem> {
+        self.theorems.iter().rev().find(|t| t.name == name)
+    }
+
+    fn add_node(&mut self, file: &FileInput, node: &NodePtr) {
+        let children = {
+            let node = node.lock().unwrap();
+            if node.node_type == NodeType::Envr
+                && VERBATIM_ENVRS.contains(&node.lexeme.as_str())
+            {
+                return;
+            }
+            node.children.clone()
+        };
+        for (i, child) in children.iter().enumerate() {
+            let command = {
+                let child = child.lock().unwrap();
+                (child.node_type == NodeType::Command)
+                    .then(|| child.lexeme.clone())
+            };
+            if let Some(command) = command {
+                let mut args = Args::new(&children, i);
+                self.add_definition(file, &command, &mut args);
+            }
+            self.add_node(file, child);
+        }
+    }
+
+    fn add_definition(
+        &mut self,
+        file: &FileInput,
+        command: &str,
+        args: &mut Args,
+    ) {
+        let occurrence = |name: &str, span: Span| {
+            let (row, col) = file.get_row_col(span.start);
+            Occurrence {
+                key: name.to_string(),
+                file: file.get_file_path().clone(),
+                span,
+                row,
+                col,
+            }
+        };
+        let text = |arg: &NodePtr| {
+            let span = arg.lock().unwrap().get_content_span();
+            file.get_span_str(&span).trim().to_string()
+        };
+        // the spaces around the content are part of the replacement
+        let replacement = |body: &NodePtr| {
+            let span = body.lock().unwrap().span;
+            let text = file.get_span_str(&span);
+            let text = text.strip_prefix('{').unwrap_or(text);
+            text.strip_suffix('}').unwrap_or(text).to_string()
+        };
+
+        if NEWCOMMAND.contains(&command) || command == "DeclareMathOperator" {
+            args.star();
+            let Some((name, span)) = args.command_name() else {
+                return;
+            };
+            let mut params = vec![];
+            if command != "DeclareMathOperator" {
+                let count =
+                    args.square().map(|n| text(&n).parse().unwrap_or(0));
+                let default = args.square().map(|d| text(&d));
+                params = vec![Param::Required; count.unwrap_or(0)];
+                if let (Some(default), Some(first)) =
+                    (default, params.first_mut())
+                {
+                    *first = Param::Optional(Some(default));
+                }
+            }
+            let Some(body) = args.curly() else {
+                return;
+            };
+            self.commands.push(Definition {
+                occurrence: occurrence(&name, span),
+                name,
+                command: command.to_string(),
+                params,
+                replacement: replacement(&body),
+                body,
+                end: None,
+            });
+        } else if DEF.contains(&command) {
+            let Some((name, span)) = args.command_name() else {
+                return;
+            };
+            let params = vec![Param::Required; args.params()];
+            let Some(body) = args.curly() else {
+                return;
+            };
+            self.commands.push(Definition {
+                occurrence: occurrence(&name, span),
+                name,
+                command: command.to_string(),
+                params,
+                replacement: replacement(&body),
+                body,
+                end: None,
+            });
+        } else if XPARSE.contains(&command) {
+            let Some((name, span)) = args.command_name() else {
+                return;
+            };
+            let (Some(spec), Some(body)) = (args.curly(), args.curly()) else {
+                return;
+            };
+            self.commands.push(Definition {
+                occurrence: occurrence(&name, span),
+                name,
+                command: command.to_string(),
+                params: xparse_params(&text(&spec)),
+                replacement: replacement(&body),
+                body,
+                end: None,
+            });
+        } else if NEWENVIRONMENT.contains(&command) {
+            args.star();
+            let Some(name) = args.curly() else {
+                return;
+            };
+            let count = args.square().map(|n| text(&n).parse().unwrap_or(0));
+            let default = args.square().map(|d| text(&d));
+            let mut params = vec![Param::Required; count.unwrap_or(0)];
+            if let (Some(default), Some(first)) = (default, params.first_mut())
+            {
+                *first = Param::Optional(Some(default));
+            }
+            let (Some(body), Some(end)) = (args.curly(), args.curly()) else {
+                return;
+            };
+            let span = name.lock().unwrap().get_content_span();
+            self.environments.push(Definition {
+                occurrence: occurrence(&text(&name), span),
+                name: text(&name),
+                command: command.to_string(),
+                params,
+                replacement: replacement(&body),
+                body,
+                end: Some(end),
+            });
+        } else if command == "newtheorem" {
+            let numbered = !args.star();
+            let Some(name) = args.curly() else {
+                return;
+            };
+            let shared = args.square().map(|s| text(&s));
+            let Some(title) = args.curly() else {
+                return;
+            };
+            let within = args.square().map(|w| text(&w));
+            let span = name.lock().unwrap().get_content_span();
+            self.theorems.push(Theorem {
+                occurrence: occurrence(&text(&name), span),
+                name: text(&name),
+                title: text(&title),
+                numbered,
+                shared,
+                within,
+            });
+        } else if command == "declaretheorem" {
+            let mut options =
+                args.square().map(|o| text(&o)).unwrap_or_default();
+            let Some(name) = args.curly() else {
+                return;
+            };
+            if let Some(more) = args.square() {
+                options = format!("{},{}", options, text(&more));
+            }
+            let option = |key: &str| {
+                options.split(',').find_map(|o| {
+                    let (k, v) = o.split_once('=')?;
+                    (k.trim() == key)
+                        .then(|| v.trim().trim_matches(['{', '}']).to_string())
+                })
+            };
+            let name_text = text(&name);
+            // the title defaults to the name, capitalised
+            let mut chars = name_text.chars();
+            let capitalised = chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect())
+                .unwrap_or_default();
+            let span = name.lock().unwrap().get_content_span();
+            self.theorems.push(Theorem {
+                occurrence: occurrence(&name_text, span),
+                title: option("name")
+                    .or(option("title"))
+                    .unwrap_or(capitalised),
+                numbered: !matches!(
+                    option("numbered").as_deref(),
+                    Some("no" | "false")
+                ),
+                shared: option("sibling").or(option("sharenumber")),
+                within: option("parent").or(option("numberwithin")),
+                name: name_text,
+            });
+        }
+    }
+}
+
+/// The parameters of an xparse argument specification, as `s O{default} m`
+/// The delimited args of `r` and `d` count as required and optional ones
+fn xparse_params(spec: &str) -> Vec<Param> {
+    let mut ret = vec![];
+    let mut chars = spec.chars().peekable();
+    // the text of the `{...}` group at the start of chars
+    let group = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        chars.next_if_eq(&'{')?;
+        let mut depth = 1;
+        let mut text = String::new();
+        for c in chars.by_ref() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            text.push(c);
+        }
+        Some(text)
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            'm' | 'v' => ret.push(Param::Required),
+            'r' | 'R' => {
+                chars.next();
+                chars.next();
+                if c == 'R' {
+                    group(&mut chars);
+                }
+                ret.push(Param::Required);
+            }
+            'o' => ret.push(Param::Optional(None)),
+            'O' => ret.push(Param::Optional(group(&mut chars))),
+            'd' | 'D' => {
+                chars.next();
+                chars.next();
+                let default = if c == 'D' { group(&mut chars) } else { None };
+                ret.push(Param::Optional(default));
+            }
+            's' => ret.push(Param::Star),
+            't' => {
+                chars.next();
+                ret.push(Param::Star);
+            }
+            // the processors of `>{...}`, and the embellishments of `e{...}`
+            '>' | 'e' => {
+                group(&mut chars);
+            }
+            _ => {}
+        }
+    }
+    ret
+}
+
+/// An arg of a definition or of a macro call, see the module doc
+enum Arg {
+    Star,
+    Command(NodePtr),
+    Param,
+    Curly(NodePtr),
+    Square(NodePtr),
+}
+
+/// The args after a command, read one by one
+pub(crate) struct Args {
+    /// With their spans, only the name for a command
+    args: Vec<(Arg, Span)>,
+    pos: usize,
+}
+
+impl Args {
+    /// The args of the command siblings[i]: its bracket args, followed by the nodes after it
+    pub(crate) fn new(siblings: &[NodePtr], i: usize) -> Self {
+        let mut args = vec![];
+        let arg_of = |node: &NodePtr| {
+            let (node_type, span) = {
+                let n = node.lock().unwrap();
+                (n.node_type.clone(), n.span)
+            };
+            match node_type {
+                NodeType::CurlyBracketArg => {
+                    Some((Arg::Curly(node.clone()), span))
+                }
+                NodeType::SquareBracketArg => {
+                    Some((Arg::Square(node.clone()), span))
+                }
+                _ => None,
+            }
+        };
+        let children = siblings[i].lock().unwrap().children.clone();
+        args.extend(children.iter().filter_map(arg_of));
+        for sibling in &siblings[i + 1..] {
+            let (node_type, lexeme, span, children) = {
+                let s = sibling.lock().unwrap();
+                (
+                    s.node_type.clone(),
+                    s.lexeme.clone(),
+                    s.span,
+                    s.children.clone(),
+                )
+            };
+            match node_type {
+                NodeType::Word if lexeme == "*" && args.is_empty() => {
+                    args.push((Arg::Star, span))
+                }
+                NodeType::Command => {
+                    let name =
+                        Span::new(span.start, span.start + lexeme.len() + 1);
+                    args.push((Arg::Command(sibling.clone()), name));
+                    args.extend(children.iter().filter_map(arg_of));
+                }
+                NodeType::Parameter => args.push((Arg::Param, span)),
+                NodeType::CurlyBracketArg | NodeType::SquareBracketArg => {
+                    args.extend(arg_of(sibling))
+                }
+                NodeType::Comment => {}
+                _ => break,
+            }
+        }
+        Args { args, pos: 0 }
+    }
+
+    /// Where the last arg read ends, None if none is read
+    pub(crate) fn end(&self) -> Option<usize> {
+        self.pos.checked_sub(1).map(|i| self.args[i].1.end)
+    }
+
+    /// Read a `*`, and return whether there was one
+    pub(crate) fn star(&mut self) -> bool {
+        let ret = matches!(self.args.get(self.pos), Some((Arg::Star, _)));
+        if ret {
+            self.pos += 1;
+        }
+        ret
+    }
+
+    pub(crate) fn square(&mut self) -> Option<NodePtr> {
+        match self.args.get(self.pos) {
+            Some((Arg::Square(node), _)) => {
+                self.pos += 1;
+                Some(node.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn curly(&mut self) -> Option<NodePtr> {
+        match self.args.get(self.pos) {
+            Some((Arg::Curly(node), _)) => {
+                self.pos += 1;
+                Some(node.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Read the `#1#2...` of `\def`, and return their number
+    fn params(&mut self) -> usize {
+        let start = self.pos;
+        while matches!(self.args.get(self.pos), Some((Arg::Param, _))) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    /// Read the name of the command defined, as `\foo` or `{\foo}`, and return it without the
+    /// backslash, with its span
+    fn command_name(&mut self) -> Option<(String, Span)> {
+        let command = match &self.args.get(self.pos)?.0 {
+            Arg::Command(node) => node.clone(),
+            Arg::Curly(node) => {
+                let paragraph = node.lock().unwrap().children.first()?.clone();
+                let children = paragraph.lock().unwrap().children.clone();
+                match &children[..] {
+                    [command]
+                        if command.lock().unwrap().node_type
+                            == NodeType::Command =>
+                    {
+                        command.clone()
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        };
+        self.pos += 1;
+        let command = command.lock().unwrap();
+        // the span of a command includes its args
+        let start = command.span.start;
+        let span = Span::new(start, start + command.lexeme.len() + 1);
+        Some((command.lexeme.clone(), span))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_test_project;
+
+    #[test]
+    fn test_symbol_table() {
+        let dir = create_test_project(
+            "symbols",
+            &[
+                (
+                    "main.tex",
+                    r#"\documentclass{article}
+\newcommand{\R}{\mathbb{R}}
+\newcommand\pair[2][x]{(#1, #2)}
+\renewcommand*{\vec}[1]{\mathbf{#1}}
+\providecommand{\R}{R}
+\def\abs#1{|#1|}
+\DeclareMathOperator*{\argmax}{arg\,max}
+\NewDocumentCommand{\norm}{s O{2} m}{\|#3\|_{#2}}
+\newenvironment{note}[1][Note]{\textbf{#1}}{\par}
+\newtheorem{theorem}{Theorem}[section]
+\newtheorem{lemma}[theorem]{Lemma}
+\newtheorem*{remark}{Remark}
+\input{b}
+\begin{document}
+\end{document}"#,
+                ),
+                (
+                    "b.tex",
+                    "\\declaretheorem[name=Definition,numbered=no]{defn}",
+                ),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let table = SymbolTable::build(&graph);
+
+        let commands: Vec<(&str, &str, &Vec<Param>)> = table
+            .commands
+            .iter()
+            .map(|d| (d.name.as_str(), d.command.as_str(), &d.params))
+            .collect();
+        use Param::*;
+        assert_eq!(
+            commands,
+            vec![
+                ("R", "newcommand", &vec![]),
+                (
+                    "pair",
+                    "newcommand",
+                    &vec![Optional(Some("x".into())), Required]
+                ),
+                ("vec", "renewcommand", &vec![Required]),
+                ("R", "providecommand", &vec![]),
+                ("abs", "def", &vec![Required]),
+                ("argmax", "DeclareMathOperator", &vec![]),
+                (
+                    "norm",
+                    "NewDocumentCommand",
+                    &vec![Star, Optional(Some("2".into())), Required]
+                ),
+            ]
+        );
+        let r = table.command("R").unwrap();
+        assert_eq!(r.command, "newcommand");
+        assert_eq!((r.occurrence.row, r.occurrence.col), (1, 12));
+        let body = r.body.lock().unwrap().span;
+        assert_eq!(
+            &graph.get_main_file().file_input.get_span_str(&body),
+            &"{\\mathbb{R}}"
+        );
+        assert!(table.command("mathbb").is_none());
+
+        let note = table.environment("note").unwrap();
+        assert_eq!(note.params, vec![Optional(Some("Note".into()))]);
+        assert!(note.end.is_some());
+
+        let theorems: Vec<_> = table
+            .theorems
+            .iter()
+            .map(|t| {
+                (
+                    t.name.as_str(),
+                    t.title.as_str(),
+                    t.numbered,
+                    t.shared.as_deref(),
+                    t.within.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            theorems,
+            vec![
+                ("theorem", "Theorem", true, None, Some("section")),
+                ("lemma", "Lemma", true, Some("theorem"), None),
+                ("remark", "Remark", false, None, None),
+                ("defn", "Definition", false, None, None),
+            ]
+        );
+    }
+}
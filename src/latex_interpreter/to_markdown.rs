@@ -0,0 +1,700 @@
+//! Convert latex to markdown
+//!
+//! The AST is walked and markdown is emitted for the constructs which have a counterpart:
+//! 1. `\part`, `\chapter`, `\section` ... `\subparagraph` become headings, the highest level used
+//!    in the document becoming `#`
+//! 1. `\emph`, `\textit` and `\textbf` become `*` and `**`, `\texttt` becomes code
+//! 1. itemize, enumerate and description become lists, quote and quotation block quotes, verbatim
+//!    and lstlisting fenced code
+//! 1. Math is kept as it is, display math (`$$`, `\[`, equation, align, ...) between `$$` lines
+//! 1. `\cite{a,b}` becomes `[@a; @b]`, `\footnote` a footnote, `\href` and `\url` links, and
+//!    figures images with the caption as the alternative text
+//! 1. Layout commands like `\label`, `\maketitle` and `\vspace` are dropped
+//!
+//! Other commands are kept verbatim or dropped, see UnknownCommands. Other environments are kept
+//! verbatim, or replaced by their content.
+//!
+//! If there is a document environment, only its content is converted. The macros defined in the
+//! file are expanded first, see expand.
+//! Whitespace is not in the AST, so the space between two nodes is taken from the source.
+
+use std::error::Error;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::ast::{Node, NodePtr, NodeType, Span};
+use super::expand::Expander;
+use super::include_graph::parse_file;
+use super::symbols::SymbolTable;
+use super::wordcount::section_title;
+use crate::utils::FileInput;
+
+/// What to do with the commands which have no markdown counterpart
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Serialize,
+    Deserialize,
+    ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownCommands {
+    /// Keep the command and its arguments as they are in the source
+    #[default]
+    Keep,
+    /// Drop the command and its arguments. For environments, only the content is kept
+    Drop,
+}
+
+#[derive(Debug, Default)]
+pub struct MarkdownOptions {
+    pub unknown_commands: UnknownCommands,
+}
+
+const SECTIONS: [&str; 7] = [
+    "part",
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+
+const CITES: [&str; 7] = [
+    "cite",
+    "citep",
+    "parencite",
+    "autocite",
+    "footcite",
+    "citet",
+    "textcite",
+];
+
+/// Commands typesetting the text of their argument without a markdown counterpart
+const PLAIN: [&str; 10] = [
+    "textrm",
+    "textsf",
+    "textsc",
+    "textup",
+    "textmd",
+    "textnormal",
+    "underline",
+    "mbox",
+    "text",
+    "caption",
+];
+
+/// Commands dropped with their arguments, as they only affect the layout (or the bibliography)
+const DROPPED: [&str; 27] = [
+    "label",
+    "maketitle",
+    "tableofcontents",
+    "listoffigures",
+    "listoftables",
+    "appendix",
+    "newpage",
+    "clearpage",
+    "cleardoublepage",
+    "pagebreak",
+    "linebreak",
+    "noindent",
+    "centering",
+    "par",
+    "vspace",
+    "hspace",
+    "smallskip",
+    "medskip",
+    "bigskip",
+    "vfill",
+    "hfill",
+    "phantomsection",
+    "index",
+    "printbibliography",
+    "bibliography",
+    "bibliographystyle",
+    "addbibresource",
+];
+
+const DISPLAY_MATH_ENVRS: [&str; 5] =
+    ["equation", "equation*", "displaymath", "math", "eqnarray"];
+
+/// Math environments kept as they are between `$$`, which MathJax understands
+const ALIGN_ENVRS: [&str; 8] = [
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "flalign",
+    "flalign*",
+];
+
+struct MdState<'a> {
+    source: &'a str,
+    options: &'a MarkdownOptions,
+    /// The index in SECTIONS of the highest sectioning command used, which becomes `#`
+    top_level: usize,
+    footnotes: Vec<String>,
+}
+
+impl MdState<'_> {
+    fn span_str(&self, span: Span) -> &str {
+        &self.source[span.start..span.end]
+    }
+}
+
+/// Convert the latex of file_input to markdown, see the module doc
+pub fn to_markdown(
+    file_input: &FileInput,
+    options: &MarkdownOptions,
+) -> Result<String, Box<dyn Error>> {
+    let ast = parse_file(file_input)?;
+    let symbols = SymbolTable::from_file(file_input, &ast);
+    let expanded = Expander::new(&symbols).expand(file_input, &ast);
+    let ast = if expanded.edits.is_empty() {
+        ast
+    } else {
+        parse_file(&expanded.file_input)?
+    };
+    let root = Node::find_document_body_nodeptr(ast.clone()).unwrap_or(ast);
+
+    let top_level = Node::find_all_nodeptr(root.clone(), &|n: &Node| {
+        n.node_type == NodeType::Command
+            && SECTIONS.contains(&n.lexeme.as_str())
+    })
+    .iter()
+    .filter_map(|n| section_level(&n.lock().unwrap().lexeme))
+    .min()
+    .unwrap_or(0);
+
+    let mut state = MdState {
+        source: expanded.file_input.get_str_content(),
+        options,
+        top_level,
+        footnotes: vec![],
+    };
+    let mut blocks = passage_blocks(&root, &mut state);
+    if !state.footnotes.is_empty() {
+        let footnotes: Vec<String> = state
+            .footnotes
+            .iter()
+            .enumerate()
+            .map(|(i, f)| format!("[^{}]: {}", i + 1, f))
+            .collect();
+        blocks.push(footnotes.join("\n"));
+    }
+
+    let mut ret = blocks.join("\n\n");
+    if !ret.is_empty() {
+        ret.push('\n');
+    }
+    Ok(ret)
+}
+
+fn section_level(name: &str) -> Option<usize> {
+    SECTIONS.iter().position(|s| *s == name)
+}
+
+fn children_of(node: &NodePtr) -> Vec<NodePtr> {
+    node.lock().unwrap().children.clone()
+}
+
+/// The markdown blocks of the paragraphs of a passage
+fn passage_blocks(passage: &NodePtr, state: &mut MdState) -> Vec<String> {
+    let mut ret: Vec<String> = vec![];
+    for paragraph in children_of(passage) {
+        ret.extend(paragraph_blocks(&children_of(&paragraph), state));
+    }
+    ret
+}
+
+/// The markdown blocks of the nodes of a paragraph
+/// Most nodes are inline and joined into one markdown paragraph, but headings, environments and
+/// display math are blocks by themselves
+fn paragraph_blocks(nodes: &[NodePtr], state: &mut MdState) -> Vec<String> {
+    let mut blocks: Vec<String> = vec![];
+    let mut text = String::new();
+    let mut prev_end: Option<usize> = None;
+
+    fn flush(text: &mut String, blocks: &mut Vec<String>) {
+        if !text.trim().is_empty() {
+            blocks.push(text.trim().to_string());
+        }
+        text.clear();
+    }
+
+    let mut i = 0;
+    while i < nodes.len() {
+        let node = nodes[i].clone();
+        let (node_type, lexeme, span) = {
+            let n = node.lock().unwrap();
+            (n.node_type.clone(), n.lexeme.clone(), n.span)
+        };
+        match node_type {
+            NodeType::Command if section_level(&lexeme).is_some() => {
+                flush(&mut text, &mut blocks);
+                let (title, skipped) = section_title(nodes, i);
+                i += skipped;
+                let level = section_level(&lexeme).unwrap();
+                let level = (level.saturating_sub(state.top_level) + 1).min(6);
+                let title =
+                    title.map(|t| inline(&t, state)).unwrap_or_default();
+                blocks.push(format!("{} {}", "#".repeat(level), title));
+                prev_end = None;
+            }
+            NodeType::Envr | NodeType::DisplayMath => {
+                flush(&mut text, &mut blocks);
+                if node_type == NodeType::Envr {
+                    blocks.extend(envr_blocks(&node, state));
+                } else {
+                    blocks.push(display_math(state.span_str(span)));
+                }
+                prev_end = None;
+            }
+            NodeType::Comment => {}
+            _ => {
+                let rendered = inline(&node, state);
+                if rendered.is_empty() {
+                    // a dropped command, the space around it is kept only once
+                    prev_end = prev_end.or(Some(span.end));
+                    i += 1;
+                    continue;
+                }
+                if let Some(prev_end) = prev_end {
+                    let gap = &state.source[prev_end..span.start];
+                    // `word%comment` joins the words, as latex does
+                    if gap.starts_with(char::is_whitespace) && !text.is_empty()
+                    {
+                        text.push(' ');
+                    }
+                }
+                text.push_str(&rendered);
+                prev_end = Some(span.end);
+            }
+        }
+        i += 1;
+    }
+    flush(&mut text, &mut blocks);
+    blocks
+}
+
+/// The markdown of a node inside a markdown paragraph
+fn inline(node: &NodePtr, state: &mut MdState) -> String {
+    let (node_type, lexeme, span) = {
+        let n = node.lock().unwrap();
+        (n.node_type.clone(), n.lexeme.clone(), n.span)
+    };
+    match node_type {
+        NodeType::Word => word(&lexeme),
+        NodeType::Operation if lexeme == "~" => " ".to_string(),
+        NodeType::Operation | NodeType::InlineMath => {
+            state.span_str(span).replace('\n', " ")
+        }
+        NodeType::Ampersand => "&".to_string(),
+        NodeType::Comment => String::new(),
+        NodeType::Command => command(node, &lexeme, state),
+        NodeType::SquareBracketArg => {
+            format!(
+                "[{}]",
+                paragraph_blocks(&children_of(node), state).join(" ")
+            )
+        }
+        NodeType::CurlyBracketArg
+        | NodeType::Paragraph
+        | NodeType::Passage
+        | NodeType::DisplayMath
+        | NodeType::Envr => {
+            let blocks = match node_type {
+                NodeType::CurlyBracketArg | NodeType::Paragraph => {
+                    let nodes: Vec<NodePtr> = match node_type {
+                        NodeType::CurlyBracketArg => children_of(node)
+                            .first()
+                            .map(children_of)
+                            .unwrap_or_default(),
+                        _ => children_of(node),
+                    };
+                    paragraph_blocks(&nodes, state)
+                }
+                _ => paragraph_blocks(std::slice::from_ref(node), state),
+            };
+            blocks.join(" ")
+        }
+        _ => state.span_str(span).to_string(),
+    }
+}
+
+/// The text of a word, with the escaped characters and the ligatures of latex resolved
+fn word(lexeme: &str) -> String {
+    if let Some(c) = lexeme.strip_prefix('\\') {
+        return match c {
+            // still special in markdown
+            "#" | "_" | "*" | "$" => lexeme.to_string(),
+            " " => " ".to_string(),
+            _ => c.to_string(),
+        };
+    }
+    match lexeme {
+        // the line break `\\`
+        "\n" => "\\\n".to_string(),
+        _ => lexeme
+            .replace("---", "—")
+            .replace("--", "–")
+            .replace("``", "\"")
+            .replace("''", "\"")
+            .replace('*', "\\*"),
+    }
+}
+
+/// The source text of the content of the nth curly bracket arg of a command, with the escaped
+/// characters unescaped. For urls, file names, keys and code
+fn raw_arg(node: &NodePtr, n: usize, state: &MdState) -> String {
+    let Some(arg) = node
+        .lock()
+        .unwrap()
+        .get_curly_bracket_args()
+        .get(n)
+        .cloned()
+    else {
+        return String::new();
+    };
+    let span = arg.lock().unwrap().get_content_span();
+    let mut ret = String::new();
+    let mut chars = state.span_str(span).chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' && chars.peek().is_some_and(|n| n.is_ascii_punctuation()) {
+            continue;
+        }
+        ret.push(c);
+    }
+    ret.trim().to_string()
+}
+
+fn command(node: &NodePtr, name: &str, state: &mut MdState) -> String {
+    let args = node.lock().unwrap().get_curly_bracket_args();
+    let mut arg =
+        |n: usize| args.get(n).map(|a| inline(a, state)).unwrap_or_default();
+    match name {
+        "emph" | "textit" | "textsl" => format!("*{}*", arg(0)),
+        "textbf" => format!("**{}**", arg(0)),
+        "texttt" => format!("`{}`", raw_arg(node, 0, state)),
+        "footnote" => {
+            let text = arg(0);
+            state.footnotes.push(text);
+            format!("[^{}]", state.footnotes.len())
+        }
+        "url" => format!("<{}>", raw_arg(node, 0, state)),
+        "href" => format!("[{}]({})", arg(1), raw_arg(node, 0, state)),
+        "includegraphics" => format!("![]({})", raw_arg(node, 0, state)),
+        "LaTeX" => "LaTeX".to_string(),
+        "TeX" => "TeX".to_string(),
+        "ldots" | "dots" | "textellipsis" => "...".to_string(),
+        _ if CITES.contains(&name) => citation(node, name, state),
+        _ if PLAIN.contains(&name) => arg(0),
+        _ if DROPPED.contains(&name) => String::new(),
+        _ => match state.options.unknown_commands {
+            UnknownCommands::Keep => {
+                let span = node.lock().unwrap().span;
+                state.span_str(span).to_string()
+            }
+            UnknownCommands::Drop => String::new(),
+        },
+    }
+}
+
+/// `\cite[p. 3]{a,b}` gives `[@a; @b, p. 3]`, `\textcite{a}` gives `@a`, in the syntax of pandoc
+fn citation(node: &NodePtr, name: &str, state: &mut MdState) -> String {
+    let keys: Vec<String> = raw_arg(node, 0, state)
+        .split(',')
+        .filter(|k| !k.trim().is_empty())
+        .map(|k| format!("@{}", k.trim()))
+        .collect();
+    let notes: Vec<String> = children_of(node)
+        .iter()
+        .filter(|c| {
+            Node::get_node_type_nodeptr((*c).clone())
+                == NodeType::SquareBracketArg
+        })
+        .map(|c| {
+            paragraph_blocks(
+                &children_of(c).first().map(children_of).unwrap_or_default(),
+                state,
+            )
+            .join(" ")
+        })
+        .collect();
+    // with one optional arg it is the postnote, with two the prenote and the postnote
+    let (pre, post) = match notes.as_slice() {
+        [post] => ("", post.as_str()),
+        [pre, post, ..] => (pre.as_str(), post.as_str()),
+        [] => ("", ""),
+    };
+
+    if name == "citet" || name == "textcite" {
+        let mut ret = keys.join(", ");
+        if !post.is_empty() {
+            ret.push_str(&format!(" [{}]", post));
+        }
+        return ret;
+    }
+    let mut ret = String::from("[");
+    if !pre.is_empty() {
+        ret.push_str(pre);
+        ret.push(' ');
+    }
+    ret.push_str(&keys.join("; "));
+    if !post.is_empty() {
+        ret.push_str(", ");
+        ret.push_str(post);
+    }
+    ret.push(']');
+    ret
+}
+
+fn envr_blocks(node: &NodePtr, state: &mut MdState) -> Vec<String> {
+    let (name, span) = {
+        let n = node.lock().unwrap();
+        (n.lexeme.clone(), n.span)
+    };
+    let body = Node::get_nth_child_nodeptr(node.clone(), 0)
+        .unwrap_or_else(Node::empty_passage_ptr);
+    match name.as_str() {
+        "document" | "abstract" | "center" | "flushleft" | "flushright" => {
+            passage_blocks(&body, state)
+        }
+        "itemize" | "enumerate" | "description" => {
+            vec![list(&body, &name, state)]
+        }
+        "quote" | "quotation" | "verse" => {
+            let content = passage_blocks(&body, state).join("\n\n");
+            let lines: Vec<String> = content
+                .lines()
+                .map(|l| {
+                    if l.is_empty() {
+                        ">".into()
+                    } else {
+                        format!("> {}", l)
+                    }
+                })
+                .collect();
+            vec![lines.join("\n")]
+        }
+        "verbatim" | "Verbatim" | "lstlisting" => {
+            vec![format!("```\n{}\n```", envr_content(state.span_str(span)))]
+        }
+        "comment" => vec![],
+        "figure" | "figure*" => figure(&body, state),
+        _ if DISPLAY_MATH_ENVRS.contains(&name.as_str()) => {
+            vec![format!("$$\n{}\n$$", envr_content(state.span_str(span)))]
+        }
+        _ if ALIGN_ENVRS.contains(&name.as_str()) => {
+            vec![format!("$$\n{}\n$$", state.span_str(span))]
+        }
+        _ => match state.options.unknown_commands {
+            UnknownCommands::Keep => vec![state.span_str(span).to_string()],
+            UnknownCommands::Drop => passage_blocks(&body, state),
+        },
+    }
+}
+
+/// The text between `\begin{name}` and `\end{name}` of the source of an environment
+fn envr_content(envr: &str) -> &str {
+    let start = envr.find('}').map_or(0, |i| i + 1);
+    let end = envr.rfind("\\end").unwrap_or(envr.len()).max(start);
+    envr[start..end]
+        .trim_start_matches([' ', '\t'])
+        .trim_start_matches('\n')
+        .trim_end()
+}
+
+/// `$$ x $$` or `\[ x \]` as a display math block, the closing delimiter being optional
+fn display_math(math: &str) -> String {
+    let (inner, close) = match math.strip_prefix("\\[") {
+        Some(inner) => (inner, "\\]"),
+        None => (math.strip_prefix("$$").unwrap_or(math), "$$"),
+    };
+    let inner = inner.strip_suffix(close).unwrap_or(inner);
+    format!("$$\n{}\n$$", inner.trim())
+}
+
+/// An item of a list, with its label (of description) and the nodes of its paragraphs
+type Item = (Option<NodePtr>, Vec<Vec<NodePtr>>);
+
+fn list(body: &NodePtr, name: &str, state: &mut MdState) -> String {
+    let mut items: Vec<Item> = vec![];
+    for paragraph in children_of(body) {
+        let mut cur: Vec<NodePtr> = vec![];
+        for child in children_of(&paragraph) {
+            let (is_item, label, args) = {
+                let c = child.lock().unwrap();
+                let is_item =
+                    c.node_type == NodeType::Command && c.lexeme == "item";
+                let label = c
+                    .children
+                    .iter()
+                    .find(|a| {
+                        a.lock().unwrap().node_type
+                            == NodeType::SquareBracketArg
+                    })
+                    .cloned();
+                (is_item, label, c.get_curly_bracket_args())
+            };
+            if !is_item {
+                cur.push(child);
+                continue;
+            }
+            // the text before the first item is lost, as in latex
+            if let Some(last) = items.last_mut() {
+                if !cur.is_empty() {
+                    last.1.push(std::mem::take(&mut cur));
+                }
+            }
+            cur.clear();
+            // `\item {text}` is parsed as an arg of \item
+            cur.extend(args);
+            items.push((label, vec![]));
+        }
+        if let Some(last) = items.last_mut() {
+            if !cur.is_empty() {
+                last.1.push(cur);
+            }
+        }
+    }
+
+    let mut ret: Vec<String> = vec![];
+    for (n, (label, paragraphs)) in items.iter().enumerate() {
+        let marker = match name {
+            "enumerate" => format!("{}. ", n + 1),
+            _ => "- ".to_string(),
+        };
+        let mut blocks: Vec<String> = vec![];
+        for p in paragraphs {
+            blocks.extend(paragraph_blocks(p, state));
+        }
+        let mut content = blocks.join("\n\n");
+        if let Some(label) = label {
+            let label = inline(label, state);
+            let label = label.trim_start_matches('[').trim_end_matches(']');
+            content = format!("**{}** {}", label, content);
+        }
+        let indent = " ".repeat(marker.len());
+        let lines: Vec<String> = content
+            .lines()
+            .enumerate()
+            .map(|(i, l)| match (i, l.is_empty()) {
+                (0, _) | (_, true) => l.to_string(),
+                _ => format!("{}{}", indent, l),
+            })
+            .collect();
+        ret.push(format!("{}{}", marker, lines.join("\n")));
+    }
+    ret.join("\n")
+}
+
+/// The images of a figure, with the caption as the alternative text
+fn figure(body: &NodePtr, state: &mut MdState) -> Vec<String> {
+    let graphics = Node::find_commands_nodeptr(body.clone(), "includegraphics");
+    if graphics.is_empty() {
+        return passage_blocks(body, state);
+    }
+    let caption =
+        match Node::find_commands_nodeptr(body.clone(), "caption").first() {
+            Some(c) => command(c, "caption", state),
+            None => String::new(),
+        };
+    graphics
+        .iter()
+        .map(|g| format!("![{}]({})", caption, raw_arg(g, 0, state)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn convert(source: &str, unknown_commands: UnknownCommands) -> String {
+        let file_input = FileInput::from_str("test.tex", source);
+        to_markdown(&file_input, &MarkdownOptions { unknown_commands }).unwrap()
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        let source = r##"\documentclass{article}
+\title{Ars}
+\begin{document}
+\maketitle
+\section{Intro}\label{sec:intro}
+Arma \emph{virumque} cano, \textbf{Troiae} qui% comment
+primus ab oris $x^2$ \cite[p. 3]{vergil,homer}.
+50\% of the \unknown{text}.
+
+\subsection*{Plan}
+\begin{itemize}
+  \item one
+  \item two
+  \begin{enumerate}
+    \item nested
+  \end{enumerate}
+\end{itemize}
+\[ e = mc^2 \]
+\end{document}
+"##;
+        assert_eq!(
+            convert(source, UnknownCommands::Keep),
+            r##"# Intro
+
+Arma *virumque* cano, **Troiae** quiprimus ab oris $x^2$ [@vergil; @homer, p. 3]. 50% of the \unknown{text}.
+
+## Plan
+
+- one
+- two
+
+  1. nested
+
+$$
+e = mc^2
+$$
+"##
+        );
+    }
+
+    #[test]
+    fn test_unknown_commands() {
+        let source =
+            "A \\unknown{x} b.\n\n\\begin{theorem}\nT\n\\end{theorem}\n";
+        assert_eq!(
+            convert(source, UnknownCommands::Keep),
+            "A \\unknown{x} b.\n\n\\begin{theorem}\nT\n\\end{theorem}\n"
+        );
+        assert_eq!(convert(source, UnknownCommands::Drop), "A b.\n\nT\n");
+
+        // the macros defined are expanded
+        let source = "\\newcommand\\known[1]{\\emph{#1}}\n\\begin{document}\nA \\known{x} b.\n\\end{document}";
+        assert_eq!(convert(source, UnknownCommands::Drop), "A *x* b.\n");
+    }
+
+    #[test]
+    fn test_footnote_and_figure() {
+        let source = "Text\\footnote{See \\url{http://a.org}.}.\n\n\\begin{figure}\n\\centering\n\\includegraphics[width=5cm]{figs/a.png}\n\\caption{A \\emph{figure}}\n\\end{figure}\n\n\\begin{quote}\nQuoted\n\\end{quote}";
+        assert_eq!(
+            convert(source, UnknownCommands::Keep),
+            "Text[^1].\n\n![A *figure*](figs/a.png)\n\n> Quoted\n\n[^1]: See <http://a.org>.\n"
+        );
+    }
+
+    #[test]
+    fn test_unclosed_display_math() {
+        assert_eq!(
+            convert("\\[ x = éa", UnknownCommands::Keep),
+            "$$\nx = éa\n$$\n"
+        );
+        assert_eq!(convert("\\[ abc", UnknownCommands::Keep), "$$\nabc\n$$\n");
+    }
+}
@@ -9,6 +9,33 @@ pub struct Token {
     pub lexeme: String,
     pub row: usize, // row (line) number in the source file, starting from 0
     pub col: usize, // column number in the source file, starting from 0
+    pub span: Span, // location in the source file, see Span
+}
+
+/// The location of a token (or an AST node) in the source file.
+/// start and end are byte offsets into the source string, end is exclusive, so
+/// `&source[span.start..span.end]` is the exact text the token (node) is scanned (parsed) from.
+///
+/// Unlike the lexeme, which may be shortened (Command(begin) for `\begin`) or rearranged by the
+/// parser, the span always points back to the original text.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// The smallest span covering both self and other
+    pub fn merge(&self, other: &Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
@@ -69,6 +96,7 @@ impl Token {
             lexeme,
             row,
             col,
+            span: Span::default(),
         }
     }
 
@@ -0,0 +1,495 @@
+//! Count the words of a document by category, across the include graph
+//!
+//! The document is walked in the order latex reads it, following `\input`, `\include`, etc into
+//! the included files, so that a section goes on in the files included in it.
+//! 1. A Word node containing a letter or a digit is a word, unless it is glued to the previous
+//!    word, as in `50\%-ish`
+//! 1. The words are counted as body text, headers (the titles of `\section` and the like),
+//!    captions or footnotes
+//! 1. Inline and display math (`$$`, `\[`, equation, align, ...) are counted as formulas
+//! 1. Figure and table environments are counted as floats. Their words are not counted, except
+//!    those of the captions
+//! 1. The args of commands like `\label`, `\cite` and `\includegraphics` are not counted, nor are
+//!    verbatim and tabular environments, see detex
+//!
+//! The calls of the macros defined by the document are counted as their expansions, see expand.
+//! A section in an expansion is at the call, and the includes in it are not followed.
+//!
+//! If the main file has a document environment, only its content is counted.
+//! The counts are broken down by section.
+
+use std::collections::HashSet;
+use std::ops::AddAssign;
+use std::path::PathBuf;
+
+use super::ast::{Node, NodePtr, NodeType, Span};
+use super::detex::{DROPPED_ENVRS, MATH_ENVRS, NON_PROSE};
+use super::edit::Edit;
+use super::expand::Expander;
+use super::include_graph::{parse_file, IncludeGraph, IncludeKind, SourceFile};
+use super::symbols::SymbolTable;
+use crate::utils::FileInput;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counts {
+    pub body: usize,
+    pub headers: usize,
+    pub captions: usize,
+    pub footnotes: usize,
+    /// The number of inline formulas
+    pub inline_math: usize,
+    /// The number of displayed formulas
+    pub display_math: usize,
+    /// The number of figures and tables
+    pub floats: usize,
+}
+
+impl Counts {
+    /// The words of all categories
+    pub fn words(&self) -> usize {
+        self.body + self.headers + self.captions + self.footnotes
+    }
+}
+
+impl AddAssign for Counts {
+    fn add_assign(&mut self, other: Self) {
+        self.body += other.body;
+        self.headers += other.headers;
+        self.captions += other.captions;
+        self.footnotes += other.footnotes;
+        self.inline_math += other.inline_math;
+        self.display_math += other.display_math;
+        self.floats += other.floats;
+    }
+}
+
+/// The counts of a section, excluding those of its subsections
+#[derive(Debug)]
+pub struct SectionCount {
+    /// The sectioning command, like `section`, None for the text before the first section
+    pub command: Option<String>,
+    pub title: String,
+    pub file: PathBuf,
+    /// The row of the sectioning command, starting from 0
+    pub row: usize,
+    pub counts: Counts,
+}
+
+#[derive(Debug)]
+pub struct WordCount {
+    /// In document order
+    pub sections: Vec<SectionCount>,
+}
+
+impl WordCount {
+    pub fn total(&self) -> Counts {
+        let mut ret = Counts::default();
+        for section in &self.sections {
+            ret += section.counts;
+        }
+        ret
+    }
+}
+
+pub const SECTIONS: [&str; 7] = [
+    "part",
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+
+/// The title of the section command nodes[i], and the number of nodes after it the title takes
+/// `\section*{title}` is parsed as the command, a word `*` and a bracket arg
+pub(crate) fn section_title(
+    nodes: &[NodePtr],
+    i: usize,
+) -> (Option<NodePtr>, usize) {
+    let title = nodes[i].lock().unwrap().get_curly_bracket_args().pop();
+    if title.is_none()
+        && i + 2 < nodes.len()
+        && nodes[i + 1].lock().unwrap().lexeme == "*"
+        && Node::get_node_type_nodeptr(nodes[i + 2].clone())
+            == NodeType::CurlyBracketArg
+    {
+        return (Some(nodes[i + 2].clone()), 2);
+    }
+    (title, 0)
+}
+
+const FLOATS: [&str; 4] = ["figure", "figure*", "table", "table*"];
+
+/// Where the words being walked are counted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Category {
+    Body,
+    Header,
+    Caption,
+    Footnote,
+    /// Inside floats, outside captions
+    Ignored,
+}
+
+struct CountState<'a> {
+    graph: &'a IncludeGraph,
+    expander: Expander<'a>,
+    /// The file and row of the macro call whose expansion is being counted
+    call: Option<(PathBuf, usize)>,
+    /// The files already counted
+    visited: HashSet<PathBuf>,
+    sections: Vec<SectionCount>,
+    category: Category,
+}
+
+impl CountState<'_> {
+    fn counts(&mut self) -> &mut Counts {
+        &mut self.sections.last_mut().unwrap().counts
+    }
+
+    fn count_word(&mut self) {
+        let category = self.category;
+        let counts = self.counts();
+        match category {
+            Category::Body => counts.body += 1,
+            Category::Header => counts.headers += 1,
+            Category::Caption => counts.captions += 1,
+            Category::Footnote => counts.footnotes += 1,
+            Category::Ignored => {}
+        }
+    }
+
+    fn with_category(&mut self, category: Category, f: impl FnOnce(&mut Self)) {
+        let prev = std::mem::replace(&mut self.category, category);
+        f(self);
+        self.category = prev;
+    }
+}
+
+/// Count the words of the document, see the module doc
+pub fn count_words(graph: &IncludeGraph) -> WordCount {
+    let main = graph.get_main_file();
+    let symbols = SymbolTable::build(graph);
+    let mut state = CountState {
+        graph,
+        expander: Expander::new(&symbols),
+        call: None,
+        visited: HashSet::from([main.get_path().clone()]),
+        sections: vec![SectionCount {
+            command: None,
+            title: String::new(),
+            file: main.get_path().clone(),
+            row: 0,
+            counts: Counts::default(),
+        }],
+        category: Category::Body,
+    };
+    let root = Node::find_document_body_nodeptr(main.ast.clone())
+        .unwrap_or(main.ast.clone());
+    passage(&root, main, &mut state);
+
+    let mut sections = state.sections;
+    if sections.len() > 1 && sections[0].counts == Counts::default() {
+        sections.remove(0);
+    }
+    WordCount { sections }
+}
+
+fn children_of(node: &NodePtr) -> Vec<NodePtr> {
+    node.lock().unwrap().children.clone()
+}
+
+fn passage(node: &NodePtr, file: &SourceFile, state: &mut CountState) {
+    for paragraph in children_of(node) {
+        nodes(&children_of(&paragraph), file, state);
+    }
+}
+
+fn nodes(nodes: &[NodePtr], file: &SourceFile, state: &mut CountState) {
+    let mut prev_word_end: Option<usize> = None;
+    let mut i = 0;
+    while i < nodes.len() {
+        let (node_type, lexeme, span) = {
+            let n = nodes[i].lock().unwrap();
+            (n.node_type.clone(), n.lexeme.clone(), n.span)
+        };
+        match node_type {
+            NodeType::Word => {
+                let glued = prev_word_end == Some(span.start);
+                if !glued && lexeme.chars().any(char::is_alphanumeric) {
+                    state.count_word();
+                }
+                prev_word_end = Some(span.end);
+                i += 1;
+                continue;
+            }
+            NodeType::Command if SECTIONS.contains(&lexeme.as_str()) => {
+                let (title, skipped) = section_title(nodes, i);
+                i += skipped;
+                section(&lexeme, span, title, file, state);
+            }
+            // the expansions are expanded in full
+            NodeType::Command if state.call.is_none() => {
+                let edit =
+                    state.expander.expand_call(&file.file_input, nodes, i);
+                match edit {
+                    Some(edit) => {
+                        expansion(&edit, file, state);
+                        // the args of the call
+                        while i + 1 < nodes.len()
+                            && nodes[i + 1].lock().unwrap().span.start
+                                < edit.span.end
+                        {
+                            i += 1;
+                        }
+                    }
+                    None => node(&nodes[i], file, state),
+                }
+            }
+            _ => node(&nodes[i], file, state),
+        }
+        prev_word_end = None;
+        i += 1;
+    }
+}
+
+fn section(
+    command: &str,
+    span: Span,
+    title: Option<NodePtr>,
+    file: &SourceFile,
+    state: &mut CountState,
+) {
+    let title_text = match &title {
+        Some(t) => {
+            let span = t.lock().unwrap().get_content_span();
+            let text = file.file_input.get_span_str(&span);
+            text.split_whitespace().collect::<Vec<&str>>().join(" ")
+        }
+        None => String::new(),
+    };
+    let (path, row) = match &state.call {
+        Some(call) => call.clone(),
+        None => (
+            file.get_path().clone(),
+            file.file_input.get_row_col(span.start).0,
+        ),
+    };
+    state.sections.push(SectionCount {
+        command: Some(command.to_string()),
+        title: title_text,
+        file: path,
+        row,
+        counts: Counts::default(),
+    });
+    if let Some(title) = title {
+        state
+            .with_category(Category::Header, |state| node(&title, file, state));
+    }
+}
+
+fn node(n: &NodePtr, file: &SourceFile, state: &mut CountState) {
+    let (node_type, lexeme, span) = {
+        let n = n.lock().unwrap();
+        (n.node_type.clone(), n.lexeme.clone(), n.span)
+    };
+    let counted = state.category != Category::Ignored;
+    match node_type {
+        NodeType::InlineMath if counted => state.counts().inline_math += 1,
+        NodeType::DisplayMath if counted => state.counts().display_math += 1,
+        NodeType::CurlyBracketArg
+        | NodeType::SquareBracketArg
+        | NodeType::Paragraph => {
+            for paragraph in children_of(n) {
+                match Node::get_node_type_nodeptr(paragraph.clone()) {
+                    NodeType::Paragraph => {
+                        nodes(&children_of(&paragraph), file, state)
+                    }
+                    _ => node(&paragraph, file, state),
+                }
+            }
+        }
+        NodeType::Passage => passage(n, file, state),
+        NodeType::Command => command(n, &lexeme, span, file, state),
+        NodeType::Envr => {
+            let body = Node::get_nth_child_nodeptr(n.clone(), 0);
+            let Some(body) = body else { return };
+            let name = lexeme.as_str();
+            if FLOATS.contains(&name) {
+                if counted {
+                    state.counts().floats += 1;
+                }
+                state.with_category(Category::Ignored, |state| {
+                    passage(&body, file, state)
+                });
+            } else if MATH_ENVRS.contains(&name) {
+                if counted {
+                    state.counts().display_math += 1;
+                }
+            } else if !DROPPED_ENVRS.contains(&name) {
+                passage(&body, file, state);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn command(
+    n: &NodePtr,
+    name: &str,
+    span: Span,
+    file: &SourceFile,
+    state: &mut CountState,
+) {
+    let args = n.lock().unwrap().get_curly_bracket_args();
+    let category = match name {
+        "caption" => Category::Caption,
+        "footnote" => Category::Footnote,
+        _ if NON_PROSE.contains(&name) => {
+            if matches!(
+                name,
+                "input" | "include" | "subfile" | "import" | "subimport"
+            ) {
+                include(span, file, state);
+            }
+            return;
+        }
+        _ => state.category,
+    };
+    state.with_category(category, |state| {
+        for arg in &args {
+            node(arg, file, state);
+        }
+    });
+}
+
+/// Count the expansion of the macro call of file replaced by edit
+fn expansion(edit: &Edit, file: &SourceFile, state: &mut CountState) {
+    let file_input = FileInput::from_str(
+        &file.get_path().to_string_lossy(),
+        &edit.replacement,
+    );
+    let Ok(ast) = parse_file(&file_input) else {
+        return;
+    };
+    let (row, _) = file.file_input.get_row_col(edit.span.start);
+    let expansion = SourceFile {
+        file_input,
+        ast: ast.clone(),
+        includes: vec![],
+        input_dir: file.input_dir.clone(),
+    };
+    state.call = Some((file.get_path().clone(), row));
+    passage(&ast, &expansion, state);
+    state.call = None;
+}
+
+/// Count the file included by the command at span
+fn include(span: Span, file: &SourceFile, state: &mut CountState) {
+    let Some((inc, child)) = state.graph.get_included(file, span) else {
+        return;
+    };
+    if !state.visited.insert(child.get_path().clone()) {
+        return;
+    }
+    let root = match inc.kind {
+        // a subfile is a document by itself
+        IncludeKind::Subfile => {
+            Node::find_document_body_nodeptr(child.ast.clone())
+                .unwrap_or(child.ast.clone())
+        }
+        _ => child.ast.clone(),
+    };
+    passage(&root, child, state);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::utils::create_test_project;
+
+    #[test]
+    fn test_count_words() {
+        let dir = create_test_project(
+            "wordcount",
+            &[
+                (
+                    "main.tex",
+                    r##"\documentclass{article}
+\title{Not counted}
+\begin{document}
+Arma virumque cano, 50\%-ish.
+\section{Troiae qui}\label{sec:troiae}
+Primus ab oris $x$ and $y$\footnote{Italiam fato} \cite{vergil}.
+\input{chapters/a}
+\end{document}
+"##,
+                ),
+                (
+                    "chapters/a.tex",
+                    r##"Profugus \emph{Laviniaque}
+\begin{figure}
+\includegraphics{a.png}
+\caption{Venit litora}
+\end{figure}
+\subsection*{Multum}
+\begin{equation}
+x = y
+\end{equation}
+\[ z \]
+"##,
+                ),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let count = count_words(&graph);
+
+        let titles: Vec<&str> =
+            count.sections.iter().map(|s| s.title.as_str()).collect();
+        assert_eq!(titles, vec!["", "Troiae qui", "Multum"]);
+        assert_eq!(count.sections[1].row, 4);
+        assert!(count.sections[2].file.ends_with("chapters/a.tex"));
+
+        assert_eq!(count.sections[0].counts.body, 4);
+        assert_eq!(
+            count.sections[1].counts,
+            Counts {
+                body: 6,
+                headers: 2,
+                captions: 2,
+                footnotes: 2,
+                inline_math: 2,
+                display_math: 0,
+                floats: 1,
+            }
+        );
+        assert_eq!(
+            count.sections[2].counts,
+            Counts {
+                headers: 1,
+                display_math: 2,
+                ..Default::default()
+            }
+        );
+        assert_eq!(count.total().words(), 17);
+    }
+    #[test]
+    fn test_count_macros() {
+        let dir = create_test_project(
+            "wordcount-macros",
+            &[(
+                "main.tex",
+                "\\newcommand\\poet[1]{Publius #1 Maro}\n\\newcommand\\chap[1]{\\section{#1}}\n\\begin{document}\n\\chap{Arma}\nCano \\poet{Vergilius}.\n\\end{document}\n",
+            )],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let count = count_words(&graph);
+
+        assert_eq!(count.sections.len(), 1);
+        assert_eq!(count.sections[0].title, "Arma");
+        assert_eq!(count.sections[0].row, 3);
+        assert_eq!(count.sections[0].counts.headers, 1);
+        assert_eq!(count.sections[0].counts.body, 4);
+    }
+}
@@ -0,0 +1,630 @@
+//! The rules on obsolete constructs, which still work but have modern replacements. The fix
+//! applies the replacement where it is mechanical.
+//!
+//! 1. `obsolete-command`: the font switches of LaTeX 2.09 (`\bf`, `\it`, `\rm`, `\sc`, ...),
+//!    which do not combine (`{\bf\it x}` is not bold), `\over`, whose numerator is everything
+//!    before it in the group, and `\centerline`, which ignores the margins of the float
+//! 1. `eqnarray`: `eqnarray`, which spaces the relations wider than the other equations, instead
+//!    of `align` of amsmath
+//! 1. `center-in-float`: the `center` environment in a float, which adds vertical space on top of
+//!    the float's, instead of `\centering`
+//! 1. `obsolete-package`: the packages superseded by others, and `inputenc` with `utf8`, which
+//!    is the default since LaTeX 2018
+
+use std::sync::Arc;
+
+use super::floats::FLOATS;
+use super::typography::in_math;
+use super::{in_verbatim, line_of, NodeContext, Reporter, Rule};
+use crate::latex_interpreter::ast::{NodePtr, NodeType, Span};
+use crate::latex_interpreter::edit::Edit;
+use crate::latex_interpreter::formatter::Package;
+
+/// The font switches: the switch, its declaration, its command, and its math command
+const FONT_SWITCHES: [(&str, &str, &str, Option<&str>); 7] = [
+    ("bf", "bfseries", "textbf", Some("mathbf")),
+    ("it", "itshape", "textit", Some("mathit")),
+    ("rm", "rmfamily", "textrm", Some("mathrm")),
+    ("sf", "sffamily", "textsf", Some("mathsf")),
+    ("tt", "ttfamily", "texttt", Some("mathtt")),
+    ("sl", "slshape", "textsl", None),
+    ("sc", "scshape", "textsc", None),
+];
+
+/// The obsolete packages, with what to use instead
+const OBSOLETE_PACKAGES: [(&str, &str); 5] = [
+    ("subfigure", "the subcaption package"),
+    ("epsfig", "`\\includegraphics` of the graphicx package"),
+    ("a4wide", "the geometry package"),
+    ("times", "the newtxtext and newtxmath packages"),
+    ("doublespace", "the setspace package"),
+];
+
+pub(super) fn rules() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(ObsoleteCommand),
+        Box::new(Eqnarray),
+        Box::new(CenterInFloat),
+        Box::new(ObsoletePackage),
+    ]
+}
+
+/// The span of the group `{...}` node starts, if node is the first thing in it and the group is
+/// not the arg of a command
+fn enclosing_group(context: &NodeContext) -> Option<Span> {
+    if context.walker.prev_sibling().is_some() {
+        return None;
+    }
+    let ancestors = context.walker.ancestors();
+    let [.., outer, group, _paragraph] = &ancestors[..] else {
+        return None;
+    };
+    if outer.lock().unwrap().node_type != NodeType::Paragraph {
+        return None;
+    }
+    let group = group.lock().unwrap();
+    (group.node_type == NodeType::CurlyBracketArg).then_some(group.span)
+}
+
+struct ObsoleteCommand;
+
+impl ObsoleteCommand {
+    /// `{\bf x}` becomes `\textbf{x}`, and `\bf` alone `\bfseries`
+    fn check_font_switch(
+        &self,
+        switch: &(&str, &str, &str, Option<&str>),
+        span: Span,
+        has_args: bool,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let (name, declaration, command, math_command) = *switch;
+        let command = if in_math(context) {
+            math_command
+        } else {
+            Some(command)
+        };
+        let name_span = Span::new(span.start, span.start + name.len() + 1);
+        let group = enclosing_group(context);
+        let next = context.walker.next_sibling();
+        let file_input = &context.file.file_input;
+        match (command, group, next) {
+            (Some(command), Some(group), Some(next)) if !has_args => {
+                let next = next.lock().unwrap().span;
+                reporter.report_fix(
+                    file_input,
+                    span,
+                    format!(
+                        "use `\\{}{{...}}` instead of `{{\\{} ...}}`",
+                        command, name
+                    ),
+                    vec![Edit::new(
+                        Span::new(group.start, next.start),
+                        &format!("\\{}{{", command),
+                    )],
+                );
+            }
+            _ if in_math(context) => {
+                let message = match command {
+                    Some(command) => format!(
+                        "use `\\{}{{...}}` instead of `\\{}`",
+                        command, name
+                    ),
+                    None => format!("`\\{}` is obsolete", name),
+                };
+                reporter.report(file_input, span, message);
+            }
+            _ => reporter.report_fix(
+                file_input,
+                span,
+                format!("use `\\{}` instead of `\\{}`", declaration, name),
+                vec![Edit::new(name_span, &format!("\\{}", declaration))],
+            ),
+        }
+    }
+
+    /// `a \over b` becomes `\frac{a}{b}`, if it is the only `\over` of its group
+    fn check_over(
+        &self,
+        span: Span,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let file_input = &context.file.file_input;
+        let message = "use `\\frac{...}{...}` instead of `\\over`".to_string();
+        let Some(paragraph) = context.walker.ancestors().pop() else {
+            return;
+        };
+        let (first, last, overs) = {
+            let paragraph = paragraph.lock().unwrap();
+            let spans: Vec<Span> = paragraph
+                .children
+                .iter()
+                .map(|c| c.lock().unwrap().span)
+                .collect();
+            let overs = paragraph
+                .children
+                .iter()
+                .filter(|c| {
+                    let c = c.lock().unwrap();
+                    c.node_type == NodeType::Command && c.lexeme == "over"
+                })
+                .count();
+            (spans.first().copied(), spans.last().copied(), overs)
+        };
+        let (Some(first), Some(last)) = (first, last) else {
+            reporter.report(file_input, span, message);
+            return;
+        };
+        let source = file_input.get_str_content();
+        let numerator = source[first.start..span.start].trim();
+        let denominator = source[span.start + "\\over".len()..last.end].trim();
+        if overs != 1 || numerator.is_empty() || denominator.is_empty() {
+            reporter.report(file_input, span, message);
+            return;
+        }
+        reporter.report_fix(
+            file_input,
+            span,
+            message,
+            vec![Edit::new(
+                Span::new(first.start, last.end),
+                &format!("\\frac{{{}}}{{{}}}", numerator, denominator),
+            )],
+        );
+    }
+}
+
+impl Rule for ObsoleteCommand {
+    fn name(&self) -> &'static str {
+        "obsolete-command"
+    }
+
+    fn description(&self) -> &'static str {
+        "obsolete command like `\\bf`, `\\over` or `\\centerline`"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let (name, span, has_args) = {
+            let node = node.lock().unwrap();
+            if node.node_type != NodeType::Command {
+                return;
+            }
+            (node.lexeme.clone(), node.span, !node.children.is_empty())
+        };
+        if !context.in_body || in_verbatim(context) {
+            return;
+        }
+        if let Some(switch) = FONT_SWITCHES.iter().find(|s| s.0 == name) {
+            self.check_font_switch(switch, span, has_args, context, reporter);
+        } else if name == "over" {
+            self.check_over(span, context, reporter);
+        } else if name == "centerline" {
+            reporter.report(
+                &context.file.file_input,
+                span,
+                "use `\\centering` or the `center` environment instead of `\\centerline`"
+                    .to_string(),
+            );
+        }
+    }
+}
+
+/// The span of the environment name in `\begin{name}` (or `\end{name}`) starting at start
+fn envr_name_span(source: &str, start: usize, name: &str) -> Option<Span> {
+    let open = start + source[start..].find('{')? + 1;
+    source[open..]
+        .starts_with(name)
+        .then_some(Span::new(open, open + name.len()))
+}
+
+/// The second `&` of each row of the eqnarray body, None if it holds other environments
+fn eqnarray_second_ampersands(body: &str, offset: usize) -> Option<Vec<Span>> {
+    if body.contains("\\begin") {
+        return None;
+    }
+    let mut ret = vec![];
+    let mut depth = 0;
+    let mut ampersands = 0;
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some((_, '\\')) = chars.next() {
+                    if depth == 0 {
+                        ampersands = 0;
+                    }
+                }
+            }
+            '%' => {
+                for (_, c) in chars.by_ref() {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '&' if depth == 0 => {
+                ampersands += 1;
+                if ampersands == 2 {
+                    ret.push(Span::new(offset + i, offset + i + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+    Some(ret)
+}
+
+struct Eqnarray;
+
+impl Rule for Eqnarray {
+    fn name(&self) -> &'static str {
+        "eqnarray"
+    }
+
+    fn description(&self) -> &'static str {
+        "`eqnarray` instead of `align`"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let (name, span, body) = {
+            let node = node.lock().unwrap();
+            if node.node_type != NodeType::Envr
+                || !["eqnarray", "eqnarray*"].contains(&node.lexeme.as_str())
+            {
+                return;
+            }
+            let body = node.children.first().map(|b| b.lock().unwrap().span);
+            (node.lexeme.clone(), node.span, body)
+        };
+        if !context.in_body || in_verbatim(context) {
+            return;
+        }
+        let file_input = &context.file.file_input;
+        let source = file_input.get_str_content();
+        let align = name.replace("eqnarray", "align");
+        let message = format!("use `{}` instead of `{}`", align, name);
+        let end = source[span.start..span.end].rfind("\\end");
+        let edits = (|| {
+            let body = body?;
+            let end = span.start + end?;
+            let mut edits = vec![
+                Edit::new(envr_name_span(source, span.start, &name)?, &align),
+                Edit::new(envr_name_span(source, end, &name)?, &align),
+            ];
+            let ampersands = eqnarray_second_ampersands(
+                &source[body.start..body.end],
+                body.start,
+            )?;
+            edits.extend(ampersands.into_iter().map(Edit::delete));
+            Some(edits)
+        })();
+        match edits {
+            Some(edits) => {
+                reporter.report_fix(file_input, span, message, edits)
+            }
+            None => reporter.report(file_input, span, message),
+        }
+    }
+}
+
+struct CenterInFloat;
+
+impl Rule for CenterInFloat {
+    fn name(&self) -> &'static str {
+        "center-in-float"
+    }
+
+    fn description(&self) -> &'static str {
+        "`center` environment in a float instead of `\\centering`"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let span = {
+            let node = node.lock().unwrap();
+            if node.node_type != NodeType::Envr || node.lexeme != "center" {
+                return;
+            }
+            node.span
+        };
+        if !context.in_body || in_verbatim(context) {
+            return;
+        }
+        // the innermost environment around the center is a float
+        let Some(float) = context
+            .walker
+            .ancestors()
+            .into_iter()
+            .rev()
+            .find(|a| a.lock().unwrap().node_type == NodeType::Envr)
+        else {
+            return;
+        };
+        let float = float.lock().unwrap();
+        if !FLOATS.contains(&float.lexeme.as_str()) {
+            return;
+        }
+
+        // \centering centers the rest of the float, so the fix needs the center to hold all of
+        // it but the caption and the label
+        let others = float.children.first().is_some_and(|body| {
+            body.lock().unwrap().children.iter().any(|paragraph| {
+                paragraph.lock().unwrap().children.iter().any(|c| {
+                    if Arc::ptr_eq(c, node) {
+                        return false;
+                    }
+                    let c = c.lock().unwrap();
+                    c.node_type != NodeType::Command
+                        || !["caption", "label"].contains(&c.lexeme.as_str())
+                })
+            })
+        });
+        let file_input = &context.file.file_input;
+        let source = file_input.get_str_content();
+        let message = format!(
+            "use `\\centering` instead of the `center` environment in `{}`",
+            float.lexeme
+        );
+        let begin_end = source[span.start..span.end].find('}');
+        let end_start = source[span.start..span.end].rfind("\\end");
+        match (others, begin_end, end_start) {
+            (false, Some(begin_end), Some(end_start)) => {
+                let begin_end = span.start + begin_end + 1;
+                // `\begin{center}x` becomes `\centering x`, not `\centeringx`
+                let centering = match source[begin_end..]
+                    .starts_with(char::is_whitespace)
+                {
+                    true => "\\centering",
+                    false => "\\centering ",
+                };
+                reporter.report_fix(
+                    file_input,
+                    span,
+                    message,
+                    vec![
+                        Edit::new(Span::new(span.start, begin_end), centering),
+                        Edit::delete(Span::new(
+                            span.start + end_start,
+                            span.end,
+                        )),
+                    ],
+                )
+            }
+            _ => reporter.report(file_input, span, message),
+        }
+    }
+}
+
+struct ObsoletePackage;
+
+impl Rule for ObsoletePackage {
+    fn name(&self) -> &'static str {
+        "obsolete-package"
+    }
+
+    fn description(&self) -> &'static str {
+        "obsolete package, or `inputenc` with `utf8`"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let (span, packages) = {
+            let node = node.lock().unwrap();
+            if node.node_type != NodeType::Command
+                || node.lexeme != "usepackage"
+            {
+                return;
+            }
+            (node.span, Package::from_usepackage(&node))
+        };
+        if in_verbatim(context) {
+            return;
+        }
+        let file_input = &context.file.file_input;
+        for package in &packages {
+            let package = package.get_name();
+            if let Some((_, instead)) =
+                OBSOLETE_PACKAGES.iter().find(|(p, _)| *p == package)
+            {
+                reporter.report(
+                    file_input,
+                    span,
+                    format!(
+                        "package `{}` is obsolete, use {} instead",
+                        package, instead
+                    ),
+                );
+            }
+        }
+
+        let Some(inputenc) =
+            packages.iter().find(|p| p.get_name() == "inputenc")
+        else {
+            return;
+        };
+        if !inputenc
+            .get_options()
+            .split(',')
+            .any(|o| o.trim() == "utf8")
+        {
+            return;
+        }
+        let message = "`inputenc` with `utf8` is not needed: UTF-8 is the default since LaTeX 2018, and xelatex and lualatex ignore it".to_string();
+        if packages.len() > 1 {
+            reporter.report(file_input, span, message);
+            return;
+        }
+        let delete = line_of(file_input.get_str_content(), span);
+        reporter.report_fix(
+            file_input,
+            span,
+            message,
+            vec![Edit::delete(delete)],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::linter::test::{lint_source, locations};
+
+    #[test]
+    fn test_obsolete_command() {
+        let (diagnostics, fixed) = lint_source(
+            "obsolete-command",
+            r"\begin{document}
+A {\bf bold} and \it italic, \textbf{\sc caps} $x = {\rm d}x + {a \over b}$ \[\frac{1}{2}\]
+\centerline{z}
+\begin{verbatim}
+{\bf code} \it
+\end{verbatim}
+\verb|{\bf code} \it|
+\end{document}",
+        );
+        assert_eq!(
+            locations(&diagnostics),
+            vec![(1, 3), (1, 17), (1, 37), (1, 53), (1, 66), (2, 0)]
+        );
+        assert!(diagnostics[5].fix.is_none());
+        assert_eq!(
+            fixed,
+            r"\begin{document}
+A \textbf{bold} and \itshape italic, \textbf{\scshape caps} $x = \mathrm{d}x + {\frac{a}{b}}$ \[\frac{1}{2}\]
+\centerline{z}
+\begin{verbatim}
+{\bf code} \it
+\end{verbatim}
+\verb|{\bf code} \it|
+\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_eqnarray() {
+        let (diagnostics, fixed) = lint_source(
+            "eqnarray",
+            r"\begin{document}
+\begin{eqnarray}
+a &=& b \\
+c &\le& {d & e}
+\end{eqnarray}
+\begin{verbatim}
+\begin{eqnarray*}\end{eqnarray*}
+\end{verbatim}
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(1, 0)]);
+        assert_eq!(
+            fixed,
+            r"\begin{document}
+\begin{align}
+a &= b \\
+c &\le {d & e}
+\end{align}
+\begin{verbatim}
+\begin{eqnarray*}\end{eqnarray*}
+\end{verbatim}
+\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_center_in_float() {
+        let (diagnostics, fixed) = lint_source(
+            "center-in-float",
+            r"\begin{document}
+\begin{figure}
+\begin{center}
+\includegraphics{a}
+\end{center}
+\caption{A}
+\end{figure}
+\begin{figure}
+\begin{center}Text\end{center}
+\end{figure}
+\begin{table}
+\begin{center}x\end{center} y
+\end{table}
+\begin{verbatim}
+\begin{figure}\begin{center}x\end{center}\end{figure}
+\end{verbatim}
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(2, 0), (8, 0), (11, 0)]);
+        assert!(diagnostics[2].fix.is_none());
+        assert_eq!(
+            fixed,
+            r"\begin{document}
+\begin{figure}
+\centering
+\includegraphics{a}
+
+\caption{A}
+\end{figure}
+\begin{figure}
+\centering Text
+\end{figure}
+\begin{table}
+\begin{center}x\end{center} y
+\end{table}
+\begin{verbatim}
+\begin{figure}\begin{center}x\end{center}\end{figure}
+\end{verbatim}
+\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_obsolete_package() {
+        let (diagnostics, fixed) = lint_source(
+            "obsolete-package",
+            r"\documentclass{article}
+\usepackage[utf8]{inputenc}
+\usepackage{subfigure}
+\usepackage[utf8]{inputenc,fontenc}
+\begin{document}
+\begin{verbatim}
+\usepackage{subfigure}
+\end{verbatim}
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(1, 0), (2, 0), (3, 0)]);
+        assert_eq!(
+            diagnostics[1].message,
+            "package `subfigure` is obsolete, use the subcaption package instead"
+        );
+        assert!(diagnostics[2].fix.is_none());
+        assert_eq!(
+            fixed,
+            r"\documentclass{article}
+\usepackage{subfigure}
+\usepackage[utf8]{inputenc,fontenc}
+\begin{document}
+\begin{verbatim}
+\usepackage{subfigure}
+\end{verbatim}
+\end{document}"
+        );
+    }
+}
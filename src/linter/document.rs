@@ -0,0 +1,229 @@
+//! The rules checking the whole document
+//!
+//! 1. The labels and references, see latex_interpreter::refs: `undefined-reference`,
+//!    `duplicate-label` and `unused-label`
+//! 1. The citations, see latex_interpreter::cites: `undefined-citation`, `duplicate-entry` and
+//!    `uncited-entry`
+//! 1. `missing-file`: an included file which can not be found
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+
+use super::{LintLevel, Reporter, Rule};
+use crate::latex_interpreter::cites::{check_citations, CiteProblem};
+use crate::latex_interpreter::include_graph::IncludeGraph;
+use crate::latex_interpreter::refs::{check_refs, Occurrence, RefProblem};
+use crate::utils::FileInput;
+
+/// The names of the rules checking references and citations, run by `tex-helper check`
+pub const CHECK_RULES: [&str; 6] = [
+    "undefined-reference",
+    "duplicate-label",
+    "unused-label",
+    "undefined-citation",
+    "duplicate-entry",
+    "uncited-entry",
+];
+
+pub(super) fn rules() -> Vec<Box<dyn Rule>> {
+    let citations: CiteProblems = Rc::default();
+    vec![
+        Box::new(RefRule {
+            name: CHECK_RULES[0],
+            description: "reference to a label which is not defined",
+            level: LintLevel::Error,
+            select: |p| matches!(p, RefProblem::Undefined(_)),
+        }),
+        Box::new(RefRule {
+            name: CHECK_RULES[1],
+            description: "label defined more than once",
+            level: LintLevel::Error,
+            select: |p| matches!(p, RefProblem::Duplicate(..)),
+        }),
+        Box::new(RefRule {
+            name: CHECK_RULES[2],
+            description: "label never referred to",
+            level: LintLevel::Warn,
+            select: |p| matches!(p, RefProblem::Unused(_)),
+        }),
+        Box::new(CiteRule {
+            name: CHECK_RULES[3],
+            description: "citation of a key which is not in the bibliography",
+            level: LintLevel::Error,
+            select: |p| matches!(p, CiteProblem::Missing(_)),
+            problems: citations.clone(),
+        }),
+        Box::new(CiteRule {
+            name: CHECK_RULES[4],
+            description: "bibliography entry defined more than once",
+            level: LintLevel::Error,
+            select: |p| matches!(p, CiteProblem::Duplicate(..)),
+            problems: citations.clone(),
+        }),
+        Box::new(CiteRule {
+            name: CHECK_RULES[5],
+            description: "bibliography entry never cited",
+            level: LintLevel::Warn,
+            select: |p| matches!(p, CiteProblem::Uncited(_)),
+            problems: citations.clone(),
+        }),
+        Box::new(MissingFile),
+    ]
+}
+
+/// Report an occurrence found by refs or cites, which may be in a bib file outside the graph
+/// bib_files caches the bib files read
+fn report_occurrence(
+    graph: &IncludeGraph,
+    bib_files: &mut Vec<FileInput>,
+    reporter: &mut Reporter,
+    occurrence: &Occurrence,
+    message: String,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(file) = graph.get_file(&occurrence.file) {
+        reporter.report(&file.file_input, occurrence.span, message);
+        return Ok(());
+    }
+    let i = match bib_files
+        .iter()
+        .position(|f| *f.get_file_path() == occurrence.file)
+    {
+        Some(i) => i,
+        None => {
+            bib_files.push(FileInput::from_file_path(&occurrence.file)?);
+            bib_files.len() - 1
+        }
+    };
+    reporter.report(&bib_files[i], occurrence.span, message);
+    Ok(())
+}
+
+/// The problems of check_refs selected by select
+struct RefRule {
+    name: &'static str,
+    description: &'static str,
+    level: LintLevel,
+    select: fn(&RefProblem) -> bool,
+}
+
+impl Rule for RefRule {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn default_level(&self) -> LintLevel {
+        self.level
+    }
+
+    fn check_document(
+        &mut self,
+        graph: &IncludeGraph,
+        reporter: &mut Reporter,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut bib_files: Vec<FileInput> = vec![];
+        for problem in check_refs(graph).iter().filter(|p| (self.select)(p)) {
+            report_occurrence(
+                graph,
+                &mut bib_files,
+                reporter,
+                problem.occurrence(),
+                problem.message(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The problems of check_citations, computed by the first of the citation rules run and shared
+/// with the others, so that the bib files are read once
+type CiteProblems = Rc<RefCell<Option<Vec<CiteProblem>>>>;
+
+/// The problems of check_citations selected by select
+struct CiteRule {
+    name: &'static str,
+    description: &'static str,
+    level: LintLevel,
+    select: fn(&CiteProblem) -> bool,
+    problems: CiteProblems,
+}
+
+impl Rule for CiteRule {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn description(&self) -> &'static str {
+        self.description
+    }
+
+    fn default_level(&self) -> LintLevel {
+        self.level
+    }
+
+    fn check_document(
+        &mut self,
+        graph: &IncludeGraph,
+        reporter: &mut Reporter,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut problems = self.problems.borrow_mut();
+        if problems.is_none() {
+            *problems = Some(check_citations(graph)?);
+        }
+        let mut bib_files: Vec<FileInput> = vec![];
+        for problem in problems
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .filter(|p| (self.select)(p))
+        {
+            report_occurrence(
+                graph,
+                &mut bib_files,
+                reporter,
+                problem.occurrence(),
+                problem.message(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct MissingFile;
+
+impl Rule for MissingFile {
+    fn name(&self) -> &'static str {
+        "missing-file"
+    }
+
+    fn description(&self) -> &'static str {
+        "included file which can not be found"
+    }
+
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Error
+    }
+
+    fn check_document(
+        &mut self,
+        graph: &IncludeGraph,
+        reporter: &mut Reporter,
+    ) -> Result<(), Box<dyn Error>> {
+        for file in graph.get_files() {
+            for include in &file.includes {
+                if include.resolved.is_none() && !include.excluded {
+                    reporter.report(
+                        &file.file_input,
+                        include.span,
+                        format!("included file `{}` not found", include.target),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
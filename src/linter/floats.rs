@@ -0,0 +1,138 @@
+//! The rules on floats
+//!
+//! 1. `label-before-caption`: in a float, `\label` gets the number of the last `\caption` before
+//!    it, so a `\label` before the `\caption` refers to the section (or to the previous float
+//!    of a subfigure) instead. The fix moves the `\label` right after the `\caption`.
+
+use super::{NodeContext, Reporter, Rule};
+use crate::latex_interpreter::ast::{NodePtr, NodeType, Span};
+use crate::latex_interpreter::edit::Edit;
+
+/// The environments whose `\caption` is numbered, including subfigures of the subcaption package
+pub const FLOATS: [&str; 10] = [
+    "figure",
+    "figure*",
+    "table",
+    "table*",
+    "subfigure",
+    "subtable",
+    "wrapfigure",
+    "wraptable",
+    "sidewaysfigure",
+    "sidewaystable",
+];
+
+pub(super) fn rules() -> Vec<Box<dyn super::Rule>> {
+    vec![Box::new(LabelBeforeCaption)]
+}
+
+/// The spans of the `\caption` and `\label` commands of a float, in order, leaving out those
+/// of the floats in it
+fn captions_and_labels(node: &NodePtr, ret: &mut Vec<(String, Span)>) {
+    let node = node.lock().unwrap();
+    match node.node_type {
+        NodeType::Envr if FLOATS.contains(&node.lexeme.as_str()) => return,
+        NodeType::Command
+            if ["caption", "label"].contains(&node.lexeme.as_str()) =>
+        {
+            ret.push((node.lexeme.clone(), node.span));
+            return;
+        }
+        _ => {}
+    }
+    for child in &node.children {
+        captions_and_labels(child, ret);
+    }
+}
+
+struct LabelBeforeCaption;
+
+impl Rule for LabelBeforeCaption {
+    fn name(&self) -> &'static str {
+        "label-before-caption"
+    }
+
+    fn description(&self) -> &'static str {
+        "`\\label` before the `\\caption` of a float"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let body = {
+            let node = node.lock().unwrap();
+            // a float in the preamble is in a macro definition
+            if node.node_type != NodeType::Envr
+                || !FLOATS.contains(&node.lexeme.as_str())
+                || !context.in_body
+            {
+                return;
+            }
+            match node.children.first() {
+                Some(body) => body.clone(),
+                None => return,
+            }
+        };
+        let mut commands: Vec<(String, Span)> = vec![];
+        captions_and_labels(&body, &mut commands);
+        let Some(caption) = commands.iter().position(|(c, _)| c == "caption")
+        else {
+            return;
+        };
+        let caption_end = commands[caption].1.end;
+
+        let file_input = &context.file.file_input;
+        for (_, span) in
+            commands[..caption].iter().filter(|(c, _)| c == "label")
+        {
+            let label = file_input.get_span_str(span);
+            reporter.report_fix(
+                file_input,
+                *span,
+                format!(
+                    "`{}` before `\\caption` does not refer to the float",
+                    label
+                ),
+                vec![
+                    Edit::delete(*span),
+                    Edit::new(Span::new(caption_end, caption_end), label),
+                ],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::latex_interpreter::include_graph::IncludeGraph;
+    use crate::linter::{fix, lint, Severity};
+    use crate::utils::create_test_project;
+
+    #[test]
+    fn test_label_before_caption() {
+        let dir = create_test_project(
+            "lint-floats",
+            &[(
+                "main.tex",
+                "\\begin{figure}\n\\centering\\label{fig:a}\n\\begin{subfigure}{.5\\textwidth}\\caption{Sub}\\label{fig:s}\\end{subfigure}\n\\caption{A \\emph{b}}\n\\end{figure}",
+            )],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let mut rules: Vec<(Box<dyn Rule>, Severity)> =
+            vec![(Box::new(LabelBeforeCaption), Severity::Warning)];
+        let diagnostics = lint(&graph, &mut rules).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].message,
+            "`\\label{fig:a}` before `\\caption` does not refer to the float"
+        );
+        assert_eq!(
+            fix(&graph, &diagnostics).0[0].1,
+            "\\begin{figure}\n\\centering\n\\begin{subfigure}{.5\\textwidth}\\caption{Sub}\\label{fig:s}\\end{subfigure}\n\\caption{A \\emph{b}}\\label{fig:a}\n\\end{figure}"
+        );
+    }
+}
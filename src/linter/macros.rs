@@ -0,0 +1,292 @@
+//! The rules on the commands and environments defined by the document, see symbols
+//!
+//! 1. `duplicate-definition`: a `\newcommand`, `\DeclareMathOperator`, `\NewDocumentCommand`,
+//!    `\newenvironment` or `\newtheorem` of a name already defined by the document, which is an
+//!    error of latex. `\renewcommand`, `\providecommand` and `\def` are left out.
+//! 1. `undefined-theorem`: a theorem environment (`theorem`, `lemma`, `definition`, ...) used
+//!    but defined neither by the document nor by the document class. Unlike the environments of
+//!    packages, amsthm provides none of them.
+
+use std::error::Error;
+use std::path::PathBuf;
+
+use super::{in_verbatim, LintLevel, NodeContext, Reporter, Rule};
+use crate::latex_interpreter::ast::{NodePtr, NodeType, Span};
+use crate::latex_interpreter::include_graph::IncludeGraph;
+use crate::latex_interpreter::refs::Occurrence;
+use crate::latex_interpreter::symbols::SymbolTable;
+
+/// The commands defining a name which must not be defined yet
+const NEW_COMMAND: [&str; 3] =
+    ["newcommand", "DeclareMathOperator", "NewDocumentCommand"];
+
+/// The usual names of theorem environments
+const THEOREM_ENVRS: [&str; 22] = [
+    "theorem",
+    "thm",
+    "lemma",
+    "lem",
+    "proposition",
+    "prop",
+    "corollary",
+    "cor",
+    "definition",
+    "defn",
+    "remark",
+    "rem",
+    "example",
+    "conjecture",
+    "claim",
+    "fact",
+    "observation",
+    "axiom",
+    "hypothesis",
+    "exercise",
+    "problem",
+    "notation",
+];
+
+/// The theorem environments defined by the document classes
+const CLASS_THEOREMS: [(&str, &[&str]); 1] = [(
+    "beamer",
+    &[
+        "theorem",
+        "lemma",
+        "corollary",
+        "definition",
+        "example",
+        "fact",
+    ],
+)];
+
+pub(super) fn rules() -> Vec<Box<dyn super::Rule>> {
+    vec![
+        Box::new(DuplicateDefinition),
+        Box::<UndefinedTheorem>::default(),
+    ]
+}
+
+struct DuplicateDefinition;
+
+impl Rule for DuplicateDefinition {
+    fn name(&self) -> &'static str {
+        "duplicate-definition"
+    }
+
+    fn description(&self) -> &'static str {
+        "command or environment defined twice"
+    }
+
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Error
+    }
+
+    fn check_document(
+        &mut self,
+        graph: &IncludeGraph,
+        reporter: &mut Reporter,
+    ) -> Result<(), Box<dyn Error>> {
+        let symbols = SymbolTable::build(graph);
+        let mut report = |occurrence: &Occurrence,
+                          name: String,
+                          first: &Occurrence| {
+            let Some(file) = graph.get_file(&occurrence.file) else {
+                return;
+            };
+            reporter.report(
+                &file.file_input,
+                occurrence.span,
+                format!("{} is already defined at {}", name, first.location()),
+            );
+        };
+
+        for (i, d) in symbols.commands.iter().enumerate() {
+            if !NEW_COMMAND.contains(&d.command.as_str()) {
+                continue;
+            }
+            if let Some(first) =
+                symbols.commands[..i].iter().find(|f| f.name == d.name)
+            {
+                report(
+                    &d.occurrence,
+                    format!("`\\{}`", d.name),
+                    &first.occurrence,
+                );
+            }
+        }
+
+        // environments and theorems share their names, in the order of their definitions
+        let mut envrs: Vec<(&Occurrence, bool)> = symbols
+            .environments
+            .iter()
+            .map(|d| (&d.occurrence, d.command == "newenvironment"))
+            .chain(symbols.theorems.iter().map(|t| (&t.occurrence, true)))
+            .collect();
+        envrs.sort_by_key(|(o, _)| {
+            let file = graph
+                .get_files()
+                .iter()
+                .position(|f| *f.get_path() == o.file);
+            (file, o.span.start)
+        });
+        for (i, (occurrence, new)) in envrs.iter().enumerate() {
+            if !new {
+                continue;
+            }
+            if let Some((first, _)) =
+                envrs[..i].iter().find(|(f, _)| f.key == occurrence.key)
+            {
+                report(
+                    occurrence,
+                    format!("environment `{}`", occurrence.key),
+                    first,
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct UndefinedTheorem {
+    class: Option<String>,
+    /// The theorem environments used, with where
+    used: Vec<(String, PathBuf, Span)>,
+}
+
+impl Rule for UndefinedTheorem {
+    fn name(&self) -> &'static str {
+        "undefined-theorem"
+    }
+
+    fn description(&self) -> &'static str {
+        "theorem environment used but not defined"
+    }
+
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Error
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        _reporter: &mut Reporter,
+    ) {
+        if in_verbatim(context) {
+            return;
+        }
+        let node = node.lock().unwrap();
+        match node.node_type {
+            NodeType::Command if node.lexeme == "documentclass" => {
+                self.class = node.get_curly_bracket_args().first().map(|arg| {
+                    arg.lock()
+                        .unwrap()
+                        .get_children_lexeme_recur()
+                        .trim()
+                        .to_string()
+                });
+            }
+            NodeType::Envr
+                if context.in_body
+                    && THEOREM_ENVRS.contains(&node.lexeme.as_str()) =>
+            {
+                // `\begin{name}`
+                let start = node.span.start;
+                let span = Span::new(start, start + node.lexeme.len() + 8);
+                self.used.push((
+                    node.lexeme.clone(),
+                    context.file.get_path().clone(),
+                    span,
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    fn check_document(
+        &mut self,
+        graph: &IncludeGraph,
+        reporter: &mut Reporter,
+    ) -> Result<(), Box<dyn Error>> {
+        let symbols = SymbolTable::build(graph);
+        let by_class: &[&str] = CLASS_THEOREMS
+            .iter()
+            .find(|(c, _)| Some(*c) == self.class.as_deref())
+            .map_or(&[], |(_, envrs)| envrs);
+        for (name, path, span) in &self.used {
+            if symbols.theorem(name).is_some()
+                || symbols.environment(name).is_some()
+                || by_class.contains(&name.as_str())
+            {
+                continue;
+            }
+            let Some(file) = graph.get_file(path) else {
+                continue;
+            };
+            reporter.report(
+                &file.file_input,
+                *span,
+                format!("theorem environment `{}` is not defined", name),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::linter::test::{lint_source, locations};
+
+    #[test]
+    fn test_duplicate_definition() {
+        let (diagnostics, _) = lint_source(
+            "duplicate-definition",
+            r"\documentclass{article}
+\newcommand{\R}{\mathbb{R}}
+\renewcommand{\R}{R}
+\def\C{C}
+\DeclareMathOperator{\C}{C}
+\newtheorem{theorem}{Theorem}
+\newtheorem*{theorem}{Theorem}
+\newenvironment{lemma}{}{}
+\begin{document}
+\begin{verbatim}
+\newcommand{\R}{R}
+\end{verbatim}
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(4, 21), (6, 13)]);
+        assert!(diagnostics[0]
+            .message
+            .starts_with("`\\C` is already defined at "));
+        assert!(diagnostics[0].message.ends_with("main.tex:4:5"));
+        assert!(diagnostics[1]
+            .message
+            .starts_with("environment `theorem` is already defined at "));
+    }
+
+    #[test]
+    fn test_undefined_theorem() {
+        let (diagnostics, _) = lint_source(
+            "undefined-theorem",
+            r"\documentclass{article}
+\newtheorem{theorem}{Theorem}
+\newenvironment{lemma}{}{}
+\begin{document}
+\begin{theorem}\end{theorem}
+\begin{lemma}\end{lemma}
+\begin{proof}\end{proof}
+\begin{claim}\end{claim}
+\begin{verbatim}
+\begin{claim}\end{claim}
+\end{verbatim}
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(7, 0)]);
+        assert_eq!(
+            diagnostics[0].message,
+            "theorem environment `claim` is not defined"
+        );
+    }
+}
@@ -0,0 +1,482 @@
+//! A lint framework over the AST of the whole document
+//!
+//! A lint is a Rule. The linter walks the AST of each file of the include graph with a Walker,
+//! and calls check_node of every enabled rule on each node, then check_document once, for the
+//! checks which need the whole document (undefined references, uncited entries, etc). Rules
+//! report Diagnostics through a Reporter, optionally with a fix: edits on the source text which
+//! resolve the problem, applied by `tex-helper lint --fix`.
+//!
+//! Each rule has a name, and a level which can be changed in the config file:
+//! ```toml
+//! [lint]
+//! unused-label = "off"
+//! uncited-entry = "error"
+//! ```
+//!
+//! To add a rule, implement Rule in a module of this directory, and add it to all_rules.
+
+pub mod deprecated;
+pub mod document;
+pub mod floats;
+pub mod macros;
+pub mod packages;
+pub mod typography;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use colored::*;
+use serde::{Deserialize, Serialize};
+
+use crate::latex_interpreter::ast::{
+    Node, NodePtr, NodeType, Walker, VERBATIM_ENVRS,
+};
+use crate::latex_interpreter::comment::literal_end;
+use crate::latex_interpreter::edit::{apply_edits, Edit};
+use crate::latex_interpreter::include_graph::{IncludeGraph, SourceFile};
+use crate::latex_interpreter::token::Span;
+use crate::utils::FileInput;
+
+/// The level of a rule, set in the `[lint]` section of the config
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LintLevel {
+    Off,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The name of the rule
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub file: PathBuf,
+    pub span: Span,
+    /// Starting from 0
+    pub row: usize,
+    /// Starting from 0, in characters
+    pub col: usize,
+    pub message: String,
+    /// The edits on file fixing the problem
+    pub fix: Option<Vec<Edit>>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Error => "ERROR".red().bold(),
+            Severity::Warning => "WARNING".yellow().bold(),
+        };
+        write!(
+            f,
+            "{}:{}:{} {}: {} [{}]",
+            self.file.display(),
+            self.row + 1,
+            self.col + 1,
+            level,
+            self.message,
+            self.rule
+        )
+    }
+}
+
+/// Where a node visited by Rule::check_node is
+pub struct NodeContext<'a> {
+    pub file: &'a SourceFile,
+    /// The walker is at the node
+    pub walker: &'a Walker,
+    /// Whether the node is in the document body, rather than in the preamble. Files without a
+    /// document environment are included in the body
+    pub in_body: bool,
+}
+
+/// Collects the diagnostics of the rule being run
+pub struct Reporter {
+    rule: &'static str,
+    severity: Severity,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Reporter {
+    /// Report a problem at span of file
+    pub fn report(&mut self, file: &FileInput, span: Span, message: String) {
+        self.push(file, span, message, None);
+    }
+
+    /// Report a problem at span of file, fixed by the edits on file
+    pub fn report_fix(
+        &mut self,
+        file: &FileInput,
+        span: Span,
+        message: String,
+        fix: Vec<Edit>,
+    ) {
+        self.push(file, span, message, Some(fix));
+    }
+
+    fn push(
+        &mut self,
+        file: &FileInput,
+        span: Span,
+        message: String,
+        fix: Option<Vec<Edit>>,
+    ) {
+        let (row, col) = file.get_row_col(span.start);
+        self.diagnostics.push(Diagnostic {
+            rule: self.rule,
+            severity: self.severity,
+            file: file.get_file_path().clone(),
+            span,
+            row,
+            col,
+            message,
+            fix,
+        });
+    }
+}
+
+pub trait Rule {
+    /// The name of the rule in the config and the diagnostics, in kebab case
+    fn name(&self) -> &'static str;
+
+    /// What the rule reports, in one line
+    fn description(&self) -> &'static str;
+
+    /// The level of the rule if the config does not set one
+    fn default_level(&self) -> LintLevel {
+        LintLevel::Warn
+    }
+
+    /// Check a node. The nodes of each file are visited in pre-order
+    fn check_node(
+        &mut self,
+        _node: &NodePtr,
+        _context: &NodeContext,
+        _reporter: &mut Reporter,
+    ) {
+    }
+
+    /// Check the whole document, after the nodes are visited
+    fn check_document(
+        &mut self,
+        _graph: &IncludeGraph,
+        _reporter: &mut Reporter,
+    ) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// The span of the whole line of span, with its newline, if nothing else is on it, else span.
+/// Deleting it removes a command without leaving an empty line
+pub(super) fn line_of(source: &str, span: Span) -> Span {
+    let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[span.end..]
+        .find('\n')
+        .map_or(source.len(), |i| span.end + i + 1);
+    if source[line_start..span.start].trim().is_empty()
+        && source[span.end..line_end].trim().is_empty()
+    {
+        Span::new(line_start, line_end)
+    } else {
+        span
+    }
+}
+
+/// Whether the node at the walker of context is in a verbatim environment, or in the text of
+/// `\verb|...|`, which are parsed as latex but are not
+pub(super) fn in_verbatim(context: &NodeContext) -> bool {
+    let ancestors = context.walker.ancestors();
+    let in_envr = ancestors.iter().any(|a| {
+        let a = a.lock().unwrap();
+        a.node_type == NodeType::Envr
+            && VERBATIM_ENVRS.contains(&a.lexeme.as_str())
+    });
+    if in_envr {
+        return true;
+    }
+    // the text of `\verb|...|` is parsed as the nodes after the command, and their children
+    let start = context.walker.cur_loc().lock().unwrap().span.start;
+    let source = context.file.file_input.get_str_content();
+    ancestors.iter().any(|a| {
+        let children = a.lock().unwrap().children.clone();
+        children.iter().any(|c| {
+            let c = c.lock().unwrap();
+            c.node_type == NodeType::Command
+                && c.lexeme == "verb"
+                && c.span.end <= start
+                && literal_end(source, "verb", c.span.end)
+                    .is_some_and(|end| start < end)
+        })
+    })
+}
+
+/// Every rule, with its default level
+pub fn all_rules() -> Vec<Box<dyn Rule>> {
+    let mut ret: Vec<Box<dyn Rule>> = vec![];
+    ret.extend(document::rules());
+    ret.extend(floats::rules());
+    ret.extend(typography::rules());
+    ret.extend(deprecated::rules());
+    ret.extend(macros::rules());
+    ret.extend(packages::rules());
+    ret
+}
+
+/// The enabled rules with their severities, the levels set in the config overriding the defaults
+pub fn configure(
+    levels: &HashMap<String, LintLevel>,
+) -> Vec<(Box<dyn Rule>, Severity)> {
+    let rules = all_rules();
+    for name in levels.keys() {
+        if !rules.iter().any(|r| r.name() == name) {
+            warn!("Unknown lint rule `{}` in the config", name);
+        }
+    }
+    rules
+        .into_iter()
+        .filter_map(|rule| {
+            let level = levels
+                .get(rule.name())
+                .copied()
+                .unwrap_or(rule.default_level());
+            match level {
+                LintLevel::Off => None,
+                LintLevel::Warn => Some((rule, Severity::Warning)),
+                LintLevel::Error => Some((rule, Severity::Error)),
+            }
+        })
+        .collect()
+}
+
+/// Run the rules on the document, and return the diagnostics sorted by file (in document order)
+/// and location
+pub fn lint(
+    graph: &IncludeGraph,
+    rules: &mut [(Box<dyn Rule>, Severity)],
+) -> Result<Vec<Diagnostic>, Box<dyn Error>> {
+    let mut reporter = Reporter {
+        rule: "",
+        severity: Severity::Warning,
+        diagnostics: vec![],
+    };
+
+    for file in graph.get_files() {
+        let body = Node::find_document_body_nodeptr(file.ast.clone());
+        let mut walker = Walker::from_root(file.ast.clone());
+        while let Some(node) = walker.next_node() {
+            let in_body = match &body {
+                None => true,
+                Some(body) => {
+                    Arc::ptr_eq(body, &node)
+                        || walker
+                            .ancestors()
+                            .iter()
+                            .any(|a| Arc::ptr_eq(a, body))
+                }
+            };
+            let context = NodeContext {
+                file,
+                walker: &walker,
+                in_body,
+            };
+            for (rule, severity) in rules.iter_mut() {
+                reporter.rule = rule.name();
+                reporter.severity = *severity;
+                rule.check_node(&node, &context, &mut reporter);
+            }
+        }
+    }
+    for (rule, severity) in rules.iter_mut() {
+        reporter.rule = rule.name();
+        reporter.severity = *severity;
+        rule.check_document(graph, &mut reporter)?;
+    }
+
+    let mut ret = reporter.diagnostics;
+    ret.sort_by_key(|d| {
+        let file = graph
+            .get_files()
+            .iter()
+            .position(|f| *f.get_path() == d.file)
+            .unwrap_or(usize::MAX);
+        (file, d.span.start)
+    });
+    Ok(ret)
+}
+
+/// Apply the fixes of diagnostics to the files of graph, and return the fixed files with their
+/// new content, and the number of problems fixed
+/// A fix overlapping an earlier one is left out, running the fixes again applies it.
+pub fn fix(
+    graph: &IncludeGraph,
+    diagnostics: &[Diagnostic],
+) -> (Vec<(PathBuf, String)>, usize) {
+    let mut edits: Vec<(PathBuf, Vec<Edit>)> = vec![];
+    let mut fixed = 0;
+    for diagnostic in diagnostics {
+        let Some(fix) = &diagnostic.fix else {
+            continue;
+        };
+        let file_edits =
+            match edits.iter_mut().find(|(f, _)| *f == diagnostic.file) {
+                Some((_, file_edits)) => file_edits,
+                None => {
+                    edits.push((diagnostic.file.clone(), vec![]));
+                    &mut edits.last_mut().unwrap().1
+                }
+            };
+        let overlaps = fix.iter().any(|e| {
+            file_edits.iter().any(|f| {
+                (e.span.start < f.span.end && f.span.start < e.span.end)
+                    || e.span == f.span
+            })
+        });
+        if overlaps {
+            debug!("Overlapping fix left out: {}", diagnostic);
+            continue;
+        }
+        file_edits.extend(fix.iter().cloned());
+        fixed += 1;
+    }
+
+    let files = edits
+        .into_iter()
+        .filter(|(_, e)| !e.is_empty())
+        .filter_map(|(path, e)| {
+            let file = graph.get_file(&path)?;
+            let content = apply_edits(file.file_input.get_str_content(), &e);
+            Some((path, content))
+        })
+        .collect();
+    (files, fixed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::latex_interpreter::ast::NodeType;
+    use crate::utils::create_test_project;
+
+    /// Lint source, the main file of a project, with the rule alone. Returns the diagnostics, and
+    /// the source with their fixes applied
+    pub(super) fn lint_source(
+        rule: &str,
+        source: &str,
+    ) -> (Vec<Diagnostic>, String) {
+        let dir = create_test_project(
+            &format!("lint-{}", rule),
+            &[("main.tex", source)],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let mut rules: Vec<(Box<dyn Rule>, Severity)> = all_rules()
+            .into_iter()
+            .filter(|r| r.name() == rule)
+            .map(|r| (r, Severity::Warning))
+            .collect();
+        assert_eq!(rules.len(), 1, "no rule `{}`", rule);
+        let diagnostics = lint(&graph, &mut rules).unwrap();
+        let (files, _) = fix(&graph, &diagnostics);
+        let fixed = match files.into_iter().next() {
+            Some((_, content)) => content,
+            None => source.to_string(),
+        };
+        (diagnostics, fixed)
+    }
+
+    /// The (row, col) of the diagnostics
+    pub(super) fn locations(diagnostics: &[Diagnostic]) -> Vec<(usize, usize)> {
+        diagnostics.iter().map(|d| (d.row, d.col)).collect()
+    }
+
+    /// Report the words `TODO`, fixed by removing them
+    struct Todo;
+
+    impl Rule for Todo {
+        fn name(&self) -> &'static str {
+            "todo"
+        }
+
+        fn description(&self) -> &'static str {
+            "TODO left in the text"
+        }
+
+        fn check_node(
+            &mut self,
+            node: &NodePtr,
+            context: &NodeContext,
+            reporter: &mut Reporter,
+        ) {
+            let node = node.lock().unwrap();
+            if node.node_type == NodeType::Word
+                && node.lexeme == "TODO"
+                && context.in_body
+            {
+                reporter.report_fix(
+                    &context.file.file_input,
+                    node.span,
+                    "TODO left".to_string(),
+                    vec![Edit::delete(node.span)],
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_lint_and_fix() {
+        let dir = create_test_project(
+            "linter",
+            &[
+                (
+                    "main.tex",
+                    "\\title{TODO}\n\\begin{document}\nTODO \\input{b}\\ref{x}\n\\end{document}",
+                ),
+                ("b.tex", "a TODO"),
+            ],
+        );
+        let graph = IncludeGraph::build(&dir.join("main.tex")).unwrap();
+        let mut levels = HashMap::new();
+        levels.insert("unused-label".to_string(), LintLevel::Off);
+        levels.insert("undefined-reference".to_string(), LintLevel::Warn);
+        let mut rules = configure(&levels);
+        assert!(!rules.iter().any(|(r, _)| r.name() == "unused-label"));
+        rules.push((Box::new(Todo), Severity::Error));
+
+        let diagnostics = lint(&graph, &mut rules).unwrap();
+        let summary: Vec<(&str, Severity, usize, usize)> = diagnostics
+            .iter()
+            .map(|d| (d.rule, d.severity, d.row, d.col))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("todo", Severity::Error, 2, 0),
+                ("undefined-reference", Severity::Warning, 2, 19),
+                ("todo", Severity::Error, 0, 2),
+            ]
+        );
+
+        let (files, fixed) = fix(&graph, &diagnostics);
+        assert_eq!(fixed, 2);
+        assert_eq!(
+            files,
+            vec![
+                (
+                    dir.join("main.tex"),
+                    "\\title{TODO}\n\\begin{document}\n \\input{b}\\ref{x}\n\\end{document}"
+                        .to_string()
+                ),
+                (dir.join("b.tex"), "a ".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,587 @@
+//! The rules on the packages loaded by the preamble, using a table of the commands and
+//! environments each package provides
+//!
+//! 1. `unused-package`: a package loaded by `\usepackage` none of whose commands and environments
+//!    is used. Only the packages of the table which do nothing by being loaded are reported. The
+//!    fix removes the package from its `\usepackage`
+//! 1. `missing-package`: a command or environment used, but provided by a package which is not
+//!    loaded, as `\toprule` without booktabs. The packages loaded by the document class and by
+//!    other packages count, and the commands and environments defined in the document are left
+//!    out. The fix loads the package after the last `\usepackage` of the main file
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::PathBuf;
+
+use super::{in_verbatim, line_of, NodeContext, Reporter, Rule};
+use crate::latex_interpreter::ast::{NodePtr, NodeType, Span};
+use crate::latex_interpreter::edit::Edit;
+use crate::latex_interpreter::formatter::Package;
+use crate::latex_interpreter::include_graph::IncludeGraph;
+use crate::latex_interpreter::symbols::SymbolTable;
+
+/// What a package provides
+struct PackageInfo {
+    name: &'static str,
+    commands: &'static str,
+    environments: &'static str,
+    /// Whether loading the package changes the document by itself, through its options, the
+    /// commands it redefines, or the syntax it adds to other packages (as the color expressions
+    /// of xcolor), so that it is used even if none of its commands are
+    has_effects: bool,
+}
+
+impl PackageInfo {
+    const fn new(
+        name: &'static str,
+        commands: &'static str,
+        environments: &'static str,
+        has_effects: bool,
+    ) -> Self {
+        PackageInfo {
+            name,
+            commands,
+            environments,
+            has_effects,
+        }
+    }
+
+    fn provides(&self, name: &Name) -> bool {
+        match name {
+            Name::Command(c) => self.commands.split(' ').any(|n| n == c),
+            Name::Envr(e) => self.environments.split(' ').any(|n| n == e),
+        }
+    }
+}
+
+/// The packages, with the commands and the environments they provide, separated by spaces
+const PACKAGES: [PackageInfo; 50] = [
+    PackageInfo::new("amsmath", "text dfrac tfrac binom dbinom tbinom eqref operatorname DeclareMathOperator intertext boldsymbol substack overset underset xrightarrow xleftarrow numberwithin tag notag iint iiint cfrac genfrac allowdisplaybreaks", "align align* gather gather* multline multline* flalign flalign* alignat alignat* split cases matrix pmatrix bmatrix Bmatrix vmatrix Vmatrix aligned gathered subequations equation*", false),
+    PackageInfo::new("amssymb", "mathbb mathfrak leqslant geqslant varnothing therefore because blacksquare square nmid lesssim gtrsim checkmark complement nexists twoheadrightarrow", "", false),
+    PackageInfo::new("amsfonts", "mathbb mathfrak", "", false),
+    PackageInfo::new("amsthm", "theoremstyle newtheoremstyle qedhere qedsymbol qed", "proof", true),
+    PackageInfo::new("mathtools", "coloneqq Coloneqq eqqcolon vcentcolon mathclap mathllap mathrlap DeclarePairedDelimiter prescript shortintertext mathtoolsset", "dcases dcases* multlined pmatrix* bmatrix*", false),
+    PackageInfo::new("bm", "bm", "", false),
+    PackageInfo::new("nicefrac", "nicefrac", "", false),
+    PackageInfo::new("xfrac", "sfrac", "", false),
+    PackageInfo::new("siunitx", "SI si num qty unit ang SIrange qtyrange numrange qtylist numlist sisetup", "", false),
+    PackageInfo::new("scalerel", "scalerel stretchrel scaleto scaleobj", "", false),
+    PackageInfo::new("graphicx", "includegraphics rotatebox scalebox resizebox reflectbox graphicspath", "", false),
+    PackageInfo::new("graphics", "includegraphics rotatebox scalebox resizebox reflectbox graphicspath", "", false),
+    PackageInfo::new("xcolor", "textcolor color colorbox fcolorbox definecolor colorlet pagecolor", "", true),
+    PackageInfo::new("color", "textcolor color colorbox fcolorbox definecolor pagecolor", "", false),
+    PackageInfo::new("tikz", "tikz usetikzlibrary tikzset tikzstyle", "tikzpicture", false),
+    PackageInfo::new("pdfpages", "includepdf", "", false),
+    PackageInfo::new("booktabs", "toprule midrule bottomrule cmidrule addlinespace", "", false),
+    PackageInfo::new("multirow", "multirow", "", false),
+    PackageInfo::new("tabularx", "", "tabularx", false),
+    PackageInfo::new("longtable", "", "longtable", false),
+    PackageInfo::new("array", "newcolumntype", "", true),
+    PackageInfo::new("multicol", "", "multicols multicols*", false),
+    PackageInfo::new("wrapfig", "", "wrapfigure wraptable", false),
+    PackageInfo::new("float", "floatstyle newfloat restylefloat floatname", "", true),
+    PackageInfo::new("caption", "captionsetup captionof", "", true),
+    PackageInfo::new("subcaption", "subcaption subcaptionbox subref", "subfigure subtable", true),
+    PackageInfo::new("listings", "lstinline lstset lstinputlisting lstdefinelanguage lstdefinestyle", "lstlisting", false),
+    PackageInfo::new("minted", "mintinline inputminted setminted newminted", "minted", false),
+    PackageInfo::new("algorithm", "", "algorithm", false),
+    PackageInfo::new("algpseudocode", "State Statex Require Ensure", "algorithmic", false),
+    PackageInfo::new("algorithmic", "STATE REQUIRE ENSURE", "algorithmic", false),
+    PackageInfo::new("hyperref", "href url nolinkurl hypersetup autoref nameref hyperref hyperlink hypertarget phantomsection texorpdfstring", "", true),
+    PackageInfo::new("url", "url urlstyle", "", false),
+    PackageInfo::new("cleveref", "cref Cref crefname Crefname crefrange Crefrange cpageref Cpageref labelcref", "", false),
+    PackageInfo::new("biblatex", "addbibresource printbibliography printbibheading parencite Parencite textcite Textcite autocite Autocite footcite smartcite fullcite citeauthor citeyear DeclareFieldFormat ExecuteBibliographyOptions", "", false),
+    PackageInfo::new("natbib", "citep Citep citet Citet citealp citealt citeauthor citeyear citeyearpar setcitestyle bibpunct", "", true),
+    PackageInfo::new("csquotes", "enquote textquote blockquote MakeOuterQuote", "displayquote", false),
+    PackageInfo::new("xspace", "xspace", "", false),
+    PackageInfo::new("soul", "hl ul st so sethlcolor", "", false),
+    PackageInfo::new("ulem", "uline uuline uwave sout xout", "", true),
+    PackageInfo::new("todonotes", "todo missingfigure listoftodos", "", false),
+    PackageInfo::new("blindtext", "blindtext Blindtext blinddocument Blinddocument", "", false),
+    PackageInfo::new("lipsum", "lipsum", "", false),
+    PackageInfo::new("enumitem", "setlist newlist setenumerate setitemize", "", true),
+    PackageInfo::new("setspace", "doublespacing onehalfspacing singlespacing setstretch", "spacing doublespace onehalfspace singlespace", true),
+    PackageInfo::new("geometry", "geometry newgeometry restoregeometry", "", true),
+    PackageInfo::new("titlesec", "titleformat titlespacing titlelabel", "", true),
+    PackageInfo::new("fancyhdr", "fancyhf fancyhead fancyfoot fancypagestyle", "", false),
+    PackageInfo::new("thmtools", "declaretheorem declaretheoremstyle listoftheorems", "", false),
+    PackageInfo::new("theoremref", "thref thlabel", "", false),
+];
+
+/// The packages loaded by other packages
+const PACKAGE_LOADS: [(&str, &[&str]); 7] = [
+    ("mathtools", &["amsmath"]),
+    ("amssymb", &["amsfonts"]),
+    ("tikz", &["xcolor", "graphicx"]),
+    ("todonotes", &["xcolor", "tikz"]),
+    ("pdfpages", &["graphicx"]),
+    ("subcaption", &["caption"]),
+    ("graphicx", &["graphics"]),
+];
+
+/// The packages loaded by document classes
+const CLASS_LOADS: [(&str, &[&str]); 5] = [
+    (
+        "beamer",
+        &[
+            "hyperref", "xcolor", "graphicx", "amsmath", "amssymb", "amsthm",
+        ],
+    ),
+    ("amsart", &["amsmath", "amsthm", "amsfonts"]),
+    ("amsbook", &["amsmath", "amsthm", "amsfonts"]),
+    ("amsproc", &["amsmath", "amsthm", "amsfonts"]),
+    ("memoir", &["booktabs", "setspace", "array"]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Name {
+    Command(String),
+    Envr(String),
+}
+
+impl Name {
+    fn display(&self) -> String {
+        match self {
+            Name::Command(c) => format!("`\\{}`", c),
+            Name::Envr(e) => format!("`\\begin{{{}}}`", e),
+        }
+    }
+}
+
+/// A command or environment used, with where
+type Use = (Name, PathBuf, Span);
+
+/// A package loaded by `\usepackage`
+struct Load {
+    package: String,
+    file: PathBuf,
+    /// The usepackage command
+    span: Span,
+    /// The curly bracket arg listing the packages
+    list: Span,
+}
+
+/// The packages loaded, and the commands and environments used by the document
+#[derive(Default)]
+struct Usage {
+    class: Option<String>,
+    loads: Vec<Load>,
+    /// Each name used, with where it is first used, in document order
+    used: Vec<Use>,
+    /// Where `\begin{document}` is, in the main file
+    document: Option<usize>,
+}
+
+impl Usage {
+    fn visit(&mut self, node: &NodePtr, context: &NodeContext) {
+        if in_verbatim(context) {
+            return;
+        }
+        let file = context.file.get_path();
+        let node = node.lock().unwrap();
+        let name = match node.node_type {
+            NodeType::Command => Name::Command(node.lexeme.clone()),
+            NodeType::Envr => Name::Envr(node.lexeme.clone()),
+            _ => return,
+        };
+        let first_arg = node.get_curly_bracket_args().first().map(|arg| {
+            let arg = arg.lock().unwrap();
+            (arg.span, arg.get_children_lexeme_recur())
+        });
+        match node.lexeme.as_str() {
+            "documentclass" if node.node_type == NodeType::Command => {
+                self.class =
+                    first_arg.map(|(_, class)| class.trim().to_string());
+                return;
+            }
+            "usepackage" | "RequirePackage"
+                if node.node_type == NodeType::Command =>
+            {
+                let Some((list, _)) = first_arg else {
+                    return;
+                };
+                for package in Package::from_usepackage(&node) {
+                    self.loads.push(Load {
+                        package: package.get_name(),
+                        file: file.clone(),
+                        span: node.span,
+                        list,
+                    });
+                }
+                return;
+            }
+            "document" if node.node_type == NodeType::Envr => {
+                self.document.get_or_insert(node.span.start);
+            }
+            _ => {}
+        }
+
+        if !self.used.iter().any(|(n, _, _)| *n == name) {
+            self.used.push((name, file.clone(), node.span));
+        }
+    }
+
+    /// The packages loaded, by the document, by the class, and by the packages loaded
+    fn loaded(&self) -> HashSet<&str> {
+        let mut ret: HashSet<&str> =
+            self.loads.iter().map(|l| l.package.as_str()).collect();
+        if let Some(class) = &self.class {
+            for (c, packages) in CLASS_LOADS {
+                if c == class {
+                    ret.extend(packages);
+                }
+            }
+        }
+        loop {
+            let more: Vec<&str> = PACKAGE_LOADS
+                .iter()
+                .filter(|(p, _)| ret.contains(p))
+                .flat_map(|(_, packages)| packages.iter().copied())
+                .filter(|p| !ret.contains(p))
+                .collect();
+            if more.is_empty() {
+                return ret;
+            }
+            ret.extend(more);
+        }
+    }
+}
+
+/// The spans of the items of the comma separated list in the curly bracket arg at list, None
+/// if it has comments
+fn list_items(source: &str, list: Span) -> Option<Vec<Span>> {
+    let text = &source[list.start..list.end];
+    let start = list.start + usize::from(text.starts_with('{'));
+    let end = list.end - usize::from(text.ends_with('}'));
+    let text = &source[start..end];
+    if text.contains('%') {
+        return None;
+    }
+    let mut ret = vec![];
+    let mut offset = start;
+    for item in text.split(',') {
+        let trimmed = item.trim_start();
+        let item_start = offset + item.len() - trimmed.len();
+        let item_end = item_start + trimmed.trim_end().len();
+        if item_end > item_start {
+            ret.push(Span::new(item_start, item_end));
+        }
+        offset += item.len() + 1;
+    }
+    Some(ret)
+}
+
+pub(super) fn rules() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(UnusedPackage::default()),
+        Box::new(MissingPackage::default()),
+    ]
+}
+
+#[derive(Default)]
+struct UnusedPackage {
+    usage: Usage,
+}
+
+impl Rule for UnusedPackage {
+    fn name(&self) -> &'static str {
+        "unused-package"
+    }
+
+    fn description(&self) -> &'static str {
+        "package loaded but none of its commands used"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        _reporter: &mut Reporter,
+    ) {
+        self.usage.visit(node, context);
+    }
+
+    fn check_document(
+        &mut self,
+        graph: &IncludeGraph,
+        reporter: &mut Reporter,
+    ) -> Result<(), Box<dyn Error>> {
+        let usage = &self.usage;
+        let unused = |load: &Load| {
+            PACKAGES.iter().any(|p| {
+                p.name == load.package
+                    && !p.has_effects
+                    && !usage.used.iter().any(|(name, _, _)| p.provides(name))
+            })
+        };
+
+        // the packages of each usepackage command, whose fixes must not overlap
+        let mut commands: Vec<Vec<&Load>> = vec![];
+        for load in &usage.loads {
+            match commands
+                .iter_mut()
+                .find(|c| c[0].file == load.file && c[0].span == load.span)
+            {
+                Some(command) => command.push(load),
+                None => commands.push(vec![load]),
+            }
+        }
+        for command in commands {
+            let Some(file) = graph.get_file(&command[0].file) else {
+                continue;
+            };
+            let file_input = &file.file_input;
+            let source = file_input.get_str_content();
+            let flags: Vec<bool> = command.iter().map(|l| unused(l)).collect();
+            let items = list_items(source, command[0].list)
+                .filter(|items| items.len() == command.len());
+
+            // An unused package is deleted with the comma after it, or, after the last package
+            // kept, with the comma before it
+            let last_kept = flags.iter().rposition(|unused| !unused);
+            for (i, load) in command.iter().enumerate() {
+                if !flags[i] {
+                    continue;
+                }
+                let message = format!(
+                    "package `{}` is loaded, but none of its commands are used",
+                    load.package
+                );
+                let fix = match (&items, last_kept) {
+                    // the whole command, with the first package
+                    (_, None) if i == 0 => Some(line_of(source, load.span)),
+                    (_, None) => None,
+                    (Some(items), Some(kept)) if i < kept => {
+                        Some(Span::new(items[i].start, items[i + 1].start))
+                    }
+                    (Some(items), Some(_)) => {
+                        Some(Span::new(items[i - 1].end, items[i].end))
+                    }
+                    (None, Some(_)) => None,
+                };
+                let span = items.as_ref().map_or(load.span, |items| items[i]);
+                match fix {
+                    Some(fix) => reporter.report_fix(
+                        file_input,
+                        span,
+                        message,
+                        vec![Edit::delete(fix)],
+                    ),
+                    None => reporter.report(file_input, span, message),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct MissingPackage {
+    usage: Usage,
+}
+
+impl Rule for MissingPackage {
+    fn name(&self) -> &'static str {
+        "missing-package"
+    }
+
+    fn description(&self) -> &'static str {
+        "command used but its package not loaded"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        _reporter: &mut Reporter,
+    ) {
+        self.usage.visit(node, context);
+    }
+
+    fn check_document(
+        &mut self,
+        graph: &IncludeGraph,
+        reporter: &mut Reporter,
+    ) -> Result<(), Box<dyn Error>> {
+        let usage = &self.usage;
+        let loaded = usage.loaded();
+        let symbols = SymbolTable::build(graph);
+
+        // the names needing the same packages are reported together, at the first of them
+        let mut missing: Vec<(Vec<&str>, Vec<&Use>)> = vec![];
+        for used in &usage.used {
+            let defined = match &used.0 {
+                Name::Command(c) => symbols.command(c).is_some(),
+                Name::Envr(e) => {
+                    symbols.environment(e).is_some()
+                        || symbols.theorem(e).is_some()
+                }
+            };
+            if defined {
+                continue;
+            }
+            let providers: Vec<&str> = PACKAGES
+                .iter()
+                .filter(|p| p.provides(&used.0))
+                .map(|p| p.name)
+                .collect();
+            if providers.is_empty()
+                || providers.iter().any(|p| loaded.contains(p))
+            {
+                continue;
+            }
+            match missing.iter_mut().find(|(p, _)| *p == providers) {
+                Some((_, names)) => names.push(used),
+                None => missing.push((providers, vec![used])),
+            }
+        }
+
+        let Some(main) = graph.get_files().first() else {
+            return Ok(());
+        };
+        let source = main.file_input.get_str_content();
+        // after the last package loaded by the main file, or before `\begin{document}`
+        let insert_at = usage
+            .loads
+            .iter()
+            .filter(|l| l.file == *main.get_path())
+            .map(|l| l.span.end)
+            .max()
+            .map(|end| source[end..].find('\n').map(|i| end + i + 1))
+            .unwrap_or(usage.document);
+
+        for (providers, names) in missing {
+            let (_, file, span) = names[0];
+            let Some(file) = graph.get_file(file) else {
+                continue;
+            };
+            let names: Vec<String> =
+                names.iter().map(|(name, _, _)| name.display()).collect();
+            let verb = if names.len() == 1 { "needs" } else { "need" };
+            let names = names.join(", ");
+            if providers.len() > 1 {
+                reporter.report(
+                    &file.file_input,
+                    *span,
+                    format!(
+                        "{} {} one of the packages {}, which are not loaded",
+                        names,
+                        verb,
+                        providers.join(", ")
+                    ),
+                );
+                continue;
+            }
+            let message = format!(
+                "{} {} the {} package, which is not loaded",
+                names, verb, providers[0]
+            );
+            match insert_at {
+                // the fix can only edit the file of the diagnostic
+                Some(at) if file.get_path() == main.get_path() => reporter
+                    .report_fix(
+                        &file.file_input,
+                        *span,
+                        message,
+                        vec![Edit::new(
+                            Span::new(at, at),
+                            &format!("\\usepackage{{{}}}\n", providers[0]),
+                        )],
+                    ),
+                _ => reporter.report(&file.file_input, *span, message),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::linter::test::lint_source;
+    use crate::linter::Diagnostic;
+
+    fn messages(diagnostics: &[Diagnostic]) -> Vec<&str> {
+        diagnostics.iter().map(|d| d.message.as_str()).collect()
+    }
+
+    #[test]
+    fn test_unused_package() {
+        let (diagnostics, fixed) = lint_source(
+            "unused-package",
+            r"\documentclass{article}
+\usepackage{amsmath, booktabs,xcolor}
+\usepackage[dvipsnames]{xcolor}
+\usepackage{hyperref}
+\usepackage{blindtext}
+\newcommand{\midrule}{\hline}
+\begin{document}
+\begin{tabular}{l}
+\toprule a \\ \midrule
+\end{tabular}
+\begin{verbatim}\blindtext\end{verbatim}
+\textcolor{red}{x} \url{a}
+\end{document}",
+        );
+        assert_eq!(
+            messages(&diagnostics),
+            vec![
+                "package `amsmath` is loaded, but none of its commands are used",
+                "package `blindtext` is loaded, but none of its commands are used",
+            ]
+        );
+        assert_eq!(
+            fixed,
+            r"\documentclass{article}
+\usepackage{booktabs,xcolor}
+\usepackage[dvipsnames]{xcolor}
+\usepackage{hyperref}
+\newcommand{\midrule}{\hline}
+\begin{document}
+\begin{tabular}{l}
+\toprule a \\ \midrule
+\end{tabular}
+\begin{verbatim}\blindtext\end{verbatim}
+\textcolor{red}{x} \url{a}
+\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_missing_package() {
+        let (diagnostics, fixed) = lint_source(
+            "missing-package",
+            r"\documentclass{article}
+\usepackage{hyperref}
+\newcommand{\R}{\mathbb{R}}
+\begin{document}
+\begin{tabularx}{\textwidth}{X}
+a
+\end{tabularx}
+\begin{verbatim}\textcolor{red}{x}\end{verbatim}
+$\R$ \cref{a} \verb|\textcolor|
+\end{document}",
+        );
+        assert_eq!(
+            messages(&diagnostics),
+            vec![
+                "`\\mathbb` needs one of the packages amssymb, amsfonts, which are not loaded",
+                "`\\begin{tabularx}` needs the tabularx package, which is not loaded",
+                "`\\cref` needs the cleveref package, which is not loaded",
+            ]
+        );
+        assert_eq!(
+            fixed,
+            r"\documentclass{article}
+\usepackage{hyperref}
+\usepackage{tabularx}
+\newcommand{\R}{\mathbb{R}}
+\begin{document}
+\begin{tabularx}{\textwidth}{X}
+a
+\end{tabularx}
+\begin{verbatim}\textcolor{red}{x}\end{verbatim}
+$\R$ \cref{a} \verb|\textcolor|
+\end{document}"
+        );
+    }
+}
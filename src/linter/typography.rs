@@ -0,0 +1,561 @@
+//! The typographic rules, in the spirit of chktex. Each problem has a fix.
+//!
+//! 1. `tilde-before-ref`: a space instead of `~` between a word and `\ref` or `\cite`, which
+//!    lets the line break between them
+//! 1. `ellipsis`: `...` instead of `\ldots` (`\dots` in math)
+//! 1. `straight-quotes`: straight `"` instead of `` and ''
+//! 1. `range-dash`: a hyphen instead of an en-dash in a range of numbers, `10-20` for `10--20`
+//! 1. `space-before-footnote`: a space between a word and `\footnote`, which is typeset before
+//!    the footnote mark
+//! 1. `display-dollars`: `$$...$$`, which breaks the vertical spacing, instead of `\[...\]`
+//! 1. `sentence-end-capital`: a sentence ending with a capital letter, as in `by NASA. The`. TeX
+//!    takes the period after a capital letter for an abbreviation, and spaces it as such, unless
+//!    it is preceded by `\@`
+//!
+//! Apart from display-dollars, the rules only check the prose of the document body: the text
+//! outside math, verbatim and the args of commands like `\label` and `\url`.
+
+use super::{in_verbatim, NodeContext, Reporter, Rule};
+use crate::latex_interpreter::ast::{NodePtr, NodeType, Span};
+use crate::latex_interpreter::detex::{MATH_ENVRS, NON_PROSE};
+use crate::latex_interpreter::edit::Edit;
+
+/// The commands which shall be tied to the word before them
+const TIED_COMMANDS: [&str; 5] = ["ref", "eqref", "pageref", "vref", "cite"];
+
+/// The punctuation after which a reference or citation is not tied
+const LOOSE_PUNCTUATION: [char; 5] = [',', ';', ':', '!', '?'];
+
+pub(super) fn rules() -> Vec<Box<dyn Rule>> {
+    vec![
+        Box::new(TildeBeforeRef),
+        Box::new(Ellipsis),
+        Box::new(StraightQuotes),
+        Box::new(RangeDash),
+        Box::new(SpaceBeforeFootnote),
+        Box::new(DisplayDollars),
+        Box::new(SentenceEndCapital),
+    ]
+}
+
+/// Whether the node at the walker of context is in math
+pub(super) fn in_math(context: &NodeContext) -> bool {
+    context.walker.ancestors().iter().any(|a| {
+        let a = a.lock().unwrap();
+        match a.node_type {
+            NodeType::InlineMath | NodeType::DisplayMath => true,
+            NodeType::Envr => MATH_ENVRS.contains(&a.lexeme.as_str()),
+            _ => false,
+        }
+    })
+}
+
+/// Whether the node at the walker of context is prose, see the module doc
+fn in_prose(context: &NodeContext) -> bool {
+    if !context.in_body || in_math(context) {
+        return false;
+    }
+    if in_verbatim(context) {
+        return false;
+    }
+    !context.walker.ancestors().iter().any(|a| {
+        let a = a.lock().unwrap();
+        a.node_type == NodeType::Command
+            && NON_PROSE.contains(&a.lexeme.as_str())
+    })
+}
+
+/// The prose word node is, with its span
+fn prose_word(node: &NodePtr, context: &NodeContext) -> Option<Span> {
+    let span = {
+        let node = node.lock().unwrap();
+        if node.node_type != NodeType::Word {
+            return None;
+        }
+        node.span
+    };
+    in_prose(context).then_some(span)
+}
+
+/// The spaces between the word before the node at the walker of context and start, with the text
+/// of the word. None if the node does not follow a word, or follows it without a space
+fn space_after_word(
+    context: &NodeContext,
+    start: usize,
+) -> Option<(Span, String)> {
+    let prev = context.walker.prev_sibling()?;
+    let prev = prev.lock().unwrap();
+    if prev.node_type != NodeType::Word {
+        return None;
+    }
+    let file_input = &context.file.file_input;
+    let gap = Span::new(prev.span.end, start);
+    let text = file_input.get_span_str(&gap);
+    if text.is_empty() || !text.chars().all(char::is_whitespace) {
+        return None;
+    }
+    Some((gap, file_input.get_span_str(&prev.span).to_string()))
+}
+
+/// The command node is, if it is one of names
+fn command_in(node: &NodePtr, names: &[&str]) -> Option<(String, Span)> {
+    let node = node.lock().unwrap();
+    (node.node_type == NodeType::Command
+        && names.contains(&node.lexeme.as_str()))
+    .then(|| (node.lexeme.clone(), node.span))
+}
+
+struct TildeBeforeRef;
+
+impl Rule for TildeBeforeRef {
+    fn name(&self) -> &'static str {
+        "tilde-before-ref"
+    }
+
+    fn description(&self) -> &'static str {
+        "space instead of `~` before `\\ref` or `\\cite`"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let Some((command, span)) = command_in(node, &TIED_COMMANDS) else {
+            return;
+        };
+        if !in_prose(context) {
+            return;
+        }
+        let Some((gap, word)) = space_after_word(context, span.start) else {
+            return;
+        };
+        // `see, \cite{a}` does not need a tie, `Fig. \ref{a}` does
+        if word.ends_with(LOOSE_PUNCTUATION) {
+            return;
+        }
+        reporter.report_fix(
+            &context.file.file_input,
+            span,
+            format!("use `~` instead of a space before `\\{}`", command),
+            vec![Edit::new(gap, "~")],
+        );
+    }
+}
+
+struct Ellipsis;
+
+impl Rule for Ellipsis {
+    fn name(&self) -> &'static str {
+        "ellipsis"
+    }
+
+    fn description(&self) -> &'static str {
+        "`...` instead of `\\ldots`"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let span = {
+            let node = node.lock().unwrap();
+            if node.node_type != NodeType::Word {
+                return;
+            }
+            node.span
+        };
+        let math = in_math(context);
+        if !math && !in_prose(context) {
+            return;
+        }
+        let file_input = &context.file.file_input;
+        let source = file_input.get_str_content();
+        let text = file_input.get_span_str(&span);
+        let Some(i) = text.find("...") else {
+            return;
+        };
+        let dots = Span::new(span.start + i, span.start + i + 3);
+        let replacement = match source[dots.end..].chars().next() {
+            _ if math => "\\dots",
+            // a space after a command is eaten, and a letter extends its name
+            Some(c) if c.is_whitespace() || c.is_alphabetic() => "\\ldots{}",
+            _ => "\\ldots",
+        };
+        reporter.report_fix(
+            file_input,
+            dots,
+            format!(
+                "use `{}` instead of `...`",
+                replacement.trim_end_matches("{}")
+            ),
+            vec![Edit::new(dots, replacement)],
+        );
+    }
+}
+
+struct StraightQuotes;
+
+impl Rule for StraightQuotes {
+    fn name(&self) -> &'static str {
+        "straight-quotes"
+    }
+
+    fn description(&self) -> &'static str {
+        "straight `\"` instead of `` and ''"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let Some(span) = prose_word(node, context) else {
+            return;
+        };
+        let file_input = &context.file.file_input;
+        let source = file_input.get_str_content();
+        for (i, _) in file_input.get_span_str(&span).match_indices('"') {
+            let start = span.start + i;
+            let before = source[..start].chars().next_back();
+            // `\"o` is an umlaut
+            if before == Some('\\') {
+                continue;
+            }
+            let opening = match before {
+                None => true,
+                Some(c) => c.is_whitespace() || "([{~".contains(c),
+            };
+            let replacement = if opening { "``" } else { "''" };
+            reporter.report_fix(
+                file_input,
+                Span::new(start, start + 1),
+                format!("use {} instead of `\"`", replacement),
+                vec![Edit::new(Span::new(start, start + 1), replacement)],
+            );
+        }
+    }
+}
+
+struct RangeDash;
+
+impl Rule for RangeDash {
+    fn name(&self) -> &'static str {
+        "range-dash"
+    }
+
+    fn description(&self) -> &'static str {
+        "hyphen instead of `--` in a range of numbers"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let Some(span) = prose_word(node, context) else {
+            return;
+        };
+        let file_input = &context.file.file_input;
+        let text = file_input.get_span_str(&span);
+        // a date like 2020-01-02 is not a range
+        let hyphens: Vec<usize> =
+            text.match_indices('-').map(|(i, _)| i).collect();
+        let [i] = hyphens[..] else {
+            return;
+        };
+        let digit_before = text[..i].ends_with(|c: char| c.is_ascii_digit());
+        let digit_after =
+            text[i + 1..].starts_with(|c: char| c.is_ascii_digit());
+        if !digit_before || !digit_after {
+            return;
+        }
+        let hyphen = Span::new(span.start + i, span.start + i + 1);
+        reporter.report_fix(
+            file_input,
+            hyphen,
+            "use `--` for a range of numbers".to_string(),
+            vec![Edit::new(hyphen, "--")],
+        );
+    }
+}
+
+struct SpaceBeforeFootnote;
+
+impl Rule for SpaceBeforeFootnote {
+    fn name(&self) -> &'static str {
+        "space-before-footnote"
+    }
+
+    fn description(&self) -> &'static str {
+        "space before `\\footnote`"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let Some((command, span)) =
+            command_in(node, &["footnote", "footnotemark"])
+        else {
+            return;
+        };
+        if !in_prose(context) {
+            return;
+        }
+        let Some((gap, _)) = space_after_word(context, span.start) else {
+            return;
+        };
+        reporter.report_fix(
+            &context.file.file_input,
+            span,
+            format!("remove the space before `\\{}`", command),
+            vec![Edit::delete(gap)],
+        );
+    }
+}
+
+struct DisplayDollars;
+
+impl Rule for DisplayDollars {
+    fn name(&self) -> &'static str {
+        "display-dollars"
+    }
+
+    fn description(&self) -> &'static str {
+        "`$$...$$` instead of `\\[...\\]`"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let span = {
+            let node = node.lock().unwrap();
+            if node.node_type != NodeType::DisplayMath {
+                return;
+            }
+            node.span
+        };
+        let file_input = &context.file.file_input;
+        let text = file_input.get_span_str(&span);
+        if !text.starts_with("$$") || !text.ends_with("$$") || text.len() < 4 {
+            return;
+        }
+        reporter.report_fix(
+            file_input,
+            span,
+            "use `\\[...\\]` instead of `$$...$$`".to_string(),
+            vec![
+                Edit::new(Span::new(span.start, span.start + 2), "\\["),
+                Edit::new(Span::new(span.end - 2, span.end), "\\]"),
+            ],
+        );
+    }
+}
+
+struct SentenceEndCapital;
+
+impl Rule for SentenceEndCapital {
+    fn name(&self) -> &'static str {
+        "sentence-end-capital"
+    }
+
+    fn description(&self) -> &'static str {
+        "sentence ending with a capital letter without `\\@`"
+    }
+
+    fn check_node(
+        &mut self,
+        node: &NodePtr,
+        context: &NodeContext,
+        reporter: &mut Reporter,
+    ) {
+        let Some(span) = prose_word(node, context) else {
+            return;
+        };
+        let file_input = &context.file.file_input;
+        let text = file_input.get_span_str(&span);
+        let Some(stripped) = text.strip_suffix(['.', '?', '!']) else {
+            return;
+        };
+        // initials like `D. E. Knuth` are not sentence ends
+        let letters = stripped.chars().filter(|c| c.is_alphabetic()).count();
+        if letters < 2 || !stripped.ends_with(char::is_uppercase) {
+            return;
+        }
+        // the sentence ends if a capitalised word follows
+        let Some(next) = context.walker.next_sibling() else {
+            return;
+        };
+        let next = next.lock().unwrap();
+        let next_text = file_input.get_span_str(&next.span);
+        let spaced = file_input
+            .get_span_str(&Span::new(span.end, next.span.start))
+            .chars()
+            .any(char::is_whitespace);
+        if next.node_type != NodeType::Word
+            || !spaced
+            || !next_text.starts_with(char::is_uppercase)
+        {
+            return;
+        }
+        let end = Span::new(span.end - 1, span.end);
+        reporter.report_fix(
+            file_input,
+            end,
+            format!(
+                "write `{}\\@{}` to end the sentence",
+                stripped,
+                &text[stripped.len()..]
+            ),
+            vec![Edit::new(Span::new(end.start, end.start), "\\@")],
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::linter::test::{lint_source, locations};
+
+    #[test]
+    fn test_tilde_before_ref() {
+        let (diagnostics, fixed) = lint_source(
+            "tilde-before-ref",
+            r"\begin{document}
+See Figure \ref{a} and Fig.~\ref{b}, as in \cite{c}, Eq. \eqref{e}; see, \cite{d}.
+$a \ref{x}$ \verb|a \ref{x}|
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(1, 11), (1, 43), (1, 57)]);
+        assert_eq!(
+            diagnostics[0].message,
+            "use `~` instead of a space before `\\ref`"
+        );
+        assert_eq!(
+            fixed,
+            r"\begin{document}
+See Figure~\ref{a} and Fig.~\ref{b}, as in~\cite{c}, Eq.~\eqref{e}; see, \cite{d}.
+$a \ref{x}$ \verb|a \ref{x}|
+\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_ellipsis() {
+        let (diagnostics, fixed) = lint_source(
+            "ellipsis",
+            r"\begin{document}
+Wait... and so... $$x ... y$$ \url{a...b} \verb|...|
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(1, 4), (1, 14), (1, 22)]);
+        assert_eq!(
+            fixed,
+            r"\begin{document}
+Wait\ldots{} and so\ldots{} $$x \dots y$$ \url{a...b} \verb|...|
+\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_straight_quotes() {
+        let (diagnostics, fixed) = lint_source(
+            "straight-quotes",
+            r#"\newcommand{\q}{"a"}
+\begin{document}
+A "quoted" text \verb|"x"|.
+\end{document}"#,
+        );
+        assert_eq!(locations(&diagnostics), vec![(2, 2), (2, 9)]);
+        assert_eq!(diagnostics[0].message, "use `` instead of `\"`");
+        assert_eq!(
+            fixed,
+            r#"\newcommand{\q}{"a"}
+\begin{document}
+A ``quoted'' text \verb|"x"|.
+\end{document}"#
+        );
+    }
+
+    #[test]
+    fn test_range_dash() {
+        let (diagnostics, fixed) = lint_source(
+            "range-dash",
+            r"\begin{document}
+Pages 10-20 on 2020-01-02, $1-2$ \label{x-1}.
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(1, 8)]);
+        assert_eq!(
+            fixed,
+            r"\begin{document}
+Pages 10--20 on 2020-01-02, $1-2$ \label{x-1}.
+\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_space_before_footnote() {
+        let (diagnostics, fixed) = lint_source(
+            "space-before-footnote",
+            r"\begin{document}
+The end \footnote{x}, the end\footnote{y}.
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(1, 8)]);
+        assert_eq!(
+            fixed,
+            r"\begin{document}
+The end\footnote{x}, the end\footnote{y}.
+\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_display_dollars() {
+        let (diagnostics, fixed) = lint_source(
+            "display-dollars",
+            r"\begin{document}
+$$x - y$$ and \[z\] and $a$
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(1, 0)]);
+        assert_eq!(
+            fixed,
+            r"\begin{document}
+\[x - y\] and \[z\] and $a$
+\end{document}"
+        );
+    }
+
+    #[test]
+    fn test_sentence_end_capital() {
+        let (diagnostics, fixed) = lint_source(
+            "sentence-end-capital",
+            r"\begin{document}
+By NASA. By D. E. Knuth. By NASA\@.
+\end{document}",
+        );
+        assert_eq!(locations(&diagnostics), vec![(1, 7)]);
+        assert_eq!(
+            diagnostics[0].message,
+            "write `NASA\\@.` to end the sentence"
+        );
+        assert_eq!(
+            fixed,
+            r"\begin{document}
+By NASA\@. By D. E. Knuth. By NASA\@.
+\end{document}"
+        );
+    }
+}
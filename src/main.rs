@@ -3,9 +3,11 @@ extern crate simplelog;
 #[macro_use]
 extern crate log;
 
+mod bib_interpreter;
 mod cli;
 mod config;
 mod latex_interpreter;
+mod linter;
 mod markdown_interpreter;
 mod utils;
 
@@ -14,6 +16,7 @@ fn main() {
         Ok(_) => {}
         Err(e) => {
             error!("{}", e);
+            std::process::exit(1);
         }
     }
 }
@@ -0,0 +1,87 @@
+//! The AST of markdown
+//!
+//! Unlike the latex AST, the markdown AST is only built once and read by the emitter, so the
+//! children are owned directly.
+//!
+//! Block nodes (Document, Heading, Paragraph, ...) contain blocks or inlines, inline nodes
+//! (Text, Emphasis, Link, ...) contain inlines. Leaf nodes keep their content in lexeme.
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum NodeType {
+    // Blocks
+    Document,
+    Heading(u8),
+    Paragraph,
+    BlockQuote,
+    /// Children are ListItems
+    List(ListKind),
+    /// Some(checked) for the items of task lists: `- [ ] todo`, `- [x] done`
+    ListItem(Option<bool>),
+    /// The lexeme is the code, the info string (language) is kept in the NodeType
+    CodeBlock(String),
+    /// The lexeme is the math, delimiters included, exactly as in the source
+    DisplayMath,
+    /// Children are TableRows, the first of which is the header
+    /// The alignment of each column is taken from the delimiter row
+    Table(Vec<Alignment>),
+    /// Children are TableCells
+    TableRow,
+    TableCell,
+    ThematicBreak,
+
+    // Inlines
+    Text,
+    Emphasis,
+    Strong,
+    Strikethrough,
+    /// The lexeme is the code
+    Code,
+    /// The lexeme is the math, delimiters included, exactly as in the source
+    InlineMath,
+    /// The lexeme is the url, children are the link text
+    Link,
+    /// The lexeme is the url, children are the alternative text
+    Image,
+    /// Latex in the markdown, like `\cite{key}`, the lexeme is passed through as it is
+    RawLatex,
+    SoftBreak,
+    HardBreak,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ListKind {
+    Bullet,
+    Ordered,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Alignment {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Node {
+    pub node_type: NodeType,
+    pub lexeme: String,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(node_type: NodeType, lexeme: &str) -> Self {
+        Node {
+            node_type,
+            lexeme: lexeme.to_string(),
+            children: vec![],
+        }
+    }
+
+    pub fn with_children(node_type: NodeType, children: Vec<Node>) -> Self {
+        Node {
+            node_type,
+            lexeme: String::new(),
+            children,
+        }
+    }
+}
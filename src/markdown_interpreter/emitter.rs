@@ -0,0 +1,412 @@
+//! Emit latex from the markdown AST
+//!
+//! 1. Headings become `\section`, `\subsection`, `\subsubsection`, `\paragraph` and
+//!    `\subparagraph`
+//! 1. Emphasis, strong and strikethrough become `\emph`, `\textbf` and `\sout`
+//! 1. Lists become itemize and enumerate, block quotes become quote
+//! 1. Code blocks become verbatim, inline code becomes `\texttt`
+//! 1. Links become `\href` (`\url` if the text is the url), images become `\includegraphics`,
+//!    in a figure with the alternative text as the caption if the image is a paragraph by itself
+//! 1. Tables become tabular, aligned as the delimiter row tells, with the rules of booktabs if
+//!    asked
+//! 1. Nested lists become nested itemize and enumerate, the items of task lists are labeled
+//!    with a box
+//! 1. Math and latex commands are passed through untouched, the special characters of latex in
+//!    the text are escaped
+//!
+//! The output is a fragment to be included in a document. The packages it needs are returned
+//! along with it.
+
+use std::fmt;
+
+use super::ast::{Alignment, ListKind, Node, NodeType};
+
+/// A package needed by the emitted latex
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Package {
+    pub name: &'static str,
+    pub options: &'static str,
+}
+
+impl fmt::Display for Package {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.options.is_empty() {
+            write!(f, "\\usepackage{{{}}}", self.name)
+        } else {
+            write!(f, "\\usepackage[{}]{{{}}}", self.options, self.name)
+        }
+    }
+}
+
+const HYPERREF: Package = Package {
+    name: "hyperref",
+    options: "",
+};
+const GRAPHICX: Package = Package {
+    name: "graphicx",
+    options: "",
+};
+const BOOKTABS: Package = Package {
+    name: "booktabs",
+    options: "",
+};
+const AMSSYMB: Package = Package {
+    name: "amssymb",
+    options: "",
+};
+// without normalem, ulem redefines \emph to underline
+const ULEM: Package = Package {
+    name: "ulem",
+    options: "normalem",
+};
+
+#[derive(Debug)]
+pub struct Latex {
+    pub body: String,
+    /// Sorted, each appears once
+    pub packages: Vec<Package>,
+}
+
+#[derive(Debug, Default)]
+pub struct EmitOptions {
+    /// Use the rules of booktabs in tables
+    pub booktabs: bool,
+}
+
+struct EmitState<'a> {
+    options: &'a EmitOptions,
+    packages: Vec<Package>,
+}
+
+impl EmitState<'_> {
+    fn require(&mut self, package: Package) {
+        if !self.packages.contains(&package) {
+            self.packages.push(package);
+        }
+    }
+}
+
+/// Emit the latex of a Document node
+pub fn emit(doc: &Node, options: &EmitOptions) -> Latex {
+    let mut state = EmitState {
+        options,
+        packages: vec![],
+    };
+    let mut body = emit_blocks(&doc.children, &mut state);
+    if !body.is_empty() {
+        body.push('\n');
+    }
+    state.packages.sort();
+    Latex {
+        body,
+        packages: state.packages,
+    }
+}
+
+/// Blocks are separated by an empty line
+fn emit_blocks(blocks: &[Node], state: &mut EmitState) -> String {
+    blocks
+        .iter()
+        .map(|b| emit_block(b, state))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+fn emit_block(node: &Node, state: &mut EmitState) -> String {
+    match &node.node_type {
+        NodeType::Heading(level) => {
+            let command = match level {
+                1 => "section",
+                2 => "subsection",
+                3 => "subsubsection",
+                4 => "paragraph",
+                _ => "subparagraph",
+            };
+            format!("\\{}{{{}}}", command, emit_inlines(&node.children, state))
+        }
+        NodeType::Paragraph => match node.children.as_slice() {
+            [image] if image.node_type == NodeType::Image => {
+                emit_figure(image, state)
+            }
+            inlines => emit_inlines(inlines, state),
+        },
+        NodeType::BlockQuote => format!(
+            "\\begin{{quote}}\n{}\n\\end{{quote}}",
+            emit_blocks(&node.children, state)
+        ),
+        NodeType::List(kind) => emit_list(node, *kind, state),
+        NodeType::CodeBlock(_) => {
+            format!("\\begin{{verbatim}}\n{}\\end{{verbatim}}", node.lexeme)
+        }
+        NodeType::DisplayMath => node.lexeme.clone(),
+        NodeType::Table(alignments) => emit_table(node, alignments, state),
+        NodeType::ThematicBreak => {
+            "\\noindent\\rule{\\linewidth}{0.4pt}".into()
+        }
+        // inlines are not expected here, but emit them anyway
+        _ => emit_inline(node, state),
+    }
+}
+
+fn emit_figure(image: &Node, state: &mut EmitState) -> String {
+    state.require(GRAPHICX);
+    let mut ret = String::from("\\begin{figure}[htbp]\n  \\centering\n");
+    ret.push_str(&format!(
+        "  \\includegraphics[width=0.8\\linewidth]{{{}}}\n",
+        image.lexeme
+    ));
+    if !image.children.is_empty() {
+        ret.push_str(&format!(
+            "  \\caption{{{}}}\n",
+            emit_inlines(&image.children, state)
+        ));
+    }
+    ret.push_str("\\end{figure}");
+    ret
+}
+
+/// Items are indented by two spaces, so are the nested lists in them
+fn emit_list(list: &Node, kind: ListKind, state: &mut EmitState) -> String {
+    let envr = match kind {
+        ListKind::Bullet => "itemize",
+        ListKind::Ordered => "enumerate",
+    };
+    let mut items: Vec<String> = vec![];
+    for item in &list.children {
+        let label = match item.node_type {
+            NodeType::ListItem(Some(checked)) => {
+                state.require(AMSSYMB);
+                if checked {
+                    "[$\\boxtimes$]"
+                } else {
+                    "[$\\square$]"
+                }
+            }
+            _ => "",
+        };
+        items.push(format!(
+            "\\item{} {}",
+            label,
+            emit_item_blocks(&item.children, state)
+        ));
+    }
+    format!(
+        "\\begin{{{}}}\n{}\n\\end{{{}}}",
+        envr,
+        indent(&items.join("\n"), "  "),
+        envr
+    )
+}
+
+/// Paragraphs in an item are separated by an empty line, other blocks by a new line
+fn emit_item_blocks(blocks: &[Node], state: &mut EmitState) -> String {
+    let mut ret = String::new();
+    for (i, block) in blocks.iter().enumerate() {
+        if i > 0 {
+            let both_paragraphs = block.node_type == NodeType::Paragraph
+                && blocks[i - 1].node_type == NodeType::Paragraph;
+            ret.push_str(if both_paragraphs { "\n\n" } else { "\n" });
+        }
+        ret.push_str(&emit_block(block, state));
+    }
+    ret
+}
+
+/// Indent the non empty lines of text, except those in verbatim, which would change the code
+fn indent(text: &str, prefix: &str) -> String {
+    let mut in_verbatim = false;
+    let mut lines: Vec<String> = vec![];
+    for line in text.lines() {
+        if line.trim_start() == "\\end{verbatim}" {
+            in_verbatim = false;
+        }
+        if in_verbatim || line.is_empty() {
+            lines.push(line.to_string());
+        } else {
+            lines.push(format!("{}{}", prefix, line));
+        }
+        if line.trim_start() == "\\begin{verbatim}" {
+            in_verbatim = true;
+        }
+    }
+    lines.join("\n")
+}
+
+/// The alignment of the columns comes from the delimiter row of the markdown table
+/// With the booktabs option, the rules of booktabs replace `\hline`
+fn emit_table(
+    table: &Node,
+    alignments: &[Alignment],
+    state: &mut EmitState,
+) -> String {
+    let column_spec: String = alignments
+        .iter()
+        .map(|a| match a {
+            Alignment::Left => 'l',
+            Alignment::Center => 'c',
+            Alignment::Right => 'r',
+        })
+        .collect();
+    let (top, mid, bottom) = if state.options.booktabs {
+        state.require(BOOKTABS);
+        ("\\toprule", "\\midrule", "\\bottomrule")
+    } else {
+        ("\\hline", "\\hline", "\\hline")
+    };
+
+    let mut ret = format!("\\begin{{tabular}}{{{}}}\n  {}\n", column_spec, top);
+    for (i, row) in table.children.iter().enumerate() {
+        let cells: Vec<String> = row
+            .children
+            .iter()
+            .map(|c| emit_inlines(&c.children, state))
+            .collect();
+        ret.push_str(&format!("  {} \\\\\n", cells.join(" & ")));
+        if i == 0 {
+            ret.push_str(&format!("  {}\n", mid));
+        }
+    }
+    ret.push_str(&format!("  {}\n\\end{{tabular}}", bottom));
+    ret
+}
+
+fn emit_inlines(inlines: &[Node], state: &mut EmitState) -> String {
+    inlines.iter().map(|i| emit_inline(i, state)).collect()
+}
+
+fn emit_inline(node: &Node, state: &mut EmitState) -> String {
+    match node.node_type {
+        NodeType::Text => escape(&node.lexeme),
+        NodeType::Emphasis => {
+            format!("\\emph{{{}}}", emit_inlines(&node.children, state))
+        }
+        NodeType::Strong => {
+            format!("\\textbf{{{}}}", emit_inlines(&node.children, state))
+        }
+        NodeType::Strikethrough => {
+            state.require(ULEM);
+            format!("\\sout{{{}}}", emit_inlines(&node.children, state))
+        }
+        NodeType::Code => format!("\\texttt{{{}}}", escape(&node.lexeme)),
+        NodeType::InlineMath | NodeType::RawLatex => node.lexeme.clone(),
+        NodeType::Link => {
+            state.require(HYPERREF);
+            let url = escape_url(&node.lexeme);
+            match node.children.as_slice() {
+                [text]
+                    if text.node_type == NodeType::Text
+                        && text.lexeme == node.lexeme =>
+                {
+                    format!("\\url{{{}}}", url)
+                }
+                children => format!(
+                    "\\href{{{}}}{{{}}}",
+                    url,
+                    emit_inlines(children, state)
+                ),
+            }
+        }
+        NodeType::Image => {
+            state.require(GRAPHICX);
+            format!("\\includegraphics[width=\\linewidth]{{{}}}", node.lexeme)
+        }
+        NodeType::SoftBreak => "\n".into(),
+        NodeType::HardBreak => "\\\\\n".into(),
+        _ => emit_block(node, state),
+    }
+}
+
+/// Escape the special characters of latex in text
+pub fn escape(text: &str) -> String {
+    let mut ret = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => ret.push_str("\\textbackslash{}"),
+            '{' | '}' | '$' | '&' | '#' | '_' | '%' => {
+                ret.push('\\');
+                ret.push(c);
+            }
+            '^' => ret.push_str("\\textasciicircum{}"),
+            '~' => ret.push_str("\\textasciitilde{}"),
+            '<' => ret.push_str("\\textless{}"),
+            '>' => ret.push_str("\\textgreater{}"),
+            '|' => ret.push_str("\\textbar{}"),
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// `\href` and `\url` take the url verbatim, except for `%` and `#`
+fn escape_url(url: &str) -> String {
+    url.replace('%', "\\%").replace('#', "\\#")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::markdown_interpreter::parser::parse;
+
+    fn md2tex(source: &str) -> String {
+        emit(&parse(source), &EmitOptions::default()).body
+    }
+
+    #[test]
+    fn test_emit() {
+        assert_eq!(
+            md2tex("# Arma\n\nI *sing* of **arms** & 50% $x_1$\n\n- a\n- b"),
+            "\\section{Arma}\n\nI \\emph{sing} of \\textbf{arms} \\& 50\\% $x_1$\n\n\\begin{itemize}\n  \\item a\n  \\item b\n\\end{itemize}\n"
+        );
+    }
+
+    #[test]
+    fn test_emit_code_and_quote() {
+        assert_eq!(
+            md2tex("> `a_b`\n\n```\n$x$ \\foo\n```"),
+            "\\begin{quote}\n\\texttt{a\\_b}\n\\end{quote}\n\n\\begin{verbatim}\n$x$ \\foo\n\\end{verbatim}\n"
+        );
+    }
+
+    #[test]
+    fn test_packages() {
+        let res = emit(
+            &parse("![Plot](plot.png)\n\n[site](https://a.b/#c) ~~x~~"),
+            &EmitOptions::default(),
+        );
+        assert_eq!(
+            res.body,
+            "\\begin{figure}[htbp]\n  \\centering\n  \\includegraphics[width=0.8\\linewidth]{plot.png}\n  \\caption{Plot}\n\\end{figure}\n\n\\href{https://a.b/\\#c}{site} \\sout{x}\n"
+        );
+        assert_eq!(res.packages, vec![GRAPHICX, HYPERREF, ULEM]);
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(
+            md2tex("a | b\n--|--\nc | d"),
+            "\\begin{tabular}{ll}\n  \\hline\n  a & b \\\\\n  \\hline\n  c & d \\\\\n  \\hline\n\\end{tabular}\n"
+        );
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let options = EmitOptions { booktabs: true };
+        let res = emit(
+            &parse("| a | b | c |\n|:--|:-:|--:|\n| 1 | 2 | 3 |"),
+            &options,
+        );
+        assert_eq!(
+            res.body,
+            "\\begin{tabular}{lcr}\n  \\toprule\n  a & b & c \\\\\n  \\midrule\n  1 & 2 & 3 \\\\\n  \\bottomrule\n\\end{tabular}\n"
+        );
+        assert_eq!(res.packages, vec![BOOKTABS]);
+    }
+
+    #[test]
+    fn test_nested_list() {
+        assert_eq!(
+            md2tex("- a\n  1. b\n  2. c\n- [x] d\n- [ ] e\n\n  f"),
+            "\\begin{itemize}\n  \\item a\n  \\begin{enumerate}\n    \\item b\n    \\item c\n  \\end{enumerate}\n  \\item[$\\boxtimes$] d\n  \\item[$\\square$] e\n\n  f\n\\end{itemize}\n"
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! The front matter of markdown
+//!
+//! A markdown file may start with a block of metadata, either YAML between two `---` lines (the
+//! closing one may also be `...`), or TOML between two `+++` lines:
+//!
+//! ```text
+//! ---
+//! title: Ars Amatoria
+//! author: [Publius Ovidius Naso, Anonymous]
+//! date: \today
+//! documentclass: article
+//! packages: [amsmath, "geometry[margin=2cm]"]
+//! bibliography: references.bib
+//! ---
+//! ```
+//!
+//! author, packages and bibliography may be a single string or a list. Other keys are ignored.
+//! A package may carry its options in square brackets after the name.
+
+use std::error::Error;
+
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub author: Vec<String>,
+    pub date: Option<String>,
+    pub documentclass: Option<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub packages: Vec<String>,
+    #[serde(deserialize_with = "one_or_many")]
+    pub bibliography: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(s) => vec![s],
+        OneOrMany::Many(v) => v,
+    })
+}
+
+/// Split the front matter from the markdown
+/// Return the front matter, if there is one, and the markdown after it
+pub fn split_front_matter(
+    source: &str,
+) -> Result<(Option<FrontMatter>, &str), Box<dyn Error>> {
+    let Some(first_line_end) = source.find('\n') else {
+        return Ok((None, source));
+    };
+    let closing: &[&str] = match source[..first_line_end].trim_end() {
+        "---" => &["---", "..."],
+        "+++" => &["+++"],
+        _ => return Ok((None, source)),
+    };
+
+    let mut offset = first_line_end + 1;
+    for line in source[offset..].split_inclusive('\n') {
+        if closing.contains(&line.trim_end()) {
+            let meta = &source[first_line_end + 1..offset];
+            let rest = &source[offset + line.len()..];
+            let front_matter = if closing[0] == "---" {
+                parse_yaml(meta)?
+            } else {
+                parse_toml(meta)?
+            };
+            return Ok((Some(front_matter), rest));
+        }
+        offset += line.len();
+    }
+    // no closing line: the `---` is a thematic break rather than front matter
+    Ok((None, source))
+}
+
+fn parse_yaml(meta: &str) -> Result<FrontMatter, Box<dyn Error>> {
+    if meta.trim().is_empty() {
+        return Ok(FrontMatter::default());
+    }
+    serde_yaml::from_str(meta)
+        .map_err(|e| format!("Invalid YAML front matter: {}", e).into())
+}
+
+fn parse_toml(meta: &str) -> Result<FrontMatter, Box<dyn Error>> {
+    let mut table: toml::Table = toml::from_str(meta)
+        .map_err(|e| format!("Invalid TOML front matter: {}", e))?;
+    // `date = 2024-03-01` is a datetime in TOML, but only its text is needed
+    if let Some(toml::Value::Datetime(date)) = table.get("date") {
+        let date = date.to_string();
+        table.insert("date".into(), toml::Value::String(date));
+    }
+    table
+        .try_into()
+        .map_err(|e| format!("Invalid TOML front matter: {}", e).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_yaml() {
+        let source = "---\ntitle: Ars Amatoria\nauthor: [Ovid, Anonymous]\ndate: 2024-03-01\npackages: amsmath\n---\n# Liber I\n";
+        let (front_matter, rest) = split_front_matter(source).unwrap();
+        assert_eq!(
+            front_matter,
+            Some(FrontMatter {
+                title: Some("Ars Amatoria".into()),
+                author: vec!["Ovid".into(), "Anonymous".into()],
+                date: Some("2024-03-01".into()),
+                packages: vec!["amsmath".into()],
+                ..Default::default()
+            })
+        );
+        assert_eq!(rest, "# Liber I\n");
+    }
+
+    #[test]
+    fn test_toml() {
+        let source = "+++\ntitle = \"Ars\"\ndate = 2024-03-01\nbibliography = [\"a.bib\", \"b.bib\"]\ndocumentclass = \"report\"\n+++\ntext";
+        let (front_matter, rest) = split_front_matter(source).unwrap();
+        let front_matter = front_matter.unwrap();
+        assert_eq!(front_matter.date, Some("2024-03-01".into()));
+        assert_eq!(front_matter.bibliography, vec!["a.bib", "b.bib"]);
+        assert_eq!(front_matter.documentclass, Some("report".into()));
+        assert_eq!(rest, "text");
+    }
+
+    #[test]
+    fn test_no_front_matter() {
+        for source in ["# Title\n", "---\nno closing line\n", "text ---\n---\n"]
+        {
+            let (front_matter, rest) = split_front_matter(source).unwrap();
+            assert_eq!(front_matter, None);
+            assert_eq!(rest, source);
+        }
+        assert!(split_front_matter("---\ntitle: [\n---\n").is_err());
+    }
+}
@@ -1 +1,5 @@
+pub mod ast;
+pub mod emitter;
+pub mod front_matter;
+pub mod parser;
 pub mod scanner;
@@ -0,0 +1,1392 @@
+//! A parser for github flavored markdown
+//!
+//! Markdown is parsed in two passes, as described in the GFM spec:
+//! 1. The tokens are split into lines, which are grouped into blocks (headings, paragraphs,
+//!    lists, ...). Block quotes and list items contain blocks themselves, so their lines are
+//!    stripped of the `>` or the list marker and parsed recursively.
+//! 1. The tokens of the leaf blocks (paragraphs, headings, table cells) are parsed into inlines
+//!    (emphasis, links, code, ...). The link reference definitions (`[r]: url`) are no blocks,
+//!    they are looked up by the reference links (`[text][r]`).
+//!
+//! Any text is valid markdown, so parsing never fails: what is not recognised is kept as text.
+//! Math (`$...$`, `$$...$$`, `\[...\]`) and latex commands with their arguments are kept exactly
+//! as in the source, for the emitter to pass through.
+
+use super::ast::{Alignment, ListKind, Node, NodeType};
+use super::scanner::{scan, Token, TokenType};
+use crate::latex_interpreter::token::Span;
+
+/// A line of the source, the newline excluded
+#[derive(Debug, Clone, Copy)]
+struct Line<'a> {
+    /// The width of the spaces at the beginning of the line
+    indent: usize,
+    /// The tokens after the indent
+    tokens: &'a [Token],
+    /// The span of the line, indent included
+    span: Span,
+}
+
+impl<'a> Line<'a> {
+    fn raw<'s>(&self, source: &'s str) -> &'s str {
+        &source[self.span.start..self.span.end]
+    }
+
+    fn is_blank(&self) -> bool {
+        self.tokens.iter().all(|t| t.token_type == TokenType::Space)
+    }
+
+    fn first_type(&self) -> Option<&TokenType> {
+        self.tokens.first().map(|t| &t.token_type)
+    }
+
+    /// Remove up to n columns of indent
+    fn dedent(&self, n: usize, source: &str) -> Line<'a> {
+        let skipped = skip_columns(self.raw(source), n);
+        Line {
+            indent: self.indent.saturating_sub(n),
+            tokens: self.tokens,
+            span: Span::new(self.span.start + skipped, self.span.end),
+        }
+    }
+
+    /// Remove the first n tokens, and the space after them
+    fn skip_tokens(&self, n: usize) -> Line<'a> {
+        let mut n = n.min(self.tokens.len());
+        if self.tokens.get(n).map(|t| &t.token_type) == Some(&TokenType::Space)
+        {
+            n += 1;
+        }
+        let tokens = &self.tokens[n..];
+        let start = match tokens.first() {
+            Some(t) => t.span.start,
+            None => self.span.end,
+        };
+        Line {
+            indent: 0,
+            tokens,
+            span: Span::new(start, self.span.end),
+        }
+    }
+}
+
+/// Return the number of bytes of the first n columns of spaces in text, a tab counting as 4
+fn skip_columns(text: &str, n: usize) -> usize {
+    let mut width = 0;
+    let mut bytes = 0;
+    for c in text.chars() {
+        if width >= n {
+            break;
+        }
+        match c {
+            ' ' => width += 1,
+            '\t' => width += 4,
+            _ => break,
+        }
+        bytes += 1;
+    }
+    bytes
+}
+
+fn split_lines<'a>(tokens: &'a [Token], source: &str) -> Vec<Line<'a>> {
+    let mut ret: Vec<Line> = vec![];
+    let mut first = 0;
+    let mut start = 0;
+    for i in 0..=tokens.len() {
+        let end = match tokens.get(i) {
+            Some(t) if t.token_type == TokenType::Newline => t.span.start,
+            Some(_) => continue,
+            None => source.len(),
+        };
+        let mut line_tokens = &tokens[first..i];
+        let mut indent = 0;
+        if let Some(Token {
+            token_type: TokenType::Indent(n),
+            ..
+        }) = line_tokens.first()
+        {
+            indent = *n as usize;
+            line_tokens = &line_tokens[1..];
+        }
+        ret.push(Line {
+            indent,
+            tokens: line_tokens,
+            span: Span::new(start, end),
+        });
+        first = i + 1;
+        start = end + 1;
+    }
+    ret
+}
+
+/// Parse source into a Document node
+pub fn parse(source: &str) -> Node {
+    let tokens = scan(source);
+    let lines = split_lines(&tokens, source);
+    // the link reference definitions are no blocks, they are looked up by parse_link
+    let definitions = definition_lines(&lines, source);
+    let lines: Vec<Line> = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| match definitions.contains(&i) {
+            true => line.skip_tokens(line.tokens.len()),
+            false => *line,
+        })
+        .collect();
+    Node::with_children(NodeType::Document, parse_blocks(&lines, source))
+}
+
+/// A link reference definition, `[label]: url "title"`
+/// Return the normalized label and the url, the title is dropped
+fn link_definition(line: &Line, source: &str) -> Option<(String, String)> {
+    if line.indent >= 4 {
+        return None;
+    }
+    let (label, rest) = line
+        .raw(source)
+        .trim()
+        .strip_prefix('[')?
+        .split_once("]:")?;
+    if label.trim().is_empty() || label.contains(['[', ']']) {
+        return None;
+    }
+    let url = rest.split_whitespace().next()?;
+    let title = rest.trim()[url.len()..].trim();
+    let quoted = [('"', '"'), ('\'', '\''), ('(', ')')].iter().any(|(o, c)| {
+        title.len() >= 2 && title.starts_with(*o) && title.ends_with(*c)
+    });
+    if !title.is_empty() && !quoted {
+        return None;
+    }
+    let url = url.trim_start_matches('<').trim_end_matches('>');
+    Some((normalize_label(label), url.to_string()))
+}
+
+/// Labels match case-insensitively, with consecutive spaces collapsed
+fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// The indices of the lines which are link reference definitions
+/// A definition can not interrupt a paragraph, so it follows a blank line or another definition,
+/// and it is not in a fenced code block
+fn definition_lines(lines: &[Line], source: &str) -> Vec<usize> {
+    let mut ret: Vec<usize> = vec![];
+    // the length of the fence of the code block the line is in
+    let mut in_fence: Option<usize> = None;
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(open) = in_fence {
+            let closing = fence(line).is_some_and(|t| t.lexeme.len() >= open);
+            if closing && line.tokens.len() <= 2 {
+                in_fence = None;
+            }
+            continue;
+        }
+        if let Some(t) = fence(line) {
+            in_fence = Some(t.lexeme.len());
+            continue;
+        }
+        let block_start =
+            i == 0 || lines[i - 1].is_blank() || ret.last() == Some(&(i - 1));
+        if block_start && link_definition(line, source).is_some() {
+            ret.push(i);
+        }
+    }
+    ret
+}
+
+/// The url of the first definition of label in source
+fn find_definition(source: &str, label: &str) -> Option<String> {
+    if !source.contains("]:") {
+        return None;
+    }
+    let label = normalize_label(label);
+    let tokens = scan(source);
+    let lines = split_lines(&tokens, source);
+    definition_lines(&lines, source)
+        .into_iter()
+        .filter_map(|i| link_definition(&lines[i], source))
+        .find(|(l, _)| *l == label)
+        .map(|(_, url)| url)
+}
+
+fn parse_blocks(lines: &[Line], source: &str) -> Vec<Node> {
+    let mut ret: Vec<Node> = vec![];
+    let mut pos = 0;
+    while pos < lines.len() {
+        if lines[pos].is_blank() {
+            pos += 1;
+            continue;
+        }
+        ret.push(parse_block(lines, &mut pos, source));
+    }
+    ret
+}
+
+/// Parse the block starting at lines[*pos], which is not blank
+fn parse_block(lines: &[Line], pos: &mut usize, source: &str) -> Node {
+    if lines[*pos].indent >= 4 {
+        return parse_indented_code(lines, pos, source);
+    }
+    if let Some(node) = parse_fenced_code(lines, pos, source) {
+        return node;
+    }
+    if let Some(node) = parse_heading(lines, pos, source) {
+        return node;
+    }
+    if let Some(node) = parse_display_math(lines, pos, source) {
+        return node;
+    }
+    if is_thematic_break(&lines[*pos]) {
+        *pos += 1;
+        return Node::new(NodeType::ThematicBreak, "");
+    }
+    if let Some(node) = parse_block_quote(lines, pos, source) {
+        return node;
+    }
+    if let Some(node) = parse_list(lines, pos, source) {
+        return node;
+    }
+    if let Some(node) = parse_table(lines, pos, source) {
+        return node;
+    }
+    parse_paragraph(lines, pos, source)
+}
+
+/// Return true if line starts a block which interrupts a paragraph
+fn interrupts_paragraph(lines: &[Line], pos: usize) -> bool {
+    let line = &lines[pos];
+    if line.indent >= 4 || line.is_blank() {
+        return false;
+    }
+    let starts_list = match list_marker(line) {
+        // an empty item or a numbered list not starting from 1 does not interrupt
+        Some((ListKind::Ordered, _)) => {
+            line.tokens[0].lexeme.starts_with("1") && line.tokens.len() > 2
+        }
+        Some(_) => line.tokens.len() > 2,
+        None => false,
+    };
+    fence(line).is_some()
+        || heading_level(line).is_some()
+        || is_thematic_break(line)
+        || line.first_type() == Some(&TokenType::RightAngleBracket)
+        || starts_display_math(line)
+        || starts_list
+        || is_table_start(lines, pos)
+}
+
+/// The opening fence of a code block: a run of at least three backticks
+fn fence<'a>(line: &Line<'a>) -> Option<&'a Token> {
+    match line.tokens.first() {
+        Some(t)
+            if t.token_type == TokenType::Backtick && t.lexeme.len() >= 3 =>
+        {
+            Some(t)
+        }
+        _ => None,
+    }
+}
+
+fn parse_fenced_code(
+    lines: &[Line],
+    pos: &mut usize,
+    source: &str,
+) -> Option<Node> {
+    let open_line = lines[*pos];
+    let open = fence(&open_line)?;
+    let info = source[open.span.end..open_line.span.end].trim();
+    let language = info.split_whitespace().next().unwrap_or("").to_string();
+
+    let mut code = String::new();
+    let mut i = *pos + 1;
+    while i < lines.len() {
+        let line = lines[i];
+        let closing = match fence(&line) {
+            Some(t) => {
+                t.lexeme.len() >= open.lexeme.len() && line.tokens.len() <= 2
+            }
+            None => false,
+        };
+        i += 1;
+        if closing {
+            break;
+        }
+        code.push_str(line.dedent(open_line.indent, source).raw(source));
+        code.push('\n');
+    }
+    *pos = i;
+    Some(Node::new(NodeType::CodeBlock(language), &code))
+}
+
+fn parse_indented_code(lines: &[Line], pos: &mut usize, source: &str) -> Node {
+    let mut end = *pos;
+    let mut i = *pos;
+    while i < lines.len() && (lines[i].indent >= 4 || lines[i].is_blank()) {
+        i += 1;
+        if !lines[i - 1].is_blank() {
+            end = i;
+        }
+    }
+    let mut code = String::new();
+    for line in &lines[*pos..end] {
+        code.push_str(line.dedent(4, source).raw(source));
+        code.push('\n');
+    }
+    *pos = end;
+    Node::new(NodeType::CodeBlock(String::new()), &code)
+}
+
+fn heading_level(line: &Line) -> Option<u8> {
+    match line.first_type() {
+        Some(TokenType::Section(n)) => Some(*n),
+        _ => None,
+    }
+}
+
+fn parse_heading(
+    lines: &[Line],
+    pos: &mut usize,
+    source: &str,
+) -> Option<Node> {
+    let line = lines[*pos];
+    let level = heading_level(&line)?;
+    let mut tokens = line.skip_tokens(1).tokens;
+    // the optional closing sequence of #
+    if let Some(last) = tokens.last() {
+        if last.token_type == TokenType::Word
+            && last.lexeme.chars().all(|c| c == '#')
+            && (tokens.len() == 1
+                || tokens[tokens.len() - 2].token_type == TokenType::Space)
+        {
+            tokens = &tokens[..tokens.len() - 1];
+        }
+    }
+    *pos += 1;
+    Some(Node::with_children(
+        NodeType::Heading(level),
+        parse_inlines(tokens, source),
+    ))
+}
+
+fn starts_display_math(line: &Line) -> bool {
+    matches!(
+        line.first_type(),
+        Some(TokenType::DoubleDollar) | Some(TokenType::SlashOpenBracket)
+    )
+}
+
+/// Display math occupying whole lines, which may not contain blank lines just as in latex
+fn parse_display_math(
+    lines: &[Line],
+    pos: &mut usize,
+    source: &str,
+) -> Option<Node> {
+    if !starts_display_math(&lines[*pos]) {
+        return None;
+    }
+    let open = &lines[*pos].tokens[0];
+    let close_type = match open.token_type {
+        TokenType::DoubleDollar => TokenType::DoubleDollar,
+        _ => TokenType::SlashCloseBracket,
+    };
+
+    for (i, line) in lines.iter().enumerate().skip(*pos) {
+        if line.is_blank() {
+            return None;
+        }
+        let skip = if i == *pos { 1 } else { 0 };
+        let close = line
+            .tokens
+            .iter()
+            .enumerate()
+            .skip(skip)
+            .find(|(_, t)| t.token_type == close_type);
+        if let Some((j, close)) = close {
+            // text after the math on the same line, leave it to the paragraph
+            if !line.tokens[j + 1..]
+                .iter()
+                .all(|t| t.token_type == TokenType::Space)
+            {
+                return None;
+            }
+            *pos = i + 1;
+            let math = &source[open.span.start..close.span.end];
+            return Some(Node::new(NodeType::DisplayMath, math));
+        }
+    }
+    None
+}
+
+/// `***`, `---` or `___`, spaces allowed in between
+fn is_thematic_break(line: &Line) -> bool {
+    let marks: Vec<&Token> = line
+        .tokens
+        .iter()
+        .filter(|t| t.token_type != TokenType::Space)
+        .collect();
+    marks.len() >= 3
+        && marks.iter().all(|t| t.token_type == marks[0].token_type)
+        && matches!(
+            marks[0].token_type,
+            TokenType::Star | TokenType::Slash | TokenType::Underline
+        )
+}
+
+fn parse_block_quote(
+    lines: &[Line],
+    pos: &mut usize,
+    source: &str,
+) -> Option<Node> {
+    if lines[*pos].first_type() != Some(&TokenType::RightAngleBracket) {
+        return None;
+    }
+    let mut inner: Vec<Line> = vec![];
+    while *pos < lines.len() {
+        let line = lines[*pos];
+        if line.first_type() == Some(&TokenType::RightAngleBracket) {
+            inner.push(strip_quote_marker(&line));
+        } else if !line.is_blank()
+            && inner.last().is_some_and(|l| !l.is_blank())
+            && !interrupts_paragraph(lines, *pos)
+        {
+            // lazy continuation of a paragraph in the quote
+            inner.push(line);
+        } else {
+            break;
+        }
+        *pos += 1;
+    }
+    Some(Node::with_children(
+        NodeType::BlockQuote,
+        parse_blocks(&inner, source),
+    ))
+}
+
+/// Remove `>` and the space after it; the rest of the spaces are the indent of the new line
+fn strip_quote_marker<'a>(line: &Line<'a>) -> Line<'a> {
+    let marker = &line.tokens[0];
+    let mut tokens = &line.tokens[1..];
+    let mut indent = 0;
+    let mut start = marker.span.end;
+    if let Some(space) = tokens.first() {
+        if space.token_type == TokenType::Space {
+            indent = space.span.end - space.span.start - 1;
+            start += 1;
+            tokens = &tokens[1..];
+        }
+    }
+    Line {
+        indent,
+        tokens,
+        span: Span::new(start, line.span.end),
+    }
+}
+
+/// Return the kind of the list and the number of tokens of the marker, if line starts with a
+/// list marker: `-`, `*` or `+` for bullet lists, `1.` or `1)` for ordered lists, followed by a
+/// space or the end of the line
+fn list_marker(line: &Line) -> Option<(ListKind, usize)> {
+    let tokens = line.tokens;
+    let (kind, len) = match tokens.first() {
+        Some(t)
+            if matches!(t.token_type, TokenType::Slash | TokenType::Star) =>
+        {
+            (ListKind::Bullet, 1)
+        }
+        Some(t) if t.token_type == TokenType::Word && t.lexeme == "+" => {
+            (ListKind::Bullet, 1)
+        }
+        Some(t)
+            if t.token_type == TokenType::Word
+                && is_ordered_marker(&t.lexeme) =>
+        {
+            (ListKind::Ordered, 1)
+        }
+        Some(t)
+            if t.token_type == TokenType::Word
+                && is_number(&t.lexeme)
+                && tokens.get(1).map(|t| &t.token_type)
+                    == Some(&TokenType::RightParenthesis) =>
+        {
+            (ListKind::Ordered, 2)
+        }
+        _ => return None,
+    };
+    match tokens.get(len) {
+        None => Some((kind, len)),
+        Some(t) if t.token_type == TokenType::Space => Some((kind, len)),
+        _ => None,
+    }
+}
+
+fn is_number(s: &str) -> bool {
+    !s.is_empty() && s.len() <= 9 && s.chars().all(|c| c.is_ascii_digit())
+}
+
+/// EG: `1.`
+fn is_ordered_marker(s: &str) -> bool {
+    match s.strip_suffix('.') {
+        Some(n) => is_number(n),
+        None => false,
+    }
+}
+
+/// Items are nested by their indent: a line indented more than the marker of the current item
+/// belongs to the item, nested lists included
+fn parse_list(lines: &[Line], pos: &mut usize, source: &str) -> Option<Node> {
+    let (kind, _) = list_marker(&lines[*pos])?;
+    let mut items: Vec<Node> = vec![];
+
+    while *pos < lines.len() {
+        let line = lines[*pos];
+        let marker_len = match list_marker(&line) {
+            Some((k, len)) if k == kind => len,
+            _ => break,
+        };
+        let first = line.skip_tokens(marker_len);
+        // the columns of the content, to which the continuation lines are dedented
+        // The box of a task is part of the content
+        let content_indent = first.span.start - line.span.start;
+        let (task, first) = task_marker(first);
+        let mut item_lines = vec![first];
+        *pos += 1;
+
+        while *pos < lines.len() {
+            let cur = lines[*pos];
+            if cur.is_blank() {
+                // the item continues if the next non blank line is indented
+                let next = (*pos..lines.len()).find(|&i| !lines[i].is_blank());
+                match next {
+                    Some(i) if lines[i].indent > line.indent => {
+                        item_lines.extend_from_slice(&lines[*pos..i]);
+                        *pos = i;
+                        continue;
+                    }
+                    Some(i)
+                        if list_marker(&lines[i])
+                            .is_some_and(|(k, _)| k == kind) =>
+                    {
+                        *pos = i;
+                    }
+                    _ => {}
+                }
+                break;
+            }
+            let nested = cur.indent > line.indent;
+            if (list_marker(&cur).is_some() && !nested)
+                || (cur.indent == 0 && interrupts_paragraph(lines, *pos))
+            {
+                break;
+            }
+            item_lines.push(cur.dedent(content_indent, source));
+            *pos += 1;
+        }
+
+        items.push(Node::with_children(
+            NodeType::ListItem(task),
+            parse_blocks(&item_lines, source),
+        ));
+    }
+
+    Some(Node::with_children(NodeType::List(kind), items))
+}
+
+/// `[ ]` or `[x]` at the beginning of an item of a task list
+/// Return whether the task is checked, and the line with the marker removed
+fn task_marker(line: Line) -> (Option<bool>, Line) {
+    let types: Vec<&TokenType> =
+        line.tokens.iter().take(4).map(|t| &t.token_type).collect();
+    let checked = match types.as_slice() {
+        [TokenType::LeftSquareBracket, TokenType::Space, TokenType::RightSquareBracket, TokenType::Space] => {
+            false
+        }
+        [TokenType::LeftSquareBracket, TokenType::Word, TokenType::RightSquareBracket, TokenType::Space]
+            if line.tokens[1].lexeme.eq_ignore_ascii_case("x") =>
+        {
+            true
+        }
+        _ => return (None, line),
+    };
+    (Some(checked), line.skip_tokens(3))
+}
+
+/// A table starts with a header row containing `|`, followed by a delimiter row like
+/// `| --- | :---: |`
+fn is_table_start(lines: &[Line], pos: usize) -> bool {
+    let header = &lines[pos];
+    let delimiter = match lines.get(pos + 1) {
+        Some(l) => l,
+        None => return false,
+    };
+    header
+        .tokens
+        .iter()
+        .any(|t| t.token_type == TokenType::Pipe)
+        && is_delimiter_row(delimiter)
+}
+
+fn is_delimiter_row(line: &Line) -> bool {
+    let mut has_dash = false;
+    for t in line.tokens {
+        match t.token_type {
+            TokenType::Slash => has_dash = true,
+            TokenType::Pipe | TokenType::Space => {}
+            TokenType::Word if t.lexeme.chars().all(|c| c == ':') => {}
+            _ => return false,
+        }
+    }
+    has_dash
+        && line.tokens.iter().any(|t| {
+            t.token_type == TokenType::Pipe
+                || (t.token_type == TokenType::Word && t.lexeme == ":")
+        })
+}
+
+/// Split the tokens of a table row into the tokens of each cell
+fn split_cells<'a>(line: &Line<'a>) -> Vec<&'a [Token]> {
+    let mut tokens = trim_spaces(line.tokens);
+    if tokens.first().map(|t| &t.token_type) == Some(&TokenType::Pipe) {
+        tokens = &tokens[1..];
+    }
+    if tokens.last().map(|t| &t.token_type) == Some(&TokenType::Pipe) {
+        tokens = &tokens[..tokens.len() - 1];
+    }
+    tokens
+        .split(|t| t.token_type == TokenType::Pipe)
+        .map(trim_spaces)
+        .collect()
+}
+
+fn trim_spaces(tokens: &[Token]) -> &[Token] {
+    let is_space = |t: &Token| t.token_type == TokenType::Space;
+    let start = tokens
+        .iter()
+        .position(|t| !is_space(t))
+        .unwrap_or(tokens.len());
+    let end = tokens
+        .iter()
+        .rposition(|t| !is_space(t))
+        .map_or(start, |i| i + 1);
+    &tokens[start..end]
+}
+
+fn parse_table(lines: &[Line], pos: &mut usize, source: &str) -> Option<Node> {
+    if !is_table_start(lines, *pos) {
+        return None;
+    }
+    let alignments: Vec<Alignment> = split_cells(&lines[*pos + 1])
+        .into_iter()
+        .map(|cell| alignment(cell, source))
+        .collect();
+    let columns = alignments.len();
+
+    let mut rows: Vec<Node> =
+        vec![parse_table_row(&lines[*pos], columns, source)];
+    *pos += 2;
+    while *pos < lines.len()
+        && !lines[*pos].is_blank()
+        && lines[*pos]
+            .tokens
+            .iter()
+            .any(|t| t.token_type == TokenType::Pipe)
+    {
+        rows.push(parse_table_row(&lines[*pos], columns, source));
+        *pos += 1;
+    }
+    Some(Node::with_children(NodeType::Table(alignments), rows))
+}
+
+/// The alignment of a cell of the delimiter row: `:---` (or `---`), `:---:` or `---:`
+fn alignment(cell: &[Token], source: &str) -> Alignment {
+    let text = match (cell.first(), cell.last()) {
+        (Some(first), Some(last)) => &source[first.span.start..last.span.end],
+        _ => "",
+    };
+    match (text.starts_with(':'), text.ends_with(':')) {
+        (true, true) => Alignment::Center,
+        (false, true) => Alignment::Right,
+        _ => Alignment::Left,
+    }
+}
+
+/// The row is padded with empty cells or truncated to columns cells
+fn parse_table_row(line: &Line, columns: usize, source: &str) -> Node {
+    let mut cells: Vec<Node> = split_cells(line)
+        .into_iter()
+        .take(columns)
+        .map(|c| {
+            Node::with_children(NodeType::TableCell, parse_inlines(c, source))
+        })
+        .collect();
+    while cells.len() < columns {
+        cells.push(Node::with_children(NodeType::TableCell, vec![]));
+    }
+    Node::with_children(NodeType::TableRow, cells)
+}
+
+fn parse_paragraph(lines: &[Line], pos: &mut usize, source: &str) -> Node {
+    let mut tokens: Vec<Token> = vec![];
+    let mut i = *pos;
+    while i < lines.len() && !lines[i].is_blank() {
+        if i > *pos {
+            if let Some(level) = setext_level(&lines[i], source) {
+                *pos = i + 1;
+                return Node::with_children(
+                    NodeType::Heading(level),
+                    parse_inlines(&tokens, source),
+                );
+            }
+            if interrupts_paragraph(lines, i) {
+                break;
+            }
+            tokens.push(Token::new(TokenType::Newline, "\n".into()));
+        }
+        tokens.extend_from_slice(lines[i].tokens);
+        i += 1;
+    }
+    *pos = i;
+    Node::with_children(NodeType::Paragraph, parse_inlines(&tokens, source))
+}
+
+/// The underline of a setext heading: `===` for level 1, `---` for level 2
+fn setext_level(line: &Line, source: &str) -> Option<u8> {
+    if line.indent >= 4 {
+        return None;
+    }
+    let text = line.raw(source).trim();
+    if text.is_empty() {
+        None
+    } else if text.chars().all(|c| c == '=') {
+        Some(1)
+    } else if text.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+fn is_space_or_newline(t: Option<&Token>) -> bool {
+    match t {
+        None => true,
+        Some(t) => {
+            matches!(t.token_type, TokenType::Space | TokenType::Newline)
+        }
+    }
+}
+
+/// Find the first index >= from whose token satisfies pred
+fn find_token(
+    tokens: &[Token],
+    from: usize,
+    pred: impl Fn(usize, &Token) -> bool,
+) -> Option<usize> {
+    (from..tokens.len()).find(|&i| pred(i, &tokens[i]))
+}
+
+/// Find the index of the bracket closing the one at tokens[open]
+fn find_closing(
+    tokens: &[Token],
+    open: usize,
+    close_type: &TokenType,
+) -> Option<usize> {
+    let open_type = &tokens[open].token_type;
+    let mut depth = 0;
+    for (i, t) in tokens.iter().enumerate().skip(open) {
+        if &t.token_type == open_type {
+            depth += 1;
+        } else if &t.token_type == close_type {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+fn parse_inlines(tokens: &[Token], source: &str) -> Vec<Node> {
+    let mut ret: Vec<Node> = vec![];
+    let mut i = 0;
+    while i < tokens.len() {
+        let t = &tokens[i];
+        let (node, next) = match t.token_type {
+            TokenType::Backtick => parse_code_span(tokens, i, source),
+            TokenType::Dollar
+            | TokenType::DoubleDollar
+            | TokenType::SlashOpenBracket => {
+                parse_inline_math(tokens, i, source)
+            }
+            TokenType::Command => parse_raw_latex(tokens, i, source),
+            TokenType::Bang => match parse_link(tokens, i + 1, source) {
+                Some((url, inner, next)) => (
+                    Some(Node {
+                        node_type: NodeType::Image,
+                        lexeme: url,
+                        children: parse_inlines(
+                            &tokens[inner.0..inner.1],
+                            source,
+                        ),
+                    }),
+                    next,
+                ),
+                None => (None, i + 1),
+            },
+            TokenType::LeftSquareBracket => match parse_link(tokens, i, source)
+            {
+                Some((url, inner, next)) => (
+                    Some(Node {
+                        node_type: NodeType::Link,
+                        lexeme: url,
+                        children: parse_inlines(
+                            &tokens[inner.0..inner.1],
+                            source,
+                        ),
+                    }),
+                    next,
+                ),
+                None => (None, i + 1),
+            },
+            TokenType::LeftAngleBracket => parse_autolink(tokens, i, source),
+            TokenType::Star | TokenType::Underline | TokenType::Tilde => {
+                parse_delimited(tokens, i, source)
+            }
+            TokenType::Space => {
+                let next = tokens.get(i + 1).map(|t| &t.token_type);
+                let width = t.span.end - t.span.start;
+                if next == Some(&TokenType::Newline) && width >= 2 {
+                    (Some(Node::new(NodeType::HardBreak, "")), i + 2)
+                } else if i == 0
+                    || next.is_none()
+                    || next == Some(&TokenType::Newline)
+                    || tokens[i - 1].token_type == TokenType::Newline
+                {
+                    // spaces around line breaks are dropped
+                    (Some(Node::new(NodeType::Text, "")), i + 1)
+                } else {
+                    (Some(Node::new(NodeType::Text, " ")), i + 1)
+                }
+            }
+            TokenType::Backslash
+                if tokens.get(i + 1).map(|t| &t.token_type)
+                    == Some(&TokenType::Newline) =>
+            {
+                (Some(Node::new(NodeType::HardBreak, "")), i + 2)
+            }
+            TokenType::Newline => {
+                (Some(Node::new(NodeType::SoftBreak, "")), i + 1)
+            }
+            _ => (None, i + 1),
+        };
+
+        // anything not recognised is text
+        let node = node.unwrap_or_else(|| Node::new(NodeType::Text, &t.lexeme));
+        push_inline(&mut ret, node);
+        i = next;
+    }
+    ret
+}
+
+/// Push node to inlines, merging consecutive texts
+fn push_inline(inlines: &mut Vec<Node>, node: Node) {
+    if node.node_type == NodeType::Text {
+        if node.lexeme.is_empty() {
+            return;
+        }
+        if let Some(last) = inlines.last_mut() {
+            if last.node_type == NodeType::Text {
+                last.lexeme.push_str(&node.lexeme);
+                return;
+            }
+        }
+    }
+    inlines.push(node);
+}
+
+/// `code`, closed by a backtick run of the same length
+fn parse_code_span(
+    tokens: &[Token],
+    i: usize,
+    source: &str,
+) -> (Option<Node>, usize) {
+    let open = &tokens[i];
+    let close = find_token(tokens, i + 1, |_, t| {
+        t.token_type == TokenType::Backtick && t.lexeme == open.lexeme
+    });
+    let close = match close {
+        Some(c) => c,
+        None => return (None, i + 1),
+    };
+    let mut code =
+        source[open.span.end..tokens[close].span.start].replace('\n', " ");
+    // one space on each side is stripped, so that code starting with ` can be written
+    if code.len() >= 2
+        && code.starts_with(' ')
+        && code.ends_with(' ')
+        && !code.trim().is_empty()
+    {
+        code = code[1..code.len() - 1].to_string();
+    }
+    (Some(Node::new(NodeType::Code, &code)), close + 1)
+}
+
+/// `$...$`, `$$...$$` or `\[...\]` in a paragraph, kept as in the source
+/// Just as github, `$` shall not be followed by a space when opening, or preceded by a space when
+/// closing, so that prices like $5 and $6 are text
+fn parse_inline_math(
+    tokens: &[Token],
+    i: usize,
+    source: &str,
+) -> (Option<Node>, usize) {
+    let open = &tokens[i];
+    let close_type = match open.token_type {
+        TokenType::SlashOpenBracket => TokenType::SlashCloseBracket,
+        _ => open.token_type.clone(),
+    };
+    if open.token_type == TokenType::Dollar
+        && is_space_or_newline(tokens.get(i + 1))
+    {
+        return (None, i + 1);
+    }
+    let close = find_token(tokens, i + 1, |j, t| {
+        t.token_type == close_type
+            && (close_type != TokenType::Dollar
+                || (j > i + 1 && !is_space_or_newline(tokens.get(j - 1))))
+    });
+    match close {
+        Some(c) => (
+            Some(Node::new(
+                NodeType::InlineMath,
+                &source[open.span.start..tokens[c].span.end],
+            )),
+            c + 1,
+        ),
+        None => (None, i + 1),
+    }
+}
+
+/// A latex command with the curly and square bracket arguments directly following it
+fn parse_raw_latex(
+    tokens: &[Token],
+    i: usize,
+    source: &str,
+) -> (Option<Node>, usize) {
+    let mut end = i + 1;
+    while let Some(t) = tokens.get(end) {
+        let close_type = match t.token_type {
+            TokenType::LeftCurlyBracket => TokenType::RightCurlyBracket,
+            TokenType::LeftSquareBracket => TokenType::RightSquareBracket,
+            _ => break,
+        };
+        match find_closing(tokens, end, &close_type) {
+            Some(c) => end = c + 1,
+            None => break,
+        }
+    }
+    let raw = &source[tokens[i].span.start..tokens[end - 1].span.end];
+    (Some(Node::new(NodeType::RawLatex, raw)), end)
+}
+
+/// `[text](url)`, or a reference to a link reference definition (`[text][label]`, `[label][]`
+/// or `[label]`), starting at tokens[i]
+/// Return the url, the range of the tokens of the text, and the index after the link
+fn parse_link(
+    tokens: &[Token],
+    i: usize,
+    source: &str,
+) -> Option<(String, (usize, usize), usize)> {
+    if tokens.get(i)?.token_type != TokenType::LeftSquareBracket {
+        return None;
+    }
+    let text_end = find_closing(tokens, i, &TokenType::RightSquareBracket)?;
+    let text = &source[tokens[i].span.end..tokens[text_end].span.start];
+    match tokens.get(text_end + 1).map(|t| &t.token_type) {
+        Some(TokenType::LeftParenthesis) => {
+            let url_end = find_closing(
+                tokens,
+                text_end + 1,
+                &TokenType::RightParenthesis,
+            )?;
+            let dest = source
+                [tokens[text_end + 1].span.end..tokens[url_end].span.start]
+                .trim();
+            // drop the optional title: [text](url "title")
+            let url = dest.split_whitespace().next().unwrap_or("");
+            let url = url.trim_start_matches('<').trim_end_matches('>');
+            Some((url.to_string(), (i + 1, text_end), url_end + 1))
+        }
+        Some(TokenType::LeftSquareBracket) => {
+            let label_end = find_closing(
+                tokens,
+                text_end + 1,
+                &TokenType::RightSquareBracket,
+            )?;
+            let label = &source
+                [tokens[text_end + 1].span.end..tokens[label_end].span.start];
+            // the collapsed reference `[label][]`
+            let label = if label.trim().is_empty() { text } else { label };
+            let url = find_definition(source, label)?;
+            Some((url, (i + 1, text_end), label_end + 1))
+        }
+        _ => {
+            let url = find_definition(source, text)?;
+            Some((url, (i + 1, text_end), text_end + 1))
+        }
+    }
+}
+
+/// `<https://example.com>`
+fn parse_autolink(
+    tokens: &[Token],
+    i: usize,
+    source: &str,
+) -> (Option<Node>, usize) {
+    let close = find_token(tokens, i + 1, |_, t| {
+        matches!(
+            t.token_type,
+            TokenType::RightAngleBracket
+                | TokenType::Space
+                | TokenType::Newline
+        )
+    });
+    let close = match close {
+        Some(c) if tokens[c].token_type == TokenType::RightAngleBracket => c,
+        _ => return (None, i + 1),
+    };
+    let url = &source[tokens[i].span.end..tokens[close].span.start];
+    if !["http://", "https://", "mailto:"]
+        .iter()
+        .any(|p| url.starts_with(p))
+    {
+        return (None, i + 1);
+    }
+    let mut node = Node::new(NodeType::Link, url);
+    node.children.push(Node::new(NodeType::Text, url));
+    (Some(node), close + 1)
+}
+
+/// The number of consecutive tokens of the same type as tokens[i]
+fn run_length(tokens: &[Token], i: usize) -> usize {
+    tokens[i..]
+        .iter()
+        .take_while(|t| t.token_type == tokens[i].token_type)
+        .count()
+}
+
+/// Emphasis (`*a*`, `_a_`), strong (`**a**`, `__a__`), both (`***a***`) and strikethrough
+/// (`~a~`, `~~a~~`)
+/// The delimiter shall not be followed by a space when opening, or preceded by a space when
+/// closing. `_` inside a word, as in snake_case, is not a delimiter.
+fn parse_delimited(
+    tokens: &[Token],
+    i: usize,
+    source: &str,
+) -> (Option<Node>, usize) {
+    let delim = &tokens[i].token_type;
+    let n = run_length(tokens, i);
+    let max = if delim == &TokenType::Tilde { 2 } else { 3 };
+    let is_word =
+        |t: Option<&Token>| t.is_some_and(|t| t.token_type == TokenType::Word);
+
+    let can_open = n <= max
+        && !is_space_or_newline(tokens.get(i + n))
+        && !(delim == &TokenType::Underline
+            && i > 0
+            && is_word(tokens.get(i - 1)));
+    if !can_open {
+        let text: String =
+            tokens[i..i + n].iter().map(|t| t.lexeme.as_str()).collect();
+        return (Some(Node::new(NodeType::Text, &text)), i + n);
+    }
+
+    let mut j = i + n;
+    while j < tokens.len() {
+        if &tokens[j].token_type != delim {
+            j += 1;
+            continue;
+        }
+        let m = run_length(tokens, j);
+        if m == n
+            && !is_space_or_newline(tokens.get(j - 1))
+            && !(delim == &TokenType::Underline && is_word(tokens.get(j + m)))
+        {
+            let inner = parse_inlines(&tokens[i + n..j], source);
+            let node = match (delim, n) {
+                (TokenType::Tilde, _) => {
+                    Node::with_children(NodeType::Strikethrough, inner)
+                }
+                (_, 1) => Node::with_children(NodeType::Emphasis, inner),
+                (_, 2) => Node::with_children(NodeType::Strong, inner),
+                _ => Node::with_children(
+                    NodeType::Strong,
+                    vec![Node::with_children(NodeType::Emphasis, inner)],
+                ),
+            };
+            return (Some(node), j + m);
+        }
+        j += m;
+    }
+
+    let text: String =
+        tokens[i..i + n].iter().map(|t| t.lexeme.as_str()).collect();
+    (Some(Node::new(NodeType::Text, &text)), i + n)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(s: &str) -> Node {
+        Node::new(NodeType::Text, s)
+    }
+
+    fn paragraph(children: Vec<Node>) -> Node {
+        Node::with_children(NodeType::Paragraph, children)
+    }
+
+    #[test]
+    fn test_blocks() {
+        let doc = parse(
+            "# Arma *virumque*\n\nI sing\nof arms\n\n> quote\n\n```rust\nfn main() {}\n```\n\n---\n",
+        );
+        assert_eq!(
+            doc.children,
+            vec![
+                Node::with_children(
+                    NodeType::Heading(1),
+                    vec![
+                        text("Arma "),
+                        Node::with_children(
+                            NodeType::Emphasis,
+                            vec![text("virumque")]
+                        )
+                    ]
+                ),
+                paragraph(vec![
+                    text("I sing"),
+                    Node::new(NodeType::SoftBreak, ""),
+                    text("of arms")
+                ]),
+                Node::with_children(
+                    NodeType::BlockQuote,
+                    vec![paragraph(vec![text("quote")])]
+                ),
+                Node::new(NodeType::CodeBlock("rust".into()), "fn main() {}\n"),
+                Node::new(NodeType::ThematicBreak, ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_setext_heading() {
+        let doc = parse("Title\n=====\nSub\n---");
+        assert_eq!(
+            doc.children,
+            vec![
+                Node::with_children(NodeType::Heading(1), vec![text("Title")]),
+                Node::with_children(NodeType::Heading(2), vec![text("Sub")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        let doc = parse("- a\n  b\n- c\n\n1. d\n");
+        let item =
+            |children| Node::with_children(NodeType::ListItem(None), children);
+        assert_eq!(
+            doc.children,
+            vec![
+                Node::with_children(
+                    NodeType::List(ListKind::Bullet),
+                    vec![
+                        item(vec![paragraph(vec![
+                            text("a"),
+                            Node::new(NodeType::SoftBreak, ""),
+                            text("b")
+                        ])]),
+                        item(vec![paragraph(vec![text("c")])]),
+                    ]
+                ),
+                Node::with_children(
+                    NodeType::List(ListKind::Ordered),
+                    vec![item(vec![paragraph(vec![text("d")])])]
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_math() {
+        let doc = parse(
+            "$$\na_1 * b_2\n$$\n\nSo $x_1^*$ and \\[y\\] \\cite[p.~1]{key}.",
+        );
+        assert_eq!(
+            doc.children,
+            vec![
+                Node::new(NodeType::DisplayMath, "$$\na_1 * b_2\n$$"),
+                paragraph(vec![
+                    text("So "),
+                    Node::new(NodeType::InlineMath, "$x_1^*$"),
+                    text(" and "),
+                    Node::new(NodeType::InlineMath, "\\[y\\]"),
+                    text(" "),
+                    Node::new(NodeType::RawLatex, "\\cite[p.~1]{key}"),
+                    text("."),
+                ]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inlines() {
+        let doc = parse(
+            "**a** snake_case ~~b~~ `c` [d](e \"t\") ![f](g.png) <https://h>",
+        );
+        let link = |url: &str, node_type, children| Node {
+            node_type,
+            lexeme: url.into(),
+            children,
+        };
+        assert_eq!(
+            doc.children,
+            vec![paragraph(vec![
+                Node::with_children(NodeType::Strong, vec![text("a")]),
+                text(" snake_case "),
+                Node::with_children(NodeType::Strikethrough, vec![text("b")]),
+                text(" "),
+                Node::new(NodeType::Code, "c"),
+                text(" "),
+                link("e", NodeType::Link, vec![text("d")]),
+                text(" "),
+                link("g.png", NodeType::Image, vec![text("f")]),
+                text(" "),
+                link("https://h", NodeType::Link, vec![text("https://h")]),
+            ])]
+        );
+    }
+
+    #[test]
+    fn test_reference_links() {
+        let doc = parse(
+            "[a][r], [R][] and ![i][Img] [r]\n[b][none]\n\n[r]: https://r \"T\"\n[img]: <i.png>\n\n```\n[c]: x\n```\n",
+        );
+        let link = |url: &str, node_type, children| Node {
+            node_type,
+            lexeme: url.into(),
+            children,
+        };
+        assert_eq!(
+            doc.children,
+            vec![
+                paragraph(vec![
+                    link("https://r", NodeType::Link, vec![text("a")]),
+                    text(", "),
+                    link("https://r", NodeType::Link, vec![text("R")]),
+                    text(" and "),
+                    link("i.png", NodeType::Image, vec![text("i")]),
+                    text(" "),
+                    link("https://r", NodeType::Link, vec![text("r")]),
+                    Node::new(NodeType::SoftBreak, ""),
+                    text("[b][none]"),
+                ]),
+                Node::new(NodeType::CodeBlock("".into()), "[c]: x\n"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let doc = parse("| a | b |\n|---|---|\n| c |\n");
+        let cell =
+            |children| Node::with_children(NodeType::TableCell, children);
+        assert_eq!(
+            doc.children,
+            vec![Node::with_children(
+                NodeType::Table(vec![Alignment::Left, Alignment::Left]),
+                vec![
+                    Node::with_children(
+                        NodeType::TableRow,
+                        vec![cell(vec![text("a")]), cell(vec![text("b")])]
+                    ),
+                    Node::with_children(
+                        NodeType::TableRow,
+                        vec![cell(vec![text("c")]), cell(vec![])]
+                    ),
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_nested_list() {
+        let doc = parse("1. a\n   - b\n     - c\n2. [x] d\n   - e");
+        let item = |task, children| {
+            Node::with_children(NodeType::ListItem(task), children)
+        };
+        let list =
+            |kind, items| Node::with_children(NodeType::List(kind), items);
+        assert_eq!(
+            doc.children,
+            vec![list(
+                ListKind::Ordered,
+                vec![
+                    item(
+                        None,
+                        vec![
+                            paragraph(vec![text("a")]),
+                            list(
+                                ListKind::Bullet,
+                                vec![item(
+                                    None,
+                                    vec![
+                                        paragraph(vec![text("b")]),
+                                        list(
+                                            ListKind::Bullet,
+                                            vec![item(
+                                                None,
+                                                vec![paragraph(vec![text(
+                                                    "c"
+                                                )])]
+                                            )]
+                                        ),
+                                    ]
+                                )]
+                            ),
+                        ]
+                    ),
+                    item(
+                        Some(true),
+                        vec![
+                            paragraph(vec![text("d")]),
+                            list(
+                                ListKind::Bullet,
+                                vec![item(
+                                    None,
+                                    vec![paragraph(vec![text("e")])]
+                                )]
+                            ),
+                        ]
+                    ),
+                ]
+            )]
+        );
+    }
+
+    #[test]
+    fn test_table_alignment() {
+        let doc = parse("a | b | c\n:-- | :-: | --:");
+        assert_eq!(
+            doc.children[0].node_type,
+            NodeType::Table(vec![
+                Alignment::Left,
+                Alignment::Center,
+                Alignment::Right
+            ])
+        );
+    }
+}
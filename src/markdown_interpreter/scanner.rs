@@ -1,39 +1,62 @@
-/// A custom scanner for Markdown
-///
-/// Scan input a &str and output a Vec of Tokens.
-/// Tokens, for the most parts, are scanned in the obvious way: all speical
-/// characters (including newline and space) have their own token types.
-///
+//! A custom scanner for Markdown
+//!
+//! Scan input a &str and output a Vec of Tokens.
+//! Tokens, for the most parts, are scanned in the obvious way: all speical
+//! characters (including newline and space) have their own token types.
+//!
+//! Unlike latex, markdown is line oriented, so the beginning of a line is scanned specially:
+//! 1. Spaces at the beginning of a line are scanned into Indent(n), where n is the width of the
+//!    spaces, a tab counting as 4. They are needed for nested lists and indented code.
+//! 1. `#` to `######` followed by a space at the beginning of a line (after the indent) are
+//!    scanned into Section(n).
+//!
+//! Elsewhere:
+//! 1. Multiple spaces are treated as one space token, the span of which records the width.
+//! 1. The basic token for Text is Word, which is scanned until the next special character or
+//!    whitespace.
+//! 1. `\` followed by letters is a latex command, passed through by the emitter.
+//!    `\` followed by a punctuation is an EscapedChar, whose lexeme is the punctuation, except
+//!    `\[` and `\]` which delimit display math.
+//! 1. Each token records its Span, the byte offsets of the text it is scanned from, so that math
+//!    and code can be passed through untouched.
+
+use crate::latex_interpreter::token::Span;
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct Token {
-    token_type: TokenType,
-    lexeme: String,
+    pub token_type: TokenType,
+    pub lexeme: String,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum TokenType {
     // Reserved Characters
-    Dollar,            // $
-    DoubleDollar,      // double dollar must be consective
-    Uptick,            // ^
-    Ampersand,         // &
-    Underline,         // _
-    LeftCurlyBracket,  // {
-    RightCurlyBracket, // }
+    Dollar,             // $
+    DoubleDollar,       // double dollar must be consective
+    Underline,          // _
+    LeftCurlyBracket,   // {
+    RightCurlyBracket,  // }
+    LeftParenthesis,    // (
+    RightParenthesis,   // )
+    LeftSquareBracket,  // [
+    RightSquareBracket, // ]
+    LeftAngleBracket,   // <
+    RightAngleBracket,  // >
+    Pipe,               // |
+    Bang,               // ! directly followed by [, starting an image
+    Backtick,           // a run of `, the lexeme is the whole run
 
     Star,        // *
     Slash,       // -
     Section(u8), // # or ##
-    Indent(u8),  //
+    Indent(u8),  // spaces at the beginning of a line
 
-    // Backslash is almost never used alone. When appear by itself, it create space in text mode
+    // Backslash is almost never used alone. At the end of a line, it is a hard line break
     Backslash, // \
     Tilde,     // ~
 
-    DoubleBackslash, // \\
-    Command,
-    LeftSquareBracket,  // [
-    RightSquareBracket, // ]
+    Command, // the lexeme does not contain the backslash
 
     SlashOpenBracket,  // \[
     SlashCloseBracket, // \]
@@ -50,13 +73,30 @@ pub enum TokenType {
 
 impl Token {
     pub fn new(token_type: TokenType, lexeme: String) -> Self {
-        Token { token_type, lexeme }
+        Token {
+            token_type,
+            lexeme,
+            span: Span::default(),
+        }
     }
 }
 
+/// Characters ending a Word
+const SPECIAL_CHARS: [char; 20] = [
+    '$', '_', '{', '}', '(', ')', '[', ']', '<', '>', '|', '!', '`', '*', '-',
+    '\\', '~', ' ', '\t', '\n',
+];
+
 pub fn scan(source: &str) -> Vec<Token> {
     let chars: Vec<char> = source.chars().collect();
     let length = chars.len();
+    // byte_offsets[i] is the byte offset of chars[i] in source, used for the span of tokens
+    // The extra last entry is the length of source
+    let byte_offsets: Vec<usize> = source
+        .char_indices()
+        .map(|(b, _)| b)
+        .chain(std::iter::once(source.len()))
+        .collect();
 
     let mut ret: Vec<Token> = Vec::new();
     let mut i = 0;
@@ -64,13 +104,22 @@ pub fn scan(source: &str) -> Vec<Token> {
     // Note we have an i+=1 at the end of the loop
     // so in match, i shall only be incremented with the extra space
     while i < length {
+        if i == 0 || chars[i - 1] == '\n' {
+            scan_beginning_of_line(&chars, &mut i, &byte_offsets, &mut ret);
+            if i >= length {
+                break;
+            }
+        }
+
+        let token_start = i;
+        let token_count = ret.len();
         match chars[i] {
-            // '#' => {
-            //     ret.push(Token::new(TokenType::Hash, "#".into()));
-            // }
             '*' => {
                 ret.push(Token::new(TokenType::Star, "*".into()));
             }
+            '-' => {
+                ret.push(Token::new(TokenType::Slash, "-".into()));
+            }
             '$' => {
                 if i + 1 < length && chars[i + 1] == '$' {
                     ret.push(Token::new(TokenType::DoubleDollar, "$$".into()));
@@ -79,31 +128,6 @@ pub fn scan(source: &str) -> Vec<Token> {
                     ret.push(Token::new(TokenType::Dollar, "$".into()));
                 }
             }
-            '%' => {
-                // check doc/latex_grammar/1_overview.md#Comments
-                // commands are ignored until the end of an line
-                let end_of_line = index_to_end_of_cur_line(&chars, i);
-                ret.push(Token::new(TokenType::Space, String::new()));
-
-                if is_beginning_of_line(&chars, i)
-                    || end_of_line == chars.len() - 1
-                {
-                    // if the comment is at the beginning of a line, ignore the
-                    // last new line character
-                    //
-                    // Note the end_of_line function return the index of the
-                    // last character index is at the last line.
-                    i = end_of_line;
-                } else {
-                    i = end_of_line - 1;
-                }
-            }
-            '^' => {
-                ret.push(Token::new(TokenType::Uptick, "^".into()));
-            }
-            '&' => {
-                ret.push(Token::new(TokenType::Ampersand, "&".into()));
-            }
             '_' => {
                 ret.push(Token::new(TokenType::Underline, "_".into()));
             }
@@ -113,32 +137,40 @@ pub fn scan(source: &str) -> Vec<Token> {
             '}' => {
                 ret.push(Token::new(TokenType::RightCurlyBracket, "}".into()));
             }
-            '\\' => {
-                if i + 1 >= length {
-                    ret.push(Token::new(TokenType::Backslash, "\\".into()));
-                } else if chars[i + 1] == '\\' {
-                    ret.push(Token::new(
-                        TokenType::DoubleBackslash,
-                        String::new(),
-                    ));
-                    i += 1;
-                } else if chars[i + 1] == '#'
-                    || chars[i + 1] == '$'
-                    || chars[i + 1] == '%'
-                    || chars[i + 1] == '^'
-                    || chars[i + 1] == '&'
-                    || chars[i + 1] == '_'
-                    || chars[i + 1] == '{'
-                    || chars[i + 1] == '}'
-                    || chars[i + 1] == '~'
-                    || chars[i + 1] == ' '
-                {
-                    ret.push(Token::new(
-                        TokenType::EscapedChar,
-                        chars[i + 1].into(),
-                    ));
+            '(' => {
+                ret.push(Token::new(TokenType::LeftParenthesis, "(".into()));
+            }
+            ')' => {
+                ret.push(Token::new(TokenType::RightParenthesis, ")".into()));
+            }
+            '<' => {
+                ret.push(Token::new(TokenType::LeftAngleBracket, "<".into()));
+            }
+            '>' => {
+                ret.push(Token::new(TokenType::RightAngleBracket, ">".into()));
+            }
+            '|' => {
+                ret.push(Token::new(TokenType::Pipe, "|".into()));
+            }
+            '!' => {
+                if i + 1 < length && chars[i + 1] == '[' {
+                    ret.push(Token::new(TokenType::Bang, "!".into()));
+                } else {
+                    ret.push(Token::new(TokenType::Word, "!".into()));
+                }
+            }
+            '`' => {
+                let start = i;
+                while i + 1 < length && chars[i + 1] == '`' {
                     i += 1;
-                } else if chars[i + 1] == '\n' {
+                }
+                ret.push(Token::new(
+                    TokenType::Backtick,
+                    chars[start..=i].iter().collect(),
+                ));
+            }
+            '\\' => {
+                if i + 1 >= length || chars[i + 1] == '\n' {
                     ret.push(Token::new(TokenType::Backslash, "\\".into()));
                     // note we do not increase i+1 here.
                 } else if chars[i + 1] == '[' {
@@ -153,6 +185,12 @@ pub fn scan(source: &str) -> Vec<Token> {
                         "\\]".into(),
                     ));
                     i += 1;
+                } else if chars[i + 1].is_ascii_punctuation() {
+                    ret.push(Token::new(
+                        TokenType::EscapedChar,
+                        chars[i + 1].into(),
+                    ));
+                    i += 1;
                 } else if chars[i + 1].is_alphabetic() {
                     let start = i + 1;
                     while i + 1 < length && chars[i + 1].is_alphabetic() {
@@ -162,6 +200,8 @@ pub fn scan(source: &str) -> Vec<Token> {
                         TokenType::Command,
                         chars[start..=i].iter().collect(),
                     ));
+                } else {
+                    ret.push(Token::new(TokenType::Backslash, "\\".into()));
                 }
             }
             '~' => {
@@ -174,16 +214,12 @@ pub fn scan(source: &str) -> Vec<Token> {
                 ret.push(Token::new(TokenType::RightSquareBracket, "]".into()));
             }
             ' ' | '\t' => {
-                if is_beginning_of_line(&chars, i) {
-                    //
-                } else {
-                    while i + 1 < length
-                        && (chars[i + 1] == ' ' || chars[i + 1] == '\t')
-                    {
-                        i += 1;
-                    }
-                    ret.push(Token::new(TokenType::Space, String::new()));
+                while i + 1 < length
+                    && (chars[i + 1] == ' ' || chars[i + 1] == '\t')
+                {
+                    i += 1;
                 }
+                ret.push(Token::new(TokenType::Space, String::new()));
             }
             '\n' => {
                 ret.push(Token::new(TokenType::Newline, "\n".into()));
@@ -192,11 +228,7 @@ pub fn scan(source: &str) -> Vec<Token> {
                 // Scan text until next reserved character or whitespace
                 let start = i;
                 while i + 1 < length
-                    && ![
-                        '#', '$', '%', '^', '&', '_', '{', '}', '\\', '~', '[',
-                        ']', ' ',
-                    ]
-                    .contains(&chars[i + 1])
+                    && !SPECIAL_CHARS.contains(&chars[i + 1])
                     && !chars[i + 1].is_whitespace()
                 {
                     i += 1;
@@ -208,93 +240,124 @@ pub fn scan(source: &str) -> Vec<Token> {
             }
         }
 
+        if ret.len() > token_count {
+            let last = ret.len() - 1;
+            ret[last].span =
+                Span::new(byte_offsets[token_start], byte_offsets[i + 1]);
+        }
+
         i += 1;
     }
     ret
 }
 
-/// return true if index = 0, or there is only spaces between source[index] and the previous newline
-/// or the 0th index
-///
-/// In particular, as latex ignore the beginning spaces of a line
-/// the first non-space character and all space before it are all considered
-/// as the beg
-///
-/// Eg:
-///# aaa {
-///#    ^
-///#     is not beginning of group
-///# arma virumque cano        {Trioae}
-///#                           ^
-///#                          is beginning of group
-/// Will panic if index is not valid, that is, index > source.len()
-fn is_beginning_of_group(source: &[char], index: usize) -> bool {
-    if index >= source.len() {
-        panic!("Index >= source.len() in function is_beginning_of_line. Program internal bug.");
+/// Scan the Indent and Section tokens at the beginning of a line, see the module doc
+/// i is moved to the first character not scanned
+fn scan_beginning_of_line(
+    chars: &[char],
+    i: &mut usize,
+    byte_offsets: &[usize],
+    ret: &mut Vec<Token>,
+) {
+    let start = *i;
+    let mut width: usize = 0;
+    while *i < chars.len() && (chars[*i] == ' ' || chars[*i] == '\t') {
+        width += if chars[*i] == '\t' { 4 } else { 1 };
+        *i += 1;
     }
-    let mut i = index;
-    while i > 0 && (source[i - 1] == ' ' || source[i - 1] == '\t') {
-        i -= 1;
+    if width > 0 && *i < chars.len() && chars[*i] != '\n' {
+        let mut token = Token::new(
+            TokenType::Indent(width.min(u8::MAX as usize) as u8),
+            String::new(),
+        );
+        token.span = Span::new(byte_offsets[start], byte_offsets[*i]);
+        ret.push(token);
+    } else if width > 0 {
+        // a blank line
+        return;
     }
 
-    if i == 0 || source[i - 1] == '{' {
-        return true;
-    }
-    false
-}
-/// return true if index = 0, or there is only spaces between source[index] and the previous newline
-/// or the 0th index
-///
-/// In particular, as latex ignore the beginning spaces of a line
-/// the first non-space character and all space before it are all considered
-/// as the beg
-///
-/// Eg:
-///# aaa\n
-///#     ^
-///#      is not beginning of line
-///# arma virumque cano \n     Trioae
-///#                           ^
-///#                           is beginning of line
-/// Will panic if index is not valid, that is, index > source.len()
-fn is_beginning_of_line(source: &[char], index: usize) -> bool {
-    if index >= source.len() {
-        panic!("Index >= source.len() in function is_beginning_of_line. Program internal bug.");
+    let start = *i;
+    let mut level: usize = 0;
+    while start + level < chars.len() && chars[start + level] == '#' {
+        level += 1;
     }
-    let mut i = index;
-    while i > 0 && (source[i - 1] == ' ' || source[i - 1] == '\t') {
-        i -= 1;
+    let followed_by_space = start + level >= chars.len()
+        || chars[start + level] == ' '
+        || chars[start + level] == '\n';
+    if (1..=6).contains(&level) && followed_by_space {
+        *i += level;
+        let mut token =
+            Token::new(TokenType::Section(level as u8), "#".repeat(level));
+        token.span = Span::new(byte_offsets[start], byte_offsets[*i]);
+        ret.push(token);
     }
+}
 
-    if i == 0 || source[i - 1] == '\n' {
-        return true;
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn types_and_lexemes(source: &str) -> Vec<(TokenType, String)> {
+        scan(source)
+            .into_iter()
+            .map(|t| (t.token_type, t.lexeme))
+            .collect()
     }
-    false
-}
 
-/// return the index of the end of the current line, including the newline character
-/// If the current line is the last line, return the last index
-///
-/// EG
-/// / aaa\n
-/// /    ^ //return 3
-/// / \n\n
-/// / ^   //return 0
-/// aaaa
-///    ^ //return 3
-/// Will panic if index is not valid, that is, index > source.len()
-fn index_to_end_of_cur_line(source: &[char], index: usize) -> usize {
-    if index >= source.len() {
-        panic!("Index >= source.len() in function is_beginning_of_line. Program internal bug.");
+    #[test]
+    fn test_beginning_of_line() {
+        assert_eq!(
+            types_and_lexemes("## Arma\n  - virum\n#no"),
+            vec![
+                (TokenType::Section(2), "##".into()),
+                (TokenType::Space, "".into()),
+                (TokenType::Word, "Arma".into()),
+                (TokenType::Newline, "\n".into()),
+                (TokenType::Indent(2), "".into()),
+                (TokenType::Slash, "-".into()),
+                (TokenType::Space, "".into()),
+                (TokenType::Word, "virum".into()),
+                (TokenType::Newline, "\n".into()),
+                (TokenType::Word, "#no".into()),
+            ]
+        );
     }
 
-    let mut i = index;
-    while i < source.len() && source[i] != '\n' {
-        i += 1;
+    #[test]
+    fn test_inline() {
+        assert_eq!(
+            types_and_lexemes("**cano** `x` ![a](b) \\*\\cite"),
+            vec![
+                (TokenType::Star, "*".into()),
+                (TokenType::Star, "*".into()),
+                (TokenType::Word, "cano".into()),
+                (TokenType::Star, "*".into()),
+                (TokenType::Star, "*".into()),
+                (TokenType::Space, "".into()),
+                (TokenType::Backtick, "`".into()),
+                (TokenType::Word, "x".into()),
+                (TokenType::Backtick, "`".into()),
+                (TokenType::Space, "".into()),
+                (TokenType::Bang, "!".into()),
+                (TokenType::LeftSquareBracket, "[".into()),
+                (TokenType::Word, "a".into()),
+                (TokenType::RightSquareBracket, "]".into()),
+                (TokenType::LeftParenthesis, "(".into()),
+                (TokenType::Word, "b".into()),
+                (TokenType::RightParenthesis, ")".into()),
+                (TokenType::Space, "".into()),
+                (TokenType::EscapedChar, "*".into()),
+                (TokenType::Command, "cite".into()),
+            ]
+        );
     }
-    // if we are at the end of the source, just return the last index
-    if i == source.len() {
-        return i - 1;
+
+    #[test]
+    fn test_span() {
+        let source = "é  $x$";
+        let tokens = scan(source);
+        assert_eq!(tokens[1].span, Span::new(2, 4));
+        assert_eq!(&source[tokens[2].span.start..tokens[4].span.end], "$x$");
     }
-    i
 }
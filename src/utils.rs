@@ -1,4 +1,5 @@
 use crate::config::Config;
+use crate::latex_interpreter::token::Span;
 use std::error::Error;
 use std::path::Path;
 use std::path::PathBuf;
@@ -39,6 +40,19 @@ pub(crate) fn overwrite_to_file_path_buf(
     Ok(())
 }
 
+/// Overwrite path with content, backing the original up as `.<file>.backup` next to it, just as
+/// `format --in-place` does
+pub(crate) fn overwrite_with_backup(
+    path: &Path,
+    content: &str,
+) -> Result<(), Box<dyn Error>> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let backup = path.with_file_name(format!(".{}.backup", file_name));
+    fs::copy(path, &backup)?;
+    info!("Backed up original file to `{}`", backup.display());
+    overwrite_to_file_path_buf(&path.to_path_buf(), content)
+}
+
 // TODO: add support for Windows
 pub(crate) fn get_config_dir() -> Result<String, Box<dyn Error>> {
     use std::env;
@@ -70,6 +84,27 @@ pub fn copy_dir_all(
     Ok(())
 }
 
+/// Lexically normalise a path: remove `.` components and resolve `..` against the preceding
+/// component, without touching the file system (unlike fs::canonicalize).
+/// EG: `chapters/../figures/./a.png` becomes `figures/a.png`
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    use std::path::Component;
+    let mut ret = PathBuf::new();
+    for comp in path.components() {
+        match comp {
+            Component::CurDir => {}
+            Component::ParentDir => match ret.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    ret.pop();
+                }
+                _ => ret.push(".."),
+            },
+            _ => ret.push(comp),
+        }
+    }
+    ret
+}
+
 /// Struct for IO and error handling
 #[derive(Debug, Clone)]
 pub struct FileInput {
@@ -100,10 +135,73 @@ impl FileInput {
         &self.file_path
     }
 
+    /// The source text a token or an AST node is created from
+    pub fn get_span_str(&self, span: &Span) -> &str {
+        &self.content[span.start..span.end]
+    }
+
+    /// Convert a byte offset in content into (row, col), both starting from 0
+    /// Like Token, col counts characters rather than bytes
+    pub fn get_row_col(&self, offset: usize) -> (usize, usize) {
+        let before = &self.content[..offset];
+        let row = before.matches('\n').count();
+        let line_start = match before.rfind('\n') {
+            Some(i) => i + 1,
+            None => 0,
+        };
+        let col = before[line_start..].chars().count();
+        (row, col)
+    }
+
     pub fn dummy() -> Self {
         FileInput::from_str("dummy/path", "")
     }
 }
 
+/// Create files (relative path, content) in a fresh temporary directory named after name, and
+/// return the directory
 #[cfg(test)]
-mod test {}
+pub(crate) fn create_test_project(
+    name: &str,
+    files: &[(&str, &str)],
+) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("tex-helper-test-{}", name));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    for (path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    dir
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize_path() {
+        assert_eq!(
+            normalize_path(Path::new("chapters/../figures/./a.png")),
+            PathBuf::from("figures/a.png")
+        );
+        assert_eq!(
+            normalize_path(Path::new("../a/b/../c.tex")),
+            PathBuf::from("../a/c.tex")
+        );
+        assert_eq!(normalize_path(Path::new("/a/./b")), PathBuf::from("/a/b"));
+    }
+
+    #[test]
+    fn test_get_row_col() {
+        let input = FileInput::from_str("dummy/path", "ab\nçd\n\ne");
+        assert_eq!(input.get_row_col(0), (0, 0));
+        assert_eq!(input.get_row_col(1), (0, 1));
+        assert_eq!(input.get_row_col(3), (1, 0));
+        // ç is two bytes
+        assert_eq!(input.get_row_col(5), (1, 1));
+        assert_eq!(input.get_row_col(8), (3, 0));
+    }
+}